@@ -0,0 +1,105 @@
+// benches/sharded_bench.rs
+//
+// Throughput of concurrent SET/GET traffic against one database, with the whole keyspace
+// behind a single lock versus split across lock-striped shards. Each thread runs the same
+// number of commands on its own keys, so with enough cores the sharded store should scale
+// with the thread count while the single lock stays flat.
+use std::sync::{Barrier, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rediodb::storage::sharded::{ShardedStore, DEFAULT_SHARDS};
+use rediodb::storage::ttl_store::TTLStore;
+
+/// Commands each thread runs per iteration.
+const OPS_PER_THREAD: usize = 10_000;
+
+/// Runs `threads` threads doing alternating SET and GET through `run`, returning the time
+/// until the last one finished.
+fn run_threads<F>(threads: usize, run: F) -> Duration
+where
+    F: Fn(&[u8], bool) + Sync,
+{
+    let barrier = Barrier::new(threads + 1);
+    thread::scope(|scope| {
+        for thread in 0..threads {
+            let (barrier, run) = (&barrier, &run);
+            scope.spawn(move || {
+                let keys: Vec<Vec<u8>> = (0..1024)
+                    .map(|i| format!("t{}:key:{}", thread, i).into_bytes())
+                    .collect();
+                barrier.wait();
+                for op in 0..OPS_PER_THREAD {
+                    run(&keys[op % keys.len()], op % 2 == 0);
+                }
+            });
+        }
+        barrier.wait();
+        let started = Instant::now();
+        // Leaving the scope joins every thread.
+        started
+    })
+    .elapsed()
+}
+
+fn bench_concurrent_throughput(c: &mut Criterion) {
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut thread_counts = vec![1];
+    while thread_counts.last().unwrap() * 2 <= cores.max(4) {
+        thread_counts.push(thread_counts.last().unwrap() * 2);
+    }
+
+    let mut group = c.benchmark_group("concurrent_set_get");
+    for &threads in &thread_counts {
+        group.throughput(Throughput::Elements((threads * OPS_PER_THREAD) as u64));
+
+        let single = Mutex::new(TTLStore::new());
+        group.bench_with_input(
+            BenchmarkId::new("single_lock", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| {
+                            run_threads(threads, |key, write| {
+                                let mut store = single.lock().unwrap();
+                                if write {
+                                    store.set(key, b"value", None).unwrap();
+                                } else {
+                                    store.get(key).unwrap();
+                                }
+                            })
+                        })
+                        .sum()
+                })
+            },
+        );
+
+        let sharded = ShardedStore::new(DEFAULT_SHARDS);
+        group.bench_with_input(
+            BenchmarkId::new("sharded", threads),
+            &threads,
+            |b, &threads| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| {
+                            run_threads(threads, |key, write| {
+                                let mut store = sharded.lock_key(key);
+                                if write {
+                                    store.set(key, b"value", None).unwrap();
+                                } else {
+                                    store.get(key).unwrap();
+                                }
+                            })
+                        })
+                        .sum()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_concurrent_throughput);
+criterion_main!(benches);
@@ -2,9 +2,13 @@
 //
 // This build script compiles the Protocol Buffers for the gRPC service.
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = prost_build::Config::new();
+    // Keys and values are `bytes` fields; generate them as `bytes::Bytes` so payloads are
+    // shared rather than copied, and print as escaped byte strings.
+    config.bytes(["."]);
     tonic_build::configure()
         // Using the default OUT_DIR for generated code.
         .build_server(true)
-        .compile(&["proto/rediodb.proto"], &["proto"])?;
+        .compile_with_config(config, &["proto/rediodb.proto"], &["proto"])?;
     Ok(())
 }
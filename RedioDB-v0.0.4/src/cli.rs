@@ -1,28 +1,56 @@
 // src/cli.rs
 
-use clap::{Parser, Subcommand};
+use bytes::Bytes;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use std::env;
+use std::path::PathBuf;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
 
 // Import gRPC client types from our generated code.
+use rediodb::server::my_service::{DB_METADATA_KEY, RDB_CHUNK_SIZE};
 use rediodb::server::rediodb_server::rediodb_client::RediodbClient;
 use rediodb::server::rediodb_server::{
     // Basic Key-Value operations
-    SetRequest, KeyRequest, ExpireRequest,
+    SetRequest, KeyRequest, ExpireRequest, KeyValue, GetExRequest, MSetRequest, MGetRequest,
+    // Generic keyspace operations
+    MultiKeyRequest, RenameRequest, CopyRequest, RestoreRequest, MigrateRequest,
     // Extended Atomic Operations
-    IncrRequest, DecrRequest, AppendRequest,
+    IncrRequest, DecrRequest, IncrByFloatRequest, AppendRequest,
     // Key Pattern Matching
-    PatternRequest,
+    PatternRequest, ScanRequest, MemberScanRequest,
+    // Logical databases
+    SelectRequest, DbSizeRequest, FlushRequest, SwapDbRequest, MoveRequest,
+    // Persistence
+    BgRewriteAofRequest, SaveRequest, BgSaveRequest, LastSaveRequest, RdbChunk, ExportRdbRequest,
+    BackupRequest,
     // Transaction support
     MultiRequest, ExecRequest,
     // Data Structures: Lists
-    ListPushRequest, ListPopRequest,
+    ListPushRequest, ListPopRequest, ListRangeRequest, ListIndexRequest, ListSetRequest,
+    ListRemRequest, ListInsertRequest, ListPosRequest, ListMoveRequest, ListEnd,
+    BlockingPopRequest, BlockingMoveRequest,
     // Data Structures: Sets
-    SetAddRequest, SetMembersRequest,
+    SetAddRequest, SetMembersRequest, SetRemRequest, SetIsMemberRequest, SetMIsMemberRequest,
+    SetPopRequest, SetMoveRequest, SetKeysRequest, SetStoreRequest, SetInterCardRequest,
     // Data Structures: Hashes
-    HashSetRequest, HashGetRequest,
+    HashSetRequest, HashGetRequest, HashFieldsRequest, HashField, HashIncrByRequest, HashIncrByFloatRequest,
+    HashRandFieldRequest, HashExpireRequest,
+    // Data Structures: Sorted Sets
+    ZAddRequest, ZRemRequest, ZScoreRequest, ZRankRequest, ZRangeRequest, ZIncrByRequest,
+    ZPopRequest, ZStoreRequest, ZMember, ZLimit, ZRangeBy, ZAggregate,
+    // Data Structures: Streams
+    XAddRequest, XTrimRequest, XTrimOptions, XTrimStrategy, XRangeRequest, XReadRequest,
+    XGroupCreateRequest, XGroupRequest, XConsumerRequest, XReadGroupRequest, XAckRequest,
+    XPendingRequest, XClaimRequest, XAutoClaimRequest, XField,
     // Pub/Sub
     PublishRequest, SubscribeRequest,
 };
+use rediodb::storage::backup;
+use rediodb::storage::sorted_set::parse_score;
 // Fix: Import PubSub as an external crate module rather than from crate::rediodb.
 // use rediodb::pubsub::PubSub;
 
@@ -35,51 +63,336 @@ use rustyline::Editor;
 #[command(
     name = "rediodb-cli", 
     about = "REDIODB CLI similar to redis-cli", 
-    version = "0.1"
+    version = "0.1",
+    arg_required_else_help = true
 )]
 struct Cli {
+    /// Database to run the command against, by index or name (like redis-cli -n)
+    #[arg(short = 'n', long, global = true)]
+    db: Option<String>,
+    /// Print every key, one per line, instead of running a command (like redis-cli --scan)
+    #[arg(long)]
+    scan: bool,
+    /// With --scan: only print keys matching this pattern
+    #[arg(long, requires = "scan")]
+    pattern: Option<String>,
+    /// With --scan: number of keys to visit per page
+    #[arg(long, requires = "scan")]
+    count: Option<i64>,
+    /// With --scan: only print keys of this type (string, list, set, hash, zset, stream)
+    #[arg(long = "type", requires = "scan")]
+    key_type: Option<String>,
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
+}
+
+impl Cli {
+    /// The request for --scan mode.
+    fn scan_request(&self) -> ScanRequest {
+        ScanRequest {
+            cursor: 0,
+            pattern: self.pattern.clone().unwrap_or_default().into(),
+            count: self.count.unwrap_or_default(),
+            r#type: self.key_type.clone().unwrap_or_default(),
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Set a key-value pair with an optional TTL (in seconds)
     Set {
-        key: String,
-        value: String,
+        key: Bytes,
+        value: Bytes,
         #[arg(default_value_t = 0)]
         ttl: i32,
+        /// Only set the key if it does not exist
+        #[arg(long)]
+        nx: bool,
+        /// Only set the key if it already exists
+        #[arg(long)]
+        xx: bool,
+        /// Return the old value
+        #[arg(long)]
+        get: bool,
+        /// Retain the TTL of the existing key
+        #[arg(long)]
+        keep_ttl: bool,
+        /// TTL in milliseconds
+        #[arg(long, default_value_t = 0)]
+        px: i64,
+        /// Expire at this Unix time, in seconds
+        #[arg(long, default_value_t = 0)]
+        exat: i64,
+        /// Expire at this Unix time, in milliseconds
+        #[arg(long, default_value_t = 0)]
+        pxat: i64,
     },
     /// Get the value for a key
     Get {
-        key: String,
+        key: Bytes,
+    },
+    /// Set a key only if it does not exist
+    SetNx {
+        key: Bytes,
+        value: Bytes,
+    },
+    /// Set a key and return its old value
+    GetSet {
+        key: Bytes,
+        value: Bytes,
+    },
+    /// Get the value of a key and delete it
+    GetDel {
+        key: Bytes,
+    },
+    /// Get the value of a key and optionally update its TTL
+    GetEx {
+        key: Bytes,
+        /// TTL in seconds
+        #[arg(long, default_value_t = 0)]
+        ex: i64,
+        /// TTL in milliseconds
+        #[arg(long, default_value_t = 0)]
+        px: i64,
+        /// Expire at this Unix time, in seconds
+        #[arg(long, default_value_t = 0)]
+        exat: i64,
+        /// Expire at this Unix time, in milliseconds
+        #[arg(long, default_value_t = 0)]
+        pxat: i64,
+        /// Remove the TTL
+        #[arg(long)]
+        persist: bool,
+    },
+    /// Set several keys at once
+    MSet {
+        /// "key value" pairs
+        #[arg(required = true)]
+        key_values: Vec<String>,
+    },
+    /// Set several keys at once, only if none of them exists
+    MSetNx {
+        /// "key value" pairs
+        #[arg(required = true)]
+        key_values: Vec<String>,
+    },
+    /// Get the values of several keys
+    MGet {
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
     },
     /// Set expiration for a key (TTL in seconds)
     Expire {
-        key: String,
-        ttl: i32,
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        ttl: i64,
+        #[command(flatten)]
+        conditions: ExpireConditions,
+    },
+    /// Set expiration for a key (TTL in milliseconds)
+    PExpire {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        ttl: i64,
+        #[command(flatten)]
+        conditions: ExpireConditions,
+    },
+    /// Expire a key at a Unix time, in seconds
+    ExpireAt {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        timestamp: i64,
+        #[command(flatten)]
+        conditions: ExpireConditions,
+    },
+    /// Expire a key at a Unix time, in milliseconds
+    PExpireAt {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        timestamp: i64,
+        #[command(flatten)]
+        conditions: ExpireConditions,
+    },
+    /// Get the remaining TTL of a key in seconds (-1 without TTL, -2 if missing)
+    Ttl {
+        key: Bytes,
+    },
+    /// Get the remaining TTL of a key in milliseconds
+    PTtl {
+        key: Bytes,
+    },
+    /// Get the Unix time, in seconds, at which a key expires
+    ExpireTime {
+        key: Bytes,
+    },
+    /// Get the Unix time, in milliseconds, at which a key expires
+    PExpireTime {
+        key: Bytes,
+    },
+    /// Remove the TTL of a key
+    Persist {
+        key: Bytes,
+    },
+    /// Delete keys
+    Del {
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Delete keys, freeing large values in the background
+    Unlink {
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
     },
-    /// Atomically increment the numeric value of a key by an amount (default is 1)
+    /// Count how many of the keys exist
+    Exists {
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Update the last access time of keys
+    Touch {
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Get the type of the value stored at a key
+    Type {
+        key: Bytes,
+    },
+    /// Rename a key, overwriting the new name
+    Rename {
+        key: Bytes,
+        new_key: Bytes,
+    },
+    /// Rename a key, only if the new name does not exist
+    RenameNx {
+        key: Bytes,
+        new_key: Bytes,
+    },
+    /// Copy the value of a key to another key
+    Copy {
+        source: Bytes,
+        destination: Bytes,
+        /// Overwrite the destination if it exists
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Serialize the value and TTL of a key, printed as hex
+    Dump {
+        key: Bytes,
+    },
+    /// Create a key from a payload printed by `dump`
+    Restore {
+        key: Bytes,
+        /// The payload, as hex
+        payload: String,
+        /// TTL in milliseconds; 0 keeps the TTL the key was dumped with
+        #[arg(default_value_t = 0)]
+        ttl: i64,
+        /// Overwrite the key if it exists
+        #[arg(long)]
+        replace: bool,
+        /// The TTL is a Unix time in milliseconds
+        #[arg(long)]
+        absttl: bool,
+        /// Seconds the key counts as idle for, for LRU eviction
+        #[arg(long, default_value_t = 0)]
+        idletime: i64,
+    },
+    /// Move keys to another instance
+    Migrate {
+        /// gRPC address of the target instance, e.g. http://10.0.0.2:50051
+        address: String,
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+        /// Database on the target, by index or name
+        #[arg(long, default_value = "")]
+        target_db: String,
+        /// Timeout in milliseconds
+        #[arg(long, default_value_t = 1000)]
+        timeout: i64,
+        /// Keep the keys on this instance
+        #[arg(long)]
+        copy: bool,
+        /// Overwrite existing keys on the target
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Atomically increment the integer value of a key by an amount (default is 1)
     Incr {
-        key: String,
-        #[arg(default_value_t = 1)]
-        amount: i32,
+        key: Bytes,
+        #[arg(default_value_t = 1, allow_hyphen_values = true)]
+        amount: i64,
     },
-    /// Atomically decrement the numeric value of a key by an amount (default is 1)
+    /// Atomically decrement the integer value of a key by an amount (default is 1)
     Decr {
-        key: String,
-        #[arg(default_value_t = 1)]
-        amount: i32,
+        key: Bytes,
+        #[arg(default_value_t = 1, allow_hyphen_values = true)]
+        amount: i64,
+    },
+    /// Atomically increment the value of a key by a float
+    IncrByFloat {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        increment: f64,
     },
     /// Append a string to the current value of a key
     Append {
-        key: String,
-        value: String,
+        key: Bytes,
+        value: Bytes,
     },
     /// List all keys matching a pattern (e.g. "*" for all keys)
     Keys {
-        pattern: String,
+        pattern: Bytes,
+    },
+    /// Incrementally iterate over keys, starting at cursor 0
+    Scan {
+        #[arg(default_value_t = 0)]
+        cursor: u64,
+        /// Only return keys matching this pattern
+        #[arg(long = "match")]
+        pattern: Option<Bytes>,
+        /// Number of keys to visit
+        #[arg(long, default_value_t = 0)]
+        count: i64,
+        /// Only return keys of this type
+        #[arg(long = "type")]
+        key_type: Option<String>,
+    },
+    /// Set Scan: incrementally iterate over the members of a set
+    SScan {
+        key: Bytes,
+        #[arg(default_value_t = 0)]
+        cursor: u64,
+        /// Only return members matching this pattern
+        #[arg(long = "match")]
+        pattern: Option<Bytes>,
+        /// Number of members to visit
+        #[arg(long, default_value_t = 0)]
+        count: i64,
+    },
+    /// Hash Scan: incrementally iterate over the fields and values of a hash
+    HScan {
+        key: Bytes,
+        #[arg(default_value_t = 0)]
+        cursor: u64,
+        /// Only return fields matching this pattern
+        #[arg(long = "match")]
+        pattern: Option<Bytes>,
+        /// Number of fields to visit
+        #[arg(long, default_value_t = 0)]
+        count: i64,
+    },
+    /// Sorted Set Scan: incrementally iterate over the members and scores of a sorted set
+    ZScan {
+        key: Bytes,
+        #[arg(default_value_t = 0)]
+        cursor: u64,
+        /// Only return members matching this pattern
+        #[arg(long = "match")]
+        pattern: Option<Bytes>,
+        /// Number of members to visit
+        #[arg(long, default_value_t = 0)]
+        count: i64,
     },
     /// Start a transaction block (queue multiple commands)
     Multi {
@@ -87,48 +400,697 @@ enum Commands {
     },
     /// Execute queued transactional commands
     Exec,
-    /// List Push: add an element to a list
+    /// List Push: add elements to the head of a list
     LPush {
-        key: String,
-        value: String,
+        key: Bytes,
+        #[arg(required = true)]
+        values: Vec<Bytes>,
     },
-    /// List Pop: remove and return an element from a list
+    /// List Pop: remove and return an element from the head of a list
     LPop {
-        key: String,
+        key: Bytes,
+    },
+    /// List Right Push: add elements to the tail of a list
+    RPush {
+        key: Bytes,
+        #[arg(required = true)]
+        values: Vec<Bytes>,
     },
-    /// Set Add: add a member to a set
+    /// List Right Pop: remove and return an element from the tail of a list
+    RPop {
+        key: Bytes,
+    },
+    /// List Push If Exists: add elements to the head of an existing list
+    LPushX {
+        key: Bytes,
+        #[arg(required = true)]
+        values: Vec<Bytes>,
+    },
+    /// List Right Push If Exists: add elements to the tail of an existing list
+    RPushX {
+        key: Bytes,
+        #[arg(required = true)]
+        values: Vec<Bytes>,
+    },
+    /// List Length: get the number of elements in a list
+    LLen {
+        key: Bytes,
+    },
+    /// List Range: get the elements between two indexes (negative indexes count from the tail)
+    LRange {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        start: i64,
+        #[arg(allow_hyphen_values = true)]
+        stop: i64,
+    },
+    /// List Index: get the element at an index
+    LIndex {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        index: i64,
+    },
+    /// List Set: replace the element at an index
+    LSet {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        index: i64,
+        value: Bytes,
+    },
+    /// List Trim: keep only the elements between two indexes
+    LTrim {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        start: i64,
+        #[arg(allow_hyphen_values = true)]
+        stop: i64,
+    },
+    /// List Remove: remove COUNT occurrences of a value (negative COUNT from the tail, 0 for all)
+    LRem {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        count: i64,
+        value: Bytes,
+    },
+    /// List Insert: insert a value before or after a pivot element
+    LInsert {
+        key: Bytes,
+        /// "before" or "after"
+        position: String,
+        pivot: Bytes,
+        value: Bytes,
+    },
+    /// List Position: get the indexes of elements equal to a value
+    LPos {
+        key: Bytes,
+        element: Bytes,
+        /// Skip to the RANK-th match (negative ranks search from the tail)
+        #[arg(long, default_value_t = 1, allow_hyphen_values = true)]
+        rank: i64,
+        /// Return up to COUNT matches (0 for all)
+        #[arg(long)]
+        count: Option<i64>,
+        /// Compare at most MAXLEN elements (0 for all)
+        #[arg(long, default_value_t = 0)]
+        maxlen: i64,
+    },
+    /// List Move: pop from one end of a list and push onto one end of another
+    LMove {
+        source: Bytes,
+        destination: Bytes,
+        /// "left" or "right"
+        wherefrom: String,
+        /// "left" or "right"
+        whereto: String,
+    },
+    /// List Right Pop Left Push: move the tail of one list to the head of another
+    RPopLPush {
+        source: Bytes,
+        destination: Bytes,
+    },
+    /// Blocking List Left Pop: pop the head of the first non-empty list, waiting for one
+    BLPop {
+        /// Keys followed by the timeout in seconds (0 waits forever)
+        #[arg(required = true, num_args = 2..)]
+        args: Vec<String>,
+    },
+    /// Blocking List Right Pop: pop the tail of the first non-empty list, waiting for one
+    BRPop {
+        /// Keys followed by the timeout in seconds (0 waits forever)
+        #[arg(required = true, num_args = 2..)]
+        args: Vec<String>,
+    },
+    /// Blocking List Move: LMOVE that waits for the source list to receive an element
+    BLMove {
+        source: Bytes,
+        destination: Bytes,
+        /// "left" or "right"
+        wherefrom: String,
+        /// "left" or "right"
+        whereto: String,
+        /// Seconds to wait (0 waits forever)
+        timeout: f64,
+    },
+    /// Set Add: add one or more members to a set
     SAdd {
-        key: String,
-        member: String,
+        key: Bytes,
+        #[arg(required = true)]
+        members: Vec<Bytes>,
     },
     /// Set Members: retrieve all members of a set
     SMembers {
-        key: String,
+        key: Bytes,
+    },
+    /// Set Remove: remove one or more members from a set
+    SRem {
+        key: Bytes,
+        #[arg(required = true)]
+        members: Vec<Bytes>,
+    },
+    /// Set Is Member: check whether a member belongs to a set
+    SIsMember {
+        key: Bytes,
+        member: Bytes,
+    },
+    /// Set Multiple Is Member: check several members at once
+    SMIsMember {
+        key: Bytes,
+        #[arg(required = true)]
+        members: Vec<Bytes>,
+    },
+    /// Set Cardinality: number of members in a set
+    SCard {
+        key: Bytes,
+    },
+    /// Set Pop: remove and return random members
+    SPop {
+        key: Bytes,
+        count: Option<i64>,
+    },
+    /// Set Random Member: return random members (a negative count allows repeats)
+    SRandMember {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        count: Option<i64>,
+    },
+    /// Set Move: move a member from one set to another
+    SMove {
+        source: Bytes,
+        destination: Bytes,
+        member: Bytes,
+    },
+    /// Set Intersection: members present in every set
+    SInter {
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Set Union: members present in any set
+    SUnion {
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Set Difference: members of the first set missing from all the others
+    SDiff {
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Set Intersection Store: store the intersection of sets in a destination key
+    SInterStore {
+        destination: Bytes,
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Set Union Store: store the union of sets in a destination key
+    SUnionStore {
+        destination: Bytes,
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Set Difference Store: store the difference of sets in a destination key
+    SDiffStore {
+        destination: Bytes,
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+    },
+    /// Set Intersection Cardinality: size of the intersection of sets
+    SInterCard {
+        /// Stop counting at this many members (0 counts all)
+        #[arg(long, default_value_t = 0)]
+        limit: i64,
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
     },
     /// Hash Set: set a field in a hash
     HSet {
-        key: String,
-        field: String,
-        value: String,
+        key: Bytes,
+        /// "field value" pairs
+        #[arg(required = true)]
+        field_values: Vec<String>,
     },
     /// Hash Get: get a field from a hash
     HGet {
-        key: String,
-        field: String,
+        key: Bytes,
+        field: Bytes,
+    },
+    /// Hash Set If Not Exists: set a field only if it does not exist yet
+    HSetNx {
+        key: Bytes,
+        field: Bytes,
+        value: Bytes,
+    },
+    /// Hash Multiple Get: get several fields at once
+    HMGet {
+        key: Bytes,
+        #[arg(required = true)]
+        fields: Vec<Bytes>,
+    },
+    /// Hash Get All: get every field and value of a hash
+    HGetAll {
+        key: Bytes,
+    },
+    /// Hash Delete: remove one or more fields
+    HDel {
+        key: Bytes,
+        #[arg(required = true)]
+        fields: Vec<Bytes>,
+    },
+    /// Hash Exists: check whether a field exists
+    HExists {
+        key: Bytes,
+        field: Bytes,
+    },
+    /// Hash Length: number of fields in a hash
+    HLen {
+        key: Bytes,
+    },
+    /// Hash Keys: all field names of a hash
+    HKeys {
+        key: Bytes,
+    },
+    /// Hash Values: all values of a hash
+    HVals {
+        key: Bytes,
+    },
+    /// Hash String Length: length of a field's value
+    HStrLen {
+        key: Bytes,
+        field: Bytes,
+    },
+    /// Hash Increment By: add an integer to a field
+    HIncrBy {
+        key: Bytes,
+        field: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        increment: i64,
+    },
+    /// Hash Increment By Float: add a float to a field
+    HIncrByFloat {
+        key: Bytes,
+        field: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        increment: f64,
+    },
+    /// Hash Random Field: return random fields (a negative count allows repeats)
+    HRandField {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        count: Option<i64>,
+        #[arg(long)]
+        withvalues: bool,
+    },
+    /// Hash Expire: set a TTL in seconds on fields
+    HExpire {
+        key: Bytes,
+        ttl: i64,
+        #[arg(required = true)]
+        fields: Vec<Bytes>,
+    },
+    /// Hash TTL: remaining TTL of fields in seconds
+    HTtl {
+        key: Bytes,
+        #[arg(required = true)]
+        fields: Vec<Bytes>,
+    },
+    /// Hash Persist: remove the TTL of fields
+    HPersist {
+        key: Bytes,
+        #[arg(required = true)]
+        fields: Vec<Bytes>,
+    },
+    /// Sorted Set Add: add members with scores, given as "score member" pairs
+    ZAdd {
+        key: Bytes,
+        /// Only add new members
+        #[arg(long)]
+        nx: bool,
+        /// Only update existing members
+        #[arg(long)]
+        xx: bool,
+        /// Only update when the new score is greater
+        #[arg(long)]
+        gt: bool,
+        /// Only update when the new score is less
+        #[arg(long)]
+        lt: bool,
+        /// Count changed members as well as added ones
+        #[arg(long)]
+        ch: bool,
+        #[arg(required = true, allow_hyphen_values = true)]
+        score_members: Vec<String>,
+    },
+    /// Sorted Set Remove: remove members from a sorted set
+    ZRem {
+        key: Bytes,
+        #[arg(required = true)]
+        members: Vec<Bytes>,
+    },
+    /// Sorted Set Score: get the score of a member
+    ZScore {
+        key: Bytes,
+        member: Bytes,
+    },
+    /// Sorted Set Cardinality: get the number of members
+    ZCard {
+        key: Bytes,
+    },
+    /// Sorted Set Rank: get the rank of a member (lowest score first)
+    ZRank {
+        key: Bytes,
+        member: Bytes,
+        /// Rank by descending score (ZREVRANK)
+        #[arg(long)]
+        rev: bool,
+    },
+    /// Sorted Set Range: get members by rank, score or lexicographical range
+    ZRange {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        start: String,
+        #[arg(allow_hyphen_values = true)]
+        stop: String,
+        /// Interpret start and stop as score bounds, e.g. "(1" or "-inf"
+        #[arg(long, conflicts_with = "by_lex")]
+        by_score: bool,
+        /// Interpret start and stop as lex bounds, e.g. "[a" or "-"
+        #[arg(long)]
+        by_lex: bool,
+        /// Return members in descending order
+        #[arg(long)]
+        rev: bool,
+        /// Limit the result to COUNT members after OFFSET (score and lex ranges only)
+        #[arg(long, num_args = 2, value_names = ["OFFSET", "COUNT"], allow_hyphen_values = true)]
+        limit: Option<Vec<i64>>,
+    },
+    /// Sorted Set Increment: increment the score of a member
+    ZIncrBy {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true, value_parser = parse_score)]
+        increment: f64,
+        member: Bytes,
+    },
+    /// Sorted Set Pop Min: remove and return the lowest-scored members
+    ZPopMin {
+        key: Bytes,
+        #[arg(default_value_t = 1)]
+        count: i64,
+    },
+    /// Sorted Set Pop Max: remove and return the highest-scored members
+    ZPopMax {
+        key: Bytes,
+        #[arg(default_value_t = 1)]
+        count: i64,
+    },
+    /// Sorted Set Union Store: store the union of sorted sets in a destination key
+    ZUnionStore {
+        destination: Bytes,
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+        /// Multiplication factor for each input key's scores
+        #[arg(long, num_args = 1.., allow_hyphen_values = true, value_parser = parse_score)]
+        weights: Vec<f64>,
+        /// How scores are combined: sum, min or max
+        #[arg(long, default_value = "sum")]
+        aggregate: String,
+    },
+    /// Sorted Set Intersection Store: store the intersection of sorted sets in a destination key
+    ZInterStore {
+        destination: Bytes,
+        #[arg(required = true)]
+        keys: Vec<Bytes>,
+        /// Multiplication factor for each input key's scores
+        #[arg(long, num_args = 1.., allow_hyphen_values = true, value_parser = parse_score)]
+        weights: Vec<f64>,
+        /// How scores are combined: sum, min or max
+        #[arg(long, default_value = "sum")]
+        aggregate: String,
+    },
+    /// Stream Add: append an entry given as "field value" pairs
+    XAdd {
+        key: Bytes,
+        /// Entry ID: "*" to generate it, "<ms>-*" or an explicit "<ms>-<seq>"
+        #[arg(long, default_value = "*")]
+        id: String,
+        /// Do not create the stream if it does not exist
+        #[arg(long)]
+        nomkstream: bool,
+        /// Trim the stream to at most this many entries
+        #[arg(long, conflicts_with = "minid")]
+        maxlen: Option<String>,
+        /// Trim entries with an ID lower than this one
+        #[arg(long)]
+        minid: Option<String>,
+        /// Allow trimming fewer entries than requested ("~")
+        #[arg(long)]
+        approx: bool,
+        /// With --approx, the maximum number of entries to trim
+        #[arg(long, default_value_t = 0)]
+        limit: i64,
+        #[arg(required = true)]
+        field_values: Vec<String>,
+    },
+    /// Stream Trim: remove old entries from a stream
+    XTrim {
+        key: Bytes,
+        /// Trim the stream to at most this many entries
+        #[arg(long, conflicts_with = "minid", required_unless_present = "minid")]
+        maxlen: Option<String>,
+        /// Trim entries with an ID lower than this one
+        #[arg(long)]
+        minid: Option<String>,
+        /// Allow trimming fewer entries than requested ("~")
+        #[arg(long)]
+        approx: bool,
+        /// With --approx, the maximum number of entries to trim
+        #[arg(long, default_value_t = 0)]
+        limit: i64,
+    },
+    /// Stream Length: get the number of entries in a stream
+    XLen {
+        key: Bytes,
+    },
+    /// Stream Range: get entries between two IDs ("-" and "+" for the extremes)
+    XRange {
+        key: Bytes,
+        #[arg(allow_hyphen_values = true)]
+        start: String,
+        end: String,
+        #[arg(long, default_value_t = 0)]
+        count: i64,
+        /// Return entries in descending order (XREVRANGE)
+        #[arg(long)]
+        rev: bool,
+    },
+    /// Stream Read: read entries after the given IDs, as "key... id..." ("$" for new entries)
+    XRead {
+        #[arg(long, default_value_t = 0)]
+        count: i64,
+        /// Keep streaming new entries, ending after this many ms without any (0 waits forever)
+        #[arg(long)]
+        block: Option<i64>,
+        #[arg(required = true)]
+        streams: Vec<String>,
+    },
+    /// Stream Group Create: create a consumer group starting after an ID ("$" for new entries)
+    XGroupCreate {
+        key: Bytes,
+        group: String,
+        #[arg(default_value = "$")]
+        id: String,
+        /// Create the stream if it does not exist
+        #[arg(long)]
+        mkstream: bool,
+    },
+    /// Stream Group Destroy: delete a consumer group
+    XGroupDestroy {
+        key: Bytes,
+        group: String,
+    },
+    /// Stream Group Create Consumer: add a consumer to a group
+    XGroupCreateConsumer {
+        key: Bytes,
+        group: String,
+        consumer: String,
+    },
+    /// Stream Group Delete Consumer: remove a consumer and its pending entries
+    XGroupDelConsumer {
+        key: Bytes,
+        group: String,
+        consumer: String,
+    },
+    /// Stream Read Group: read entries as a group consumer, as "key... id..." (">" for new entries)
+    XReadGroup {
+        group: String,
+        consumer: String,
+        #[arg(long, default_value_t = 0)]
+        count: i64,
+        /// Do not add delivered entries to the pending list
+        #[arg(long)]
+        noack: bool,
+        /// Wait up to this many ms for new entries
+        #[arg(long, default_value_t = 0)]
+        block: i64,
+        #[arg(required = true)]
+        streams: Vec<String>,
+    },
+    /// Stream Acknowledge: remove entries from a group's pending list
+    XAck {
+        key: Bytes,
+        group: String,
+        #[arg(required = true)]
+        ids: Vec<String>,
+    },
+    /// Stream Pending: summarize a group's pending entries, or list those between START and END
+    XPending {
+        key: Bytes,
+        group: String,
+        #[arg(allow_hyphen_values = true, requires = "end")]
+        start: Option<String>,
+        #[arg(requires = "count")]
+        end: Option<String>,
+        count: Option<i64>,
+        /// Only list entries of this consumer
+        consumer: Option<String>,
+        /// Only list entries idle for at least this many ms
+        #[arg(long, default_value_t = 0)]
+        idle: i64,
+    },
+    /// Stream Claim: take over pending entries idle for at least MIN_IDLE_MS
+    XClaim {
+        key: Bytes,
+        group: String,
+        consumer: String,
+        min_idle_ms: i64,
+        #[arg(required = true)]
+        ids: Vec<String>,
+        /// Set the idle time of claimed entries
+        #[arg(long)]
+        idle: Option<i64>,
+        /// Set the delivery count of claimed entries
+        #[arg(long)]
+        retry_count: Option<i64>,
+        /// Claim IDs that are not pending yet
+        #[arg(long)]
+        force: bool,
+        /// Return IDs only, without incrementing the delivery count
+        #[arg(long)]
+        justid: bool,
+    },
+    /// Stream Auto Claim: scan pending entries from START and take over idle ones
+    XAutoClaim {
+        key: Bytes,
+        group: String,
+        consumer: String,
+        min_idle_ms: i64,
+        #[arg(default_value = "0-0")]
+        start: String,
+        #[arg(long, default_value_t = 100)]
+        count: i64,
+        /// Return IDs only
+        #[arg(long)]
+        justid: bool,
     },
     /// Publish a message to a channel
     Publish {
         channel: String,
-        message: String,
+        message: Bytes,
     },
     /// Subscribe to messages on channels (supports multiple channels)
     Subscribe {
         channels: Vec<String>,
+        /// Also receive messages on every channel matching this glob pattern
+        #[arg(long)]
+        pattern: Option<String>,
+    },
+    /// Check a database exists; in the interactive shell, run later commands against it
+    Select {
+        db: String,
+    },
+    /// Number of keys in the database
+    DbSize,
+    /// Remove every key of the database
+    FlushDb {
+        /// Free the keys on a background thread
+        #[arg(long = "async")]
+        lazy: bool,
+    },
+    /// Remove every key of every database
+    FlushAll {
+        /// Free the keys on a background thread
+        #[arg(long = "async")]
+        lazy: bool,
+    },
+    /// Exchange the keys of two databases
+    SwapDb {
+        first: String,
+        second: String,
+    },
+    /// Move a key to another database
+    Move {
+        key: Bytes,
+        /// Destination database, by index or name
+        destination: String,
+    },
+    /// Rewrite the append-only file from the current dataset, in the background
+    BgRewriteAof,
+    /// Save a snapshot of every database, waiting until it is written
+    Save,
+    /// Save a snapshot of every database in the background
+    BgSave,
+    /// Print the Unix time of the last successful snapshot
+    LastSave,
+    /// Load a Redis RDB file into the server; keys with the same names are replaced
+    ImportRdb {
+        file: PathBuf,
+    },
+    /// Save every database to a Redis RDB file
+    ExportRdb {
+        file: PathBuf,
+    },
+    /// Write a consistent copy of the dataset below the server's backup root, or verify one
+    #[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
+    Backup {
+        /// Directory relative to the server's backup root; must be missing or empty
+        dir: Option<PathBuf>,
+        #[command(subcommand)]
+        action: Option<BackupAction>,
     },
     /// Start an interactive shell
     Interactive,
 }
 
+/// Subcommands of `backup`.
+#[derive(Subcommand)]
+enum BackupAction {
+    /// Check a local backup directory against its manifest, without a server
+    Verify {
+        dir: PathBuf,
+    },
+}
+
+/// Conditions shared by the EXPIRE family of commands.
+#[derive(Args)]
+struct ExpireConditions {
+    /// Only set the expiry if the key has no TTL
+    #[arg(long)]
+    nx: bool,
+    /// Only set the expiry if the key already has a TTL
+    #[arg(long)]
+    xx: bool,
+    /// Only set the expiry if it is later than the current one
+    #[arg(long)]
+    gt: bool,
+    /// Only set the expiry if it is earlier than the current one
+    #[arg(long)]
+    lt: bool,
+}
+
+impl ExpireConditions {
+    /// The request for `key` with these conditions.
+    fn request(self, key: Bytes, ttl: i64) -> ExpireRequest {
+        ExpireRequest { key, ttl, nx: self.nx, xx: self.xx, gt: self.gt, lt: self.lt }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
@@ -137,23 +1099,136 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let address = env::var("REDIO_ADDRESS")
         .unwrap_or_else(|_| "http://127.0.0.1:50051".to_string());
 
+    if cli.scan && cli.command.is_some() {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, "--scan cannot be used with a command")
+            .exit();
+    }
+
+    let db = cli.db.as_deref();
+    if cli.scan {
+        if let Err(err) = run_scan(cli.scan_request(), &address, db).await {
+            eprintln!("{}", render_error(err.as_ref()));
+            std::process::exit(1);
+        }
+    }
+
     match cli.command {
-        Commands::Interactive => {
-            run_interactive(&address).await?;
+        Some(Commands::Interactive) => {
+            run_interactive(&address, cli.db).await?;
+        }
+        Some(Commands::Backup { action: Some(BackupAction::Verify { dir }), .. }) => {
+            match backup::verify(&dir) {
+                Ok(manifest) => println!(
+                    "Backup OK: {} keys in {} files ({} bytes), {:?} taken at {} ms",
+                    manifest.keys(),
+                    manifest.files.len(),
+                    manifest.size(),
+                    manifest.kind,
+                    manifest.created
+                ),
+                Err(err) => {
+                    eprintln!("Backup verification failed: {}", err);
+                    std::process::exit(1);
+                }
+            }
         }
-        other => {
-            execute_command(other, &address).await?;
+        Some(other) => {
+            if let Err(err) = execute_command(other, &address, db).await {
+                eprintln!("{}", render_error(err.as_ref()));
+                std::process::exit(1);
+            }
         }
+        None => {}
     }
 
     Ok(())
 }
 
-async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = RediodbClient::connect(address.to_string()).await?;
+/// Prints every key of a full SCAN, one per line, as the server streams the pages.
+async fn run_scan(
+    req: ScanRequest,
+    address: &str,
+    db: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = connect(address, db).await?;
+    let mut pages = client.scan_stream(req).await?.into_inner();
+    while let Some(page) = pages.message().await? {
+        for key in page.keys {
+            println!("{}", key.escape_ascii());
+        }
+    }
+    Ok(())
+}
+
+/// Renders a failed command the way redis-cli does: server errors such as WRONGTYPE are
+/// shown as `(error) <message>`, anything else (e.g. connection failures) as `Error: ...`.
+fn render_error(err: &(dyn std::error::Error + 'static)) -> String {
+    match err.downcast_ref::<tonic::Status>() {
+        Some(status) => format!("(error) {}", status.message()),
+        None => format!("Error: {}", err),
+    }
+}
+
+/// Formats bytes as lowercase hex, two digits per byte.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parses hex written by `to_hex`; None if it is not valid hex.
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Tags every request with the database to run against.
+#[derive(Clone)]
+struct SelectDb(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for SelectDb {
+    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        if let Some(db) = &self.0 {
+            request.metadata_mut().insert(DB_METADATA_KEY, db.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Connects to the server; requests run against `db`, or the server's default database.
+async fn connect(
+    address: &str,
+    db: Option<&str>,
+) -> Result<RediodbClient<InterceptedService<Channel, SelectDb>>, Box<dyn std::error::Error>> {
+    let db = db.map(|db| db.parse()).transpose().map_err(|_| "invalid database name")?;
+    let channel = Channel::from_shared(address.to_string())?.connect().await?;
+    Ok(RediodbClient::with_interceptor(channel, SelectDb(db)))
+}
+
+async fn execute_command(
+    cmd: Commands,
+    address: &str,
+    db: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = connect(address, db).await?;
     match cmd {
-        Commands::Set { key, value, ttl } => {
-            let req = tonic::Request::new(SetRequest { key, value, ttl });
+        Commands::Set { key, value, ttl, nx, xx, get, keep_ttl, px, exat, pxat } => {
+            let req = tonic::Request::new(SetRequest {
+                key,
+                value,
+                ttl,
+                nx,
+                xx,
+                get,
+                keep_ttl,
+                px,
+                exat,
+                pxat,
+            });
             let resp = client.set(req).await?;
             println!("Set Response: {:?}", resp.into_inner());
         }
@@ -162,11 +1237,158 @@ async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std
             let resp = client.get(req).await?;
             println!("Get Response: {:?}", resp.into_inner());
         }
-        Commands::Expire { key, ttl } => {
-            let req = tonic::Request::new(ExpireRequest { key, ttl });
+        Commands::SetNx { key, value } => {
+            let req = tonic::Request::new(KeyValue { key, value });
+            let resp = client.set_nx(req).await?;
+            println!("SetNx Response: {:?}", resp.into_inner());
+        }
+        Commands::GetSet { key, value } => {
+            let req = tonic::Request::new(KeyValue { key, value });
+            let resp = client.get_set(req).await?;
+            println!("GetSet Response: {:?}", resp.into_inner());
+        }
+        Commands::GetDel { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.get_del(req).await?;
+            println!("GetDel Response: {:?}", resp.into_inner());
+        }
+        Commands::GetEx { key, ex, px, exat, pxat, persist } => {
+            let req = tonic::Request::new(GetExRequest { key, ex, px, exat, pxat, persist });
+            let resp = client.get_ex(req).await?;
+            println!("GetEx Response: {:?}", resp.into_inner());
+        }
+        Commands::MSet { key_values } => {
+            let req = tonic::Request::new(MSetRequest { pairs: to_key_values(&key_values)? });
+            let resp = client.m_set(req).await?;
+            println!("MSet Response: {:?}", resp.into_inner());
+        }
+        Commands::MSetNx { key_values } => {
+            let req = tonic::Request::new(MSetRequest { pairs: to_key_values(&key_values)? });
+            let resp = client.m_set_nx(req).await?;
+            println!("MSetNx Response: {:?}", resp.into_inner());
+        }
+        Commands::MGet { keys } => {
+            let req = tonic::Request::new(MGetRequest { keys });
+            let resp = client.m_get(req).await?;
+            println!("MGet Response: {:?}", resp.into_inner());
+        }
+        Commands::Expire { key, ttl, conditions } => {
+            let req = tonic::Request::new(conditions.request(key, ttl));
             let resp = client.expire(req).await?;
             println!("Expire Response: {:?}", resp.into_inner());
         }
+        Commands::PExpire { key, ttl, conditions } => {
+            let req = tonic::Request::new(conditions.request(key, ttl));
+            let resp = client.p_expire(req).await?;
+            println!("PExpire Response: {:?}", resp.into_inner());
+        }
+        Commands::ExpireAt { key, timestamp, conditions } => {
+            let req = tonic::Request::new(conditions.request(key, timestamp));
+            let resp = client.expire_at(req).await?;
+            println!("ExpireAt Response: {:?}", resp.into_inner());
+        }
+        Commands::PExpireAt { key, timestamp, conditions } => {
+            let req = tonic::Request::new(conditions.request(key, timestamp));
+            let resp = client.p_expire_at(req).await?;
+            println!("PExpireAt Response: {:?}", resp.into_inner());
+        }
+        Commands::Ttl { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.ttl(req).await?;
+            println!("Ttl Response: {:?}", resp.into_inner());
+        }
+        Commands::PTtl { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.p_ttl(req).await?;
+            println!("PTtl Response: {:?}", resp.into_inner());
+        }
+        Commands::ExpireTime { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.expire_time(req).await?;
+            println!("ExpireTime Response: {:?}", resp.into_inner());
+        }
+        Commands::PExpireTime { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.p_expire_time(req).await?;
+            println!("PExpireTime Response: {:?}", resp.into_inner());
+        }
+        Commands::Persist { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.persist(req).await?;
+            println!("Persist Response: {:?}", resp.into_inner());
+        }
+        Commands::Del { keys } => {
+            let req = tonic::Request::new(MultiKeyRequest { keys });
+            let resp = client.del(req).await?;
+            println!("Del Response: {:?}", resp.into_inner());
+        }
+        Commands::Unlink { keys } => {
+            let req = tonic::Request::new(MultiKeyRequest { keys });
+            let resp = client.unlink(req).await?;
+            println!("Unlink Response: {:?}", resp.into_inner());
+        }
+        Commands::Exists { keys } => {
+            let req = tonic::Request::new(MultiKeyRequest { keys });
+            let resp = client.exists(req).await?;
+            println!("Exists Response: {:?}", resp.into_inner());
+        }
+        Commands::Touch { keys } => {
+            let req = tonic::Request::new(MultiKeyRequest { keys });
+            let resp = client.touch(req).await?;
+            println!("Touch Response: {:?}", resp.into_inner());
+        }
+        Commands::Type { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.r#type(req).await?;
+            println!("Type Response: {:?}", resp.into_inner());
+        }
+        Commands::Rename { key, new_key } => {
+            let req = tonic::Request::new(RenameRequest { key, new_key });
+            let resp = client.rename(req).await?;
+            println!("Rename Response: {:?}", resp.into_inner());
+        }
+        Commands::RenameNx { key, new_key } => {
+            let req = tonic::Request::new(RenameRequest { key, new_key });
+            let resp = client.rename_nx(req).await?;
+            println!("RenameNx Response: {:?}", resp.into_inner());
+        }
+        Commands::Copy { source, destination, replace } => {
+            let req = tonic::Request::new(CopyRequest { source, destination, replace });
+            let resp = client.copy(req).await?;
+            println!("Copy Response: {:?}", resp.into_inner());
+        }
+        Commands::Dump { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            match client.dump(req).await?.into_inner().payload {
+                Some(payload) => println!("{}", to_hex(&payload)),
+                None => println!("(nil)"),
+            }
+        }
+        Commands::Restore { key, payload, ttl, replace, absttl, idletime } => {
+            let payload = parse_hex(&payload).ok_or("payload is not valid hex")?;
+            let req = tonic::Request::new(RestoreRequest {
+                key,
+                payload: payload.into(),
+                ttl,
+                replace,
+                absttl,
+                idle_time: idletime,
+            });
+            let resp = client.restore(req).await?;
+            println!("Restore Response: {:?}", resp.into_inner());
+        }
+        Commands::Migrate { address, keys, target_db, timeout, copy, replace } => {
+            let req = tonic::Request::new(MigrateRequest {
+                address,
+                db: target_db,
+                keys,
+                timeout_ms: timeout,
+                copy,
+                replace,
+            });
+            let resp = client.migrate(req).await?;
+            println!("Migrate Response: {:?}", resp.into_inner());
+        }
         Commands::Incr { key, amount } => {
             let req = tonic::Request::new(IncrRequest { key, amount });
             let resp = client.incr(req).await?;
@@ -177,6 +1399,11 @@ async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std
             let resp = client.decr(req).await?;
             println!("Decr Response: {:?}", resp.into_inner());
         }
+        Commands::IncrByFloat { key, increment } => {
+            let req = tonic::Request::new(IncrByFloatRequest { key, increment });
+            let resp = client.incr_by_float(req).await?;
+            println!("IncrByFloat Response: {:?}", resp.into_inner());
+        }
         Commands::Append { key, value } => {
             let req = tonic::Request::new(AppendRequest { key, value });
             let resp = client.append(req).await?;
@@ -187,6 +1414,31 @@ async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std
             let resp = client.keys(req).await?;
             println!("Keys Response: {:?}", resp.into_inner());
         }
+        Commands::Scan { cursor, pattern, count, key_type } => {
+            let req = tonic::Request::new(ScanRequest {
+                cursor,
+                pattern: pattern.unwrap_or_default(),
+                count,
+                r#type: key_type.unwrap_or_default(),
+            });
+            let resp = client.scan(req).await?;
+            println!("Scan Response: {:?}", resp.into_inner());
+        }
+        Commands::SScan { key, cursor, pattern, count } => {
+            let req = tonic::Request::new(member_scan_request(key, cursor, pattern, count));
+            let resp = client.s_scan(req).await?;
+            println!("SScan Response: {:?}", resp.into_inner());
+        }
+        Commands::HScan { key, cursor, pattern, count } => {
+            let req = tonic::Request::new(member_scan_request(key, cursor, pattern, count));
+            let resp = client.h_scan(req).await?;
+            println!("HScan Response: {:?}", resp.into_inner());
+        }
+        Commands::ZScan { key, cursor, pattern, count } => {
+            let req = tonic::Request::new(member_scan_request(key, cursor, pattern, count));
+            let resp = client.z_scan(req).await?;
+            println!("ZScan Response: {:?}", resp.into_inner());
+        }
         Commands::Multi { commands } => {
             let req = tonic::Request::new(MultiRequest { commands });
             let resp = client.multi(req).await?;
@@ -197,8 +1449,8 @@ async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std
             let resp = client.exec(req).await?;
             println!("Exec Response: {:?}", resp.into_inner());
         }
-        Commands::LPush { key, value } => {
-            let req = tonic::Request::new(ListPushRequest { key, value });
+        Commands::LPush { key, values } => {
+            let req = tonic::Request::new(list_push_request(key, values, false));
             let resp = client.l_push(req).await?;
             println!("LPush Response: {:?}", resp.into_inner());
         }
@@ -207,8 +1459,120 @@ async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std
             let resp = client.l_pop(req).await?;
             println!("LPop Response: {:?}", resp.into_inner());
         }
-        Commands::SAdd { key, member } => {
-            let req = tonic::Request::new(SetAddRequest { key, member });
+        Commands::RPush { key, values } => {
+            let req = tonic::Request::new(list_push_request(key, values, false));
+            let resp = client.r_push(req).await?;
+            println!("RPush Response: {:?}", resp.into_inner());
+        }
+        Commands::RPop { key } => {
+            let req = tonic::Request::new(ListPopRequest { key });
+            let resp = client.r_pop(req).await?;
+            println!("RPop Response: {:?}", resp.into_inner());
+        }
+        Commands::LPushX { key, values } => {
+            let req = tonic::Request::new(list_push_request(key, values, true));
+            let resp = client.l_push(req).await?;
+            println!("LPushX Response: {:?}", resp.into_inner());
+        }
+        Commands::RPushX { key, values } => {
+            let req = tonic::Request::new(list_push_request(key, values, true));
+            let resp = client.r_push(req).await?;
+            println!("RPushX Response: {:?}", resp.into_inner());
+        }
+        Commands::LLen { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.l_len(req).await?;
+            println!("LLen Response: {:?}", resp.into_inner());
+        }
+        Commands::LRange { key, start, stop } => {
+            let req = tonic::Request::new(ListRangeRequest { key, start, stop });
+            let resp = client.l_range(req).await?;
+            println!("LRange Response: {:?}", resp.into_inner());
+        }
+        Commands::LIndex { key, index } => {
+            let req = tonic::Request::new(ListIndexRequest { key, index });
+            let resp = client.l_index(req).await?;
+            println!("LIndex Response: {:?}", resp.into_inner());
+        }
+        Commands::LSet { key, index, value } => {
+            let req = tonic::Request::new(ListSetRequest { key, index, value });
+            let resp = client.l_set(req).await?;
+            println!("LSet Response: {:?}", resp.into_inner());
+        }
+        Commands::LTrim { key, start, stop } => {
+            let req = tonic::Request::new(ListRangeRequest { key, start, stop });
+            let resp = client.l_trim(req).await?;
+            println!("LTrim Response: {:?}", resp.into_inner());
+        }
+        Commands::LRem { key, count, value } => {
+            let req = tonic::Request::new(ListRemRequest { key, count, value });
+            let resp = client.l_rem(req).await?;
+            println!("LRem Response: {:?}", resp.into_inner());
+        }
+        Commands::LInsert { key, position, pivot, value } => {
+            let before = match position.to_ascii_lowercase().as_str() {
+                "before" => true,
+                "after" => false,
+                other => return Err(format!("unknown position '{}', expected before or after", other).into()),
+            };
+            let req = tonic::Request::new(ListInsertRequest { key, before, pivot, value });
+            let resp = client.l_insert(req).await?;
+            println!("LInsert Response: {:?}", resp.into_inner());
+        }
+        Commands::LPos { key, element, rank, count, maxlen } => {
+            let req = tonic::Request::new(ListPosRequest { key, element, rank, count, maxlen });
+            let resp = client.l_pos(req).await?;
+            println!("LPos Response: {:?}", resp.into_inner());
+        }
+        Commands::LMove { source, destination, wherefrom, whereto } => {
+            let req = tonic::Request::new(ListMoveRequest {
+                source,
+                destination,
+                wherefrom: parse_list_end(&wherefrom)? as i32,
+                whereto: parse_list_end(&whereto)? as i32,
+            });
+            let resp = client.l_move(req).await?;
+            println!("LMove Response: {:?}", resp.into_inner());
+        }
+        Commands::RPopLPush { source, destination } => {
+            let req = tonic::Request::new(ListMoveRequest {
+                source,
+                destination,
+                wherefrom: ListEnd::Right as i32,
+                whereto: ListEnd::Left as i32,
+            });
+            let resp = client.l_move(req).await?;
+            println!("RPopLPush Response: {:?}", resp.into_inner());
+        }
+        Commands::BLPop { args } => {
+            let (keys, timeout) = split_timeout(args)?;
+            let req = tonic::Request::new(BlockingPopRequest { keys: to_bytes(keys), timeout });
+            let resp = client.bl_pop(req).await?;
+            println!("BLPop Response: {:?}", resp.into_inner());
+        }
+        Commands::BRPop { args } => {
+            let (keys, timeout) = split_timeout(args)?;
+            let req = tonic::Request::new(BlockingPopRequest { keys: to_bytes(keys), timeout });
+            let resp = client.br_pop(req).await?;
+            println!("BRPop Response: {:?}", resp.into_inner());
+        }
+        Commands::BLMove { source, destination, wherefrom, whereto, timeout } => {
+            let req = tonic::Request::new(BlockingMoveRequest {
+                source,
+                destination,
+                wherefrom: parse_list_end(&wherefrom)? as i32,
+                whereto: parse_list_end(&whereto)? as i32,
+                timeout,
+            });
+            let resp = client.bl_move(req).await?;
+            println!("BLMove Response: {:?}", resp.into_inner());
+        }
+        Commands::SAdd { key, members } => {
+            let req = tonic::Request::new(SetAddRequest {
+                key,
+                member: Bytes::new(),
+                members,
+            });
             let resp = client.s_add(req).await?;
             println!("SAdd Response: {:?}", resp.into_inner());
         }
@@ -217,8 +1581,93 @@ async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std
             let resp = client.s_members(req).await?;
             println!("SMembers Response: {:?}", resp.into_inner());
         }
-        Commands::HSet { key, field, value } => {
-            let req = tonic::Request::new(HashSetRequest { key, field, value });
+        Commands::SRem { key, members } => {
+            let req = tonic::Request::new(SetRemRequest { key, members });
+            let resp = client.s_rem(req).await?;
+            println!("SRem Response: {:?}", resp.into_inner());
+        }
+        Commands::SIsMember { key, member } => {
+            let req = tonic::Request::new(SetIsMemberRequest { key, member });
+            let resp = client.s_is_member(req).await?;
+            println!("SIsMember Response: {:?}", resp.into_inner());
+        }
+        Commands::SMIsMember { key, members } => {
+            let req = tonic::Request::new(SetMIsMemberRequest { key, members });
+            let resp = client.sm_is_member(req).await?;
+            println!("SMIsMember Response: {:?}", resp.into_inner());
+        }
+        Commands::SCard { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.s_card(req).await?;
+            println!("SCard Response: {:?}", resp.into_inner());
+        }
+        Commands::SPop { key, count } => {
+            let req = tonic::Request::new(SetPopRequest { key, count });
+            let resp = client.s_pop(req).await?;
+            println!("SPop Response: {:?}", resp.into_inner());
+        }
+        Commands::SRandMember { key, count } => {
+            let req = tonic::Request::new(SetPopRequest { key, count });
+            let resp = client.s_rand_member(req).await?;
+            println!("SRandMember Response: {:?}", resp.into_inner());
+        }
+        Commands::SMove { source, destination, member } => {
+            let req = tonic::Request::new(SetMoveRequest { source, destination, member });
+            let resp = client.s_move(req).await?;
+            println!("SMove Response: {:?}", resp.into_inner());
+        }
+        Commands::SInter { keys } => {
+            let req = tonic::Request::new(SetKeysRequest { keys });
+            let resp = client.s_inter(req).await?;
+            println!("SInter Response: {:?}", resp.into_inner());
+        }
+        Commands::SUnion { keys } => {
+            let req = tonic::Request::new(SetKeysRequest { keys });
+            let resp = client.s_union(req).await?;
+            println!("SUnion Response: {:?}", resp.into_inner());
+        }
+        Commands::SDiff { keys } => {
+            let req = tonic::Request::new(SetKeysRequest { keys });
+            let resp = client.s_diff(req).await?;
+            println!("SDiff Response: {:?}", resp.into_inner());
+        }
+        Commands::SInterStore { destination, keys } => {
+            let req = tonic::Request::new(SetStoreRequest { destination, keys });
+            let resp = client.s_inter_store(req).await?;
+            println!("SInterStore Response: {:?}", resp.into_inner());
+        }
+        Commands::SUnionStore { destination, keys } => {
+            let req = tonic::Request::new(SetStoreRequest { destination, keys });
+            let resp = client.s_union_store(req).await?;
+            println!("SUnionStore Response: {:?}", resp.into_inner());
+        }
+        Commands::SDiffStore { destination, keys } => {
+            let req = tonic::Request::new(SetStoreRequest { destination, keys });
+            let resp = client.s_diff_store(req).await?;
+            println!("SDiffStore Response: {:?}", resp.into_inner());
+        }
+        Commands::SInterCard { limit, keys } => {
+            let req = tonic::Request::new(SetInterCardRequest { keys, limit });
+            let resp = client.s_inter_card(req).await?;
+            println!("SInterCard Response: {:?}", resp.into_inner());
+        }
+        Commands::HSet { key, field_values } => {
+            if field_values.len() % 2 != 0 {
+                return Err("expected field/value pairs".into());
+            }
+            let fields = field_values
+                .chunks(2)
+                .map(|pair| HashField {
+                    field: pair[0].clone().into(),
+                    value: pair[1].clone().into(),
+                })
+                .collect();
+            let req = tonic::Request::new(HashSetRequest {
+                key,
+                field: Bytes::new(),
+                value: Bytes::new(),
+                fields,
+            });
             let resp = client.h_set(req).await?;
             println!("HSet Response: {:?}", resp.into_inner());
         }
@@ -227,20 +1676,395 @@ async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std
             let resp = client.h_get(req).await?;
             println!("HGet Response: {:?}", resp.into_inner());
         }
+        Commands::HSetNx { key, field, value } => {
+            let req = tonic::Request::new(HashSetRequest {
+                key,
+                field,
+                value,
+                fields: Default::default(),
+            });
+            let resp = client.h_set_nx(req).await?;
+            println!("HSetNx Response: {:?}", resp.into_inner());
+        }
+        Commands::HMGet { key, fields } => {
+            let req = tonic::Request::new(HashFieldsRequest { key, fields });
+            let resp = client.hm_get(req).await?;
+            println!("HMGet Response: {:?}", resp.into_inner());
+        }
+        Commands::HGetAll { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.h_get_all(req).await?;
+            println!("HGetAll Response: {:?}", resp.into_inner());
+        }
+        Commands::HDel { key, fields } => {
+            let req = tonic::Request::new(HashFieldsRequest { key, fields });
+            let resp = client.h_del(req).await?;
+            println!("HDel Response: {:?}", resp.into_inner());
+        }
+        Commands::HExists { key, field } => {
+            let req = tonic::Request::new(HashGetRequest { key, field });
+            let resp = client.h_exists(req).await?;
+            println!("HExists Response: {:?}", resp.into_inner());
+        }
+        Commands::HLen { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.h_len(req).await?;
+            println!("HLen Response: {:?}", resp.into_inner());
+        }
+        Commands::HKeys { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.h_keys(req).await?;
+            println!("HKeys Response: {:?}", resp.into_inner());
+        }
+        Commands::HVals { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.h_vals(req).await?;
+            println!("HVals Response: {:?}", resp.into_inner());
+        }
+        Commands::HStrLen { key, field } => {
+            let req = tonic::Request::new(HashGetRequest { key, field });
+            let resp = client.h_str_len(req).await?;
+            println!("HStrLen Response: {:?}", resp.into_inner());
+        }
+        Commands::HIncrBy { key, field, increment } => {
+            let req = tonic::Request::new(HashIncrByRequest { key, field, increment });
+            let resp = client.h_incr_by(req).await?;
+            println!("HIncrBy Response: {:?}", resp.into_inner());
+        }
+        Commands::HIncrByFloat { key, field, increment } => {
+            let req = tonic::Request::new(HashIncrByFloatRequest { key, field, increment });
+            let resp = client.h_incr_by_float(req).await?;
+            println!("HIncrByFloat Response: {:?}", resp.into_inner());
+        }
+        Commands::HRandField { key, count, withvalues } => {
+            let req = tonic::Request::new(HashRandFieldRequest {
+                key,
+                count,
+                with_values: withvalues,
+            });
+            let resp = client.h_rand_field(req).await?;
+            println!("HRandField Response: {:?}", resp.into_inner());
+        }
+        Commands::HExpire { key, ttl, fields } => {
+            let req = tonic::Request::new(HashExpireRequest { key, fields, ttl });
+            let resp = client.h_expire(req).await?;
+            println!("HExpire Response: {:?}", resp.into_inner());
+        }
+        Commands::HTtl { key, fields } => {
+            let req = tonic::Request::new(HashFieldsRequest { key, fields });
+            let resp = client.h_ttl(req).await?;
+            println!("HTtl Response: {:?}", resp.into_inner());
+        }
+        Commands::HPersist { key, fields } => {
+            let req = tonic::Request::new(HashFieldsRequest { key, fields });
+            let resp = client.h_persist(req).await?;
+            println!("HPersist Response: {:?}", resp.into_inner());
+        }
+        Commands::ZAdd { key, nx, xx, gt, lt, ch, score_members } => {
+            if score_members.len() % 2 != 0 {
+                return Err("expected score/member pairs".into());
+            }
+            let mut members = Vec::new();
+            for pair in score_members.chunks(2) {
+                let score = parse_score(&pair[0])?;
+                members.push(ZMember { member: pair[1].clone().into(), score });
+            }
+            let req = tonic::Request::new(ZAddRequest { key, members, nx, xx, gt, lt, ch });
+            let resp = client.z_add(req).await?;
+            println!("ZAdd Response: {:?}", resp.into_inner());
+        }
+        Commands::ZRem { key, members } => {
+            let req = tonic::Request::new(ZRemRequest { key, members });
+            let resp = client.z_rem(req).await?;
+            println!("ZRem Response: {:?}", resp.into_inner());
+        }
+        Commands::ZScore { key, member } => {
+            let req = tonic::Request::new(ZScoreRequest { key, member });
+            let resp = client.z_score(req).await?;
+            println!("ZScore Response: {:?}", resp.into_inner());
+        }
+        Commands::ZCard { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.z_card(req).await?;
+            println!("ZCard Response: {:?}", resp.into_inner());
+        }
+        Commands::ZRank { key, member, rev } => {
+            let req = tonic::Request::new(ZRankRequest { key, member, reverse: rev });
+            let resp = client.z_rank(req).await?;
+            println!("ZRank Response: {:?}", resp.into_inner());
+        }
+        Commands::ZRange { key, start, stop, by_score, by_lex, rev, limit } => {
+            let by = if by_score {
+                ZRangeBy::Score
+            } else if by_lex {
+                ZRangeBy::Lex
+            } else {
+                ZRangeBy::Index
+            };
+            let limit = limit.map(|l| ZLimit { offset: l[0], count: l[1] });
+            let req = tonic::Request::new(ZRangeRequest {
+                key,
+                start: start.into(),
+                stop: stop.into(),
+                by: by as i32,
+                rev,
+                limit,
+            });
+            let resp = client.z_range(req).await?;
+            println!("ZRange Response: {:?}", resp.into_inner());
+        }
+        Commands::ZIncrBy { key, increment, member } => {
+            let req = tonic::Request::new(ZIncrByRequest { key, increment, member });
+            let resp = client.z_incr_by(req).await?;
+            println!("ZIncrBy Response: {:?}", resp.into_inner());
+        }
+        Commands::ZPopMin { key, count } => {
+            let req = tonic::Request::new(ZPopRequest { key, count });
+            let resp = client.z_pop_min(req).await?;
+            println!("ZPopMin Response: {:?}", resp.into_inner());
+        }
+        Commands::ZPopMax { key, count } => {
+            let req = tonic::Request::new(ZPopRequest { key, count });
+            let resp = client.z_pop_max(req).await?;
+            println!("ZPopMax Response: {:?}", resp.into_inner());
+        }
+        Commands::ZUnionStore { destination, keys, weights, aggregate } => {
+            let aggregate = parse_aggregate(&aggregate)? as i32;
+            let req = tonic::Request::new(ZStoreRequest { destination, keys, weights, aggregate });
+            let resp = client.z_union_store(req).await?;
+            println!("ZUnionStore Response: {:?}", resp.into_inner());
+        }
+        Commands::ZInterStore { destination, keys, weights, aggregate } => {
+            let aggregate = parse_aggregate(&aggregate)? as i32;
+            let req = tonic::Request::new(ZStoreRequest { destination, keys, weights, aggregate });
+            let resp = client.z_inter_store(req).await?;
+            println!("ZInterStore Response: {:?}", resp.into_inner());
+        }
+        Commands::XAdd { key, id, nomkstream, maxlen, minid, approx, limit, field_values } => {
+            if field_values.len() % 2 != 0 {
+                return Err("expected field/value pairs".into());
+            }
+            let fields = field_values
+                .chunks(2)
+                .map(|pair| XField {
+                    field: pair[0].clone().into(),
+                    value: pair[1].clone().into(),
+                })
+                .collect();
+            let trim = trim_options(maxlen, minid, approx, limit);
+            let req = tonic::Request::new(XAddRequest { key, id, fields, nomkstream, trim });
+            let resp = client.x_add(req).await?;
+            println!("XAdd Response: {:?}", resp.into_inner());
+        }
+        Commands::XTrim { key, maxlen, minid, approx, limit } => {
+            let trim = trim_options(maxlen, minid, approx, limit);
+            let req = tonic::Request::new(XTrimRequest { key, trim });
+            let resp = client.x_trim(req).await?;
+            println!("XTrim Response: {:?}", resp.into_inner());
+        }
+        Commands::XLen { key } => {
+            let req = tonic::Request::new(KeyRequest { key });
+            let resp = client.x_len(req).await?;
+            println!("XLen Response: {:?}", resp.into_inner());
+        }
+        Commands::XRange { key, start, end, count, rev } => {
+            let req = tonic::Request::new(XRangeRequest { key, start, end, count, rev });
+            let resp = client.x_range(req).await?;
+            println!("XRange Response: {:?}", resp.into_inner());
+        }
+        Commands::XRead { count, block, streams } => {
+            let (keys, ids) = split_streams(streams)?;
+            let req = tonic::Request::new(XReadRequest {
+                keys: to_bytes(keys),
+                ids,
+                count,
+                block_ms: block.unwrap_or(0),
+            });
+            if block.is_some() {
+                let mut stream = client.x_read_stream(req).await?.into_inner();
+                println!("Waiting for entries (Ctrl+C to exit)...");
+                while let Some(resp) = stream.message().await? {
+                    println!("XRead Response: {:?}", resp);
+                }
+            } else {
+                let resp = client.x_read(req).await?;
+                println!("XRead Response: {:?}", resp.into_inner());
+            }
+        }
+        Commands::XGroupCreate { key, group, id, mkstream } => {
+            let req = tonic::Request::new(XGroupCreateRequest { key, group, id, mkstream });
+            let resp = client.x_group_create(req).await?;
+            println!("XGroupCreate Response: {:?}", resp.into_inner());
+        }
+        Commands::XGroupDestroy { key, group } => {
+            let req = tonic::Request::new(XGroupRequest { key, group });
+            let resp = client.x_group_destroy(req).await?;
+            println!("XGroupDestroy Response: {:?}", resp.into_inner());
+        }
+        Commands::XGroupCreateConsumer { key, group, consumer } => {
+            let req = tonic::Request::new(XConsumerRequest { key, group, consumer });
+            let resp = client.x_group_create_consumer(req).await?;
+            println!("XGroupCreateConsumer Response: {:?}", resp.into_inner());
+        }
+        Commands::XGroupDelConsumer { key, group, consumer } => {
+            let req = tonic::Request::new(XConsumerRequest { key, group, consumer });
+            let resp = client.x_group_del_consumer(req).await?;
+            println!("XGroupDelConsumer Response: {:?}", resp.into_inner());
+        }
+        Commands::XReadGroup { group, consumer, count, noack, block, streams } => {
+            let (keys, ids) = split_streams(streams)?;
+            let req = tonic::Request::new(XReadGroupRequest {
+                group,
+                consumer,
+                keys: to_bytes(keys),
+                ids,
+                count,
+                noack,
+                block_ms: block,
+            });
+            let resp = client.x_read_group(req).await?;
+            println!("XReadGroup Response: {:?}", resp.into_inner());
+        }
+        Commands::XAck { key, group, ids } => {
+            let req = tonic::Request::new(XAckRequest { key, group, ids });
+            let resp = client.x_ack(req).await?;
+            println!("XAck Response: {:?}", resp.into_inner());
+        }
+        Commands::XPending { key, group, start, end, count, consumer, idle } => {
+            let req = tonic::Request::new(XPendingRequest {
+                key,
+                group,
+                start: start.unwrap_or_default(),
+                end: end.unwrap_or_default(),
+                count: count.unwrap_or(0),
+                consumer: consumer.unwrap_or_default(),
+                min_idle_ms: idle,
+            });
+            let resp = client.x_pending(req).await?;
+            println!("XPending Response: {:?}", resp.into_inner());
+        }
+        Commands::XClaim { key, group, consumer, min_idle_ms, ids, idle, retry_count, force, justid } => {
+            let req = tonic::Request::new(XClaimRequest {
+                key,
+                group,
+                consumer,
+                min_idle_ms,
+                ids,
+                idle_ms: idle,
+                retry_count,
+                force,
+                justid,
+            });
+            let resp = client.x_claim(req).await?;
+            println!("XClaim Response: {:?}", resp.into_inner());
+        }
+        Commands::XAutoClaim { key, group, consumer, min_idle_ms, start, count, justid } => {
+            let req = tonic::Request::new(XAutoClaimRequest {
+                key,
+                group,
+                consumer,
+                min_idle_ms,
+                start,
+                count,
+                justid,
+            });
+            let resp = client.x_auto_claim(req).await?;
+            println!("XAutoClaim Response: {:?}", resp.into_inner());
+        }
         Commands::Publish { channel, message } => {
             let req = tonic::Request::new(PublishRequest { channel, message });
             let resp = client.publish(req).await?;
             println!("Publish Response: {:?}", resp.into_inner());
         }
-        Commands::Subscribe { channels } => {
+        Commands::Select { db } => {
+            let req = tonic::Request::new(SelectRequest { db });
+            let resp = client.select(req).await?;
+            println!("Select Response: {:?}", resp.into_inner());
+        }
+        Commands::DbSize => {
+            let req = tonic::Request::new(DbSizeRequest {});
+            let resp = client.db_size(req).await?;
+            println!("DbSize Response: {:?}", resp.into_inner());
+        }
+        Commands::FlushDb { lazy } => {
+            let req = tonic::Request::new(FlushRequest { r#async: lazy });
+            let resp = client.flush_db(req).await?;
+            println!("FlushDb Response: {:?}", resp.into_inner());
+        }
+        Commands::FlushAll { lazy } => {
+            let req = tonic::Request::new(FlushRequest { r#async: lazy });
+            let resp = client.flush_all(req).await?;
+            println!("FlushAll Response: {:?}", resp.into_inner());
+        }
+        Commands::SwapDb { first, second } => {
+            let req = tonic::Request::new(SwapDbRequest { first, second });
+            let resp = client.swap_db(req).await?;
+            println!("SwapDb Response: {:?}", resp.into_inner());
+        }
+        Commands::Move { key, destination } => {
+            let req = tonic::Request::new(MoveRequest { key, db: destination });
+            let resp = client.r#move(req).await?;
+            println!("Move Response: {:?}", resp.into_inner());
+        }
+        Commands::BgRewriteAof => {
+            let req = tonic::Request::new(BgRewriteAofRequest {});
+            let resp = client.bg_rewrite_aof(req).await?;
+            println!("BgRewriteAof Response: {:?}", resp.into_inner());
+        }
+        Commands::Save => {
+            let req = tonic::Request::new(SaveRequest {});
+            let resp = client.save(req).await?;
+            println!("Save Response: {:?}", resp.into_inner());
+        }
+        Commands::BgSave => {
+            let req = tonic::Request::new(BgSaveRequest {});
+            let resp = client.bg_save(req).await?;
+            println!("BgSave Response: {:?}", resp.into_inner());
+        }
+        Commands::LastSave => {
+            let req = tonic::Request::new(LastSaveRequest {});
+            let resp = client.last_save(req).await?;
+            println!("LastSave Response: {:?}", resp.into_inner());
+        }
+        Commands::ImportRdb { file } => {
+            let bytes = Bytes::from(std::fs::read(&file)?);
+            let chunks: Vec<_> = (0..bytes.len())
+                .step_by(RDB_CHUNK_SIZE)
+                .map(|start| RdbChunk {
+                    data: bytes.slice(start..bytes.len().min(start + RDB_CHUNK_SIZE)),
+                })
+                .collect();
+            let resp = client.import_rdb(futures_util::stream::iter(chunks)).await?;
+            println!("ImportRdb Response: {:?}", resp.into_inner());
+        }
+        Commands::ExportRdb { file } => {
+            let req = tonic::Request::new(ExportRdbRequest {});
+            let mut chunks = client.export_rdb(req).await?.into_inner();
+            let mut bytes = Vec::new();
+            while let Some(chunk) = chunks.message().await? {
+                bytes.extend_from_slice(&chunk.data);
+            }
+            std::fs::write(&file, &bytes)?;
+            println!("Wrote {} bytes to {}", bytes.len(), file.display());
+        }
+        Commands::Backup { dir: Some(dir), action: None } => {
+            let req = tonic::Request::new(BackupRequest { path: dir.display().to_string() });
+            let resp = client.backup(req).await?;
+            println!("Backup Response: {:?}", resp.into_inner());
+        }
+        Commands::Subscribe { channels, pattern } => {
             let req = tonic::Request::new(SubscribeRequest {
                 channels,
-                pattern: "".into(),
+                pattern: pattern.unwrap_or_default(),
             });
             let mut stream = client.subscribe(req).await?.into_inner();
             println!("Subscribed. Listening for messages (Ctrl+C to exit)...");
             while let Some(msg) = stream.message().await? {
-                println!("Received message on channel '{}': {}", msg.channel, msg.message);
+                println!(
+                    "Received message on channel '{}': {}",
+                    msg.channel,
+                    msg.message.escape_ascii()
+                );
             }
         }
         _ => {}
@@ -248,13 +2072,119 @@ async fn execute_command(cmd: Commands, address: &str) -> Result<(), Box<dyn std
     Ok(())
 }
 
-async fn run_interactive(address: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Builds a request for SSCAN/HSCAN/ZSCAN.
+fn member_scan_request(
+    key: Bytes,
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: i64,
+) -> MemberScanRequest {
+    MemberScanRequest {
+        key,
+        cursor,
+        pattern: pattern.unwrap_or_default(),
+        count,
+    }
+}
+
+/// Pairs up the "key value..." arguments of MSET/MSETNX.
+fn to_key_values(args: &[String]) -> Result<Vec<KeyValue>, String> {
+    if !args.len().is_multiple_of(2) {
+        return Err("expected key/value pairs".to_string());
+    }
+    Ok(args
+        .chunks(2)
+        .map(|pair| KeyValue { key: pair[0].clone().into(), value: pair[1].clone().into() })
+        .collect())
+}
+
+/// Builds a push request for the LPUSH/RPUSH family.
+fn list_push_request(key: Bytes, values: Vec<Bytes>, only_if_exists: bool) -> ListPushRequest {
+    ListPushRequest {
+        key,
+        value: Bytes::new(),
+        values,
+        only_if_exists,
+    }
+}
+
+/// Converts positional arguments into key or value fields.
+fn to_bytes(args: Vec<String>) -> Vec<Bytes> {
+    args.into_iter().map(Bytes::from).collect()
+}
+
+/// Splits the "key... timeout" arguments of BLPOP/BRPOP.
+fn split_timeout(mut args: Vec<String>) -> Result<(Vec<String>, f64), String> {
+    let timeout = args.pop().unwrap_or_default();
+    let timeout = timeout
+        .parse()
+        .map_err(|_| format!("timeout '{}' is not a number", timeout))?;
+    Ok((args, timeout))
+}
+
+/// Parses the LEFT/RIGHT arguments of LMOVE.
+fn parse_list_end(end: &str) -> Result<ListEnd, String> {
+    match end.to_ascii_lowercase().as_str() {
+        "left" => Ok(ListEnd::Left),
+        "right" => Ok(ListEnd::Right),
+        other => Err(format!("unknown list end '{}', expected left or right", other)),
+    }
+}
+
+/// Parses the AGGREGATE option of ZUNIONSTORE/ZINTERSTORE.
+fn parse_aggregate(aggregate: &str) -> Result<ZAggregate, String> {
+    match aggregate.to_ascii_lowercase().as_str() {
+        "sum" => Ok(ZAggregate::Sum),
+        "min" => Ok(ZAggregate::Min),
+        "max" => Ok(ZAggregate::Max),
+        other => Err(format!("unknown aggregate '{}', expected sum, min or max", other)),
+    }
+}
+
+/// Builds the trimming options of XADD/XTRIM, if any.
+fn trim_options(
+    maxlen: Option<String>,
+    minid: Option<String>,
+    approximate: bool,
+    limit: i64,
+) -> Option<XTrimOptions> {
+    let (strategy, threshold) = match (maxlen, minid) {
+        (Some(maxlen), _) => (XTrimStrategy::Maxlen, maxlen),
+        (None, Some(minid)) => (XTrimStrategy::Minid, minid),
+        (None, None) => return None,
+    };
+    Some(XTrimOptions {
+        strategy: strategy as i32,
+        threshold,
+        approximate,
+        limit,
+    })
+}
+
+/// Splits the "key... id..." arguments of XREAD/XREADGROUP into keys and IDs.
+fn split_streams(mut streams: Vec<String>) -> Result<(Vec<String>, Vec<String>), String> {
+    if !streams.len().is_multiple_of(2) {
+        return Err("expected the same number of stream keys and IDs".to_string());
+    }
+    let ids = streams.split_off(streams.len() / 2);
+    Ok((streams, ids))
+}
+
+async fn run_interactive(
+    address: &str,
+    mut db: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("Starting RedioDB interactive shell. Type 'exit' or 'quit' to leave.");
     // Fix: Supply both generic parameters for Editor
     let mut rl = Editor::<(), DefaultHistory>::new()?;
     
     loop {
-        let line = rl.readline("rediodb> ");
+        // Like redis-cli, show the selected database unless it is the default one.
+        let prompt = match &db {
+            Some(db) => format!("rediodb[{}]> ", db),
+            None => "rediodb> ".to_string(),
+        };
+        let line = rl.readline(&prompt);
         match line {
             Ok(line) => {
                 let line = line.trim();
@@ -270,8 +2200,20 @@ async fn run_interactive(address: &str) -> Result<(), Box<dyn std::error::Error>
                 clap_args.extend(args);
                 match Cli::try_parse_from(clap_args) {
                     Ok(cli) => {
-                        if let Err(e) = execute_command(cli.command, address).await {
-                            eprintln!("Error: {}", e);
+                        // A database given on the line applies to that command only.
+                        let target = cli.db.as_deref().or(db.as_deref());
+                        let selected = match &cli.command {
+                            Some(Commands::Select { db }) => Some(db.clone()),
+                            _ => None,
+                        };
+                        let result = match cli.command {
+                            Some(command) => execute_command(command, address, target).await,
+                            None => run_scan(cli.scan_request(), address, target).await,
+                        };
+                        match result {
+                            Ok(()) if selected.is_some() => db = selected,
+                            Ok(()) => {}
+                            Err(e) => eprintln!("{}", render_error(e.as_ref())),
                         }
                     }
                     Err(e) => eprintln!("Parse error: {}", e),
@@ -2,6 +2,7 @@
 //
 // Stub for clustering and replication functionality.
 // In production, implement distributed consensus and replication logic.
+#[derive(Default)]
 pub struct ClusterManager;
 
 impl ClusterManager {
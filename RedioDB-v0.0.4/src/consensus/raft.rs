@@ -1,5 +1,6 @@
 // src/consensus/raft.rs
 /// A simple stub for Raft-based consensus functionality.
+#[derive(Default)]
 pub struct RaftNode;
 
 impl RaftNode {
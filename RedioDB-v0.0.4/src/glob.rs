@@ -0,0 +1,99 @@
+// src/glob.rs
+//
+// Redis-compatible glob matching, shared by KEYS, the MATCH option of the SCAN family and
+// pattern subscriptions. Patterns support `*`, `?`, `[abc]`, `[^abc]`, ranges such as `[a-z]`
+// and backslash escapes, and are matched byte by byte like Redis does.
+
+/// Returns true if `string` matches the glob `pattern`.
+///
+/// Malformed patterns are accepted the way Redis accepts them: an unterminated `[` class
+/// ends at the end of the pattern, and a trailing backslash matches itself.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Pattern position just after the last `*`, and the string position it was retried at.
+    let mut star: Option<(usize, usize)> = None;
+    while s < string.len() {
+        if p < pattern.len() {
+            if pattern[p] == b'*' {
+                p += 1;
+                star = Some((p, s));
+                continue;
+            }
+            if let Some(next) = match_token(pattern, p, string[s]) {
+                p = next;
+                s += 1;
+                continue;
+            }
+        }
+        // Mismatch: let the last `*` swallow one more byte, if there was one.
+        match star {
+            Some((star_p, star_s)) => {
+                p = star_p;
+                s = star_s + 1;
+                star = Some((star_p, s));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Escapes every glob metacharacter in `literal`, so the result only matches `literal`.
+pub fn glob_escape(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Helper function: Matches the single-byte token starting at `pattern[p]` (anything but `*`)
+/// against `c`. Returns the position after the token if it matches.
+fn match_token(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match pattern[p] {
+        b'?' => Some(p + 1),
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        b'[' => {
+            let (matched, next) = match_class(pattern, p + 1, c);
+            matched.then_some(next)
+        }
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Helper function: Matches `c` against the class whose body starts at `pattern[start]`.
+/// Returns whether it matched and the position after the closing `]`.
+fn match_class(pattern: &[u8], start: usize, c: u8) -> (bool, usize) {
+    let mut i = start;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    while i < pattern.len() {
+        match pattern[i] {
+            b']' => {
+                i += 1;
+                break;
+            }
+            b'\\' if i + 1 < pattern.len() => {
+                matched |= pattern[i + 1] == c;
+                i += 2;
+            }
+            low if i + 2 < pattern.len() && pattern[i + 1] == b'-' => {
+                let high = pattern[i + 2];
+                let (low, high) = if low <= high { (low, high) } else { (high, low) };
+                matched |= (low..=high).contains(&c);
+                i += 3;
+            }
+            literal => {
+                matched |= literal == c;
+                i += 1;
+            }
+        }
+    }
+    (matched != negate, i)
+}
@@ -1,6 +1,7 @@
 pub mod ai;
 pub mod cluster;
 pub mod consensus;
+pub mod glob;
 pub mod monitoring;
 pub mod pubsub;
 pub mod query;
@@ -3,9 +3,17 @@
 
 use tonic::transport::Server;
 use rediodb::server::rediodb_server::rediodb_server::RediodbServer;
-use rediodb::server::my_service::MyService;
-use env_logger;
+use rediodb::server::my_service::{self, MyService};
+use rediodb::server::resp;
+use rediodb::storage::backup;
+use rediodb::storage::databases::DatabaseConfig;
+use rediodb::storage::engine::{self, EngineKind};
+use rediodb::storage::expiry::ActiveExpireConfig;
+use rediodb::storage::eviction::MemoryConfig;
+use rediodb::storage::rdb;
+use rediodb::storage::snapshot::SnapshotConfig;
 use std::env;
+use std::path::{Path, PathBuf};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -18,6 +26,86 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     let service = MyService::default();
 
+    // Create the logical databases before anything touches them, loading the keyspace
+    // from the durable engine if one is configured.
+    let database_config = DatabaseConfig::from_env()?;
+    let engine = EngineKind::from_env()?;
+    // A backup to restore from is verified, and a RocksDB checkpoint put in place, first.
+    // It is restored once: a marker in the data directory keeps restarts from restoring it
+    // again over newer writes.
+    let restore_dir = env::var("REDIO_RESTORE_FROM").ok().map(PathBuf::from);
+    let restore_marker = engine::data_dir_from_env().join(backup::RESTORE_MARKER_FILENAME);
+    let restored = match &restore_dir {
+        Some(dir) => backup::restore_files(dir, &engine, &restore_marker)?,
+        None => None,
+    };
+    if let (Some(dir), None) = (&restore_dir, &restored) {
+        println!("Skipped restoring the backup at {}, which was restored already", dir.display());
+    }
+    my_service::configure_databases(database_config, &engine)?;
+    if let Some(report) = my_service::append_only_file_report() {
+        println!("Replayed {} entries from the append-only file", report.entries);
+        if let Some((offset, len)) = report.truncated {
+            println!("Dropped a truncated entry of {} bytes at offset {}", len, offset);
+        }
+    }
+    if let (Some(dir), Some(manifest)) = (&restore_dir, &restored) {
+        my_service::restore_backup(dir, manifest)?;
+        println!("Restored {} keys from the backup at {}", manifest.keys(), dir.display());
+    }
+
+    // Load the last snapshot, unless a durable engine or a restored backup already holds
+    // the keyspace.
+    let snapshot_config = SnapshotConfig::from_env()?;
+    let load = matches!(engine, EngineKind::Memory) && restored.is_none();
+    if let Some(info) = my_service::configure_snapshots(snapshot_config, load)? {
+        println!("Loaded {} keys from the snapshot", info.keys);
+    }
+    if let Some(manifest) = &restored {
+        my_service::record_restore(&restore_marker, manifest)?;
+    }
+
+    // Import a Redis RDB file given to migrate from Redis, once: a marker in the data
+    // directory keeps restarts from importing it again over newer writes.
+    if let Ok(path) = env::var("REDIO_IMPORT_RDB") {
+        let marker = engine::data_dir_from_env().join(rdb::IMPORT_MARKER_FILENAME);
+        match my_service::import_rdb_file(Path::new(&path), &marker)? {
+            Some(report) => println!(
+                "Imported {} keys from {} ({} expired, {} skipped)",
+                report.imported(),
+                path,
+                report.expired,
+                report.skipped
+            ),
+            None => println!("Skipped importing {}, which was imported already", path),
+        }
+    }
+
+    // Apply the memory limit before serving traffic.
+    let memory_config = MemoryConfig::from_env()?;
+    my_service::configure_memory(memory_config);
+
+    // Reclaim expired keys in the background so unread keys don't linger in memory.
+    let expire_config = ActiveExpireConfig::from_env();
+    my_service::spawn_active_expiry(expire_config);
+
+    // Sync the append-only file and rewrite it when it grows, if it is enabled.
+    my_service::spawn_append_only_file();
+
+    // Only write backups requested over the network below the backup root.
+    my_service::configure_backups(backup::BackupConfig::from_env());
+
+    // Save snapshots at the configured save points.
+    my_service::spawn_snapshots();
+
+    // Serve Redis clients over RESP next to gRPC, unless REDIO_RESP_ADDRESS is set empty.
+    let resp_addr = env::var("REDIO_RESP_ADDRESS").unwrap_or_else(|_| resp::DEFAULT_RESP_ADDRESS.to_string());
+    if !resp_addr.is_empty() {
+        let listener = tokio::net::TcpListener::bind(&resp_addr).await?;
+        println!("Serving RESP on {}", resp_addr);
+        tokio::spawn(resp::serve(listener));
+    }
+
     println!("Starting REDIODB server on {}", addr);
 
     Server::builder()
@@ -1,7 +1,7 @@
 // src/monitoring.rs
 //
 // Provides Prometheus metrics integration.
-use prometheus::{Encoder, TextEncoder, Counter, register_counter};
+use prometheus::{Encoder, TextEncoder, Counter, IntCounter, IntCounterVec, register_counter, register_int_counter, register_int_counter_vec};
 use lazy_static::lazy_static;
 
 lazy_static! {
@@ -10,6 +10,26 @@ lazy_static! {
         "edgedb_command_total",
         "Total number of commands processed"
     ).unwrap();
+
+    // Counter for keys removed because their TTL elapsed, labelled by how they were found
+    // ("lazy" when touched by a command, "active" when reclaimed by the background cycle).
+    pub static ref EXPIRED_KEYS_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "rediodb_expired_keys_total",
+        "Total number of keys removed because their TTL elapsed",
+        &["mode"]
+    ).unwrap();
+
+    // Counter for active expiry cycles that stopped early because they hit their time budget.
+    pub static ref EXPIRE_CYCLE_TIME_CAP_COUNTER: IntCounter = register_int_counter!(
+        "rediodb_expire_cycle_time_cap_reached_total",
+        "Total number of active expiry cycles that reached their time budget"
+    ).unwrap();
+
+    // Counter for keys removed by the maxmemory eviction policy.
+    pub static ref EVICTED_KEYS_COUNTER: IntCounter = register_int_counter!(
+        "rediodb_evicted_keys_total",
+        "Total number of keys evicted to stay under maxmemory"
+    ).unwrap();
 }
 
 /// Gathers and returns metrics in Prometheus text format.
@@ -1,11 +1,22 @@
 // src/pubsub.rs
 //
 // A simple Pub/Sub system using Tokio broadcast channels.
+use std::collections::HashSet;
+
 use tokio::sync::broadcast;
 
+use crate::glob::glob_match;
+
+/// A message published to a channel. The payload is binary safe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub channel: String,
+    pub payload: Vec<u8>,
+}
+
 /// PubSub structure encapsulating a broadcast sender.
 pub struct PubSub {
-    sender: broadcast::Sender<String>,
+    sender: broadcast::Sender<Message>,
 }
 
 impl PubSub {
@@ -15,13 +26,58 @@ impl PubSub {
         PubSub { sender }
     }
 
-    /// Publishes a message to all subscribers.
-    pub fn publish(&self, message: String) {
-        let _ = self.sender.send(message);
+    /// Publishes a message on a channel to all subscribers. Returns how many subscribers it
+    /// was handed to; each keeps only the channels its `ChannelFilter` matches.
+    pub fn publish(&self, channel: &str, payload: &[u8]) -> usize {
+        self.sender
+            .send(Message {
+                channel: channel.to_string(),
+                payload: payload.to_vec(),
+            })
+            .unwrap_or(0)
     }
 
-    /// Returns a new subscriber to the broadcast channel.
-    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+    /// Returns a new subscriber to the broadcast channel. It receives the messages of every
+    /// channel; use a `ChannelFilter` to keep the ones it subscribed to.
+    pub fn subscribe(&self) -> broadcast::Receiver<Message> {
         self.sender.subscribe()
     }
 }
+
+impl Default for PubSub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The channels a subscriber listens to: exact channel names, plus an optional glob pattern
+/// (PSUBSCRIBE).
+#[derive(Debug, Clone, Default)]
+pub struct ChannelFilter {
+    channels: HashSet<String>,
+    pattern: Option<String>,
+}
+
+impl ChannelFilter {
+    /// Creates a filter; an empty `pattern` subscribes to no pattern.
+    pub fn new(channels: impl IntoIterator<Item = String>, pattern: &str) -> Self {
+        ChannelFilter {
+            channels: channels.into_iter().collect(),
+            pattern: (!pattern.is_empty()).then(|| pattern.to_string()),
+        }
+    }
+
+    /// Returns true if the filter subscribes to nothing at all.
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.pattern.is_none()
+    }
+
+    /// Returns true if messages published on `channel` should be delivered.
+    pub fn matches(&self, channel: &str) -> bool {
+        self.channels.contains(channel)
+            || self
+                .pattern
+                .as_ref()
+                .is_some_and(|pattern| glob_match(pattern.as_bytes(), channel.as_bytes()))
+    }
+}
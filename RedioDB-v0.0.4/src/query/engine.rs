@@ -1,5 +1,6 @@
 // src/query/engine.rs
 /// A simple stub for a query engine.
+#[derive(Default)]
 pub struct QueryEngine;
 
 impl QueryEngine {
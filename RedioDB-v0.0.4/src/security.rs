@@ -1,6 +1,7 @@
 // src/security.rs
 //
 // Stub for security, authentication, and access control.
+#[derive(Default)]
 pub struct SecurityManager;
 
 impl SecurityManager {
@@ -1,7 +1,11 @@
 //
 // This module includes the generated gRPC code and re-exports the custom service implementation.
+// The generated code nests a `rediodb_server` module of its own.
+#[allow(clippy::module_inception)]
 pub mod rediodb_server {
     tonic::include_proto!("rediodb");
 }
 
 pub mod my_service;
+pub mod resp;
+pub mod resp_commands;
@@ -1,36 +1,91 @@
 // src/server/my_service.rs
 //
 // The gRPC service implementation for EdgeDB.
-// Implements the generated Rediodb trait using TTLStore and stubs for extended features.
+// Implements the generated Rediodb trait using sharded TTLStores and stubs for extended features.
 
+use std::path::Path;
 use std::pin::Pin;
-use std::sync::Mutex;
-use std::time::Duration;
-use tonic::{Request, Response, Status};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, UNIX_EPOCH};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::Endpoint;
+use tonic::{Request, Response, Status, Streaming};
+use bytes::Bytes;
 use futures_core::Stream;
-use futures_util::stream::empty;
+use futures_util::stream::unfold;
 use lazy_static::lazy_static;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
 
 use crate::consensus::raft::RaftNode;
 use crate::query::engine::QueryEngine;
 use crate::ai::inference::InferenceEngine;
-use crate::storage::ttl_store::TTLStore;
+use crate::pubsub::{ChannelFilter, PubSub};
+use crate::storage::aof::{self, AofLoadReport};
+use crate::storage::backup::{self, BackupConfig, BackupKind, BackupManifest};
+use crate::storage::databases::{DatabaseConfig, Databases};
+use crate::storage::engine::EngineKind;
+use crate::storage::multi_key::{KeyShards, MultiKey};
+use crate::storage::sharded::ShardedStore;
+use crate::storage::dump::RestoreOptions;
+use crate::storage::rdb::{self, RdbReport};
+use crate::storage::snapshot::{self, SnapshotConfig, SnapshotInfo, Snapshotter};
+use crate::storage::expiry::{self, ActiveExpireConfig, ExpireOptions};
+use crate::storage::eviction::MemoryConfig;
+use crate::storage::error::StoreError;
+use crate::storage::blocking::{self, BlockedOp, Delivery};
+use crate::storage::string_value::{SetExpiry, SetOptions};
+use crate::storage::util;
+use crate::storage::scan::ScanOptions;
+use crate::storage::sorted_set::{Aggregate, LexBound, ScoreBound, ZAddOptions, ZRangeBy};
+use crate::storage::stream::{
+    self as streams, ClaimOptions, GroupReadId, StreamEntry, StreamId, StreamRead, TrimSpec,
+    TrimStrategy, XAddId,
+};
+use crate::server::rediodb_server::rediodb_client::RediodbClient;
 use crate::server::rediodb_server::rediodb_server::Rediodb;
 use crate::server::rediodb_server::{
     // Basic operations
     QueryRequest, QueryResponse, SetRequest, ResponseMessage, KeyRequest, ValueResponse, ExpireRequest, TtlResponse,
+    SetResponse, KeyValue, GetExRequest, MSetRequest, MGetRequest, MGetValue, MGetResponse,
+    // Generic keyspace operations
+    MultiKeyRequest, RenameRequest, CopyRequest, RestoreRequest, MigrateRequest, DumpResponse,
     // Atomic operations
-    IncrRequest, DecrRequest, AppendRequest,
+    IncrRequest, DecrRequest, IncrByFloatRequest, AppendRequest,
     // Pattern matching
-    PatternRequest, KeysResponse,
+    PatternRequest, KeysResponse, ScanRequest, ScanResponse, MemberScanRequest, SScanResponse,
+    HScanResponse, ZScanResponse,
+    // Logical databases
+    SelectRequest, SelectResponse, DbSizeRequest, FlushRequest, SwapDbRequest, MoveRequest,
+    // Persistence
+    BgRewriteAofRequest, SaveRequest, BgSaveRequest, LastSaveRequest, RdbChunk, ImportRdbResponse,
+    ExportRdbRequest, BackupRequest, BackupResponse,
     // Transactions
     MultiRequest, ExecRequest,
     // List operations
-    ListPushRequest, ListPopRequest,
+    ListPushRequest, ListPopRequest, ListRangeRequest, ListValuesResponse, ListIndexRequest,
+    ListSetRequest, ListRemRequest, ListInsertRequest, ListPosRequest, ListPositionsResponse,
+    ListMoveRequest, ListEnd, BlockingPopRequest, BlockingPopResponse, BlockingMoveRequest,
     // Set operations
-    SetAddRequest, SetMembersRequest, SetMembersResponse,
+    SetAddRequest, SetMembersRequest, SetMembersResponse, SetRemRequest, SetIsMemberRequest,
+    SetIsMemberResponse, SetMIsMemberRequest, SetMIsMemberResponse, SetPopRequest,
+    SetMoveRequest, SetKeysRequest, SetStoreRequest, SetInterCardRequest,
     // Hash operations
-    HashSetRequest, HashGetRequest,
+    HashSetRequest, HashGetRequest, HashFieldsRequest, HashValue, HashMGetResponse,
+    HashMapResponse, HashExistsResponse, HashKeysResponse, HashValsResponse, HashIncrByRequest,
+    HashIncrByResponse, HashIncrByFloatRequest, HashRandFieldRequest, HashField,
+    HashRandFieldResponse, HashExpireRequest, HashFieldStatusResponse,
+    // Sorted set operations
+    ZAddRequest, ZRemRequest, ZScoreRequest, ZRankRequest, ZRangeRequest, ZIncrByRequest,
+    ZPopRequest, ZStoreRequest, ZMember, ZMembersResponse, ScoreResponse, RankResponse,
+    CountResponse, ZRangeBy as ProtoZRangeBy, ZAggregate,
+    // Stream operations
+    XAddRequest, XTrimRequest, XTrimOptions, XTrimStrategy, XRangeRequest, XEntriesResponse,
+    XReadRequest, XReadResponse, XGroupCreateRequest, XGroupRequest, XConsumerRequest,
+    XReadGroupRequest, XAckRequest, XPendingRequest, XPendingResponse, XPendingConsumer,
+    XPendingEntry, XClaimRequest, XAutoClaimRequest, XAutoClaimResponse, XEntry, XField,
+    XStreamEntries,
     // Pub/Sub
     PublishRequest, SubscribeRequest, PubSubMessage,
 };
@@ -39,7 +94,165 @@ lazy_static! {
     static ref RAFT_NODE: Mutex<RaftNode> = Mutex::new(RaftNode::new());
     static ref QUERY_ENGINE: Mutex<QueryEngine> = Mutex::new(QueryEngine::new());
     static ref INFERENCE_ENGINE: Mutex<InferenceEngine> = Mutex::new(InferenceEngine::new("model.onnx"));
-    static ref STORAGE: Mutex<TTLStore> = Mutex::new(TTLStore::new());
+    /// Wakes blocked stream readers whenever an entry is added to any stream.
+    pub(crate) static ref STREAM_NOTIFY: Notify = Notify::new();
+    pub(crate) static ref PUBSUB: PubSub = PubSub::new();
+}
+
+/// The logical databases; sized by `configure_databases`, or with the defaults on first use.
+static DATABASES: OnceLock<Databases> = OnceLock::new();
+
+/// Request metadata naming the database a request runs against, by index or name.
+pub const DB_METADATA_KEY: &str = "x-redio-db";
+
+/// How long MIGRATE waits for the target instance when the request sets no timeout.
+const DEFAULT_MIGRATE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Size of the chunks RDB files are streamed in, well below gRPC's default message limit.
+pub const RDB_CHUNK_SIZE: usize = 1 << 20;
+
+/// Largest RDB file ImportRdb accepts, as the whole upload is buffered before it is read.
+pub const MAX_RDB_UPLOAD: usize = 1 << 30;
+
+pub(crate) fn databases() -> &'static Databases {
+    DATABASES.get_or_init(|| Databases::new(DatabaseConfig::default()))
+}
+
+/// Creates the logical databases on the given storage engine, loading what a durable engine
+/// already holds. Must be called before the server handles requests; later calls have no
+/// effect.
+pub fn configure_databases(config: DatabaseConfig, engine: &EngineKind) -> Result<(), StoreError> {
+    let _ = DATABASES.set(Databases::open(config, engine)?);
+    Ok(())
+}
+
+/// Starts the background tasks that reclaim expired keys, one per shard of every database.
+pub fn spawn_active_expiry(config: ActiveExpireConfig) -> Vec<JoinHandle<()>> {
+    databases()
+        .all()
+        .iter()
+        .flat_map(|db| db.shards())
+        .map(|shard| expiry::spawn_active_expiry(shard, config))
+        .collect()
+}
+
+/// Starts the append-only file's background task, which syncs it and starts automatic
+/// rewrites. Returns None if the AOF is disabled.
+pub fn spawn_append_only_file() -> Option<JoinHandle<()>> {
+    let aof = databases().aof()?;
+    Some(aof::spawn_background(aof.clone(), databases()))
+}
+
+/// What replaying the append-only file at startup found, if it is enabled.
+pub fn append_only_file_report() -> Option<AofLoadReport> {
+    databases().aof().map(|aof| aof.load_report())
+}
+
+static SNAPSHOTS: OnceLock<Arc<Snapshotter>> = OnceLock::new();
+
+pub(crate) fn snapshots() -> &'static Arc<Snapshotter> {
+    SNAPSHOTS.get_or_init(|| Arc::new(Snapshotter::new(SnapshotConfig::default(), databases())))
+}
+
+/// Sets up SAVE/BGSAVE, first loading the last snapshot into the databases if `load` is set.
+/// Must be called after `configure_databases` and before the server handles requests; later
+/// calls have no effect.
+pub fn configure_snapshots(
+    config: SnapshotConfig,
+    load: bool,
+) -> Result<Option<SnapshotInfo>, StoreError> {
+    let info = if load {
+        snapshot::load(&config.path, databases())?
+    } else {
+        None
+    };
+    let _ = SNAPSHOTS.set(Arc::new(Snapshotter::new(config, databases())));
+    Ok(info)
+}
+
+/// Starts the background task saving snapshots at the configured save points, if any.
+pub fn spawn_snapshots() -> Option<JoinHandle<()>> {
+    let snapshotter = snapshots();
+    if snapshotter.config().save_points.is_empty() {
+        return None;
+    }
+    Some(snapshot::spawn_background(snapshotter.clone(), databases()))
+}
+
+/// Imports a Redis RDB file from the server's disk into the databases, replacing keys with
+/// the same names, unless the `marker` file records that this file was imported already.
+/// Once the imported keys are durable, `marker` records the import. Returns None if the
+/// import was skipped. Must be called after `configure_snapshots`.
+pub fn import_rdb_file(path: &Path, marker: &Path) -> Result<Option<RdbReport>, StoreError> {
+    let bytes = std::fs::read(path).map_err(|err| StoreError::Storage(err.to_string()))?;
+    let stamp = rdb::import_stamp(&bytes);
+    if util::read_marker(marker)?.as_deref() == Some(stamp.as_str()) {
+        return Ok(None);
+    }
+    let report = rdb::import(&bytes, databases())?;
+    persist_startup_writes()?;
+    util::write_marker(marker, &stamp)?;
+    Ok(Some(report))
+}
+
+/// Helper function: Make the keys written at startup durable before a marker records them:
+/// sync the append-only file, or save a snapshot with the in-memory engine, which would
+/// lose them otherwise. RocksDB has them already.
+fn persist_startup_writes() -> Result<(), StoreError> {
+    let databases = databases();
+    match databases.aof() {
+        Some(aof) => aof.sync(),
+        None if databases.rocksdb().is_none() => snapshots().save(databases),
+        None => Ok(()),
+    }
+}
+
+static BACKUPS: OnceLock<BackupConfig> = OnceLock::new();
+
+fn backup_config() -> &'static BackupConfig {
+    BACKUPS.get_or_init(BackupConfig::default)
+}
+
+/// Sets the directory that backups requested over the network are written below. Must be
+/// called before the server handles requests; later calls have no effect.
+pub fn configure_backups(config: BackupConfig) {
+    let _ = BACKUPS.set(config);
+}
+
+/// Replaces the keys of the databases with those of the backup in `dir`, once
+/// `backup::restore_files` put its files in place. Must be called after
+/// `configure_databases` and before the server handles requests.
+pub fn restore_backup(dir: &Path, manifest: &BackupManifest) -> Result<(), StoreError> {
+    backup::restore_keys(dir, manifest, databases())
+}
+
+/// Makes the keys of a restored backup durable, then records the restore in the `marker`
+/// file so restarts do not restore the backup again. Must be called after
+/// `configure_snapshots`.
+pub fn record_restore(marker: &Path, manifest: &BackupManifest) -> Result<(), StoreError> {
+    persist_startup_writes()?;
+    backup::record_restore(marker, manifest)
+}
+
+/// Applies the memory limit and eviction policy to every database.
+pub fn configure_memory(config: MemoryConfig) {
+    databases().set_memory_config(config);
+}
+
+impl From<StoreError> for Status {
+    fn from(err: StoreError) -> Self {
+        match err {
+            StoreError::OutOfMemory => Status::resource_exhausted(err.to_string()),
+            StoreError::InvalidArgument(_) => Status::invalid_argument(err.to_string()),
+            StoreError::NoGroup | StoreError::NoSuchKey => Status::not_found(err.to_string()),
+            StoreError::BusyGroup | StoreError::BusyKey => Status::already_exists(err.to_string()),
+            StoreError::WrongType => Status::failed_precondition(err.to_string()),
+            StoreError::NotInteger | StoreError::NotFloat | StoreError::Overflow => {
+                Status::invalid_argument(err.to_string())
+            }
+            StoreError::Storage(_) => Status::internal(err.to_string()),
+        }
+    }
 }
 
 /// MyService implements the Rediodb gRPC trait.
@@ -53,6 +266,7 @@ impl Rediodb for MyService {
         &self,
         request: Request<QueryRequest>,
     ) -> Result<Response<QueryResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
         let query_text = req.query.unwrap_or_default().query;
         if !RAFT_NODE.lock().unwrap().propose(&query_text) {
@@ -60,7 +274,7 @@ impl Rediodb for MyService {
         }
         let result = QUERY_ENGINE.lock().unwrap().execute(&query_text);
         let _ = INFERENCE_ENGINE.lock().unwrap().infer(&query_text);
-        STORAGE.lock().unwrap().set("last_query", &query_text, None);
+        db.write(b"last_query", |store| store.set(b"last_query", query_text.as_bytes(), None))?;
         Ok(Response::new(QueryResponse { result }))
     }
 
@@ -68,70 +282,431 @@ impl Rediodb for MyService {
     async fn set(
         &self,
         request: Request<SetRequest>,
-    ) -> Result<Response<ResponseMessage>, Status> {
+    ) -> Result<Response<SetResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
-        let ttl_duration = if req.ttl > 0 {
-            Some(Duration::from_secs(req.ttl as u64))
-        } else {
-            None
-        };
-        STORAGE.lock().unwrap().set(&req.key, &req.value, ttl_duration);
-        let reply = ResponseMessage {
-            status: "success".into(),
-            message: format!("Key '{}' set successfully", req.key),
+        let expiry = to_set_expiry(req.ttl as i64, req.px, req.exat, req.pxat)
+            .map_err(Status::invalid_argument)?;
+        let opts = SetOptions {
+            nx: req.nx,
+            xx: req.xx,
+            get: req.get,
+            keep_ttl: req.keep_ttl,
+            expiry,
         };
-        Ok(Response::new(reply))
+        opts.validate().map_err(Status::invalid_argument)?;
+        let outcome = db
+            .write(&req.key, |store| store.set_with_options(&req.key, &req.value, opts))?;
+        Ok(Response::new(SetResponse {
+            written: outcome.written,
+            old_value: outcome.old_value.map(Bytes::from),
+        }))
     }
 
     async fn get(
         &self,
         request: Request<KeyRequest>,
     ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let value = db.read(&key, |store| store.get(&key))?.unwrap_or_default();
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn set_nx(
+        &self,
+        request: Request<KeyValue>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let opts = SetOptions { nx: true, ..SetOptions::default() };
+        let outcome = db
+            .write(&req.key, |store| store.set_with_options(&req.key, &req.value, opts))?;
+        Ok(Response::new(CountResponse { count: outcome.written as i64 }))
+    }
+
+    async fn get_set(
+        &self,
+        request: Request<KeyValue>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let opts = SetOptions { get: true, ..SetOptions::default() };
+        let outcome = db
+            .write(&req.key, |store| store.set_with_options(&req.key, &req.value, opts))?;
+        Ok(Response::new(ValueResponse { value: outcome.old_value.unwrap_or_default().into() }))
+    }
+
+    async fn get_del(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
         let key = request.into_inner().key;
-        let value = STORAGE.lock().unwrap().get(&key).unwrap_or_default();
-        Ok(Response::new(ValueResponse { value }))
+        let value = db.write(&key, |store| store.get_del(&key))?.unwrap_or_default();
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn get_ex(
+        &self,
+        request: Request<GetExRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let expiry = to_set_expiry(req.ex, req.px, req.exat, req.pxat)
+            .map_err(Status::invalid_argument)?;
+        if req.persist && expiry.is_some() {
+            return Err(Status::invalid_argument(
+                "PERSIST and an expiration at the same time are not compatible",
+            ));
+        }
+        let value = db
+            .write(&req.key, |store| store.get_ex(&req.key, expiry, req.persist))?
+            .unwrap_or_default();
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn m_set(
+        &self,
+        request: Request<MSetRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let db = select_db(&request)?;
+        let pairs = to_pairs(request.into_inner());
+        db.write_keys(pairs.iter().map(|(key, _)| key), |store| store.m_set(&pairs))?;
+        let reply = ResponseMessage {
+            status: "success".into(),
+            message: format!("{} keys set", pairs.len()),
+        };
+        Ok(Response::new(reply))
+    }
+
+    async fn m_set_nx(
+        &self,
+        request: Request<MSetRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let pairs = to_pairs(request.into_inner());
+        let written = db.write_keys(pairs.iter().map(|(key, _)| key), |store| {
+            store.m_set_nx(&pairs)
+        })?;
+        Ok(Response::new(CountResponse { count: written as i64 }))
+    }
+
+    async fn m_get(
+        &self,
+        request: Request<MGetRequest>,
+    ) -> Result<Response<MGetResponse>, Status> {
+        let db = select_db(&request)?;
+        let keys = to_vecs(&request.into_inner().keys);
+        let values = db.read_keys(&keys, |store| Ok(store.m_get(&keys)))?;
+        Ok(Response::new(MGetResponse {
+            values: values.into_iter().map(|value| MGetValue { value: value.map(Bytes::from) }).collect(),
+        }))
     }
 
     async fn expire(
         &self,
         request: Request<ExpireRequest>,
-    ) -> Result<Response<ResponseMessage>, Status> {
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
-        let ttl_duration = Duration::from_secs(req.ttl as u64);
-        if STORAGE.lock().unwrap().expire(&req.key, ttl_duration) {
-            let reply = ResponseMessage {
-                status: "success".into(),
-                message: format!("TTL for key '{}' set to {} seconds", req.key, req.ttl),
-            };
-            Ok(Response::new(reply))
-        } else {
-            Err(Status::not_found("Key not found"))
-        }
+        let (expiry, opts) = to_expire(&req, 1000, false).map_err(Status::invalid_argument)?;
+        let set = db.write(&req.key, |store| {
+            Ok(store.expire_with_options(&req.key, expiry, opts))
+        })?;
+        Ok(Response::new(CountResponse { count: set as i64 }))
+    }
+
+    async fn p_expire(
+        &self,
+        request: Request<ExpireRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let (expiry, opts) = to_expire(&req, 1, false).map_err(Status::invalid_argument)?;
+        let set = db.write(&req.key, |store| {
+            Ok(store.expire_with_options(&req.key, expiry, opts))
+        })?;
+        Ok(Response::new(CountResponse { count: set as i64 }))
+    }
+
+    async fn expire_at(
+        &self,
+        request: Request<ExpireRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let (expiry, opts) = to_expire(&req, 1000, true).map_err(Status::invalid_argument)?;
+        let set = db.write(&req.key, |store| {
+            Ok(store.expire_with_options(&req.key, expiry, opts))
+        })?;
+        Ok(Response::new(CountResponse { count: set as i64 }))
+    }
+
+    async fn p_expire_at(
+        &self,
+        request: Request<ExpireRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let (expiry, opts) = to_expire(&req, 1, true).map_err(Status::invalid_argument)?;
+        let set = db.write(&req.key, |store| {
+            Ok(store.expire_with_options(&req.key, expiry, opts))
+        })?;
+        Ok(Response::new(CountResponse { count: set as i64 }))
     }
 
     async fn ttl(
         &self,
         request: Request<KeyRequest>,
     ) -> Result<Response<TtlResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let ttl_value = db.read(&key, |store| Ok(store.ttl(&key)))?.unwrap_or(-2);
+        Ok(Response::new(TtlResponse { ttl: ttl_value }))
+    }
+
+    async fn p_ttl(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<TtlResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let ttl_value = db.read(&key, |store| Ok(store.pttl(&key)))?.unwrap_or(-2);
+        Ok(Response::new(TtlResponse { ttl: ttl_value }))
+    }
+
+    async fn expire_time(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<TtlResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let ttl_value = db.read(&key, |store| Ok(store.expire_time(&key)))?.unwrap_or(-2);
+        Ok(Response::new(TtlResponse { ttl: ttl_value }))
+    }
+
+    async fn p_expire_time(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<TtlResponse>, Status> {
+        let db = select_db(&request)?;
         let key = request.into_inner().key;
-        let ttl_value = STORAGE.lock().unwrap().ttl(&key).unwrap_or(-1);
+        let ttl_value = db.read(&key, |store| Ok(store.pexpire_time(&key)))?.unwrap_or(-2);
         Ok(Response::new(TtlResponse { ttl: ttl_value }))
     }
 
+    async fn persist(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let persisted = db.write(&key, |store| Ok(store.persist(&key)))?;
+        Ok(Response::new(CountResponse { count: persisted as i64 }))
+    }
+
     async fn del(
+        &self,
+        request: Request<MultiKeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let keys = to_vecs(&request.into_inner().keys);
+        let count = db.write_keys(&keys, |store| Ok(store.del_keys(&keys)))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn unlink(
+        &self,
+        request: Request<MultiKeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let keys = to_vecs(&request.into_inner().keys);
+        let count = db.write_keys(&keys, |store| Ok(store.unlink(&keys)))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn exists(
+        &self,
+        request: Request<MultiKeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let keys = to_vecs(&request.into_inner().keys);
+        let count = db.read_keys(&keys, |store| Ok(store.exists(&keys)))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn touch(
+        &self,
+        request: Request<MultiKeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let keys = to_vecs(&request.into_inner().keys);
+        let count = db.read_keys(&keys, |store| Ok(store.touch(&keys)))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn r#type(
         &self,
         request: Request<KeyRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let value = db.read(&key, |store| Ok(store.key_type(&key)))?;
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn rename(
+        &self,
+        request: Request<RenameRequest>,
     ) -> Result<Response<ResponseMessage>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        db.write_keys([&req.key, &req.new_key], |store| store.rename(&req.key, &req.new_key))?;
+        // The destination may now be a stream that blocked readers wait on.
+        STREAM_NOTIFY.notify_waiters();
+        let reply = ResponseMessage {
+            status: "success".into(),
+            message: format!(
+                "Key '{}' renamed to '{}'",
+                String::from_utf8_lossy(&req.key),
+                String::from_utf8_lossy(&req.new_key)
+            ),
+        };
+        Ok(Response::new(reply))
+    }
+
+    async fn rename_nx(
+        &self,
+        request: Request<RenameRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let renamed = db.write_keys([&req.key, &req.new_key], |store| {
+            store.rename_nx(&req.key, &req.new_key)
+        })?;
+        if renamed {
+            STREAM_NOTIFY.notify_waiters();
+        }
+        Ok(Response::new(CountResponse { count: renamed as i64 }))
+    }
+
+    async fn copy(
+        &self,
+        request: Request<CopyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let copied = db
+            .write_keys([&req.source, &req.destination], |store| {
+                store.copy(&req.source, &req.destination, req.replace)
+            })?;
+        if copied {
+            STREAM_NOTIFY.notify_waiters();
+        }
+        Ok(Response::new(CountResponse { count: copied as i64 }))
+    }
+
+    async fn dump(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<DumpResponse>, Status> {
+        let db = select_db(&request)?;
         let key = request.into_inner().key;
-        if STORAGE.lock().unwrap().del(&key) {
-            let reply = ResponseMessage {
-                status: "success".into(),
-                message: format!("Key '{}' deleted", key),
-            };
-            Ok(Response::new(reply))
-        } else {
-            Err(Status::not_found("Key not found"))
+        let payload = db.read(&key, |store| Ok(store.dump(&key)))?;
+        Ok(Response::new(DumpResponse { payload: payload.map(Bytes::from) }))
+    }
+
+    async fn restore(
+        &self,
+        request: Request<RestoreRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let idle_time = u64::try_from(req.idle_time)
+            .map_err(|_| StoreError::InvalidArgument("Invalid IDLETIME value, must be >= 0".into()))?;
+        let opts = RestoreOptions {
+            replace: req.replace,
+            abs_ttl: req.absttl,
+            idle_time: (idle_time > 0).then_some(idle_time),
+        };
+        db.write(&req.key, |store| store.restore(&req.key, &req.payload, req.ttl, opts))?;
+        STREAM_NOTIFY.notify_waiters();
+        Ok(Response::new(ResponseMessage {
+            status: "success".into(),
+            message: "OK".into(),
+        }))
+    }
+
+    async fn migrate(
+        &self,
+        request: Request<MigrateRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let mut payloads = Vec::new();
+        for key in &req.keys {
+            if let Some(payload) = db.read(key, |store| Ok(store.dump(key)))? {
+                payloads.push((key.clone(), payload));
+            }
+        }
+        let reply = |message: &str| ResponseMessage {
+            status: "success".into(),
+            message: message.into(),
+        };
+        if payloads.is_empty() {
+            return Ok(Response::new(reply("NOKEY")));
+        }
+
+        let timeout = match req.timeout_ms {
+            0 => DEFAULT_MIGRATE_TIMEOUT,
+            ms => Duration::from_millis(u64::try_from(ms).map_err(|_| {
+                StoreError::InvalidArgument("timeout is negative".into())
+            })?),
+        };
+        let target_db = match req.db.as_str() {
+            "" => None,
+            db => Some(db.parse::<MetadataValue<Ascii>>().map_err(|_| {
+                StoreError::InvalidArgument("invalid database selector".into())
+            })?),
+        };
+        let endpoint = Endpoint::from_shared(req.address)
+            .map_err(|_| StoreError::InvalidArgument("invalid target address".into()))?
+            .connect_timeout(timeout)
+            .timeout(timeout);
+        let channel = endpoint.connect().await.map_err(|err| {
+            let message = format!("IOERR error or timeout connecting to the target: {}", err);
+            Status::unavailable(message)
+        })?;
+        let mut target = RediodbClient::new(channel);
+        for (key, payload) in &payloads {
+            // A TTL of 0 keeps the deadline carried by the payload.
+            let mut restore = Request::new(RestoreRequest {
+                key: key.clone(),
+                payload: payload.clone().into(),
+                ttl: 0,
+                replace: req.replace,
+                absttl: false,
+                idle_time: 0,
+            });
+            if let Some(db) = &target_db {
+                restore.metadata_mut().insert(DB_METADATA_KEY, db.clone());
+            }
+            target.restore(restore).await?;
+
+            // Each key is deleted as soon as the target has it, so a failure part way
+            // through never leaves a key on both instances. It is only deleted if it
+            // still holds what was sent, so a write made during the transfer is not lost.
+            if !req.copy {
+                db.write(key, |shard| {
+                    if shard.dump(key).as_ref() == Some(payload) {
+                        shard.del(key);
+                    }
+                    Ok(())
+                })?;
+            }
         }
+        Ok(Response::new(reply("OK")))
     }
 
     // Extended Atomic Operations
@@ -139,39 +714,44 @@ impl Rediodb for MyService {
         &self,
         request: Request<IncrRequest>,
     ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
-        let new_val = STORAGE
-            .lock()
-            .unwrap()
-            .incr(&req.key, req.amount)
-            .ok_or_else(|| Status::internal("Failed to increment key"))?;
-        Ok(Response::new(ValueResponse { value: new_val }))
+        let new_val = db
+            .write(&req.key, |store| store.incr(&req.key, req.amount))?;
+        Ok(Response::new(ValueResponse { value: new_val.to_string().into() }))
     }
 
     async fn decr(
         &self,
         request: Request<DecrRequest>,
     ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let new_val = db
+            .write(&req.key, |store| store.decr(&req.key, req.amount))?;
+        Ok(Response::new(ValueResponse { value: new_val.to_string().into() }))
+    }
+
+    async fn incr_by_float(
+        &self,
+        request: Request<IncrByFloatRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
-        let new_val = STORAGE
-            .lock()
-            .unwrap()
-            .decr(&req.key, req.amount)
-            .ok_or_else(|| Status::internal("Failed to decrement key"))?;
-        Ok(Response::new(ValueResponse { value: new_val }))
+        let new_val = db
+            .write(&req.key, |store| store.incr_by_float(&req.key, req.increment))?;
+        Ok(Response::new(ValueResponse { value: new_val.into() }))
     }
 
     async fn append(
         &self,
         request: Request<AppendRequest>,
     ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
-        let new_val = STORAGE
-            .lock()
-            .unwrap()
-            .append(&req.key, &req.value)
-            .ok_or_else(|| Status::internal("Failed to append to key"))?;
-        Ok(Response::new(ValueResponse { value: new_val }))
+        let new_val = db
+            .write(&req.key, |store| store.append(&req.key, &req.value))?;
+        Ok(Response::new(ValueResponse { value: new_val.into() }))
     }
 
     // Key Pattern Matching
@@ -179,98 +759,1439 @@ impl Rediodb for MyService {
         &self,
         request: Request<PatternRequest>,
     ) -> Result<Response<KeysResponse>, Status> {
+        let db = select_db(&request)?;
         let pattern = request.into_inner().pattern;
-        let keys = STORAGE.lock().unwrap().keys(&pattern);
-        Ok(Response::new(KeysResponse { keys }))
+        let keys = db.keys(&pattern)?;
+        Ok(Response::new(KeysResponse { keys: to_bytes(keys) }))
     }
 
-    // Transaction Support
-    async fn multi(
+    async fn scan(
         &self,
-        request: Request<MultiRequest>,
-    ) -> Result<Response<ResponseMessage>, Status> {
+        request: Request<ScanRequest>,
+    ) -> Result<Response<ScanResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
-        println!("Queuing transaction commands: {:?}", req.commands);
-        Ok(Response::new(ResponseMessage {
-            status: "success".into(),
-            message: "Transaction block queued".into(),
-        }))
+        let opts = to_scan_options(&req.pattern, req.count).map_err(Status::invalid_argument)?;
+        let type_name = (!req.r#type.is_empty()).then_some(req.r#type.as_str());
+        let page = db.scan(req.cursor, &opts, type_name)?;
+        Ok(Response::new(ScanResponse { cursor: page.cursor, keys: to_bytes(page.items) }))
     }
 
-    async fn exec(
+    async fn scan_stream(
         &self,
-        _request: Request<ExecRequest>,
-    ) -> Result<Response<ResponseMessage>, Status> {
-        Ok(Response::new(ResponseMessage {
-            status: "success".into(),
-            message: "Transaction executed".into(),
-        }))
+        request: Request<ScanRequest>,
+    ) -> Result<Response<Self::ScanStreamStream>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let opts = to_scan_options(&req.pattern, req.count).map_err(Status::invalid_argument)?;
+        let type_name = (!req.r#type.is_empty()).then_some(req.r#type);
+        // The store is only locked for one page at a time, so other clients are served in
+        // between. Pages where nothing matched are not sent.
+        let stream = unfold(Some(req.cursor), move |cursor| {
+            let (opts, type_name) = (opts.clone(), type_name.clone());
+            async move {
+                let mut cursor = cursor?;
+                loop {
+                    let page = match db.scan(cursor, &opts, type_name.as_deref()) {
+                        Ok(page) => page,
+                        Err(err) => return Some((Err(err.into()), None)),
+                    };
+                    cursor = page.cursor;
+                    let next = (cursor != 0).then_some(cursor);
+                    if !page.items.is_empty() {
+                        return Some((Ok(ScanResponse { cursor, keys: to_bytes(page.items) }), next));
+                    }
+                    next?;
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(stream)))
     }
 
-    // Data Structures: Lists
-    async fn l_push(
+    type ScanStreamStream = ScanStream;
+
+    async fn s_scan(
         &self,
-        request: Request<ListPushRequest>,
-    ) -> Result<Response<ResponseMessage>, Status> {
+        request: Request<MemberScanRequest>,
+    ) -> Result<Response<SScanResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
-        STORAGE.lock().unwrap().l_push(&req.key, &req.value);
-        Ok(Response::new(ResponseMessage {
-            status: "success".into(),
-            message: format!("Value '{}' pushed to list '{}'", req.value, req.key),
-        }))
+        let opts = to_scan_options(&req.pattern, req.count).map_err(Status::invalid_argument)?;
+        let page = db.read(&req.key, |store| store.s_scan(&req.key, req.cursor, &opts))?;
+        Ok(Response::new(SScanResponse { cursor: page.cursor, members: to_bytes(page.items) }))
     }
 
-    async fn l_pop(
+    async fn h_scan(
         &self,
-        request: Request<ListPopRequest>,
-    ) -> Result<Response<ValueResponse>, Status> {
-        let key = request.into_inner().key;
-        let popped = STORAGE.lock().unwrap().l_pop(&key).unwrap_or_default();
-        Ok(Response::new(ValueResponse { value: popped }))
+        request: Request<MemberScanRequest>,
+    ) -> Result<Response<HScanResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let opts = to_scan_options(&req.pattern, req.count).map_err(Status::invalid_argument)?;
+        let page = db.read(&req.key, |store| store.h_scan(&req.key, req.cursor, &opts))?;
+        let fields = page
+            .items
+            .into_iter()
+            .map(|(field, value)| HashField { field: field.into(), value: value.into() })
+            .collect();
+        Ok(Response::new(HScanResponse { cursor: page.cursor, fields }))
     }
 
-    // Data Structures: Sets
-    async fn s_add(
+    async fn z_scan(
         &self,
-        request: Request<SetAddRequest>,
-    ) -> Result<Response<ResponseMessage>, Status> {
+        request: Request<MemberScanRequest>,
+    ) -> Result<Response<ZScanResponse>, Status> {
+        let db = select_db(&request)?;
         let req = request.into_inner();
-        STORAGE.lock().unwrap().s_add(&req.key, &req.member);
-        Ok(Response::new(ResponseMessage {
-            status: "success".into(),
-            message: format!("Member '{}' added to set '{}'", req.member, req.key),
-        }))
+        let opts = to_scan_options(&req.pattern, req.count).map_err(Status::invalid_argument)?;
+        let page = db.read(&req.key, |store| store.z_scan(&req.key, req.cursor, &opts))?;
+        let members = page
+            .items
+            .into_iter()
+            .map(|(member, score)| ZMember { member: member.into(), score })
+            .collect();
+        Ok(Response::new(ZScanResponse { cursor: page.cursor, members }))
     }
 
-    async fn s_members(
+    // Logical Databases
+    async fn select(
         &self,
-        request: Request<SetMembersRequest>,
-    ) -> Result<Response<SetMembersResponse>, Status> {
-        let key = request.into_inner().key;
-        let members = STORAGE.lock().unwrap().s_members(&key);
-        Ok(Response::new(SetMembersResponse { members }))
+        request: Request<SelectRequest>,
+    ) -> Result<Response<SelectResponse>, Status> {
+        let index = databases().resolve(&request.into_inner().db)?;
+        Ok(Response::new(SelectResponse { db: index as i64 }))
     }
 
-    // Data Structures: Hashes
-    async fn h_set(
+    async fn db_size(
         &self,
-        request: Request<HashSetRequest>,
+        request: Request<DbSizeRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let count = db.count_keys()?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn flush_db(
+        &self,
+        request: Request<FlushRequest>,
     ) -> Result<Response<ResponseMessage>, Status> {
-        let req = request.into_inner();
-        STORAGE.lock().unwrap().h_set(&req.key, &req.field, &req.value);
-        Ok(Response::new(ResponseMessage {
+        let db = select_db(&request)?;
+        db.flush(request.into_inner().r#async)?;
+        let reply = ResponseMessage {
             status: "success".into(),
-            message: format!("Field '{}' set for hash '{}'", req.field, req.key),
-        }))
+            message: "Database flushed".into(),
+        };
+        Ok(Response::new(reply))
     }
 
-    async fn h_get(
+    async fn flush_all(
         &self,
-        request: Request<HashGetRequest>,
-    ) -> Result<Response<ValueResponse>, Status> {
-        let req = request.into_inner();
-        let value = STORAGE.lock().unwrap().h_get(&req.key, &req.field).unwrap_or_default();
-        Ok(Response::new(ValueResponse { value }))
+        request: Request<FlushRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        databases().flush_all(request.into_inner().r#async)?;
+        let reply = ResponseMessage {
+            status: "success".into(),
+            message: "All databases flushed".into(),
+        };
+        Ok(Response::new(reply))
+    }
+
+    async fn swap_db(
+        &self,
+        request: Request<SwapDbRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let req = request.into_inner();
+        let first = databases().resolve(&req.first)?;
+        let second = databases().resolve(&req.second)?;
+        databases().swap(first, second)?;
+        // Streams that blocked readers wait on may have appeared.
+        STREAM_NOTIFY.notify_waiters();
+        let reply = ResponseMessage {
+            status: "success".into(),
+            message: format!("Databases {} and {} swapped", first, second),
+        };
+        Ok(Response::new(reply))
+    }
+
+    async fn r#move(
+        &self,
+        request: Request<MoveRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let from = db_index(&request)?;
+        let req = request.into_inner();
+        let to = databases().resolve(&req.db)?;
+        let moved = databases().move_key(&req.key, from, to)?;
+        if moved {
+            STREAM_NOTIFY.notify_waiters();
+        }
+        Ok(Response::new(CountResponse { count: moved as i64 }))
+    }
+
+    // Persistence
+    async fn bg_rewrite_aof(
+        &self,
+        _request: Request<BgRewriteAofRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let aof = databases()
+            .aof()
+            .ok_or_else(|| StoreError::InvalidArgument("append-only file is disabled".into()))?;
+        aof::spawn_rewrite(aof, databases())?;
+        let reply = ResponseMessage {
+            status: "success".into(),
+            message: "Background append only file rewriting started".into(),
+        };
+        Ok(Response::new(reply))
+    }
+
+    async fn save(
+        &self,
+        _request: Request<SaveRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        tokio::task::spawn_blocking(|| snapshots().save(databases()))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))??;
+        let reply = ResponseMessage {
+            status: "success".into(),
+            message: "OK".into(),
+        };
+        Ok(Response::new(reply))
+    }
+
+    async fn bg_save(
+        &self,
+        _request: Request<BgSaveRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        snapshot::spawn_save(snapshots(), databases())?;
+        let reply = ResponseMessage {
+            status: "success".into(),
+            message: "Background saving started".into(),
+        };
+        Ok(Response::new(reply))
+    }
+
+    async fn last_save(
+        &self,
+        _request: Request<LastSaveRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let at = snapshots().last_save().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Ok(Response::new(CountResponse { count: at.as_secs() as i64 }))
+    }
+
+    async fn import_rdb(
+        &self,
+        request: Request<Streaming<RdbChunk>>,
+    ) -> Result<Response<ImportRdbResponse>, Status> {
+        let mut chunks = request.into_inner();
+        let mut file = Vec::new();
+        while let Some(chunk) = chunks.message().await? {
+            if file.len() + chunk.data.len() > MAX_RDB_UPLOAD {
+                return Err(Status::resource_exhausted(format!(
+                    "RDB file exceeds the {} byte upload limit",
+                    MAX_RDB_UPLOAD
+                )));
+            }
+            file.extend_from_slice(&chunk.data);
+        }
+        let report = tokio::task::spawn_blocking(move || rdb::import(&file, databases()))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))??;
+        Ok(Response::new(ImportRdbResponse {
+            keys: report.imported() as i64,
+            expired: report.expired as i64,
+            skipped: report.skipped as i64,
+        }))
+    }
+
+    async fn export_rdb(
+        &self,
+        _request: Request<ExportRdbRequest>,
+    ) -> Result<Response<Self::ExportRdbStream>, Status> {
+        let export = tokio::task::spawn_blocking(|| rdb::export(databases()))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))??;
+        if export.skipped > 0 {
+            log::warn!("Left {} streams out of the RDB export", export.skipped);
+        }
+        let bytes = Bytes::from(export.bytes);
+        let chunks: Vec<_> = (0..bytes.len())
+            .step_by(RDB_CHUNK_SIZE)
+            .map(|start| {
+                let end = bytes.len().min(start + RDB_CHUNK_SIZE);
+                RdbChunk { data: bytes.slice(start..end) }
+            })
+            .collect();
+        Ok(Response::new(Box::pin(futures_util::stream::iter(chunks.into_iter().map(Ok)))))
+    }
+
+    type ExportRdbStream = RdbStream;
+
+    async fn backup(
+        &self,
+        request: Request<BackupRequest>,
+    ) -> Result<Response<BackupResponse>, Status> {
+        let dir = backup_config().resolve(&request.into_inner().path)?;
+        let manifest =
+            tokio::task::spawn_blocking(move || backup::backup(databases(), &dir))
+                .await
+                .map_err(|err| Status::internal(err.to_string()))??;
+        let kind = match manifest.kind {
+            BackupKind::Snapshot => "snapshot",
+            BackupKind::RocksDb => "rocksdb",
+        };
+        Ok(Response::new(BackupResponse {
+            kind: kind.into(),
+            keys: manifest.keys() as i64,
+            created: manifest.created as i64,
+            files: manifest.files.len() as i64,
+            bytes: manifest.size() as i64,
+        }))
+    }
+
+    // Transaction Support
+    async fn multi(
+        &self,
+        request: Request<MultiRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let req = request.into_inner();
+        println!("Queuing transaction commands: {:?}", req.commands);
+        Ok(Response::new(ResponseMessage {
+            status: "success".into(),
+            message: "Transaction block queued".into(),
+        }))
+    }
+
+    async fn exec(
+        &self,
+        _request: Request<ExecRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        Ok(Response::new(ResponseMessage {
+            status: "success".into(),
+            message: "Transaction executed".into(),
+        }))
+    }
+
+    // Data Structures: Lists
+    async fn l_push(
+        &self,
+        request: Request<ListPushRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let values = push_values(&req);
+        let len = db.write(&req.key, |store| {
+            if req.only_if_exists {
+                store.l_push_x(&req.key, &values)
+            } else {
+                store.l_push(&req.key, &values)
+            }
+        })?;
+        Ok(Response::new(CountResponse { count: len as i64 }))
+    }
+
+    async fn l_pop(
+        &self,
+        request: Request<ListPopRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let popped = db.write(&key, |store| store.l_pop(&key))?.unwrap_or_default();
+        Ok(Response::new(ValueResponse { value: popped.into() }))
+    }
+
+    async fn r_push(
+        &self,
+        request: Request<ListPushRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let values = push_values(&req);
+        let len = db.write(&req.key, |store| {
+            if req.only_if_exists {
+                store.r_push_x(&req.key, &values)
+            } else {
+                store.r_push(&req.key, &values)
+            }
+        })?;
+        Ok(Response::new(CountResponse { count: len as i64 }))
+    }
+
+    async fn r_pop(
+        &self,
+        request: Request<ListPopRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let popped = db.write(&key, |store| store.r_pop(&key))?.unwrap_or_default();
+        Ok(Response::new(ValueResponse { value: popped.into() }))
+    }
+
+    async fn l_len(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let count = db.read(&key, |store| store.l_len(&key))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn l_range(
+        &self,
+        request: Request<ListRangeRequest>,
+    ) -> Result<Response<ListValuesResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let values = db.read(&req.key, |store| store.l_range(&req.key, req.start, req.stop))?;
+        Ok(Response::new(ListValuesResponse { values: to_bytes(values) }))
+    }
+
+    async fn l_index(
+        &self,
+        request: Request<ListIndexRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let value = db
+            .read(&req.key, |store| store.l_index(&req.key, req.index))?
+            .unwrap_or_default();
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn l_set(
+        &self,
+        request: Request<ListSetRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        db
+            .write(&req.key, |store| store.l_set(&req.key, req.index, &req.value))?;
+        Ok(Response::new(ResponseMessage {
+            status: "success".into(),
+            message: format!(
+                "Element {} of list '{}' set",
+                req.index,
+                String::from_utf8_lossy(&req.key)
+            ),
+        }))
+    }
+
+    async fn l_trim(
+        &self,
+        request: Request<ListRangeRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        db.write(&req.key, |store| store.l_trim(&req.key, req.start, req.stop))?;
+        Ok(Response::new(ResponseMessage {
+            status: "success".into(),
+            message: format!("List '{}' trimmed", String::from_utf8_lossy(&req.key)),
+        }))
+    }
+
+    async fn l_rem(
+        &self,
+        request: Request<ListRemRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let count = db.write(&req.key, |store| store.l_rem(&req.key, req.count, &req.value))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn l_insert(
+        &self,
+        request: Request<ListInsertRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let count = db
+            .write(&req.key, |store| store.l_insert(&req.key, req.before, &req.pivot, &req.value))?;
+        Ok(Response::new(CountResponse { count }))
+    }
+
+    async fn l_pos(
+        &self,
+        request: Request<ListPosRequest>,
+    ) -> Result<Response<ListPositionsResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        if req.rank == i64::MIN {
+            return Err(Status::invalid_argument("value is out of range"));
+        }
+        let count = req.count.unwrap_or(1);
+        if count < 0 {
+            return Err(Status::invalid_argument("COUNT can't be negative"));
+        }
+        if req.maxlen < 0 {
+            return Err(Status::invalid_argument("MAXLEN can't be negative"));
+        }
+        let rank = if req.rank == 0 { 1 } else { req.rank };
+        let positions = db
+            .read(&req.key, |store| {
+                store.l_pos(&req.key, &req.element, rank, count as usize, req.maxlen as usize)
+            })?
+            .into_iter()
+            .map(|position| position as i64)
+            .collect();
+        Ok(Response::new(ListPositionsResponse { positions }))
+    }
+
+    async fn l_move(
+        &self,
+        request: Request<ListMoveRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let from_front = req.wherefrom() == ListEnd::Left;
+        let to_front = req.whereto() == ListEnd::Left;
+        let value = db
+            .write_keys([&req.source, &req.destination], |store| {
+                store.l_move(&req.source, &req.destination, from_front, to_front)
+            })?
+            .unwrap_or_default();
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn bl_pop(
+        &self,
+        request: Request<BlockingPopRequest>,
+    ) -> Result<Response<BlockingPopResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let timeout = to_block_timeout(req.timeout).map_err(Status::invalid_argument)?;
+        let popped = blocking::blocking_pop(db, to_vecs(&req.keys), BlockedOp::Pop { front: true }, timeout).await?;
+        Ok(Response::new(to_blocking_pop(popped)))
+    }
+
+    async fn br_pop(
+        &self,
+        request: Request<BlockingPopRequest>,
+    ) -> Result<Response<BlockingPopResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let timeout = to_block_timeout(req.timeout).map_err(Status::invalid_argument)?;
+        let popped = blocking::blocking_pop(db, to_vecs(&req.keys), BlockedOp::Pop { front: false }, timeout).await?;
+        Ok(Response::new(to_blocking_pop(popped)))
+    }
+
+    async fn bl_move(
+        &self,
+        request: Request<BlockingMoveRequest>,
+    ) -> Result<Response<BlockingPopResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let timeout = to_block_timeout(req.timeout).map_err(Status::invalid_argument)?;
+        let op = BlockedOp::Move {
+            from_front: req.wherefrom() == ListEnd::Left,
+            destination: req.destination.to_vec(),
+            to_front: req.whereto() == ListEnd::Left,
+        };
+        let popped = blocking::blocking_pop(db, vec![req.source.to_vec()], op, timeout).await?;
+        Ok(Response::new(to_blocking_pop(popped)))
+    }
+
+    // Data Structures: Sets
+    async fn s_add(
+        &self,
+        request: Request<SetAddRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let members = if req.members.is_empty() {
+            vec![req.member.to_vec()]
+        } else {
+            to_vecs(&req.members)
+        };
+        let added = db
+            .write(&req.key, |store| store.s_add(&req.key, &members))?;
+        Ok(Response::new(CountResponse { count: added as i64 }))
+    }
+
+    async fn s_members(
+        &self,
+        request: Request<SetMembersRequest>,
+    ) -> Result<Response<SetMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let members = db.read(&key, |store| store.s_members(&key))?;
+        Ok(Response::new(SetMembersResponse { members: to_bytes(members) }))
+    }
+
+    async fn s_rem(
+        &self,
+        request: Request<SetRemRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let removed = db.write(&req.key, |store| store.s_rem(&req.key, &to_vecs(&req.members)))?;
+        Ok(Response::new(CountResponse { count: removed as i64 }))
+    }
+
+    async fn s_is_member(
+        &self,
+        request: Request<SetIsMemberRequest>,
+    ) -> Result<Response<SetIsMemberResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let is_member = db.read(&req.key, |store| store.s_is_member(&req.key, &req.member))?;
+        Ok(Response::new(SetIsMemberResponse { is_member }))
+    }
+
+    async fn sm_is_member(
+        &self,
+        request: Request<SetMIsMemberRequest>,
+    ) -> Result<Response<SetMIsMemberResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let is_member = db.read(&req.key, |store| {
+            store.s_mis_member(&req.key, &to_vecs(&req.members))
+        })?;
+        Ok(Response::new(SetMIsMemberResponse { is_member }))
+    }
+
+    async fn s_card(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let count = db.read(&key, |store| store.s_card(&key))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn s_pop(
+        &self,
+        request: Request<SetPopRequest>,
+    ) -> Result<Response<SetMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let count = match req.count {
+            Some(count) => usize::try_from(count)
+                .map_err(|_| Status::invalid_argument("count can't be negative"))?,
+            None => 1,
+        };
+        let members = db.write(&req.key, |store| store.s_pop(&req.key, count))?;
+        Ok(Response::new(SetMembersResponse { members: to_bytes(members) }))
+    }
+
+    async fn s_rand_member(
+        &self,
+        request: Request<SetPopRequest>,
+    ) -> Result<Response<SetMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let members = db
+            .read(&req.key, |store| store.s_rand_member(&req.key, req.count.unwrap_or(1)))?;
+        Ok(Response::new(SetMembersResponse { members: to_bytes(members) }))
+    }
+
+    async fn s_move(
+        &self,
+        request: Request<SetMoveRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let moved = db.write_keys([&req.source, &req.destination], |store| {
+            store.s_move(&req.source, &req.destination, &req.member)
+        })?;
+        Ok(Response::new(CountResponse { count: moved as i64 }))
+    }
+
+    async fn s_inter(
+        &self,
+        request: Request<SetKeysRequest>,
+    ) -> Result<Response<SetMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let keys = to_vecs(&request.into_inner().keys);
+        let members = db.read_keys(&keys, |store| store.s_inter(&keys))?;
+        Ok(Response::new(SetMembersResponse { members: to_bytes(members) }))
+    }
+
+    async fn s_union(
+        &self,
+        request: Request<SetKeysRequest>,
+    ) -> Result<Response<SetMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let keys = to_vecs(&request.into_inner().keys);
+        let members = db.read_keys(&keys, |store| store.s_union(&keys))?;
+        Ok(Response::new(SetMembersResponse { members: to_bytes(members) }))
+    }
+
+    async fn s_diff(
+        &self,
+        request: Request<SetKeysRequest>,
+    ) -> Result<Response<SetMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let keys = to_vecs(&request.into_inner().keys);
+        let members = db.read_keys(&keys, |store| store.s_diff(&keys))?;
+        Ok(Response::new(SetMembersResponse { members: to_bytes(members) }))
+    }
+
+    async fn s_inter_store(
+        &self,
+        request: Request<SetStoreRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let len = db.write_keys(req.keys.iter().chain([&req.destination]), |store| {
+            store.s_inter_store(&req.destination, &to_vecs(&req.keys))
+        })?;
+        Ok(Response::new(CountResponse { count: len as i64 }))
+    }
+
+    async fn s_union_store(
+        &self,
+        request: Request<SetStoreRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let len = db.write_keys(req.keys.iter().chain([&req.destination]), |store| {
+            store.s_union_store(&req.destination, &to_vecs(&req.keys))
+        })?;
+        Ok(Response::new(CountResponse { count: len as i64 }))
+    }
+
+    async fn s_diff_store(
+        &self,
+        request: Request<SetStoreRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let len = db.write_keys(req.keys.iter().chain([&req.destination]), |store| {
+            store.s_diff_store(&req.destination, &to_vecs(&req.keys))
+        })?;
+        Ok(Response::new(CountResponse { count: len as i64 }))
+    }
+
+    async fn s_inter_card(
+        &self,
+        request: Request<SetInterCardRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let limit = usize::try_from(req.limit)
+            .map_err(|_| Status::invalid_argument("LIMIT can't be negative"))?;
+        let count = db.read_keys(&req.keys, |store| {
+            store.s_inter_card(&to_vecs(&req.keys), limit)
+        })?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    // Data Structures: Hashes
+    async fn h_set(
+        &self,
+        request: Request<HashSetRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let fields: Vec<(Vec<u8>, Vec<u8>)> = if req.fields.is_empty() {
+            vec![(req.field.to_vec(), req.value.to_vec())]
+        } else {
+            req.fields
+                .into_iter()
+                .map(|field| (field.field.into(), field.value.into()))
+                .collect()
+        };
+        let added = db
+            .write(&req.key, |store| store.h_set(&req.key, &fields))?;
+        Ok(Response::new(CountResponse { count: added as i64 }))
+    }
+
+    async fn h_get(
+        &self,
+        request: Request<HashGetRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let value = db.read(&req.key, |store| {
+            store.h_get(&req.key, &req.field)
+        })?.unwrap_or_default();
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn h_set_nx(
+        &self,
+        request: Request<HashSetRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let set = db
+            .write(&req.key, |store| store.h_set_nx(&req.key, &req.field, &req.value))?;
+        Ok(Response::new(CountResponse { count: set as i64 }))
+    }
+
+    async fn hm_get(
+        &self,
+        request: Request<HashFieldsRequest>,
+    ) -> Result<Response<HashMGetResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let values = db.read(&req.key, |store| store.h_mget(&req.key, &to_vecs(&req.fields)))?;
+        Ok(Response::new(HashMGetResponse {
+            values: values.into_iter().map(|value| HashValue { value: value.map(Bytes::from) }).collect(),
+        }))
+    }
+
+    async fn h_get_all(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<HashMapResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let fields = db.read(&key, |store| store.h_get_all(&key))?;
+        Ok(Response::new(HashMapResponse {
+            fields: fields
+                .into_iter()
+                .map(|(field, value)| HashField { field: field.into(), value: value.into() })
+                .collect(),
+        }))
+    }
+
+    async fn h_del(
+        &self,
+        request: Request<HashFieldsRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let removed = db.write(&req.key, |store| store.h_del(&req.key, &to_vecs(&req.fields)))?;
+        Ok(Response::new(CountResponse { count: removed as i64 }))
+    }
+
+    async fn h_exists(
+        &self,
+        request: Request<HashGetRequest>,
+    ) -> Result<Response<HashExistsResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let exists = db.read(&req.key, |store| store.h_exists(&req.key, &req.field))?;
+        Ok(Response::new(HashExistsResponse { exists }))
+    }
+
+    async fn h_len(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let count = db.read(&key, |store| store.h_len(&key))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn h_keys(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<HashKeysResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let fields = db.read(&key, |store| store.h_keys(&key))?;
+        Ok(Response::new(HashKeysResponse { fields: to_bytes(fields) }))
+    }
+
+    async fn h_vals(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<HashValsResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let values = db.read(&key, |store| store.h_vals(&key))?;
+        Ok(Response::new(HashValsResponse { values: to_bytes(values) }))
+    }
+
+    async fn h_str_len(
+        &self,
+        request: Request<HashGetRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let len = db.read(&req.key, |store| store.h_strlen(&req.key, &req.field))?;
+        Ok(Response::new(CountResponse { count: len as i64 }))
+    }
+
+    async fn h_incr_by(
+        &self,
+        request: Request<HashIncrByRequest>,
+    ) -> Result<Response<HashIncrByResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let value = db
+            .write(&req.key, |store| store.h_incr_by(&req.key, &req.field, req.increment))?;
+        Ok(Response::new(HashIncrByResponse { value }))
+    }
+
+    async fn h_incr_by_float(
+        &self,
+        request: Request<HashIncrByFloatRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let value = db
+            .write(&req.key, |store| store.h_incr_by_float(&req.key, &req.field, req.increment))?;
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn h_rand_field(
+        &self,
+        request: Request<HashRandFieldRequest>,
+    ) -> Result<Response<HashRandFieldResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let fields = db
+            .read(&req.key, |store| store.h_rand_field(&req.key, req.count.unwrap_or(1)))?;
+        let fields = fields
+            .into_iter()
+            .map(|(field, value)| HashField {
+                field: field.into(),
+                value: if req.with_values { value.into() } else { Bytes::new() },
+            })
+            .collect();
+        Ok(Response::new(HashRandFieldResponse { fields }))
+    }
+
+    async fn h_expire(
+        &self,
+        request: Request<HashExpireRequest>,
+    ) -> Result<Response<HashFieldStatusResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let ttl = u64::try_from(req.ttl)
+            .map_err(|_| Status::invalid_argument("TTL can't be negative"))?;
+        if req.ttl.checked_mul(1000).is_none() {
+            return Err(Status::invalid_argument("invalid expire time"));
+        }
+        let results = db
+            .write(&req.key, |store| {
+                store.h_expire(&req.key, &to_vecs(&req.fields), Duration::from_secs(ttl))
+            })?;
+        Ok(Response::new(HashFieldStatusResponse { results }))
+    }
+
+    async fn h_ttl(
+        &self,
+        request: Request<HashFieldsRequest>,
+    ) -> Result<Response<HashFieldStatusResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let results = db.read(&req.key, |store| store.h_ttl(&req.key, &to_vecs(&req.fields)))?;
+        Ok(Response::new(HashFieldStatusResponse { results }))
+    }
+
+    async fn h_persist(
+        &self,
+        request: Request<HashFieldsRequest>,
+    ) -> Result<Response<HashFieldStatusResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let results = db.write(&req.key, |store| store.h_persist(&req.key, &to_vecs(&req.fields)))?;
+        Ok(Response::new(HashFieldStatusResponse { results }))
+    }
+
+    // Data Structures: Sorted Sets
+    async fn z_add(
+        &self,
+        request: Request<ZAddRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let opts = ZAddOptions {
+            nx: req.nx,
+            xx: req.xx,
+            gt: req.gt,
+            lt: req.lt,
+            ch: req.ch,
+        };
+        opts.validate().map_err(Status::invalid_argument)?;
+        let mut members = Vec::with_capacity(req.members.len());
+        for m in req.members {
+            if m.score.is_nan() {
+                return Err(Status::invalid_argument("value is not a valid float"));
+            }
+            members.push((m.score, m.member.to_vec()));
+        }
+        let count = db
+            .write(&req.key, |store| store.z_add(&req.key, &members, opts))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn z_rem(
+        &self,
+        request: Request<ZRemRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let count = db.write(&req.key, |store| store.z_rem(&req.key, &to_vecs(&req.members)))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn z_score(
+        &self,
+        request: Request<ZScoreRequest>,
+    ) -> Result<Response<ScoreResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let score = db.read(&req.key, |store| store.z_score(&req.key, &req.member))?;
+        Ok(Response::new(ScoreResponse { score }))
+    }
+
+    async fn z_card(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let count = db.read(&key, |store| store.z_card(&key))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn z_rank(
+        &self,
+        request: Request<ZRankRequest>,
+    ) -> Result<Response<RankResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let rank = db
+            .read(&req.key, |store| store.z_rank(&req.key, &req.member, req.reverse))?
+            .map(|rank| rank as i64);
+        Ok(Response::new(RankResponse { rank }))
+    }
+
+    async fn z_range(
+        &self,
+        request: Request<ZRangeRequest>,
+    ) -> Result<Response<ZMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        // With REV, score and lex ranges are given from the upper bound down.
+        let (min, max) = if req.rev {
+            (&req.stop, &req.start)
+        } else {
+            (&req.start, &req.stop)
+        };
+        let by = match req.by() {
+            ProtoZRangeBy::Index => {
+                let start = as_text(&req.start).parse::<i64>();
+                let stop = as_text(&req.stop).parse::<i64>();
+                match (start, stop) {
+                    (Ok(start), Ok(stop)) => ZRangeBy::Index(start, stop),
+                    _ => return Err(Status::invalid_argument("value is not an integer or out of range")),
+                }
+            }
+            ProtoZRangeBy::Score => ZRangeBy::Score(
+                ScoreBound::parse(as_text(min)).map_err(Status::invalid_argument)?,
+                ScoreBound::parse(as_text(max)).map_err(Status::invalid_argument)?,
+            ),
+            ProtoZRangeBy::Lex => ZRangeBy::Lex(
+                LexBound::parse(min).map_err(Status::invalid_argument)?,
+                LexBound::parse(max).map_err(Status::invalid_argument)?,
+            ),
+        };
+        let limit = match req.limit {
+            Some(_) if matches!(by, ZRangeBy::Index(..)) => {
+                return Err(Status::invalid_argument(
+                    "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+                ))
+            }
+            Some(limit) => Some((
+                limit.offset.max(0) as usize,
+                (limit.count >= 0).then_some(limit.count as usize),
+            )),
+            None => None,
+        };
+        let members = db.read(&req.key, |store| store.z_range(&req.key, &by, req.rev, limit))?;
+        Ok(Response::new(to_zmembers(members)))
+    }
+
+    async fn z_incr_by(
+        &self,
+        request: Request<ZIncrByRequest>,
+    ) -> Result<Response<ScoreResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let score = db
+            .write(&req.key, |store| store.z_incr_by(&req.key, req.increment, &req.member))?;
+        Ok(Response::new(ScoreResponse { score: Some(score) }))
+    }
+
+    async fn z_pop_min(
+        &self,
+        request: Request<ZPopRequest>,
+    ) -> Result<Response<ZMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let count = if req.count > 0 { req.count as usize } else { 1 };
+        let members = db.write(&req.key, |store| store.z_pop_min(&req.key, count))?;
+        Ok(Response::new(to_zmembers(members)))
+    }
+
+    async fn z_pop_max(
+        &self,
+        request: Request<ZPopRequest>,
+    ) -> Result<Response<ZMembersResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let count = if req.count > 0 { req.count as usize } else { 1 };
+        let members = db.write(&req.key, |store| store.z_pop_max(&req.key, count))?;
+        Ok(Response::new(to_zmembers(members)))
+    }
+
+    async fn z_union_store(
+        &self,
+        request: Request<ZStoreRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let aggregate = to_aggregate(&req).map_err(Status::invalid_argument)?;
+        let count = db.write_keys(req.keys.iter().chain([&req.destination]), |store| {
+            store.z_union_store(&req.destination, &to_vecs(&req.keys), &req.weights, aggregate)
+        })?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn z_inter_store(
+        &self,
+        request: Request<ZStoreRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let aggregate = to_aggregate(&req).map_err(Status::invalid_argument)?;
+        let count = db.write_keys(req.keys.iter().chain([&req.destination]), |store| {
+            store.z_inter_store(&req.destination, &to_vecs(&req.keys), &req.weights, aggregate)
+        })?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn x_add(
+        &self,
+        request: Request<XAddRequest>,
+    ) -> Result<Response<ValueResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        if req.fields.is_empty() {
+            return Err(Status::invalid_argument("wrong number of arguments for 'xadd' command"));
+        }
+        let id = req.id.parse::<XAddId>().map_err(Status::invalid_argument)?;
+        let trim = req
+            .trim
+            .as_ref()
+            .map(to_trim_spec)
+            .transpose()
+            .map_err(Status::invalid_argument)?;
+        let fields = req.fields.into_iter().map(|f| (f.field.into(), f.value.into())).collect();
+        let added = db
+            .write(&req.key, |store| store.x_add(&req.key, id, fields, req.nomkstream, trim))?;
+        let value = match added {
+            Some(id) => {
+                STREAM_NOTIFY.notify_waiters();
+                id.to_string().into_bytes()
+            }
+            // Only NOMKSTREAM on a missing key adds nothing.
+            None => Vec::new(),
+        };
+        Ok(Response::new(ValueResponse { value: value.into() }))
+    }
+
+    async fn x_trim(
+        &self,
+        request: Request<XTrimRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let trim = req
+            .trim
+            .as_ref()
+            .ok_or_else(|| Status::invalid_argument("trim options are required"))?;
+        let spec = to_trim_spec(trim).map_err(Status::invalid_argument)?;
+        let count = db.write(&req.key, |store| store.x_trim(&req.key, spec))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn x_len(
+        &self,
+        request: Request<KeyRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let key = request.into_inner().key;
+        let count = db.read(&key, |store| store.x_len(&key))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn x_range(
+        &self,
+        request: Request<XRangeRequest>,
+    ) -> Result<Response<XEntriesResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let start = streams::parse_range_start(&req.start).map_err(Status::invalid_argument)?;
+        let end = streams::parse_range_end(&req.end).map_err(Status::invalid_argument)?;
+        let entries = match (start, end) {
+            (Some(start), Some(end)) => {
+                db
+                    .read(&req.key, |store| {
+                        store.x_range(&req.key, start, end, to_count(req.count), req.rev)
+                    })?
+            }
+            _ => Vec::new(),
+        };
+        Ok(Response::new(XEntriesResponse { entries: to_xentries(entries) }))
+    }
+
+    async fn x_read(
+        &self,
+        request: Request<XReadRequest>,
+    ) -> Result<Response<XReadResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let keys = to_vecs(&req.keys);
+        let streams = db.read_keys(&keys, |store| {
+            let ids = resolve_read_ids(store, &keys, &req.ids)?;
+            store.x_read(&keys, &ids, to_count(req.count))
+        })?;
+        Ok(Response::new(to_xread(streams)))
+    }
+
+    async fn x_read_stream(
+        &self,
+        request: Request<XReadRequest>,
+    ) -> Result<Response<Self::XReadStreamStream>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let keys = to_vecs(&req.keys);
+        let ids = db.read_keys(&keys, |store| resolve_read_ids(store, &keys, &req.ids))?;
+        let count = to_count(req.count);
+        let block = (req.block_ms > 0).then(|| Duration::from_millis(req.block_ms as u64));
+        // Each message carries the entries added since the previous one; the stream ends
+        // once no entry arrives within `block_ms`, or after reporting an error.
+        let stream = unfold(Some((keys, ids)), move |state| async move {
+            let (keys, mut ids) = state?;
+            let deadline = block.map(|block| tokio::time::Instant::now() + block);
+            let read = || db.read_keys(&keys, |store| store.x_read(&keys, &ids, count));
+            let streams = match wait_for_entries(deadline, read).await {
+                Ok(streams) => streams,
+                Err(err) => return Some((Err(err.into()), None)),
+            };
+            if streams.is_empty() {
+                return None;
+            }
+            for (key, entries) in &streams {
+                let position = keys.iter().position(|k| k == key);
+                if let (Some(position), Some((last, _))) = (position, entries.last()) {
+                    ids[position] = *last;
+                }
+            }
+            Some((Ok(to_xread(streams)), Some((keys, ids))))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type XReadStreamStream = XReadStream;
+
+    async fn x_group_create(
+        &self,
+        request: Request<XGroupCreateRequest>,
+    ) -> Result<Response<ResponseMessage>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let id = match req.id.as_str() {
+            "" | "$" => None,
+            id => Some(id.parse::<StreamId>().map_err(Status::invalid_argument)?),
+        };
+        db
+            .write(&req.key, |store| store.x_group_create(&req.key, &req.group, id, req.mkstream))?;
+        Ok(Response::new(ResponseMessage {
+            status: "success".into(),
+            message: format!(
+                "Consumer group '{}' created on '{}'",
+                req.group,
+                String::from_utf8_lossy(&req.key)
+            ),
+        }))
+    }
+
+    async fn x_group_destroy(
+        &self,
+        request: Request<XGroupRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let destroyed = db
+            .write(&req.key, |store| store.x_group_destroy(&req.key, &req.group))?;
+        Ok(Response::new(CountResponse { count: destroyed as i64 }))
+    }
+
+    async fn x_group_create_consumer(
+        &self,
+        request: Request<XConsumerRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let created = db
+            .write(&req.key, |store| {
+                store.x_group_create_consumer(&req.key, &req.group, &req.consumer)
+            })?;
+        Ok(Response::new(CountResponse { count: created as i64 }))
+    }
+
+    async fn x_group_del_consumer(
+        &self,
+        request: Request<XConsumerRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let pending = db
+            .write(&req.key, |store| {
+                store.x_group_del_consumer(&req.key, &req.group, &req.consumer)
+            })?;
+        Ok(Response::new(CountResponse { count: pending as i64 }))
+    }
+
+    async fn x_read_group(
+        &self,
+        request: Request<XReadGroupRequest>,
+    ) -> Result<Response<XReadResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        if req.keys.is_empty() || req.keys.len() != req.ids.len() {
+            return Err(Status::invalid_argument(
+                "Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified.",
+            ));
+        }
+        let ids = req
+            .ids
+            .iter()
+            .map(|id| match id.as_str() {
+                ">" => Ok(GroupReadId::New),
+                id => id.parse().map(GroupReadId::After),
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(Status::invalid_argument)?;
+        let count = to_count(req.count);
+        let read = || {
+            db.write_keys(&req.keys, |store| {
+                let keys = to_vecs(&req.keys);
+                store.x_read_group(&req.group, &req.consumer, &keys, &ids, count, req.noack)
+            })
+        };
+        // Only reads of new entries block; replaying the pending list never does.
+        let streams = if req.block_ms > 0 && ids.iter().all(|id| *id == GroupReadId::New) {
+            let deadline = tokio::time::Instant::now() + Duration::from_millis(req.block_ms as u64);
+            wait_for_entries(Some(deadline), read).await?
+        } else {
+            read()?
+        };
+        Ok(Response::new(to_xread(streams)))
+    }
+
+    async fn x_ack(
+        &self,
+        request: Request<XAckRequest>,
+    ) -> Result<Response<CountResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let ids = parse_ids(&req.ids).map_err(Status::invalid_argument)?;
+        let count = db.write(&req.key, |store| store.x_ack(&req.key, &req.group, &ids))?;
+        Ok(Response::new(CountResponse { count: count as i64 }))
+    }
+
+    async fn x_pending(
+        &self,
+        request: Request<XPendingRequest>,
+    ) -> Result<Response<XPendingResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let mut range = None;
+        if req.count > 0 {
+            let start = if req.start.is_empty() { "-" } else { req.start.as_str() };
+            let end = if req.end.is_empty() { "+" } else { req.end.as_str() };
+            let start = streams::parse_range_start(start).map_err(Status::invalid_argument)?;
+            let end = streams::parse_range_end(end).map_err(Status::invalid_argument)?;
+            range = start.zip(end);
+        }
+        let (summary, entries) = db.read(&req.key, |store| {
+            let summary = store.x_pending_summary(&req.key, &req.group)?;
+            let mut entries = Vec::new();
+            if let Some((start, end)) = range {
+                let consumer = (!req.consumer.is_empty()).then_some(req.consumer.as_str());
+                entries = store.x_pending_range(
+                    &req.key,
+                    &req.group,
+                    start,
+                    end,
+                    req.count as usize,
+                    consumer,
+                    req.min_idle_ms.max(0) as u64,
+                )?;
+            }
+            Ok((summary, entries))
+        })?;
+        Ok(Response::new(XPendingResponse {
+            count: summary.count as i64,
+            min_id: summary.min.map(|id| id.to_string()).unwrap_or_default(),
+            max_id: summary.max.map(|id| id.to_string()).unwrap_or_default(),
+            consumers: summary
+                .consumers
+                .into_iter()
+                .map(|(name, count)| XPendingConsumer { name, count: count as i64 })
+                .collect(),
+            entries: entries
+                .into_iter()
+                .map(|info| XPendingEntry {
+                    id: info.id.to_string(),
+                    consumer: info.consumer,
+                    idle_ms: info.idle_ms as i64,
+                    deliveries: info.deliveries as i64,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn x_claim(
+        &self,
+        request: Request<XClaimRequest>,
+    ) -> Result<Response<XEntriesResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let ids = parse_ids(&req.ids).map_err(Status::invalid_argument)?;
+        let opts = ClaimOptions {
+            idle_ms: req.idle_ms.map(|ms| ms.max(0) as u64),
+            retry_count: req.retry_count.map(|count| count.max(0) as u64),
+            force: req.force,
+            justid: req.justid,
+        };
+        let min_idle_ms = req.min_idle_ms.max(0) as u64;
+        let entries = db.write(&req.key, |store| {
+            store.x_claim(&req.key, &req.group, &req.consumer, min_idle_ms, &ids, opts)
+        })?;
+        Ok(Response::new(XEntriesResponse { entries: to_xentries(entries) }))
+    }
+
+    async fn x_auto_claim(
+        &self,
+        request: Request<XAutoClaimRequest>,
+    ) -> Result<Response<XAutoClaimResponse>, Status> {
+        let db = select_db(&request)?;
+        let req = request.into_inner();
+        let start = match req.start.as_str() {
+            "" | "-" => StreamId::MIN,
+            start => start.parse().map_err(Status::invalid_argument)?,
+        };
+        let count = if req.count > 0 { req.count as usize } else { 100 };
+        let min_idle_ms = req.min_idle_ms.max(0) as u64;
+        let (next, entries, deleted) = db.write(&req.key, |store| {
+            store.x_auto_claim(
+                &req.key,
+                &req.group,
+                &req.consumer,
+                min_idle_ms,
+                start,
+                count,
+                req.justid,
+            )
+        })?;
+        Ok(Response::new(XAutoClaimResponse {
+            next_start: next.to_string(),
+            entries: to_xentries(entries),
+            deleted_ids: deleted.into_iter().map(|id| id.to_string()).collect(),
+        }))
     }
 
     // Enhanced Pub/Sub
@@ -279,7 +2200,7 @@ impl Rediodb for MyService {
         request: Request<PublishRequest>,
     ) -> Result<Response<ResponseMessage>, Status> {
         let req = request.into_inner();
-        println!("Publishing to channel '{}': {}", req.channel, req.message);
+        PUBSUB.publish(&req.channel, &req.message);
         Ok(Response::new(ResponseMessage {
             status: "success".into(),
             message: format!("Message published to channel '{}'", req.channel),
@@ -290,13 +2211,306 @@ impl Rediodb for MyService {
         &self,
         request: Request<SubscribeRequest>,
     ) -> Result<Response<Self::SubscribeStream>, Status> {
-        let _req = request.into_inner();
-        let stream = empty();
+        let req = request.into_inner();
+        let filter = ChannelFilter::new(req.channels, &req.pattern);
+        if filter.is_empty() {
+            return Err(Status::invalid_argument("no channels or pattern to subscribe to"));
+        }
+        // Subscribers that fall behind skip the messages they missed rather than failing.
+        let stream = unfold(PUBSUB.subscribe(), move |mut receiver| {
+            let filter = filter.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(msg) if filter.matches(&msg.channel) => {
+                            let reply = PubSubMessage { channel: msg.channel, message: msg.payload.into() };
+                            return Some((Ok(reply), receiver));
+                        }
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
         Ok(Response::new(Box::pin(stream)))
     }
 
     type SubscribeStream = SubscribeStream;
 }
 
+/// Index of the database a request runs against: the one named by its `x-redio-db`
+/// metadata, or database 0.
+fn db_index<T>(request: &Request<T>) -> Result<usize, StoreError> {
+    match request.metadata().get(DB_METADATA_KEY) {
+        Some(value) => {
+            let selector = value.to_str().map_err(|_| {
+                StoreError::InvalidArgument("invalid database selector".into())
+            })?;
+            databases().resolve(selector)
+        }
+        None => Ok(0),
+    }
+}
+
+/// The database a request runs against.
+fn select_db<T>(request: &Request<T>) -> Result<&'static ShardedStore, StoreError> {
+    Ok(databases().get(db_index(request)?))
+}
+
+/// Converts the EX/PX/EXAT/PXAT fields of SET and GETEX, of which at most one may be
+/// non-zero; all zero means no expiration.
+fn to_set_expiry(ex: i64, px: i64, exat: i64, pxat: i64) -> Result<Option<SetExpiry>, String> {
+    let given = [ex, px, exat, pxat].iter().filter(|value| **value != 0).count();
+    if given > 1 {
+        return Err("only one of EX, PX, EXAT and PXAT may be given".into());
+    }
+    let millis = |value: i64, scale: i64| match value.checked_mul(scale) {
+        Some(ms) if ms >= 0 => Ok(Duration::from_millis(ms as u64)),
+        _ => Err("invalid expire time".to_string()),
+    };
+    let expiry = if ex != 0 {
+        Some(SetExpiry::In(millis(ex, 1000)?))
+    } else if px != 0 {
+        Some(SetExpiry::In(millis(px, 1)?))
+    } else if exat != 0 {
+        Some(SetExpiry::At(UNIX_EPOCH + millis(exat, 1000)?))
+    } else if pxat != 0 {
+        Some(SetExpiry::At(UNIX_EPOCH + millis(pxat, 1)?))
+    } else {
+        None
+    };
+    Ok(expiry)
+}
+
+/// Converts an EXPIRE family request: `ttl` is multiplied by `scale` to get milliseconds,
+/// and is a Unix time if `absolute` is set. Negative values are in the past.
+fn to_expire(
+    req: &ExpireRequest,
+    scale: i64,
+    absolute: bool,
+) -> Result<(SetExpiry, ExpireOptions), String> {
+    let opts = ExpireOptions { nx: req.nx, xx: req.xx, gt: req.gt, lt: req.lt };
+    opts.validate()?;
+    let millis = req.ttl.checked_mul(scale).ok_or("invalid expire time")?;
+    let millis = Duration::from_millis(millis.max(0) as u64);
+    let expiry = if absolute {
+        SetExpiry::At(UNIX_EPOCH + millis)
+    } else {
+        SetExpiry::In(millis)
+    };
+    Ok((expiry, opts))
+}
+
+/// Converts the MATCH and COUNT fields of the SCAN family; an empty pattern matches
+/// everything and a zero count uses the default.
+fn to_scan_options(pattern: &[u8], count: i64) -> Result<ScanOptions, String> {
+    if count < 0 {
+        return Err("COUNT must be positive".into());
+    }
+    Ok(ScanOptions {
+        pattern: (!pattern.is_empty()).then(|| pattern.to_vec()),
+        count: count as usize,
+    })
+}
+
+/// The key/value pairs of an MSET request.
+fn to_pairs(req: MSetRequest) -> Vec<(Vec<u8>, Vec<u8>)> {
+    req.pairs.into_iter().map(|pair| (pair.key.into(), pair.value.into())).collect()
+}
+
+/// The values of a push request: `values` if set, otherwise the single `value`.
+fn push_values(req: &ListPushRequest) -> Vec<Vec<u8>> {
+    if req.values.is_empty() {
+        vec![req.value.to_vec()]
+    } else {
+        to_vecs(&req.values)
+    }
+}
+
+/// Copies request payloads out of their shared buffers for the store.
+fn to_vecs(values: &[Bytes]) -> Vec<Vec<u8>> {
+    values.iter().map(|value| value.to_vec()).collect()
+}
+
+/// Wraps values read from the store for a reply message.
+fn to_bytes(values: Vec<Vec<u8>>) -> Vec<Bytes> {
+    values.into_iter().map(Bytes::from).collect()
+}
+
+/// Converts a blocking-pop timeout in seconds; 0 waits forever.
+pub(crate) fn to_block_timeout(seconds: f64) -> Result<Option<Duration>, String> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return Err("timeout is negative or not a number".into());
+    }
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|timeout| tokio::time::Instant::now().checked_add(*timeout).is_some())
+        .map(Some)
+        .ok_or_else(|| "timeout is out of range".into())
+}
+
+/// Converts the result of a blocking pop into its reply message.
+fn to_blocking_pop(popped: Option<Delivery>) -> BlockingPopResponse {
+    match popped {
+        Some((key, value)) => BlockingPopResponse {
+            timed_out: false,
+            key: key.into(),
+            value: value.into(),
+        },
+        None => BlockingPopResponse { timed_out: true, ..Default::default() },
+    }
+}
+
+/// Converts (member, score) pairs into the sorted set reply message.
+fn to_zmembers(members: Vec<(Vec<u8>, f64)>) -> ZMembersResponse {
+    ZMembersResponse {
+        members: members
+            .into_iter()
+            .map(|(member, score)| ZMember { member: member.into(), score })
+            .collect(),
+    }
+}
+
+/// Validates a ZUNIONSTORE/ZINTERSTORE request and returns its aggregate function.
+fn to_aggregate(req: &ZStoreRequest) -> Result<Aggregate, String> {
+    if req.keys.is_empty() {
+        return Err("at least 1 input key is needed".to_string());
+    }
+    if !req.weights.is_empty() && req.weights.len() != req.keys.len() {
+        return Err("the number of weights must match the number of keys".to_string());
+    }
+    if req.weights.iter().any(|w| w.is_nan()) {
+        return Err("weight value is not a float".to_string());
+    }
+    Ok(match req.aggregate() {
+        ZAggregate::Sum => Aggregate::Sum,
+        ZAggregate::Min => Aggregate::Min,
+        ZAggregate::Max => Aggregate::Max,
+    })
+}
+
+/// Reads a numeric argument sent as bytes. Invalid UTF-8 reads as the empty string, which
+/// every number parser rejects.
+fn as_text(bytes: &[u8]) -> &str {
+    std::str::from_utf8(bytes).unwrap_or_default()
+}
+
+/// Converts a non-positive COUNT into "no limit".
+fn to_count(count: i64) -> Option<usize> {
+    (count > 0).then_some(count as usize)
+}
+
+/// Parses a list of stream IDs.
+fn parse_ids(ids: &[String]) -> Result<Vec<StreamId>, String> {
+    ids.iter().map(|id| id.parse()).collect()
+}
+
+/// Validates XADD/XTRIM trimming options.
+fn to_trim_spec(trim: &XTrimOptions) -> Result<TrimSpec, String> {
+    let strategy = match trim.strategy() {
+        XTrimStrategy::Maxlen => TrimStrategy::MaxLen(
+            trim.threshold
+                .parse()
+                .map_err(|_| "value is not an integer or out of range".to_string())?,
+        ),
+        XTrimStrategy::Minid => TrimStrategy::MinId(trim.threshold.parse()?),
+    };
+    if trim.limit != 0 && !trim.approximate {
+        return Err("syntax error, LIMIT cannot be used without the special ~ option".to_string());
+    }
+    Ok(TrimSpec {
+        strategy,
+        approximate: trim.approximate,
+        limit: trim.limit.max(0) as usize,
+    })
+}
+
+/// Resolves the IDs of an XREAD request, replacing `$` with the stream's last ID.
+pub(crate) fn resolve_read_ids(
+    store: &mut impl KeyShards,
+    keys: &[Vec<u8>],
+    ids: &[String],
+) -> Result<Vec<StreamId>, StoreError> {
+    if keys.is_empty() || keys.len() != ids.len() {
+        return Err(StoreError::InvalidArgument(
+            "Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified."
+                .to_string(),
+        ));
+    }
+    keys.iter()
+        .zip(ids)
+        .map(|(key, id)| match id.as_str() {
+            "$" => Ok(store.shard_mut(key).x_last_id(key)?.unwrap_or_default()),
+            id => id.parse().map_err(StoreError::InvalidArgument),
+        })
+        .collect()
+}
+
+/// Polls `read` until it returns entries, waking up whenever a stream is written.
+/// Returns an empty result once `deadline` passes; without a deadline, waits forever.
+/// A failed read is returned immediately.
+pub(crate) async fn wait_for_entries<T, E>(
+    deadline: Option<tokio::time::Instant>,
+    mut read: impl FnMut() -> Result<Vec<T>, E>,
+) -> Result<Vec<T>, E> {
+    loop {
+        // Register for the wake-up before reading, so an XADD in between is not missed.
+        let notified = STREAM_NOTIFY.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        let found = read()?;
+        if !found.is_empty() {
+            return Ok(found);
+        }
+        match deadline {
+            Some(deadline) => {
+                if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                    return Ok(found);
+                }
+            }
+            None => notified.await,
+        }
+    }
+}
+
+/// Converts stream entries into their reply messages.
+fn to_xentries(entries: Vec<StreamEntry>) -> Vec<XEntry> {
+    entries
+        .into_iter()
+        .map(|(id, fields)| XEntry {
+            id: id.to_string(),
+            fields: fields
+                .into_iter()
+                .map(|(field, value)| XField { field: field.into(), value: value.into() })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Converts per-stream entries into the XREAD/XREADGROUP reply message.
+fn to_xread(streams: Vec<StreamRead>) -> XReadResponse {
+    XReadResponse {
+        streams: streams
+            .into_iter()
+            .map(|(key, entries)| XStreamEntries {
+                key: key.into(),
+                entries: to_xentries(entries),
+            })
+            .collect(),
+    }
+}
+
 // Define the SubscribeStream type alias only once as a pinned box.
 pub type SubscribeStream = Pin<Box<dyn Stream<Item = Result<PubSubMessage, Status>> + Send + 'static>>;
+
+/// Chunks of the RDB file returned by the ExportRdb RPC.
+pub type RdbStream = Pin<Box<dyn Stream<Item = Result<RdbChunk, Status>> + Send + 'static>>;
+
+/// Stream of SCAN pages returned by the ScanStream RPC.
+pub type ScanStream = Pin<Box<dyn Stream<Item = Result<ScanResponse, Status>> + Send + 'static>>;
+
+/// Stream of XREAD replies returned by the blocking XReadStream RPC.
+pub type XReadStream = Pin<Box<dyn Stream<Item = Result<XReadResponse, Status>> + Send + 'static>>;
@@ -0,0 +1,397 @@
+// src/server/resp.rs
+//
+// The Redis serialization protocol (RESP), served on a TCP listener next to the gRPC server
+// so redis-cli and Redis client libraries can talk to RedioDB.
+// Commands arrive as arrays of bulk strings, or as inline commands (one line of words, as
+// typed in telnet), and may be pipelined: every complete command received is run in order
+// and the replies are written back together. Connections speak RESP2 until HELLO 3 switches
+// them to RESP3, which adds maps, sets, doubles, booleans and a single null type; `Reply`
+// is encoded for whichever the connection speaks. Commands themselves are in
+// `resp_commands`.
+use std::io;
+use std::time::Duration;
+
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::server::resp_commands::{self, Client};
+use crate::storage::string_value::format_float;
+
+/// Address of the RESP listener when `REDIO_RESP_ADDRESS` is not set: the Redis port.
+pub const DEFAULT_RESP_ADDRESS: &str = "0.0.0.0:6379";
+
+/// Most arguments a command may have.
+const MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+
+/// Largest argument, the same limit as Redis's `proto-max-bulk-len`.
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Longest inline command.
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// Most unparsed input a connection may buffer, the same limit as Redis's
+/// `client-query-buffer-limit`; a client sending more is disconnected.
+const MAX_QUERY_BUFFER: usize = 1024 * 1024 * 1024;
+
+/// The protocol version a connection speaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+/// A reply to a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    /// A status such as `OK`.
+    Simple(String),
+    /// An error, starting with its code (`ERR`, `WRONGTYPE`...).
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    /// A missing value.
+    Null,
+    /// A missing array, e.g. a BLPOP that timed out; RESP3 has a single null.
+    NullArray,
+    Array(Vec<Reply>),
+    /// Field/value pairs; a flat array in RESP2.
+    Map(Vec<(Reply, Reply)>),
+    /// Unordered unique members; an array in RESP2.
+    Set(Vec<Reply>),
+    /// A floating point number; a bulk string in RESP2.
+    Double(f64),
+    /// A boolean; the integer 1 or 0 in RESP2.
+    Boolean(bool),
+}
+
+impl Reply {
+    /// Appends the reply, encoded for `protocol`, to `out`.
+    pub fn encode(&self, protocol: Protocol, out: &mut Vec<u8>) {
+        let resp3 = protocol == Protocol::Resp3;
+        match self {
+            Reply::Simple(status) => put_line(out, b'+', status.as_bytes()),
+            Reply::Error(message) => put_line(out, b'-', message.as_bytes()),
+            Reply::Integer(value) => put_line(out, b':', value.to_string().as_bytes()),
+            Reply::Bulk(bytes) => {
+                put_line(out, b'$', bytes.len().to_string().as_bytes());
+                out.extend_from_slice(bytes);
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Null if resp3 => out.extend_from_slice(b"_\r\n"),
+            Reply::Null => out.extend_from_slice(b"$-1\r\n"),
+            Reply::NullArray if resp3 => out.extend_from_slice(b"_\r\n"),
+            Reply::NullArray => out.extend_from_slice(b"*-1\r\n"),
+            Reply::Array(items) => {
+                put_line(out, b'*', items.len().to_string().as_bytes());
+                for item in items {
+                    item.encode(protocol, out);
+                }
+            }
+            Reply::Map(pairs) => {
+                let (kind, len) = if resp3 { (b'%', pairs.len()) } else { (b'*', pairs.len() * 2) };
+                put_line(out, kind, len.to_string().as_bytes());
+                for (field, value) in pairs {
+                    field.encode(protocol, out);
+                    value.encode(protocol, out);
+                }
+            }
+            Reply::Set(items) => {
+                put_line(out, if resp3 { b'~' } else { b'*' }, items.len().to_string().as_bytes());
+                for item in items {
+                    item.encode(protocol, out);
+                }
+            }
+            Reply::Double(value) if resp3 => put_line(out, b',', format_float(*value).as_bytes()),
+            Reply::Double(value) => Reply::Bulk(format_float(*value).into_bytes()).encode(protocol, out),
+            Reply::Boolean(value) if resp3 => {
+                out.extend_from_slice(if *value { b"#t\r\n" } else { b"#f\r\n" })
+            }
+            Reply::Boolean(value) => Reply::Integer(*value as i64).encode(protocol, out),
+        }
+    }
+}
+
+/// Helper function: Append a line starting with the type byte `kind`.
+fn put_line(out: &mut Vec<u8>, kind: u8, line: &[u8]) {
+    out.push(kind);
+    out.extend_from_slice(line);
+    out.extend_from_slice(b"\r\n");
+}
+
+/// A parsed command: its arguments and the number of bytes it took.
+pub type Parsed = (Vec<Vec<u8>>, usize);
+
+/// Parses the command at the start of `input`: its arguments, the command name first, and
+/// the number of bytes it took. Returns None if the command is not complete yet. An empty
+/// argument list is an empty line or array, which is ignored.
+pub fn parse_command(input: &[u8]) -> Result<Option<Parsed>, String> {
+    let mut buf = BytesMut::from(input);
+    let parsed = RequestParser::default().parse(&mut buf)?;
+    Ok(parsed.map(|args| (args, input.len() - buf.len())))
+}
+
+/// Parses the commands of a connection as their bytes arrive. The bytes of a command are
+/// consumed as far as they can be parsed and the arguments read so far kept, so a large
+/// command arriving over many reads is only scanned once.
+#[derive(Debug, Default)]
+pub struct RequestParser {
+    /// Arguments of the array being read.
+    args: Vec<Vec<u8>>,
+    /// Arguments of the array still to read; None between commands.
+    remaining: Option<usize>,
+    /// Length of the next bulk string, once its header was read.
+    bulk_len: Option<usize>,
+}
+
+impl RequestParser {
+    /// Takes the next command from the start of `input`, consuming its bytes. Returns None
+    /// if it is not complete yet; call again once more bytes arrived.
+    pub fn parse(&mut self, input: &mut BytesMut) -> Result<Option<Vec<Vec<u8>>>, String> {
+        let Some(mut remaining) = self.remaining else {
+            match input.first() {
+                None => return Ok(None),
+                Some(b'*') => return self.parse_multibulk(input),
+                Some(_) => {}
+            }
+            let Some((args, used)) = parse_inline(input)? else {
+                return Ok(None);
+            };
+            input.advance(used);
+            return Ok(Some(args));
+        };
+        while remaining > 0 {
+            let len = match self.bulk_len {
+                Some(len) => len,
+                None => {
+                    let Some((line, next)) = read_line(input, 0)? else {
+                        return Ok(None);
+                    };
+                    if line.first() != Some(&b'$') {
+                        let got =
+                            line.first().map_or(String::new(), |byte| (*byte as char).to_string());
+                        return Err(format!("Protocol error: expected '$', got '{}'", got));
+                    }
+                    let len = parse_length(&line[1..])
+                        .filter(|len| (0..=MAX_BULK_LEN as i64).contains(len))
+                        .ok_or("Protocol error: invalid bulk length")? as usize;
+                    input.advance(next);
+                    self.bulk_len = Some(len);
+                    len
+                }
+            };
+            if input.len() < len + 2 {
+                return Ok(None);
+            }
+            if &input[len..len + 2] != b"\r\n" {
+                return Err("Protocol error: invalid bulk length".into());
+            }
+            self.args.push(input.split_to(len).to_vec());
+            input.advance(2);
+            self.bulk_len = None;
+            remaining -= 1;
+            self.remaining = Some(remaining);
+        }
+        self.remaining = None;
+        Ok(Some(std::mem::take(&mut self.args)))
+    }
+
+    /// Helper method: Read the header of an array of bulk strings, then its arguments.
+    fn parse_multibulk(&mut self, input: &mut BytesMut) -> Result<Option<Vec<Vec<u8>>>, String> {
+        let Some((line, next)) = read_line(input, 0)? else {
+            return Ok(None);
+        };
+        let count = parse_length(&line[1..])
+            .filter(|count| *count <= MAX_MULTIBULK_LEN as i64)
+            .ok_or("Protocol error: invalid multibulk length")?;
+        let count = count.max(0) as usize;
+        input.advance(next);
+        self.args = Vec::with_capacity(count.min(1024));
+        self.remaining = Some(count);
+        self.parse(input)
+    }
+}
+
+/// Helper function: Parse an inline command, split into words like redis-cli does:
+/// double-quoted words may hold escapes (`\n`, `\xff`...), single-quoted ones only `\'`.
+fn parse_inline(input: &[u8]) -> Result<Option<Parsed>, String> {
+    let Some(end) = input.iter().position(|byte| *byte == b'\n') else {
+        if input.len() > MAX_INLINE_LEN {
+            return Err("Protocol error: too big inline request".into());
+        }
+        return Ok(None);
+    };
+    let line = input[..end].strip_suffix(b"\r").unwrap_or(&input[..end]);
+    let unbalanced = || "Protocol error: unbalanced quotes in request".to_string();
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while line.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(Some((args, end + 1)));
+        }
+        let mut arg = Vec::new();
+        let quote = match line[i] {
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                Some(quote)
+            }
+            _ => None,
+        };
+        loop {
+            let Some(&byte) = line.get(i) else {
+                if quote.is_some() {
+                    return Err(unbalanced());
+                }
+                break;
+            };
+            i += 1;
+            match (quote, byte) {
+                (None, byte) if byte.is_ascii_whitespace() => break,
+                (Some(quote), byte) if byte == quote => {
+                    // A closing quote must end the word.
+                    if line.get(i).is_some_and(|next| !next.is_ascii_whitespace()) {
+                        return Err(unbalanced());
+                    }
+                    break;
+                }
+                (Some(b'"'), b'\\') if i < line.len() => {
+                    let hex = line.get(i + 1..i + 3).and_then(|hex| {
+                        u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+                    });
+                    match (line[i], hex) {
+                        (b'x', Some(value)) => {
+                            arg.push(value);
+                            i += 3;
+                            continue;
+                        }
+                        (b'n', _) => arg.push(b'\n'),
+                        (b'r', _) => arg.push(b'\r'),
+                        (b't', _) => arg.push(b'\t'),
+                        (b'b', _) => arg.push(0x08),
+                        (b'a', _) => arg.push(0x07),
+                        (other, _) => arg.push(other),
+                    }
+                    i += 1;
+                }
+                (Some(b'\''), b'\\') if line.get(i) == Some(&b'\'') => {
+                    arg.push(b'\'');
+                    i += 1;
+                }
+                (_, byte) => arg.push(byte),
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// Helper function: The line starting at `pos`, without its CRLF, and the position after
+/// it; None if the line is not complete yet.
+fn read_line(input: &[u8], pos: usize) -> Result<Option<(&[u8], usize)>, String> {
+    match input[pos..].windows(2).position(|pair| pair == b"\r\n") {
+        Some(len) => Ok(Some((&input[pos..pos + len], pos + len + 2))),
+        None if input.len() - pos > MAX_INLINE_LEN => {
+            Err("Protocol error: too big count or bulk length".into())
+        }
+        None => Ok(None),
+    }
+}
+
+/// Helper function: Parse the length of an array or bulk string.
+fn parse_length(digits: &[u8]) -> Option<i64> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Accepts RESP connections on `listener`, serving each in its own task.
+pub async fn serve(listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((socket, peer)) => {
+                tokio::spawn(async move {
+                    if let Err(err) = handle_connection(socket).await {
+                        log::debug!("RESP connection from {} failed: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => {
+                // Typically out of file descriptors; give connections time to close.
+                log::warn!("Failed to accept a RESP connection: {}", err);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// Serves one connection until the client disconnects, sends QUIT or breaks the protocol.
+pub async fn handle_connection(socket: TcpStream) -> io::Result<()> {
+    let _ = socket.set_nodelay(true);
+    let (mut reader, mut writer) = socket.into_split();
+    let mut client = Client::default();
+    let mut input = BytesMut::with_capacity(16 * 1024);
+    let mut parser = RequestParser::default();
+    let mut output = Vec::new();
+    loop {
+        // Run every complete command received so far; the replies go out together.
+        loop {
+            let args = match parser.parse(&mut input) {
+                Ok(Some(args)) => args,
+                Ok(None) => break,
+                Err(message) => {
+                    Reply::Error(message).encode(client.protocol, &mut output);
+                    return writer.write_all(&output).await;
+                }
+            };
+            if args.is_empty() {
+                continue;
+            }
+            if resp_commands::is_blocking(&args) && !output.is_empty() {
+                // Earlier replies must not wait for a command that may block.
+                writer.write_all(&output).await?;
+                output.clear();
+            }
+            // Keep reading while the command runs, so a client that disconnects while
+            // blocked is noticed and its wait cancelled.
+            let reply = {
+                let command = resp_commands::execute(&mut client, args);
+                tokio::pin!(command);
+                loop {
+                    tokio::select! {
+                        biased;
+                        reply = &mut command => break reply,
+                        read = read_input(&mut reader, &mut input) => {
+                            if !read? {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+            };
+            reply.encode(client.protocol, &mut output);
+            if client.closing {
+                return writer.write_all(&output).await;
+            }
+        }
+        if !output.is_empty() {
+            writer.write_all(&output).await?;
+            output.clear();
+        }
+        if !read_input(&mut reader, &mut input).await? {
+            return Ok(());
+        }
+    }
+}
+
+/// Helper function: Read more of the client's input. Returns false once it disconnected,
+/// and fails if the client sent more than `MAX_QUERY_BUFFER` bytes that were not run yet.
+async fn read_input(reader: &mut OwnedReadHalf, input: &mut BytesMut) -> io::Result<bool> {
+    if reader.read_buf(input).await? == 0 {
+        return Ok(false);
+    }
+    if input.len() > MAX_QUERY_BUFFER {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "query buffer limit exceeded"));
+    }
+    Ok(true)
+}
@@ -0,0 +1,1794 @@
+// src/server/resp_commands.rs
+//
+// The commands served over RESP.
+// Each command reads its arguments the way Redis does and runs the same store operations as
+// the matching gRPC method of `MyService`, against the database the connection SELECTed.
+// Errors are replied with Redis's codes and messages, which `StoreError` already carries.
+// PUBLISH reaches the gRPC subscribers. Commands the store has no operations for get Redis's
+// unknown command error, as do those only served over gRPC: SUBSCRIBE and PSUBSCRIBE, which
+// need a push connection, MULTI/EXEC, MIGRATE, and XPENDING, XCLAIM and XAUTOCLAIM.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::server::my_service::{
+    databases, resolve_read_ids, snapshots, to_block_timeout, wait_for_entries, PUBSUB,
+    STREAM_NOTIFY,
+};
+use crate::server::resp::{Protocol, Reply};
+use crate::storage::aof;
+use crate::storage::blocking::{self, BlockedOp};
+use crate::storage::dump::RestoreOptions;
+use crate::storage::error::StoreError;
+use crate::storage::expiry::ExpireOptions;
+use crate::storage::scan::ScanOptions;
+use crate::storage::multi_key::MultiKey;
+use crate::storage::sharded::ShardedStore;
+use crate::storage::snapshot;
+use crate::storage::sorted_set::{
+    parse_score, Aggregate, LexBound, ScoreBound, ZAddOptions, ZRangeBy,
+};
+use crate::storage::stream::{
+    self as streams, GroupReadId, StreamEntry, StreamId, StreamRead, TrimSpec, TrimStrategy,
+    XAddId,
+};
+use crate::storage::string_value::{SetExpiry, SetOptions};
+
+/// Source of client IDs, as CLIENT ID and HELLO report them.
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The state of a RESP connection.
+pub struct Client {
+    pub id: u64,
+    /// Index of the database commands run against (SELECT).
+    pub db: usize,
+    /// The protocol replies are encoded in (HELLO).
+    pub protocol: Protocol,
+    /// The name set with CLIENT SETNAME or HELLO SETNAME.
+    pub name: Option<Vec<u8>>,
+    /// Set by QUIT: the connection is closed once the reply is sent.
+    pub closing: bool,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            db: 0,
+            protocol: Protocol::Resp2,
+            name: None,
+            closing: false,
+        }
+    }
+}
+
+/// Whether a command may keep the connection waiting (BLPOP and friends, and stream reads
+/// with BLOCK).
+pub fn is_blocking(args: &[Vec<u8>]) -> bool {
+    let name = args[0].to_ascii_lowercase();
+    match name.as_slice() {
+        b"blpop" | b"brpop" | b"blmove" | b"brpoplpush" => true,
+        b"xread" | b"xreadgroup" => args.iter().any(|arg| arg.eq_ignore_ascii_case(b"block")),
+        _ => false,
+    }
+}
+
+/// Runs a command, given with its name first, for `client`.
+pub async fn execute(client: &mut Client, args: Vec<Vec<u8>>) -> Reply {
+    let name = lower(&args[0]);
+    let result = match name.as_str() {
+        "blpop" | "brpop" | "blmove" | "brpoplpush" => {
+            blocking_pop(client, &name, &args[1..]).await
+        }
+        "xread" | "xreadgroup" => x_read(client, &name, &args[1..]).await,
+        "save" => save(&args[1..]).await,
+        _ => command(client, &name, &args),
+    };
+    result.unwrap_or_else(|err| Reply::Error(err.to_string()))
+}
+
+/// Helper function: Run a command that never waits.
+fn command(client: &mut Client, name: &str, full: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    let args = &full[1..];
+    let db = databases().get(client.db);
+    let protocol = client.protocol;
+    match name {
+        // Connection
+        "ping" => {
+            arity(name, args, 0, 1)?;
+            Ok(match args.first() {
+                Some(message) => Reply::Bulk(message.clone()),
+                None => Reply::Simple("PONG".into()),
+            })
+        }
+        "echo" => {
+            arity(name, args, 1, 1)?;
+            Ok(Reply::Bulk(args[0].clone()))
+        }
+        "quit" => {
+            client.closing = true;
+            Ok(ok())
+        }
+        "select" => {
+            arity(name, args, 1, 1)?;
+            client.db = databases().resolve(text(&args[0]))?;
+            Ok(ok())
+        }
+        "hello" => hello(client, args),
+        "client" => client_command(client, args),
+        "command" => match args.first().map(|sub| lower(sub)).as_deref() {
+            // Clients only ask for documentation to improve their hints.
+            None => Ok(Reply::Array(Vec::new())),
+            Some("docs") => Ok(Reply::Map(Vec::new())),
+            Some(_) => Err(unknown_subcommand("COMMAND", &args[0])),
+        },
+
+        // Strings
+        "get" => {
+            arity(name, args, 1, 1)?;
+            Ok(bulk_or_null(db.read(&args[0], |store| store.get(&args[0]))?))
+        }
+        "set" => set(db, args),
+        "setnx" => {
+            arity(name, args, 2, 2)?;
+            let opts = SetOptions { nx: true, ..SetOptions::default() };
+            let outcome = db.write(&args[0], |store| {
+                store.set_with_options(&args[0], &args[1], opts)
+            })?;
+            Ok(Reply::Integer(outcome.written as i64))
+        }
+        "setex" | "psetex" => {
+            arity(name, args, 3, 3)?;
+            let option = if name == "setex" { "ex" } else { "px" };
+            let expiry = set_expiry(option, &args[1], name)?;
+            let opts = SetOptions { expiry: Some(expiry), ..SetOptions::default() };
+            db.write(&args[0], |store| store.set_with_options(&args[0], &args[2], opts))?;
+            Ok(ok())
+        }
+        "getset" => {
+            arity(name, args, 2, 2)?;
+            let opts = SetOptions { get: true, ..SetOptions::default() };
+            let outcome = db.write(&args[0], |store| {
+                store.set_with_options(&args[0], &args[1], opts)
+            })?;
+            Ok(bulk_or_null(outcome.old_value))
+        }
+        "getdel" => {
+            arity(name, args, 1, 1)?;
+            Ok(bulk_or_null(db.write(&args[0], |store| store.get_del(&args[0]))?))
+        }
+        "getex" => get_ex(db, args),
+        "mget" => {
+            arity(name, args, 1, usize::MAX)?;
+            let values = db.read_keys(args, |store| Ok(store.m_get(args)))?;
+            Ok(Reply::Array(values.into_iter().map(bulk_or_null).collect()))
+        }
+        "mset" | "msetnx" => {
+            if args.is_empty() || !args.len().is_multiple_of(2) {
+                return Err(wrong_arity(name));
+            }
+            let pairs: Vec<_> = args.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+            db.write_keys(args.iter().step_by(2), |store| {
+                if name == "mset" {
+                    store.m_set(&pairs)?;
+                    Ok(ok())
+                } else {
+                    Ok(Reply::Integer(store.m_set_nx(&pairs)? as i64))
+                }
+            })
+        }
+        "incr" | "decr" => {
+            arity(name, args, 1, 1)?;
+            let value = db.write(&args[0], |store| {
+                if name == "incr" {
+                    store.incr(&args[0], 1)
+                } else {
+                    store.decr(&args[0], 1)
+                }
+            })?;
+            Ok(Reply::Integer(value))
+        }
+        "incrby" | "decrby" => {
+            arity(name, args, 2, 2)?;
+            let amount = int(&args[1])?;
+            let value = db.write(&args[0], |store| {
+                if name == "incrby" {
+                    store.incr(&args[0], amount)
+                } else {
+                    store.decr(&args[0], amount)
+                }
+            })?;
+            Ok(Reply::Integer(value))
+        }
+        "incrbyfloat" => {
+            arity(name, args, 2, 2)?;
+            let delta = float(&args[1])?;
+            let value = db.write(&args[0], |store| store.incr_by_float(&args[0], delta))?;
+            Ok(Reply::Bulk(value.into_bytes()))
+        }
+        "append" => {
+            arity(name, args, 2, 2)?;
+            let value = db.write(&args[0], |store| store.append(&args[0], &args[1]))?;
+            Ok(Reply::Integer(value.len() as i64))
+        }
+        "strlen" => {
+            arity(name, args, 1, 1)?;
+            let value = db.read(&args[0], |store| store.get(&args[0]))?;
+            Ok(Reply::Integer(value.map_or(0, |value| value.len()) as i64))
+        }
+
+        // Generic keyspace
+        "del" | "unlink" | "exists" | "touch" => {
+            arity(name, args, 1, usize::MAX)?;
+            let count = db.write_keys(args, |store| {
+                Ok(match name {
+                    "del" => store.del_keys(args),
+                    "unlink" => store.unlink(args),
+                    "exists" => store.exists(args),
+                    _ => store.touch(args),
+                })
+            })?;
+            Ok(Reply::Integer(count as i64))
+        }
+        "type" => {
+            arity(name, args, 1, 1)?;
+            Ok(Reply::Simple(db.read(&args[0], |store| Ok(store.key_type(&args[0])))?.into()))
+        }
+        "expire" => expire(db, name, args, 1000, false),
+        "pexpire" => expire(db, name, args, 1, false),
+        "expireat" => expire(db, name, args, 1000, true),
+        "pexpireat" => expire(db, name, args, 1, true),
+        "ttl" | "pttl" | "expiretime" | "pexpiretime" => {
+            arity(name, args, 1, 1)?;
+            let value = db.read(&args[0], |store| {
+                Ok(match name {
+                    "ttl" => store.ttl(&args[0]),
+                    "pttl" => store.pttl(&args[0]),
+                    "expiretime" => store.expire_time(&args[0]),
+                    _ => store.pexpire_time(&args[0]),
+                })
+            })?;
+            Ok(Reply::Integer(value.unwrap_or(-2)))
+        }
+        "persist" => {
+            arity(name, args, 1, 1)?;
+            Ok(Reply::Integer(db.write(&args[0], |store| Ok(store.persist(&args[0])))? as i64))
+        }
+        "keys" => {
+            arity(name, args, 1, 1)?;
+            Ok(bulks(db.keys(&args[0])?))
+        }
+        "scan" => {
+            arity(name, args, 1, usize::MAX)?;
+            let cursor = cursor(&args[0])?;
+            let (opts, type_name) = scan_options(&args[1..], true)?;
+            let page = db.scan(cursor, &opts, type_name.as_deref())?;
+            Ok(scan_reply(page.cursor, bulks(page.items)))
+        }
+        "rename" => {
+            arity(name, args, 2, 2)?;
+            db.write_keys(args, |store| store.rename(&args[0], &args[1]))?;
+            // The destination may now be a stream that blocked readers wait on.
+            STREAM_NOTIFY.notify_waiters();
+            Ok(ok())
+        }
+        "renamenx" => {
+            arity(name, args, 2, 2)?;
+            let renamed = db.write_keys(args, |store| store.rename_nx(&args[0], &args[1]))?;
+            if renamed {
+                STREAM_NOTIFY.notify_waiters();
+            }
+            Ok(Reply::Integer(renamed as i64))
+        }
+        "copy" => copy(client, args),
+        "move" => {
+            arity(name, args, 2, 2)?;
+            let to = databases().resolve(text(&args[1]))?;
+            let moved = databases().move_key(&args[0], client.db, to)?;
+            if moved {
+                STREAM_NOTIFY.notify_waiters();
+            }
+            Ok(Reply::Integer(moved as i64))
+        }
+        "dump" => {
+            arity(name, args, 1, 1)?;
+            Ok(bulk_or_null(db.read(&args[0], |store| Ok(store.dump(&args[0])))?))
+        }
+        "restore" => restore(db, args),
+
+        // Logical databases
+        "dbsize" => {
+            arity(name, args, 0, 0)?;
+            Ok(Reply::Integer(db.count_keys()? as i64))
+        }
+        "flushdb" | "flushall" => {
+            arity(name, args, 0, 1)?;
+            let lazy = match args.first().map(|mode| lower(mode)).as_deref() {
+                None | Some("sync") => false,
+                Some("async") => true,
+                Some(_) => return Err(syntax_error()),
+            };
+            if name == "flushdb" {
+                db.flush(lazy)?;
+            } else {
+                databases().flush_all(lazy)?;
+            }
+            Ok(ok())
+        }
+        "swapdb" => {
+            arity(name, args, 2, 2)?;
+            let first = databases().resolve(text(&args[0]))?;
+            let second = databases().resolve(text(&args[1]))?;
+            databases().swap(first, second)?;
+            STREAM_NOTIFY.notify_waiters();
+            Ok(ok())
+        }
+
+        // Persistence
+        "bgsave" => {
+            arity(name, args, 0, 1)?;
+            snapshot::spawn_save(snapshots(), databases())?;
+            Ok(Reply::Simple("Background saving started".into()))
+        }
+        "lastsave" => {
+            arity(name, args, 0, 0)?;
+            let at = snapshots().last_save().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Ok(Reply::Integer(at.as_secs() as i64))
+        }
+        "bgrewriteaof" => {
+            arity(name, args, 0, 0)?;
+            let aof = databases()
+                .aof()
+                .ok_or_else(|| StoreError::InvalidArgument("append-only file is disabled".into()))?;
+            aof::spawn_rewrite(aof, databases())?;
+            Ok(Reply::Simple("Background append only file rewriting started".into()))
+        }
+
+        // Lists
+        "lpush" | "rpush" | "lpushx" | "rpushx" => {
+            arity(name, args, 2, usize::MAX)?;
+            let (key, values) = (&args[0], &args[1..]);
+            let len = db.write(key, |store| match name {
+                "lpush" => store.l_push(key, values),
+                "rpush" => store.r_push(key, values),
+                "lpushx" => store.l_push_x(key, values),
+                _ => store.r_push_x(key, values),
+            })?;
+            Ok(Reply::Integer(len as i64))
+        }
+        "lpop" | "rpop" => list_pop(db, name, args),
+        "llen" => {
+            arity(name, args, 1, 1)?;
+            Ok(Reply::Integer(db.read(&args[0], |store| store.l_len(&args[0]))? as i64))
+        }
+        "lrange" => {
+            arity(name, args, 3, 3)?;
+            let (start, stop) = (int(&args[1])?, int(&args[2])?);
+            Ok(bulks(db.read(&args[0], |store| store.l_range(&args[0], start, stop))?))
+        }
+        "lindex" => {
+            arity(name, args, 2, 2)?;
+            let index = int(&args[1])?;
+            Ok(bulk_or_null(db.read(&args[0], |store| store.l_index(&args[0], index))?))
+        }
+        "lset" => {
+            arity(name, args, 3, 3)?;
+            let index = int(&args[1])?;
+            db.write(&args[0], |store| store.l_set(&args[0], index, &args[2]))?;
+            Ok(ok())
+        }
+        "ltrim" => {
+            arity(name, args, 3, 3)?;
+            let (start, stop) = (int(&args[1])?, int(&args[2])?);
+            db.write(&args[0], |store| store.l_trim(&args[0], start, stop))?;
+            Ok(ok())
+        }
+        "lrem" => {
+            arity(name, args, 3, 3)?;
+            let count = int(&args[1])?;
+            Ok(Reply::Integer(db.write(&args[0], |store| {
+                store.l_rem(&args[0], count, &args[2])
+            })? as i64))
+        }
+        "linsert" => {
+            arity(name, args, 4, 4)?;
+            let before = match lower(&args[1]).as_str() {
+                "before" => true,
+                "after" => false,
+                _ => return Err(syntax_error()),
+            };
+            let len = db.write(&args[0], |store| {
+                store.l_insert(&args[0], before, &args[2], &args[3])
+            })?;
+            Ok(Reply::Integer(len))
+        }
+        "lpos" => list_pos(db, args),
+        "lmove" => {
+            arity(name, args, 4, 4)?;
+            let (from_front, to_front) = (list_end(&args[2])?, list_end(&args[3])?);
+            let moved = db.write_keys(&args[..2], |store| {
+                store.l_move(&args[0], &args[1], from_front, to_front)
+            })?;
+            Ok(bulk_or_null(moved))
+        }
+        "rpoplpush" => {
+            arity(name, args, 2, 2)?;
+            let moved = db.write_keys(args, |store| store.l_move(&args[0], &args[1], false, true))?;
+            Ok(bulk_or_null(moved))
+        }
+
+        // Sets
+        "sadd" | "srem" => {
+            arity(name, args, 2, usize::MAX)?;
+            let count = db.write(&args[0], |store| {
+                if name == "sadd" {
+                    store.s_add(&args[0], &args[1..])
+                } else {
+                    store.s_rem(&args[0], &args[1..])
+                }
+            })?;
+            Ok(Reply::Integer(count as i64))
+        }
+        "smembers" => {
+            arity(name, args, 1, 1)?;
+            Ok(set_of(db.read(&args[0], |store| store.s_members(&args[0]))?))
+        }
+        "sismember" => {
+            arity(name, args, 2, 2)?;
+            let found = db.read(&args[0], |store| store.s_is_member(&args[0], &args[1]))?;
+            Ok(Reply::Integer(found as i64))
+        }
+        "smismember" => {
+            arity(name, args, 2, usize::MAX)?;
+            let found = db.read(&args[0], |store| store.s_mis_member(&args[0], &args[1..]))?;
+            Ok(Reply::Array(found.into_iter().map(|found| Reply::Integer(found as i64)).collect()))
+        }
+        "scard" => {
+            arity(name, args, 1, 1)?;
+            Ok(Reply::Integer(db.read(&args[0], |store| store.s_card(&args[0]))? as i64))
+        }
+        "spop" => {
+            arity(name, args, 1, 2)?;
+            match args.get(1) {
+                Some(count) => {
+                    let count = positive(count)?;
+                    Ok(set_of(db.write(&args[0], |store| store.s_pop(&args[0], count))?))
+                }
+                None => Ok(bulk_or_null(db.write(&args[0], |store| {
+                    store.s_pop(&args[0], 1)
+                })?.pop())),
+            }
+        }
+        "srandmember" => {
+            arity(name, args, 1, 2)?;
+            let count = args.get(1).map(|count| int(count)).transpose()?;
+            let members =
+                db.read(&args[0], |store| store.s_rand_member(&args[0], count.unwrap_or(1)))?;
+            match count {
+                Some(_) => Ok(bulks(members)),
+                None => Ok(bulk_or_null(members.into_iter().next())),
+            }
+        }
+        "smove" => {
+            arity(name, args, 3, 3)?;
+            let moved = db.write_keys(&args[..2], |store| {
+                store.s_move(&args[0], &args[1], &args[2])
+            })?;
+            Ok(Reply::Integer(moved as i64))
+        }
+        "sinter" | "sunion" | "sdiff" => {
+            arity(name, args, 1, usize::MAX)?;
+            let members = db.read_keys(args, |store| match name {
+                "sinter" => store.s_inter(args),
+                "sunion" => store.s_union(args),
+                _ => store.s_diff(args),
+            })?;
+            Ok(set_of(members))
+        }
+        "sinterstore" | "sunionstore" | "sdiffstore" => {
+            arity(name, args, 2, usize::MAX)?;
+            let (destination, keys) = (&args[0], &args[1..]);
+            let len = db.write_keys(args, |store| match name {
+                "sinterstore" => store.s_inter_store(destination, keys),
+                "sunionstore" => store.s_union_store(destination, keys),
+                _ => store.s_diff_store(destination, keys),
+            })?;
+            Ok(Reply::Integer(len as i64))
+        }
+        "sintercard" => {
+            arity(name, args, 2, usize::MAX)?;
+            let keys = numkeys(name, args)?;
+            let limit = match &args[1 + keys.len()..] {
+                [] => 0,
+                [option, limit] if lower(option) == "limit" => usize::try_from(int(limit)?)
+                    .map_err(|_| StoreError::InvalidArgument("LIMIT can't be negative".into()))?,
+                _ => return Err(syntax_error()),
+            };
+            Ok(Reply::Integer(db.read_keys(keys, |store| store.s_inter_card(keys, limit))? as i64))
+        }
+        "sscan" => {
+            arity(name, args, 2, usize::MAX)?;
+            let cursor = cursor(&args[1])?;
+            let (opts, _) = scan_options(&args[2..], false)?;
+            let page = db.read(&args[0], |store| store.s_scan(&args[0], cursor, &opts))?;
+            Ok(scan_reply(page.cursor, bulks(page.items)))
+        }
+
+        // Hashes
+        "hset" | "hmset" => {
+            if args.len() < 3 || args.len().is_multiple_of(2) {
+                return Err(wrong_arity(name));
+            }
+            let fields: Vec<_> =
+                args[1..].chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+            let added = db.write(&args[0], |store| store.h_set(&args[0], &fields))?;
+            Ok(if name == "hset" { Reply::Integer(added as i64) } else { ok() })
+        }
+        "hsetnx" => {
+            arity(name, args, 3, 3)?;
+            let set = db.write(&args[0], |store| store.h_set_nx(&args[0], &args[1], &args[2]))?;
+            Ok(Reply::Integer(set as i64))
+        }
+        "hget" => {
+            arity(name, args, 2, 2)?;
+            Ok(bulk_or_null(db.read(&args[0], |store| store.h_get(&args[0], &args[1]))?))
+        }
+        "hmget" => {
+            arity(name, args, 2, usize::MAX)?;
+            let values = db.read(&args[0], |store| store.h_mget(&args[0], &args[1..]))?;
+            Ok(Reply::Array(values.into_iter().map(bulk_or_null).collect()))
+        }
+        "hgetall" => {
+            arity(name, args, 1, 1)?;
+            let fields = db.read(&args[0], |store| store.h_get_all(&args[0]))?;
+            Ok(Reply::Map(
+                fields
+                    .into_iter()
+                    .map(|(field, value)| (Reply::Bulk(field), Reply::Bulk(value)))
+                    .collect(),
+            ))
+        }
+        "hdel" => {
+            arity(name, args, 2, usize::MAX)?;
+            Ok(Reply::Integer(db.write(&args[0], |store| {
+                store.h_del(&args[0], &args[1..])
+            })? as i64))
+        }
+        "hexists" => {
+            arity(name, args, 2, 2)?;
+            let exists = db.read(&args[0], |store| store.h_exists(&args[0], &args[1]))?;
+            Ok(Reply::Integer(exists as i64))
+        }
+        "hlen" => {
+            arity(name, args, 1, 1)?;
+            Ok(Reply::Integer(db.read(&args[0], |store| store.h_len(&args[0]))? as i64))
+        }
+        "hkeys" => {
+            arity(name, args, 1, 1)?;
+            Ok(bulks(db.read(&args[0], |store| store.h_keys(&args[0]))?))
+        }
+        "hvals" => {
+            arity(name, args, 1, 1)?;
+            Ok(bulks(db.read(&args[0], |store| store.h_vals(&args[0]))?))
+        }
+        "hstrlen" => {
+            arity(name, args, 2, 2)?;
+            let len = db.read(&args[0], |store| store.h_strlen(&args[0], &args[1]))?;
+            Ok(Reply::Integer(len as i64))
+        }
+        "hincrby" => {
+            arity(name, args, 3, 3)?;
+            let delta = int(&args[2])?;
+            Ok(Reply::Integer(db.write(&args[0], |store| {
+                store.h_incr_by(&args[0], &args[1], delta)
+            })?))
+        }
+        "hincrbyfloat" => {
+            arity(name, args, 3, 3)?;
+            let delta = float(&args[2])?;
+            let value = db.write(&args[0], |store| {
+                store.h_incr_by_float(&args[0], &args[1], delta)
+            })?;
+            Ok(Reply::Bulk(value.into_bytes()))
+        }
+        "hrandfield" => {
+            arity(name, args, 1, 3)?;
+            let Some(count) = args.get(1) else {
+                let fields = db.read(&args[0], |store| store.h_rand_field(&args[0], 1))?;
+                return Ok(bulk_or_null(fields.into_iter().next().map(|(field, _)| field)));
+            };
+            let with_values = match args.get(2) {
+                None => false,
+                Some(option) if lower(option) == "withvalues" => true,
+                Some(_) => return Err(syntax_error()),
+            };
+            let count = int(count)?;
+            let fields = db.read(&args[0], |store| store.h_rand_field(&args[0], count))?;
+            Ok(if with_values {
+                pairs(
+                    protocol,
+                    fields
+                        .into_iter()
+                        .map(|(field, value)| (Reply::Bulk(field), Reply::Bulk(value)))
+                        .collect(),
+                )
+            } else {
+                bulks(fields.into_iter().map(|(field, _)| field).collect())
+            })
+        }
+        "hexpire" | "httl" | "hpersist" => {
+            let fields_at = if name == "hexpire" { 2 } else { 1 };
+            arity(name, args, fields_at + 2, usize::MAX)?;
+            let fields = hash_fields(&args[fields_at..])?;
+            let results = match name {
+                "hexpire" => {
+                    let seconds = int(&args[1])?;
+                    if seconds.checked_mul(1000).is_none() {
+                        return Err(StoreError::InvalidArgument(
+                            "invalid expire time in 'hexpire' command".into(),
+                        ));
+                    }
+                    let seconds = u64::try_from(seconds).map_err(|_| {
+                        StoreError::InvalidArgument("invalid expire time, must be >= 0".into())
+                    })?;
+                    let ttl = Duration::from_secs(seconds);
+                    db.write(&args[0], |store| store.h_expire(&args[0], &fields, ttl))?
+                }
+                "httl" => db.read(&args[0], |store| store.h_ttl(&args[0], &fields))?,
+                _ => db.write(&args[0], |store| store.h_persist(&args[0], &fields))?,
+            };
+            Ok(Reply::Array(results.into_iter().map(Reply::Integer).collect()))
+        }
+        "hscan" => {
+            arity(name, args, 2, usize::MAX)?;
+            let cursor = cursor(&args[1])?;
+            let (opts, _) = scan_options(&args[2..], false)?;
+            let page = db.read(&args[0], |store| store.h_scan(&args[0], cursor, &opts))?;
+            let items = page.items.into_iter().flat_map(|(field, value)| [field, value]).collect();
+            Ok(scan_reply(page.cursor, bulks(items)))
+        }
+
+        // Sorted sets
+        "zadd" => z_add(db, args),
+        "zrem" => {
+            arity(name, args, 2, usize::MAX)?;
+            Ok(Reply::Integer(db.write(&args[0], |store| {
+                store.z_rem(&args[0], &args[1..])
+            })? as i64))
+        }
+        "zscore" => {
+            arity(name, args, 2, 2)?;
+            let score = db.read(&args[0], |store| store.z_score(&args[0], &args[1]))?;
+            Ok(score.map_or(Reply::Null, Reply::Double))
+        }
+        "zmscore" => {
+            arity(name, args, 2, usize::MAX)?;
+            let scores = db.read(&args[0], |store| {
+                args[1..]
+                    .iter()
+                    .map(|member| {
+                        Ok(store.z_score(&args[0], member)?.map_or(Reply::Null, Reply::Double))
+                    })
+                    .collect::<Result<_, StoreError>>()
+            })?;
+            Ok(Reply::Array(scores))
+        }
+        "zcard" => {
+            arity(name, args, 1, 1)?;
+            Ok(Reply::Integer(db.read(&args[0], |store| store.z_card(&args[0]))? as i64))
+        }
+        "zrank" | "zrevrank" => {
+            arity(name, args, 2, 2)?;
+            let rank = db.read(&args[0], |store| {
+                store.z_rank(&args[0], &args[1], name == "zrevrank")
+            })?;
+            Ok(rank.map_or(Reply::Null, |rank| Reply::Integer(rank as i64)))
+        }
+        "zincrby" => {
+            arity(name, args, 3, 3)?;
+            let increment = float(&args[1])?;
+            Ok(Reply::Double(db.write(&args[0], |store| {
+                store.z_incr_by(&args[0], increment, &args[2])
+            })?))
+        }
+        "zrange" => z_range(db, protocol, name, args, RangeKind::Index, false),
+        "zrevrange" => z_range(db, protocol, name, args, RangeKind::Index, true),
+        "zrangebyscore" => z_range(db, protocol, name, args, RangeKind::Score, false),
+        "zrevrangebyscore" => z_range(db, protocol, name, args, RangeKind::Score, true),
+        "zrangebylex" => z_range(db, protocol, name, args, RangeKind::Lex, false),
+        "zrevrangebylex" => z_range(db, protocol, name, args, RangeKind::Lex, true),
+        "zcount" | "zlexcount" => {
+            arity(name, args, 3, 3)?;
+            let by = if name == "zcount" {
+                score_range(&args[1], &args[2])?
+            } else {
+                lex_range(&args[1], &args[2])?
+            };
+            let members = db.read(&args[0], |store| store.z_range(&args[0], &by, false, None))?;
+            Ok(Reply::Integer(members.len() as i64))
+        }
+        "zpopmin" | "zpopmax" => {
+            arity(name, args, 1, 2)?;
+            let count = args.get(1).map(|count| positive(count)).transpose()?;
+            let members = db.write(&args[0], |store| {
+                if name == "zpopmin" {
+                    store.z_pop_min(&args[0], count.unwrap_or(1))
+                } else {
+                    store.z_pop_max(&args[0], count.unwrap_or(1))
+                }
+            })?;
+            // Without a count, even RESP3 replies with a flat member and score.
+            let protocol = if count.is_some() { protocol } else { Protocol::Resp2 };
+            Ok(scored(protocol, members, true))
+        }
+        "zunionstore" | "zinterstore" => z_store(db, name, args),
+        "zscan" => {
+            arity(name, args, 2, usize::MAX)?;
+            let cursor = cursor(&args[1])?;
+            let (opts, _) = scan_options(&args[2..], false)?;
+            let page = db.read(&args[0], |store| store.z_scan(&args[0], cursor, &opts))?;
+            let items = page
+                .items
+                .into_iter()
+                .flat_map(|(member, score)| [member, format_score(score)])
+                .collect();
+            Ok(scan_reply(page.cursor, bulks(items)))
+        }
+
+        // Streams
+        "xadd" => x_add(db, args),
+        "xtrim" => {
+            arity(name, args, 3, usize::MAX)?;
+            let (spec, used) = trim_spec(&args[1..])?;
+            if 1 + used != args.len() {
+                return Err(syntax_error());
+            }
+            Ok(Reply::Integer(db.write(&args[0], |store| store.x_trim(&args[0], spec))? as i64))
+        }
+        "xlen" => {
+            arity(name, args, 1, 1)?;
+            Ok(Reply::Integer(db.read(&args[0], |store| store.x_len(&args[0]))? as i64))
+        }
+        "xrange" | "xrevrange" => {
+            arity(name, args, 3, 5)?;
+            let rev = name == "xrevrange";
+            // XREVRANGE takes the end of the range first.
+            let (start, end) = if rev { (&args[2], &args[1]) } else { (&args[1], &args[2]) };
+            let start = streams::parse_range_start(text(start)).map_err(StoreError::InvalidArgument)?;
+            let end = streams::parse_range_end(text(end)).map_err(StoreError::InvalidArgument)?;
+            let count = match &args[3..] {
+                [] => None,
+                [option, count] if lower(option) == "count" => {
+                    let count = int(count)?;
+                    (count > 0).then_some(count as usize)
+                }
+                _ => return Err(syntax_error()),
+            };
+            let entries = match (start, end) {
+                (Some(start), Some(end)) => {
+                    db.read(&args[0], |store| store.x_range(&args[0], start, end, count, rev))?
+                }
+                _ => Vec::new(),
+            };
+            Ok(Reply::Array(entries.into_iter().map(entry_reply).collect()))
+        }
+        "xgroup" => x_group(db, args),
+        "xack" => {
+            arity(name, args, 3, usize::MAX)?;
+            let ids = stream_ids(&args[2..])?;
+            let group = text(&args[1]);
+            let acked = db.write(&args[0], |store| store.x_ack(&args[0], group, &ids))?;
+            Ok(Reply::Integer(acked as i64))
+        }
+
+        // Pub/Sub
+        "publish" => {
+            arity(name, args, 2, 2)?;
+            let receivers = PUBSUB.publish(&String::from_utf8_lossy(&args[0]), &args[1]);
+            Ok(Reply::Integer(receivers as i64))
+        }
+
+        _ => Err(unknown_command(full)),
+    }
+}
+
+/// Helper function: HELLO [protover [AUTH username password] [SETNAME clientname]].
+fn hello(client: &mut Client, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    let mut protocol = client.protocol;
+    if let Some(version) = args.first() {
+        protocol = match text(version).parse::<i64>() {
+            Ok(2) => Protocol::Resp2,
+            Ok(3) => Protocol::Resp3,
+            Ok(_) => return Ok(Reply::Error("NOPROTO unsupported protocol version".into())),
+            Err(_) => {
+                return Err(StoreError::InvalidArgument(
+                    "Protocol version is not an integer or out of range".into(),
+                ))
+            }
+        };
+    }
+    let mut name = None;
+    let mut i = 1;
+    while i < args.len() {
+        match lower(&args[i]).as_str() {
+            "auth" if i + 2 < args.len() => {
+                // No passwords are configured, so only the default user exists.
+                if args[i + 1] != b"default" {
+                    return Ok(Reply::Error(
+                        "WRONGPASS invalid username-password pair or user is disabled.".into(),
+                    ));
+                }
+                i += 3;
+            }
+            "setname" if i + 1 < args.len() => {
+                check_client_name(&args[i + 1])?;
+                name = Some(args[i + 1].clone());
+                i += 2;
+            }
+            _ => {
+                return Err(StoreError::InvalidArgument(format!(
+                    "Syntax error in HELLO option '{}'",
+                    String::from_utf8_lossy(&args[i])
+                )))
+            }
+        }
+    }
+    client.protocol = protocol;
+    if name.is_some() {
+        client.name = name;
+    }
+    let text = |value: &str| Reply::Bulk(value.as_bytes().to_vec());
+    Ok(Reply::Map(vec![
+        (text("server"), text("rediodb")),
+        (text("version"), text(env!("CARGO_PKG_VERSION"))),
+        (text("proto"), Reply::Integer(if protocol == Protocol::Resp3 { 3 } else { 2 })),
+        (text("id"), Reply::Integer(client.id as i64)),
+        (text("mode"), text("standalone")),
+        (text("role"), text("master")),
+        (text("modules"), Reply::Array(Vec::new())),
+    ]))
+}
+
+/// Helper function: CLIENT SETNAME, GETNAME, ID and SETINFO.
+fn client_command(client: &mut Client, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    let Some(sub) = args.first() else {
+        return Err(wrong_arity("client"));
+    };
+    match (lower(sub).as_str(), args.len()) {
+        ("setname", 2) => {
+            check_client_name(&args[1])?;
+            client.name = (!args[1].is_empty()).then(|| args[1].clone());
+            Ok(ok())
+        }
+        ("getname", 1) => Ok(bulk_or_null(client.name.clone())),
+        ("id", 1) => Ok(Reply::Integer(client.id as i64)),
+        // Client libraries announce themselves; there is nowhere to show it yet.
+        ("setinfo", 3) => Ok(ok()),
+        ("setname" | "getname" | "id" | "setinfo", _) => Err(wrong_arity(format!("client|{}", lower(sub)))),
+        _ => Err(unknown_subcommand("CLIENT", sub)),
+    }
+}
+
+/// Helper function: Reject client names Redis rejects.
+fn check_client_name(name: &[u8]) -> Result<(), StoreError> {
+    if name.iter().all(|byte| (b'!'..=b'~').contains(byte)) {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidArgument(
+            "Client names cannot contain spaces, newlines or special characters.".into(),
+        ))
+    }
+}
+
+/// Helper function: SET key value [NX | XX] [GET] [EX | PX | EXAT | PXAT time | KEEPTTL].
+fn set(db: &ShardedStore, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("set", args, 2, usize::MAX)?;
+    let mut opts = SetOptions::default();
+    let mut i = 2;
+    while i < args.len() {
+        let option = lower(&args[i]);
+        match option.as_str() {
+            "nx" => opts.nx = true,
+            "xx" => opts.xx = true,
+            "get" => opts.get = true,
+            "keepttl" if opts.expiry.is_none() => opts.keep_ttl = true,
+            "ex" | "px" | "exat" | "pxat"
+                if opts.expiry.is_none() && !opts.keep_ttl && i + 1 < args.len() =>
+            {
+                opts.expiry = Some(set_expiry(&option, &args[i + 1], "set")?);
+                i += 1;
+            }
+            _ => return Err(syntax_error()),
+        }
+        i += 1;
+    }
+    opts.validate().map_err(|_| syntax_error())?;
+    let outcome = db.write(&args[0], |store| store.set_with_options(&args[0], &args[1], opts))?;
+    Ok(if opts.get {
+        bulk_or_null(outcome.old_value)
+    } else if outcome.written {
+        ok()
+    } else {
+        Reply::Null
+    })
+}
+
+/// Helper function: GETEX key [EX | PX | EXAT | PXAT time | PERSIST].
+fn get_ex(db: &ShardedStore, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("getex", args, 1, 3)?;
+    let (expiry, persist) = match &args[1..] {
+        [] => (None, false),
+        [option] if lower(option) == "persist" => (None, true),
+        [option, time] => {
+            let option = lower(option);
+            if !matches!(option.as_str(), "ex" | "px" | "exat" | "pxat") {
+                return Err(syntax_error());
+            }
+            (Some(set_expiry(&option, time, "getex")?), false)
+        }
+        _ => return Err(syntax_error()),
+    };
+    Ok(bulk_or_null(db.write(&args[0], |store| store.get_ex(&args[0], expiry, persist))?))
+}
+
+/// Helper function: The expiry of an EX, PX, EXAT or PXAT option; Redis only accepts
+/// positive times there.
+fn set_expiry(option: &str, time: &[u8], command: &str) -> Result<SetExpiry, StoreError> {
+    let scale = if option.starts_with('p') { 1 } else { 1000 };
+    let millis = int(time)?
+        .checked_mul(scale)
+        .filter(|millis| *millis > 0)
+        .ok_or_else(|| {
+            StoreError::InvalidArgument(format!("invalid expire time in '{}' command", command))
+        })?;
+    let millis = Duration::from_millis(millis as u64);
+    Ok(if option.ends_with("at") {
+        SetExpiry::At(UNIX_EPOCH + millis)
+    } else {
+        SetExpiry::In(millis)
+    })
+}
+
+/// Helper function: The EXPIRE family: the time is multiplied by `scale` to get
+/// milliseconds, and is a Unix time if `absolute` is set.
+fn expire(
+    db: &ShardedStore,
+    name: &str,
+    args: &[Vec<u8>],
+    scale: i64,
+    absolute: bool,
+) -> Result<Reply, StoreError> {
+    arity(name, args, 2, usize::MAX)?;
+    let mut opts = ExpireOptions::default();
+    for option in &args[2..] {
+        match lower(option).as_str() {
+            "nx" => opts.nx = true,
+            "xx" => opts.xx = true,
+            "gt" => opts.gt = true,
+            "lt" => opts.lt = true,
+            _ => {
+                return Err(StoreError::InvalidArgument(format!(
+                    "Unsupported option {}",
+                    String::from_utf8_lossy(option)
+                )))
+            }
+        }
+    }
+    opts.validate().map_err(StoreError::InvalidArgument)?;
+    let millis = int(&args[1])?.checked_mul(scale).ok_or_else(|| {
+        StoreError::InvalidArgument(format!("invalid expire time in '{}' command", name))
+    })?;
+    // Times in the past delete the key.
+    let millis = Duration::from_millis(millis.max(0) as u64);
+    let expiry = if absolute {
+        SetExpiry::At(UNIX_EPOCH + millis)
+    } else {
+        SetExpiry::In(millis)
+    };
+    let set = db.write(&args[0], |store| Ok(store.expire_with_options(&args[0], expiry, opts)))?;
+    Ok(Reply::Integer(set as i64))
+}
+
+/// Helper function: COPY source destination [DB destination-db] [REPLACE].
+fn copy(client: &Client, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("copy", args, 2, 5)?;
+    let (source, destination) = (&args[0], &args[1]);
+    let mut target = client.db;
+    let mut replace = false;
+    let mut i = 2;
+    while i < args.len() {
+        match lower(&args[i]).as_str() {
+            "replace" => replace = true,
+            "db" if i + 1 < args.len() => {
+                target = databases().resolve(text(&args[i + 1]))?;
+                i += 1;
+            }
+            _ => return Err(syntax_error()),
+        }
+        i += 1;
+    }
+    let db = databases().get(client.db);
+    let copied = if target == client.db {
+        db.write_keys(&args[..2], |store| store.copy(source, destination, replace))?
+    } else {
+        // Across databases the key travels as a DUMP payload, which carries its TTL.
+        match db.read(source, |store| Ok(store.dump(source)))? {
+            Some(payload) => {
+                let opts = RestoreOptions { replace, ..RestoreOptions::default() };
+                databases().get(target).write(destination, |store| {
+                    match store.restore(destination, &payload, 0, opts) {
+                        Ok(()) => Ok(true),
+                        Err(StoreError::BusyKey) => Ok(false),
+                        Err(err) => Err(err),
+                    }
+                })?
+            }
+            None => false,
+        }
+    };
+    if copied {
+        STREAM_NOTIFY.notify_waiters();
+    }
+    Ok(Reply::Integer(copied as i64))
+}
+
+/// Helper function: RESTORE key ttl payload [REPLACE] [ABSTTL] [IDLETIME seconds].
+fn restore(db: &ShardedStore, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("restore", args, 3, usize::MAX)?;
+    let ttl = int(&args[1])?;
+    let mut opts = RestoreOptions::default();
+    let mut i = 3;
+    while i < args.len() {
+        match lower(&args[i]).as_str() {
+            "replace" => opts.replace = true,
+            "absttl" => opts.abs_ttl = true,
+            "idletime" if i + 1 < args.len() => {
+                let idle = u64::try_from(int(&args[i + 1])?).map_err(|_| {
+                    StoreError::InvalidArgument("Invalid IDLETIME value, must be >= 0".into())
+                })?;
+                opts.idle_time = Some(idle);
+                i += 1;
+            }
+            _ => return Err(syntax_error()),
+        }
+        i += 1;
+    }
+    db.write(&args[0], |store| store.restore(&args[0], &args[2], ttl, opts))?;
+    STREAM_NOTIFY.notify_waiters();
+    Ok(ok())
+}
+
+/// Helper function: LPOP/RPOP key [count]. With a count, a missing key replies with a
+/// null array rather than an empty one.
+fn list_pop(db: &ShardedStore, name: &str, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity(name, args, 1, 2)?;
+    let key = &args[0];
+    let front = name == "lpop";
+    let count = args.get(1).map(|count| positive(count)).transpose()?;
+    db.write(key, |store| {
+        let Some(count) = count else {
+            let popped = if front { store.l_pop(key)? } else { store.r_pop(key)? };
+            return Ok(bulk_or_null(popped));
+        };
+        if store.l_len(key)? == 0 {
+            return Ok(Reply::NullArray);
+        }
+        let mut popped = Vec::new();
+        while popped.len() < count {
+            let value = if front { store.l_pop(key)? } else { store.r_pop(key)? };
+            match value {
+                Some(value) => popped.push(value),
+                None => break,
+            }
+        }
+        Ok(bulks(popped))
+    })
+}
+
+/// Helper function: LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len].
+fn list_pos(db: &ShardedStore, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("lpos", args, 2, usize::MAX)?;
+    let (mut rank, mut count, mut maxlen) = (1, None, 0);
+    let options = &args[2..];
+    if !options.len().is_multiple_of(2) {
+        return Err(syntax_error());
+    }
+    for option in options.chunks(2) {
+        let value = int(&option[1])?;
+        match lower(&option[0]).as_str() {
+            "rank" if value == 0 => {
+                return Err(StoreError::InvalidArgument(
+                    "RANK can't be zero: use 1 to start from the first match, 2 from the second \
+                     ... or use negative to start from the end of the list"
+                        .into(),
+                ))
+            }
+            "rank" if value == i64::MIN => {
+                return Err(StoreError::InvalidArgument("value is out of range".into()))
+            }
+            "rank" => rank = value,
+            "count" => {
+                count = Some(usize::try_from(value).map_err(|_| {
+                    StoreError::InvalidArgument("COUNT can't be negative".into())
+                })?)
+            }
+            "maxlen" => {
+                maxlen = usize::try_from(value).map_err(|_| {
+                    StoreError::InvalidArgument("MAXLEN can't be negative".into())
+                })?
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    let positions =
+        db.read(&args[0], |store| {
+            store.l_pos(&args[0], &args[1], rank, count.unwrap_or(1), maxlen)
+        })?;
+    let mut positions = positions.into_iter().map(|position| Reply::Integer(position as i64));
+    Ok(match count {
+        Some(_) => Reply::Array(positions.collect()),
+        None => positions.next().unwrap_or(Reply::Null),
+    })
+}
+
+/// Helper function: Whether a LEFT/RIGHT argument names the front of the list.
+fn list_end(arg: &[u8]) -> Result<bool, StoreError> {
+    match lower(arg).as_str() {
+        "left" => Ok(true),
+        "right" => Ok(false),
+        _ => Err(syntax_error()),
+    }
+}
+
+/// Helper function: BLPOP/BRPOP key [key ...] timeout, BLMOVE source destination
+/// LEFT|RIGHT LEFT|RIGHT timeout and BRPOPLPUSH source destination timeout.
+async fn blocking_pop(client: &Client, name: &str, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    let db = databases().get(client.db);
+    let (keys, op, timeout) = match name {
+        "blpop" | "brpop" => {
+            arity(name, args, 2, usize::MAX)?;
+            let (timeout, keys) = args.split_last().unwrap();
+            (keys.to_vec(), BlockedOp::Pop { front: name == "blpop" }, timeout)
+        }
+        "blmove" => {
+            arity(name, args, 5, 5)?;
+            let op = BlockedOp::Move {
+                from_front: list_end(&args[2])?,
+                destination: args[1].clone(),
+                to_front: list_end(&args[3])?,
+            };
+            (vec![args[0].clone()], op, &args[4])
+        }
+        _ => {
+            arity(name, args, 3, 3)?;
+            let op = BlockedOp::Move {
+                from_front: false,
+                destination: args[1].clone(),
+                to_front: true,
+            };
+            (vec![args[0].clone()], op, &args[2])
+        }
+    };
+    let seconds = text(timeout).parse::<f64>().map_err(|_| {
+        StoreError::InvalidArgument("timeout is not a float or out of range".into())
+    })?;
+    let timeout = to_block_timeout(seconds).map_err(StoreError::InvalidArgument)?;
+    let popped = blocking::blocking_pop(db, keys, op, timeout).await?;
+    Ok(match (popped, name) {
+        (Some((key, value)), "blpop" | "brpop") => {
+            Reply::Array(vec![Reply::Bulk(key), Reply::Bulk(value)])
+        }
+        (Some((_, value)), _) => Reply::Bulk(value),
+        (None, "blpop" | "brpop") => Reply::NullArray,
+        (None, _) => Reply::Null,
+    })
+}
+
+/// Helper function: XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...],
+/// and XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS ...
+async fn x_read(client: &Client, name: &str, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    let db = databases().get(client.db);
+    let mut rest = args;
+    let mut consumer = None;
+    if name == "xreadgroup" {
+        match rest {
+            [option, group, name, tail @ ..] if lower(option) == "group" => {
+                consumer = Some((text(group), text(name)));
+                rest = tail;
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    let (mut count, mut block, mut noack) = (None, None, false);
+    let streams = loop {
+        match rest {
+            [option, value, tail @ ..] if lower(option) == "count" => {
+                let value = int(value)?;
+                count = (value > 0).then_some(value as usize);
+                rest = tail;
+            }
+            [option, value, tail @ ..] if lower(option) == "block" => {
+                let ms = int(value)?;
+                if ms < 0 {
+                    return Err(StoreError::InvalidArgument("timeout is negative".into()));
+                }
+                let timeout =
+                    to_block_timeout(ms as f64 / 1000.0).map_err(StoreError::InvalidArgument)?;
+                // BLOCK 0 waits forever.
+                block = Some(timeout.map(|timeout| tokio::time::Instant::now() + timeout));
+                rest = tail;
+            }
+            [option, tail @ ..] if consumer.is_some() && lower(option) == "noack" => {
+                noack = true;
+                rest = tail;
+            }
+            [option, tail @ ..] if lower(option) == "streams" => break tail,
+            _ => return Err(syntax_error()),
+        }
+    };
+    if streams.is_empty() || !streams.len().is_multiple_of(2) {
+        let (command, id) = if consumer.is_some() { ("xreadgroup", ">") } else { ("xread", "$") };
+        return Err(StoreError::InvalidArgument(format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+            command, id
+        )));
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+    let found = match consumer {
+        None => {
+            let ids: Vec<String> = ids.iter().map(|id| text(id).to_string()).collect();
+            let after = db.read_keys(keys, |store| resolve_read_ids(store, keys, &ids))?;
+            let read = || db.read_keys(keys, |store| store.x_read(keys, &after, count));
+            match block {
+                Some(deadline) => wait_for_entries(deadline, read).await?,
+                None => read()?,
+            }
+        }
+        Some((group, consumer)) => {
+            let ids = ids
+                .iter()
+                .map(|id| match text(id) {
+                    ">" => Ok(GroupReadId::New),
+                    id => id.parse().map(GroupReadId::After),
+                })
+                .collect::<Result<Vec<_>, String>>()
+                .map_err(StoreError::InvalidArgument)?;
+            let read = || {
+                db.write_keys(keys, |store| {
+                    store.x_read_group(group, consumer, keys, &ids, count, noack)
+                })
+            };
+            // Only reads of new entries block; replaying the pending list never does.
+            match block {
+                Some(deadline) if ids.iter().all(|id| *id == GroupReadId::New) => {
+                    wait_for_entries(deadline, read).await?
+                }
+                _ => read()?,
+            }
+        }
+    };
+    Ok(stream_reads(client.protocol, found))
+}
+
+/// Helper function: SAVE, written off the async workers.
+async fn save(args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("save", args, 0, 0)?;
+    tokio::task::spawn_blocking(|| snapshots().save(databases()))
+        .await
+        .map_err(|err| StoreError::Storage(err.to_string()))??;
+    Ok(ok())
+}
+
+/// Helper function: The keys of a command taking `numkeys key [key ...]` after its first
+/// argument.
+fn numkeys<'a>(name: &str, args: &'a [Vec<u8>]) -> Result<&'a [Vec<u8>], StoreError> {
+    let count = int(&args[0])?;
+    if count <= 0 {
+        return Err(StoreError::InvalidArgument(format!(
+            "numkeys should be greater than 0 in '{}' command",
+            name
+        )));
+    }
+    args.get(1..1 + count as usize).ok_or_else(|| {
+        StoreError::InvalidArgument("Number of keys can't be greater than number of args".into())
+    })
+}
+
+/// Helper function: The fields of `FIELDS numfields field [field ...]`.
+fn hash_fields(args: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, StoreError> {
+    if lower(&args[0]) != "fields" {
+        return Err(StoreError::InvalidArgument(
+            "Mandatory argument FIELDS is missing or not at the right position".into(),
+        ));
+    }
+    let fields = &args[2..];
+    if usize::try_from(int(&args[1])?).ok() != Some(fields.len()) {
+        return Err(StoreError::InvalidArgument(
+            "The `numfields` parameter must match the number of arguments".into(),
+        ));
+    }
+    Ok(fields.to_vec())
+}
+
+/// Helper function: ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...].
+fn z_add(db: &ShardedStore, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("zadd", args, 3, usize::MAX)?;
+    let mut opts = ZAddOptions::default();
+    let mut incr = false;
+    let mut i = 1;
+    while let Some(option) = args.get(i) {
+        match lower(option).as_str() {
+            "nx" => opts.nx = true,
+            "xx" => opts.xx = true,
+            "gt" => opts.gt = true,
+            "lt" => opts.lt = true,
+            "ch" => opts.ch = true,
+            "incr" => incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    let pairs = &args[i..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(syntax_error());
+    }
+    opts.validate().map_err(StoreError::InvalidArgument)?;
+    let members = pairs
+        .chunks(2)
+        .map(|pair| Ok((float(&pair[0])?, pair[1].clone())))
+        .collect::<Result<Vec<_>, StoreError>>()?;
+    let key = &args[0];
+    db.write(key, |store| {
+        if !incr {
+            return Ok(Reply::Integer(store.z_add(key, &members, opts)? as i64));
+        }
+        let [(increment, member)] = members.as_slice() else {
+            return Err(StoreError::InvalidArgument(
+                "INCR option supports a single increment-element pair".into(),
+            ));
+        };
+        // With INCR, a condition that is not met replies with a null instead of the score.
+        let current = store.z_score(key, member)?;
+        let updated = current.map(|score| score + increment);
+        let skipped = match current {
+            Some(_) => opts.nx,
+            None => opts.xx,
+        } || (opts.gt && updated.zip(current).is_some_and(|(new, old)| new <= old))
+            || (opts.lt && updated.zip(current).is_some_and(|(new, old)| new >= old));
+        if skipped {
+            return Ok(Reply::Null);
+        }
+        Ok(Reply::Double(store.z_incr_by(key, *increment, member)?))
+    })
+}
+
+/// How ZRANGE reads its start and stop.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RangeKind {
+    Index,
+    Score,
+    Lex,
+}
+
+/// Helper function: ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+/// [WITHSCORES], and its older forms, which fix `kind` and `rev`.
+fn z_range(
+    db: &ShardedStore,
+    protocol: Protocol,
+    name: &str,
+    args: &[Vec<u8>],
+    mut kind: RangeKind,
+    mut rev: bool,
+) -> Result<Reply, StoreError> {
+    arity(name, args, 3, usize::MAX)?;
+    let mut with_scores = false;
+    let mut limit = None;
+    let mut i = 3;
+    while i < args.len() {
+        match lower(&args[i]).as_str() {
+            "byscore" if name == "zrange" => kind = RangeKind::Score,
+            "bylex" if name == "zrange" => kind = RangeKind::Lex,
+            "rev" if name == "zrange" => rev = true,
+            "withscores" => with_scores = true,
+            "limit" if i + 2 < args.len() => {
+                let (offset, count) = (int(&args[i + 1])?, int(&args[i + 2])?);
+                limit = Some((offset.max(0) as usize, (count >= 0).then_some(count as usize)));
+                i += 2;
+            }
+            _ => return Err(syntax_error()),
+        }
+        i += 1;
+    }
+    if limit.is_some() && kind == RangeKind::Index {
+        return Err(StoreError::InvalidArgument(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                .into(),
+        ));
+    }
+    if with_scores && kind == RangeKind::Lex {
+        return Err(StoreError::InvalidArgument(
+            "syntax error, WITHSCORES not supported in combination with BYLEX".into(),
+        ));
+    }
+    // With REV, score and lex ranges are given from the upper bound down.
+    let (min, max) = if rev { (&args[2], &args[1]) } else { (&args[1], &args[2]) };
+    let by = match kind {
+        RangeKind::Index => ZRangeBy::Index(int(&args[1])?, int(&args[2])?),
+        RangeKind::Score => score_range(min, max)?,
+        RangeKind::Lex => lex_range(min, max)?,
+    };
+    let members = db.read(&args[0], |store| store.z_range(&args[0], &by, rev, limit))?;
+    Ok(scored(protocol, members, with_scores))
+}
+
+/// Helper function: A range of scores such as `(1 +inf`.
+fn score_range(min: &[u8], max: &[u8]) -> Result<ZRangeBy, StoreError> {
+    Ok(ZRangeBy::Score(
+        ScoreBound::parse(text(min)).map_err(StoreError::InvalidArgument)?,
+        ScoreBound::parse(text(max)).map_err(StoreError::InvalidArgument)?,
+    ))
+}
+
+/// Helper function: A range of members such as `[a (c`.
+fn lex_range(min: &[u8], max: &[u8]) -> Result<ZRangeBy, StoreError> {
+    Ok(ZRangeBy::Lex(
+        LexBound::parse(min).map_err(StoreError::InvalidArgument)?,
+        LexBound::parse(max).map_err(StoreError::InvalidArgument)?,
+    ))
+}
+
+/// Helper function: ZUNIONSTORE/ZINTERSTORE destination numkeys key [key ...]
+/// [WEIGHTS weight [weight ...]] [AGGREGATE SUM | MIN | MAX].
+fn z_store(db: &ShardedStore, name: &str, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity(name, args, 3, usize::MAX)?;
+    let destination = &args[0];
+    let count = int(&args[1])?;
+    if count <= 0 {
+        return Err(StoreError::InvalidArgument(format!(
+            "at least 1 input key is needed for '{}' command",
+            name
+        )));
+    }
+    let keys = args.get(2..2 + count as usize).ok_or_else(syntax_error)?;
+    let mut weights = Vec::new();
+    let mut aggregate = Aggregate::Sum;
+    let mut i = 2 + keys.len();
+    while i < args.len() {
+        match lower(&args[i]).as_str() {
+            "weights" if i + keys.len() < args.len() => {
+                weights = args[i + 1..=i + keys.len()]
+                    .iter()
+                    .map(|weight| {
+                        parse_score(text(weight)).map_err(|_| {
+                            StoreError::InvalidArgument("weight value is not a float".into())
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                i += keys.len();
+            }
+            "aggregate" if i + 1 < args.len() => {
+                aggregate = match lower(&args[i + 1]).as_str() {
+                    "sum" => Aggregate::Sum,
+                    "min" => Aggregate::Min,
+                    "max" => Aggregate::Max,
+                    _ => return Err(syntax_error()),
+                };
+                i += 1;
+            }
+            _ => return Err(syntax_error()),
+        }
+        i += 1;
+    }
+    let len = db.write_keys(keys.iter().chain([destination]), |store| {
+        if name == "zunionstore" {
+            store.z_union_store(destination, keys, &weights, aggregate)
+        } else {
+            store.z_inter_store(destination, keys, &weights, aggregate)
+        }
+    })?;
+    Ok(Reply::Integer(len as i64))
+}
+
+/// Helper function: XADD key [NOMKSTREAM] [MAXLEN | MINID [= | ~] threshold [LIMIT count]]
+/// <* | id> field value [field value ...].
+fn x_add(db: &ShardedStore, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("xadd", args, 4, usize::MAX)?;
+    let mut nomkstream = false;
+    let mut trim = None;
+    let mut i = 1;
+    while let Some(option) = args.get(i) {
+        match lower(option).as_str() {
+            "nomkstream" => {
+                nomkstream = true;
+                i += 1;
+            }
+            "maxlen" | "minid" => {
+                let (spec, used) = trim_spec(&args[i..])?;
+                trim = Some(spec);
+                i += used;
+            }
+            _ => break,
+        }
+    }
+    let id = args.get(i).ok_or_else(|| wrong_arity("xadd"))?;
+    let id = text(id).parse::<XAddId>().map_err(StoreError::InvalidArgument)?;
+    let fields = &args[i + 1..];
+    if fields.is_empty() || !fields.len().is_multiple_of(2) {
+        return Err(wrong_arity("xadd"));
+    }
+    let fields = fields.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+    match db.write(&args[0], |store| store.x_add(&args[0], id, fields, nomkstream, trim))? {
+        Some(id) => {
+            STREAM_NOTIFY.notify_waiters();
+            Ok(Reply::Bulk(id.to_string().into_bytes()))
+        }
+        // Only NOMKSTREAM on a missing key adds nothing.
+        None => Ok(Reply::Null),
+    }
+}
+
+/// Helper function: XGROUP CREATE key group id|$ [MKSTREAM], XGROUP DESTROY key group, and
+/// XGROUP CREATECONSUMER | DELCONSUMER key group consumer.
+fn x_group(db: &ShardedStore, args: &[Vec<u8>]) -> Result<Reply, StoreError> {
+    arity("xgroup", args, 1, usize::MAX)?;
+    let (name, args) = (&args[0], &args[1..]);
+    let sub = lower(name);
+    match sub.as_str() {
+        "create" => {
+            arity("xgroup|create", args, 3, 4)?;
+            let mkstream = match args.get(3) {
+                None => false,
+                Some(option) if lower(option) == "mkstream" => true,
+                Some(_) => return Err(syntax_error()),
+            };
+            let id = match text(&args[2]) {
+                "$" => None,
+                id => Some(id.parse::<StreamId>().map_err(StoreError::InvalidArgument)?),
+            };
+            let group = text(&args[1]);
+            db.write(&args[0], |store| store.x_group_create(&args[0], group, id, mkstream))?;
+            Ok(ok())
+        }
+        "destroy" => {
+            arity("xgroup|destroy", args, 2, 2)?;
+            let group = text(&args[1]);
+            let destroyed = db.write(&args[0], |store| store.x_group_destroy(&args[0], group))?;
+            Ok(Reply::Integer(destroyed as i64))
+        }
+        "createconsumer" | "delconsumer" => {
+            arity(format!("xgroup|{}", sub).as_str(), args, 3, 3)?;
+            let (group, consumer) = (text(&args[1]), text(&args[2]));
+            let count = db.write(&args[0], |store| {
+                if sub == "createconsumer" {
+                    store.x_group_create_consumer(&args[0], group, consumer).map(usize::from)
+                } else {
+                    store.x_group_del_consumer(&args[0], group, consumer)
+                }
+            })?;
+            Ok(Reply::Integer(count as i64))
+        }
+        _ => Err(unknown_subcommand("XGROUP", name)),
+    }
+}
+
+/// Helper function: Parse stream IDs.
+fn stream_ids(args: &[Vec<u8>]) -> Result<Vec<StreamId>, StoreError> {
+    args.iter().map(|id| text(id).parse().map_err(StoreError::InvalidArgument)).collect()
+}
+
+/// Helper function: Parse `MAXLEN | MINID [= | ~] threshold [LIMIT count]` at the start of
+/// `args`, returning the trimming request and the number of arguments it took.
+fn trim_spec(args: &[Vec<u8>]) -> Result<(TrimSpec, usize), StoreError> {
+    let mut i = 1;
+    let approximate = match args.get(1).map(Vec::as_slice) {
+        Some(b"~") => true,
+        Some(b"=") => false,
+        _ => {
+            i -= 1;
+            false
+        }
+    };
+    i += 1;
+    let threshold = args.get(i).ok_or_else(syntax_error)?;
+    let strategy = if lower(&args[0]) == "maxlen" {
+        TrimStrategy::MaxLen(usize::try_from(int(threshold)?).map_err(|_| {
+            StoreError::InvalidArgument("The MAXLEN argument must be >= 0.".into())
+        })?)
+    } else {
+        TrimStrategy::MinId(text(threshold).parse().map_err(StoreError::InvalidArgument)?)
+    };
+    i += 1;
+    let mut limit = 0;
+    if args.get(i).is_some_and(|option| lower(option) == "limit") {
+        let count = args.get(i + 1).ok_or_else(syntax_error)?;
+        if !approximate {
+            return Err(StoreError::InvalidArgument(
+                "syntax error, LIMIT cannot be used without the special ~ option".into(),
+            ));
+        }
+        limit = usize::try_from(int(count)?)
+            .map_err(|_| StoreError::InvalidArgument("The LIMIT argument must be >= 0.".into()))?;
+        i += 2;
+    }
+    Ok((TrimSpec { strategy, approximate, limit }, i))
+}
+
+/// Helper function: The MATCH, COUNT and (if `with_type`) TYPE options of the SCAN family.
+fn scan_options(
+    args: &[Vec<u8>],
+    with_type: bool,
+) -> Result<(ScanOptions, Option<String>), StoreError> {
+    let mut opts = ScanOptions { pattern: None, count: 0 };
+    let mut type_name = None;
+    if !args.len().is_multiple_of(2) {
+        return Err(syntax_error());
+    }
+    for option in args.chunks(2) {
+        match lower(&option[0]).as_str() {
+            "match" => opts.pattern = Some(option[1].clone()),
+            "count" => {
+                opts.count = usize::try_from(int(&option[1])?)
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(syntax_error)?
+            }
+            "type" if with_type => type_name = Some(lower(&option[1])),
+            _ => return Err(syntax_error()),
+        }
+    }
+    Ok((opts, type_name))
+}
+
+/// Helper function: Parse a SCAN cursor.
+fn cursor(arg: &[u8]) -> Result<u64, StoreError> {
+    text(arg).parse().map_err(|_| StoreError::InvalidArgument("invalid cursor".into()))
+}
+
+/// Helper function: A page of the SCAN family: the next cursor and the items.
+fn scan_reply(cursor: u64, items: Reply) -> Reply {
+    Reply::Array(vec![Reply::Bulk(cursor.to_string().into_bytes()), items])
+}
+
+/// Helper function: A stream entry: its ID and its fields and values.
+fn entry_reply((id, fields): StreamEntry) -> Reply {
+    let fields = fields.into_iter().flat_map(|(field, value)| [field, value]).collect();
+    Reply::Array(vec![Reply::Bulk(id.to_string().into_bytes()), bulks(fields)])
+}
+
+/// Helper function: The entries XREAD and XREADGROUP found, by stream: a map in RESP3, an
+/// array of pairs in RESP2, and a null if there are none.
+fn stream_reads(protocol: Protocol, streams: Vec<StreamRead>) -> Reply {
+    if streams.is_empty() {
+        return Reply::NullArray;
+    }
+    let streams = streams.into_iter().map(|(key, entries)| {
+        (Reply::Bulk(key), Reply::Array(entries.into_iter().map(entry_reply).collect()))
+    });
+    match protocol {
+        Protocol::Resp3 => Reply::Map(streams.collect()),
+        Protocol::Resp2 => Reply::Array(
+            streams.map(|(key, entries)| Reply::Array(vec![key, entries])).collect(),
+        ),
+    }
+}
+
+/// Helper function: Sorted set members, with their scores if `with_scores` is set.
+fn scored(protocol: Protocol, members: Vec<(Vec<u8>, f64)>, with_scores: bool) -> Reply {
+    if !with_scores {
+        return bulks(members.into_iter().map(|(member, _)| member).collect());
+    }
+    pairs(
+        protocol,
+        members
+            .into_iter()
+            .map(|(member, score)| (Reply::Bulk(member), Reply::Double(score)))
+            .collect(),
+    )
+}
+
+/// Helper function: Pairs such as members and scores: one array per pair in RESP3, a flat
+/// array in RESP2.
+fn pairs(protocol: Protocol, pairs: Vec<(Reply, Reply)>) -> Reply {
+    match protocol {
+        Protocol::Resp3 => Reply::Array(
+            pairs.into_iter().map(|(first, second)| Reply::Array(vec![first, second])).collect(),
+        ),
+        Protocol::Resp2 => {
+            Reply::Array(pairs.into_iter().flat_map(|(first, second)| [first, second]).collect())
+        }
+    }
+}
+
+/// Helper function: A score as ZSCAN replies it, as a string.
+fn format_score(score: f64) -> Vec<u8> {
+    crate::storage::string_value::format_float(score).into_bytes()
+}
+
+/// Helper function: Fail unless the command has between `min` and `max` arguments.
+fn arity(name: &str, args: &[Vec<u8>], min: usize, max: usize) -> Result<(), StoreError> {
+    if (min..=max).contains(&args.len()) {
+        Ok(())
+    } else {
+        Err(wrong_arity(name))
+    }
+}
+
+/// Helper function: The error of a command called with the wrong number of arguments.
+fn wrong_arity(name: impl AsRef<str>) -> StoreError {
+    StoreError::InvalidArgument(format!(
+        "wrong number of arguments for '{}' command",
+        name.as_ref()
+    ))
+}
+
+/// Helper function: The error of a command this server does not know.
+fn unknown_command(full: &[Vec<u8>]) -> StoreError {
+    let mut message = format!(
+        "unknown command '{}', with args beginning with: ",
+        String::from_utf8_lossy(&full[0])
+    );
+    for arg in &full[1..] {
+        message.push_str(&format!("'{}' ", String::from_utf8_lossy(arg)));
+    }
+    StoreError::InvalidArgument(message)
+}
+
+/// Helper function: The error of a subcommand this server does not know.
+fn unknown_subcommand(command: &str, sub: &[u8]) -> StoreError {
+    StoreError::InvalidArgument(format!(
+        "unknown subcommand '{}'. Try {} HELP.",
+        String::from_utf8_lossy(sub),
+        command
+    ))
+}
+
+/// Helper function: The error of malformed options.
+fn syntax_error() -> StoreError {
+    StoreError::InvalidArgument("syntax error".into())
+}
+
+/// Helper function: An argument lowercased, for matching command names and options.
+fn lower(arg: &[u8]) -> String {
+    String::from_utf8_lossy(arg).to_ascii_lowercase()
+}
+
+/// Helper function: A numeric argument as text. Invalid UTF-8 reads as the empty string,
+/// which every number parser rejects.
+fn text(arg: &[u8]) -> &str {
+    std::str::from_utf8(arg).unwrap_or_default()
+}
+
+/// Helper function: Parse an integer argument.
+fn int(arg: &[u8]) -> Result<i64, StoreError> {
+    text(arg).parse().map_err(|_| StoreError::NotInteger)
+}
+
+/// Helper function: Parse a count that must not be negative.
+fn positive(arg: &[u8]) -> Result<usize, StoreError> {
+    usize::try_from(int(arg)?)
+        .map_err(|_| StoreError::InvalidArgument("value is out of range, must be positive".into()))
+}
+
+/// Helper function: Parse a float argument, accepting `inf` and `-inf`.
+fn float(arg: &[u8]) -> Result<f64, StoreError> {
+    parse_score(text(arg)).map_err(|_| StoreError::NotFloat)
+}
+
+/// Helper function: The `OK` status.
+fn ok() -> Reply {
+    Reply::Simple("OK".into())
+}
+
+/// Helper function: A value, or null if there is none.
+fn bulk_or_null(value: Option<Vec<u8>>) -> Reply {
+    value.map_or(Reply::Null, Reply::Bulk)
+}
+
+/// Helper function: An array of values.
+fn bulks(values: Vec<Vec<u8>>) -> Reply {
+    Reply::Array(values.into_iter().map(Reply::Bulk).collect())
+}
+
+/// Helper function: Set members; an array in RESP2.
+fn set_of(members: Vec<Vec<u8>>) -> Reply {
+    Reply::Set(members.into_iter().map(Reply::Bulk).collect())
+}
@@ -0,0 +1,841 @@
+// src/storage/aof.rs
+//
+// Append-only file persistence for the in-memory engine.
+// Every command that changes a database appends one entry to the log: the keys it changed,
+// each as deleted, with its new value and deadline if the command created or replaced it, or
+// with the edits it made if it only changed the key in place, so an LPUSH onto a long list
+// logs the pushed values rather than the list. Logging what a command did rather than its
+// arguments makes replay deterministic (SPOP or INCRBYFLOAT replay exactly), turns relative
+// TTLs into wall-clock deadlines, and keeps multi-key commands atomic, since an entry is the
+// same batch a durable engine would be handed.
+//
+// The file starts with `AOF_MAGIC`, followed by frames of
+// `<payload length: u32 LE><CRC-32 of the payload: u32 LE><payload>`; a payload is the
+// database index, the number of writes and the writes, encoded with `codec`. A crash can
+// leave the last frame partly written: loading drops it, truncates the file and reports it.
+// Damage anywhere else fails the load, as the rest of the log cannot be trusted.
+//
+// An entry that could not be written or synced is cut off the file and fails the command.
+// Under `everysec`, a failed background sync fails every write until a sync succeeds, since
+// the writes acknowledged meanwhile may not be on disk.
+//
+// The log is replayed before the server accepts requests. A rewrite, started by BGREWRITEAOF
+// or once the log outgrew its last rewrite, copies the current dataset to a new file one
+// shard at a time while entries keep going to the old one. The writes to shards already
+// copied also go to a buffer, which is appended to the new file before it atomically replaces
+// the old one; writes to the other shards are part of the copy, and replaying their edits
+// twice would apply them twice.
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use tokio::task::JoinHandle;
+
+use crate::storage::codec::{self, put_bytes, put_deadline, put_f64, put_varint, Decoder};
+use crate::storage::databases::Databases;
+use crate::storage::engine::{BatchOp, Edit, Record, StorageEngine, DEFAULT_DATA_DIR};
+use crate::storage::error::StoreError;
+use crate::storage::eviction::parse_memory_size;
+use crate::storage::sharded::shard_index;
+use crate::storage::ttl_store::{StoreValue, TTLStore};
+use crate::storage::util::{io_error, lock, rename_durably};
+
+/// Name of the log in the data directory when `REDIO_APPENDFILENAME` is not set.
+pub const DEFAULT_AOF_FILENAME: &str = "appendonly.aof";
+
+/// First bytes of every append-only file; the digit is the format version.
+pub const AOF_MAGIC: &[u8] = b"REDIOAOF1";
+
+/// Keys per entry when a rewrite stores the dataset.
+const REWRITE_BATCH: usize = 128;
+
+/// Tags of the writes in an entry.
+const PUT: u8 = 0;
+const DELETE: u8 = 1;
+const UPDATE: u8 = 2;
+
+/// Tags of the edits in an update.
+const DEADLINE: u8 = 0;
+const PUSH: u8 = 1;
+const POP: u8 = 2;
+const SET_ADD: u8 = 3;
+const SET_REMOVE: u8 = 4;
+const HASH_SET: u8 = 5;
+const HASH_UPDATE: u8 = 6;
+const HASH_DEADLINE: u8 = 7;
+const HASH_REMOVE: u8 = 8;
+const ZADD: u8 = 9;
+const ZREMOVE: u8 = 10;
+
+/// When appended entries are flushed to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// After every entry, before the command replies; no acknowledged write is ever lost.
+    Always,
+    /// Once per second in the background; a crash loses at most about a second of writes.
+    EverySec,
+    /// Whenever the operating system flushes its buffers.
+    No,
+}
+
+impl std::str::FromStr for FsyncPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "always" => Ok(FsyncPolicy::Always),
+            "everysec" => Ok(FsyncPolicy::EverySec),
+            "no" => Ok(FsyncPolicy::No),
+            _ => Err(format!("invalid fsync policy '{}'", s)),
+        }
+    }
+}
+
+/// Where the log lives, how often it is synced and when it is rewritten.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AofConfig {
+    /// Path of the log; its directory is created if missing.
+    pub path: PathBuf,
+    pub fsync: FsyncPolicy,
+    /// Growth since the last rewrite, in percent, that triggers an automatic rewrite;
+    /// 0 disables automatic rewrites.
+    pub rewrite_percentage: u64,
+    /// Size in bytes below which the log is never rewritten automatically.
+    pub rewrite_min_size: u64,
+}
+
+impl AofConfig {
+    /// A configuration with the default policies for the log at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        AofConfig {
+            path: path.into(),
+            fsync: FsyncPolicy::EverySec,
+            rewrite_percentage: 100,
+            rewrite_min_size: 64 * 1024 * 1024,
+        }
+    }
+
+    /// Reads the configuration from `REDIO_DATA_DIR` and `REDIO_APPENDFILENAME` (the log's
+    /// directory and name), `REDIO_APPENDFSYNC` (`always`, `everysec` or `no`),
+    /// `REDIO_AUTO_AOF_REWRITE_PERCENTAGE` and `REDIO_AUTO_AOF_REWRITE_MIN_SIZE` (a size
+    /// such as `64mb`), falling back to the defaults for missing values.
+    pub fn from_env() -> Result<Self, String> {
+        let dir = env::var("REDIO_DATA_DIR").unwrap_or_else(|_| DEFAULT_DATA_DIR.to_string());
+        let name =
+            env::var("REDIO_APPENDFILENAME").unwrap_or_else(|_| DEFAULT_AOF_FILENAME.to_string());
+        let mut config = AofConfig::new(Path::new(&dir).join(name));
+        if let Ok(value) = env::var("REDIO_APPENDFSYNC") {
+            config.fsync = value.parse()?;
+        }
+        if let Ok(value) = env::var("REDIO_AUTO_AOF_REWRITE_PERCENTAGE") {
+            config.rewrite_percentage = value
+                .parse()
+                .map_err(|_| format!("invalid REDIO_AUTO_AOF_REWRITE_PERCENTAGE '{}'", value))?;
+        }
+        if let Ok(value) = env::var("REDIO_AUTO_AOF_REWRITE_MIN_SIZE") {
+            config.rewrite_min_size = parse_memory_size(&value)? as u64;
+        }
+        Ok(config)
+    }
+}
+
+/// What loading a log found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AofLoadReport {
+    /// Number of entries replayed.
+    pub entries: usize,
+    /// Offset and length of the partly written tail that was dropped, if any.
+    pub truncated: Option<(u64, u64)>,
+}
+
+/// A write read back from the log.
+#[derive(Debug, Clone)]
+pub enum LoggedWrite {
+    /// A key and its new record.
+    Put(Vec<u8>, Record),
+    /// A deleted key.
+    Delete(Vec<u8>),
+    /// A key and the edits made to it in place.
+    Update(Vec<u8>, Vec<Edit>),
+}
+
+/// Encodes the writes one command made to `database` as a frame of the log.
+pub fn encode_entry(database: usize, batch: &[BatchOp<'_>]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    put_varint(&mut payload, database as u64);
+    put_varint(&mut payload, batch.len() as u64);
+    for op in batch {
+        match *op {
+            BatchOp::Put {
+                key,
+                value,
+                expire_at,
+            } => {
+                payload.push(PUT);
+                put_bytes(&mut payload, key);
+                put_bytes(&mut payload, &codec::encode_record(value, expire_at));
+            }
+            BatchOp::Delete { key } => {
+                payload.push(DELETE);
+                put_bytes(&mut payload, key);
+            }
+            BatchOp::Update { key, edits } => {
+                payload.push(UPDATE);
+                put_bytes(&mut payload, key);
+                put_varint(&mut payload, edits.len() as u64);
+                for edit in edits {
+                    encode_edit(edit, &mut payload);
+                }
+            }
+        }
+    }
+    let mut frame = Vec::with_capacity(payload.len() + 8);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&codec::crc32(&payload).to_le_bytes());
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Reads a log, calling `visit` with the database and the writes of every entry in order.
+/// A partly written last entry is skipped and reported; any other damage is an error.
+pub fn read_log(
+    bytes: &[u8],
+    visit: &mut dyn FnMut(usize, Vec<LoggedWrite>) -> Result<(), StoreError>,
+) -> Result<AofLoadReport, StoreError> {
+    let mut report = AofLoadReport::default();
+    if bytes.len() < AOF_MAGIC.len() && AOF_MAGIC.starts_with(bytes) {
+        // Crashed while creating the file.
+        report.truncated = (!bytes.is_empty()).then_some((0, bytes.len() as u64));
+        return Ok(report);
+    }
+    if !bytes.starts_with(AOF_MAGIC) {
+        return Err(StoreError::Storage("not an append-only file".into()));
+    }
+    let mut offset = AOF_MAGIC.len();
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        let torn = Some((offset as u64, rest.len() as u64));
+        if rest.len() < 8 {
+            report.truncated = torn;
+            break;
+        }
+        let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(rest[4..8].try_into().unwrap());
+        if rest.len() - 8 < len {
+            report.truncated = torn;
+            break;
+        }
+        let payload = &rest[8..8 + len];
+        if codec::crc32(payload) != checksum {
+            // Only the last entry can have been cut short by a crash.
+            if 8 + len == rest.len() {
+                report.truncated = torn;
+                break;
+            }
+            return Err(damaged(offset, "checksum mismatch".into()));
+        }
+        let (database, writes) =
+            decode_entry(payload).map_err(|err| damaged(offset, err.to_string()))?;
+        visit(database, writes)?;
+        report.entries += 1;
+        offset += 8 + len;
+    }
+    Ok(report)
+}
+
+/// Helper function: Decode the payload of a frame.
+fn decode_entry(payload: &[u8]) -> Result<(usize, Vec<LoggedWrite>), StoreError> {
+    let mut decoder = Decoder::new(payload);
+    let database = decoder.varint()? as usize;
+    let count = decoder.len()?;
+    let mut writes = Vec::with_capacity(count);
+    for _ in 0..count {
+        let tag = decoder.u8()?;
+        let key = decoder.bytes()?;
+        writes.push(match tag {
+            PUT => LoggedWrite::Put(key, codec::decode_record(&decoder.bytes()?)?),
+            DELETE => LoggedWrite::Delete(key),
+            UPDATE => {
+                let count = decoder.len()?;
+                let mut edits = Vec::with_capacity(count);
+                for _ in 0..count {
+                    edits.push(decode_edit(&mut decoder)?);
+                }
+                LoggedWrite::Update(key, edits)
+            }
+            tag => return Err(codec::corrupt(&format!("unknown write tag {}", tag))),
+        });
+    }
+    if !decoder.is_empty() {
+        return Err(codec::corrupt("trailing bytes"));
+    }
+    Ok((database, writes))
+}
+
+/// Helper function: Append the encoding of an edit.
+fn encode_edit(edit: &Edit, out: &mut Vec<u8>) {
+    match edit {
+        Edit::Deadline(expire_at) => {
+            out.push(DEADLINE);
+            put_deadline(out, *expire_at);
+        }
+        Edit::Push { front, values } => {
+            out.push(PUSH);
+            out.push(u8::from(*front));
+            put_varint(out, values.len() as u64);
+            for value in values {
+                put_bytes(out, value);
+            }
+        }
+        Edit::Pop { front } => {
+            out.push(POP);
+            out.push(u8::from(*front));
+        }
+        Edit::SetAdd(member) => {
+            out.push(SET_ADD);
+            put_bytes(out, member);
+        }
+        Edit::SetRemove(member) => {
+            out.push(SET_REMOVE);
+            put_bytes(out, member);
+        }
+        Edit::HashSet { field, value } => {
+            out.push(HASH_SET);
+            put_bytes(out, field);
+            put_bytes(out, value);
+        }
+        Edit::HashUpdate { field, value } => {
+            out.push(HASH_UPDATE);
+            put_bytes(out, field);
+            put_bytes(out, value);
+        }
+        Edit::HashDeadline { field, expire_at } => {
+            out.push(HASH_DEADLINE);
+            put_bytes(out, field);
+            put_deadline(out, *expire_at);
+        }
+        Edit::HashRemove(field) => {
+            out.push(HASH_REMOVE);
+            put_bytes(out, field);
+        }
+        Edit::ZAdd { member, score } => {
+            out.push(ZADD);
+            put_bytes(out, member);
+            put_f64(out, *score);
+        }
+        Edit::ZRemove(member) => {
+            out.push(ZREMOVE);
+            put_bytes(out, member);
+        }
+    }
+}
+
+/// Helper function: Decode an edit written by `encode_edit`.
+fn decode_edit(decoder: &mut Decoder<'_>) -> Result<Edit, StoreError> {
+    let edit = match decoder.u8()? {
+        DEADLINE => Edit::Deadline(decoder.deadline()?),
+        PUSH => {
+            let front = decoder.u8()? != 0;
+            let count = decoder.len()?;
+            let mut values = Vec::with_capacity(count);
+            for _ in 0..count {
+                values.push(decoder.bytes()?);
+            }
+            Edit::Push { front, values }
+        }
+        POP => Edit::Pop { front: decoder.u8()? != 0 },
+        SET_ADD => Edit::SetAdd(decoder.bytes()?),
+        SET_REMOVE => Edit::SetRemove(decoder.bytes()?),
+        HASH_SET => Edit::HashSet { field: decoder.bytes()?, value: decoder.bytes()? },
+        HASH_UPDATE => Edit::HashUpdate { field: decoder.bytes()?, value: decoder.bytes()? },
+        HASH_DEADLINE => Edit::HashDeadline {
+            field: decoder.bytes()?,
+            expire_at: decoder.deadline()?,
+        },
+        HASH_REMOVE => Edit::HashRemove(decoder.bytes()?),
+        ZADD => {
+            let member = decoder.bytes()?;
+            let score = decoder.f64()?;
+            if score.is_nan() {
+                return Err(codec::corrupt("NaN score"));
+            }
+            Edit::ZAdd { member, score }
+        }
+        ZREMOVE => Edit::ZRemove(decoder.bytes()?),
+        tag => return Err(codec::corrupt(&format!("unknown edit tag {}", tag))),
+    };
+    Ok(edit)
+}
+
+/// Helper function: Error for a log damaged at `offset`.
+fn damaged(offset: usize, reason: String) -> StoreError {
+    StoreError::Storage(format!(
+        "append-only file damaged at offset {}: {}",
+        offset, reason
+    ))
+}
+
+/// Helper function: Apply logged writes to a store.
+fn apply(store: &mut TTLStore, writes: &[LoggedWrite]) -> Result<(), StoreError> {
+    let batch: Vec<BatchOp<'_>> = writes
+        .iter()
+        .map(|write| match write {
+            LoggedWrite::Put(key, (value, expire_at)) => BatchOp::Put {
+                key,
+                value,
+                expire_at: *expire_at,
+            },
+            LoggedWrite::Delete(key) => BatchOp::Delete { key },
+            LoggedWrite::Update(key, edits) => BatchOp::Update { key, edits },
+        })
+        .collect();
+    // Deadlines are applied as logged, whether or not they passed since.
+    store.set_replaying(true);
+    let result = store.write_batch(&batch);
+    store.set_replaying(false);
+    result
+}
+
+/// An open append-only file, shared by the engines of every database.
+pub struct Aof {
+    config: AofConfig,
+    state: Mutex<AofState>,
+    /// Keyspace of each database as replayed at open, until its engine loads it.
+    replayed: Mutex<HashMap<usize, TTLStore>>,
+    report: AofLoadReport,
+    rewriting: AtomicBool,
+}
+
+/// The file being appended to, and its bookkeeping.
+struct AofState {
+    file: File,
+    /// Bytes in the file.
+    size: u64,
+    /// Size after the last load or rewrite, the baseline of automatic rewrites.
+    base_size: u64,
+    /// Whether entries were appended since the last fsync.
+    unsynced: bool,
+    /// Why the last background fsync failed, until one succeeds; appends fail meanwhile.
+    sync_error: Option<String>,
+    /// The writes to append to the new file while a rewrite runs.
+    rewrite_buffer: Option<RewriteBuffer>,
+}
+
+/// The writes made while a rewrite runs to the shards it already copied.
+struct RewriteBuffer {
+    /// The writes, encoded as entries.
+    entries: Vec<u8>,
+    /// Number of shards of each database, to find the shard of a key.
+    shard_counts: Vec<usize>,
+    /// The database and shard the rewrite copies next.
+    next: (usize, usize),
+}
+
+impl RewriteBuffer {
+    /// Helper method: Whether the rewrite already copied the shard of `key` in `database`,
+    /// so a write to it has to go to the new file as well.
+    fn copied(&self, database: usize, key: &[u8]) -> bool {
+        match self.shard_counts.get(database) {
+            Some(&count) => (database, shard_index(key, count)) < self.next,
+            None => true,
+        }
+    }
+}
+
+impl Aof {
+    /// Opens the log described by `config`, creating it if needed, and replays it.
+    /// A partly written last entry is cut off the file and reported.
+    pub fn open(config: AofConfig) -> Result<Arc<Aof>, StoreError> {
+        if let Some(dir) = config
+            .path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&config.path)
+            .map_err(io_error)?;
+        let bytes = fs::read(&config.path).map_err(io_error)?;
+        let mut replayed: HashMap<usize, TTLStore> = HashMap::new();
+        let report = read_log(&bytes, &mut |database, writes| {
+            apply(replayed.entry(database).or_default(), &writes)
+        })?;
+
+        let mut size = bytes.len() as u64;
+        if let Some((offset, len)) = report.truncated {
+            log::warn!(
+                "Dropped a partly written entry of {} bytes at offset {} of {}",
+                len,
+                offset,
+                config.path.display()
+            );
+            file.set_len(offset).map_err(io_error)?;
+            size = offset;
+        }
+        if size == 0 {
+            file.write_all(AOF_MAGIC).map_err(io_error)?;
+            file.sync_all().map_err(io_error)?;
+            size = AOF_MAGIC.len() as u64;
+        }
+        log::info!(
+            "Replayed {} entries from {}",
+            report.entries,
+            config.path.display()
+        );
+        Ok(Arc::new(Aof {
+            config,
+            state: Mutex::new(AofState {
+                file,
+                size,
+                base_size: size,
+                unsynced: false,
+                sync_error: None,
+                rewrite_buffer: None,
+            }),
+            replayed: Mutex::new(replayed),
+            report,
+            rewriting: AtomicBool::new(false),
+        }))
+    }
+
+    /// The configuration the log was opened with.
+    pub fn config(&self) -> &AofConfig {
+        &self.config
+    }
+
+    /// What loading the log found when it was opened.
+    pub fn load_report(&self) -> AofLoadReport {
+        self.report
+    }
+
+    /// Size of the log in bytes.
+    pub fn size(&self) -> u64 {
+        lock(&self.state).size
+    }
+
+    /// Whether a rewrite is running.
+    pub fn is_rewriting(&self) -> bool {
+        self.rewriting.load(Ordering::Acquire)
+    }
+
+    /// The engine logging the changes of logical database `index`.
+    pub fn database(self: &Arc<Self>, index: usize) -> AofEngine {
+        AofEngine {
+            aof: Arc::clone(self),
+            database: index,
+        }
+    }
+
+    /// Drops what was replayed for databases no engine loaded, e.g. because fewer databases
+    /// are configured than when the entries were logged.
+    pub fn finish_loading(&self) {
+        for (index, _) in lock(&self.replayed).drain() {
+            log::warn!(
+                "Ignoring the logged keys of database {}, which is not configured",
+                index
+            );
+        }
+    }
+
+    /// Appends the writes one command made to `database`, syncing them first under the
+    /// `always` policy. Fails while the last background sync failed.
+    pub fn append(&self, database: usize, batch: &[BatchOp<'_>]) -> Result<(), StoreError> {
+        let frame = encode_entry(database, batch);
+        let mut state = lock(&self.state);
+        if let Some(err) = &state.sync_error {
+            return Err(StoreError::Storage(format!(
+                "the append-only file could not be synced: {}",
+                err
+            )));
+        }
+        let mut written = state.file.write_all(&frame);
+        if written.is_ok() && self.config.fsync == FsyncPolicy::Always {
+            written = state.file.sync_data();
+        }
+        if let Err(err) = written {
+            // Cut off whatever part of the entry was written, so the command's retry does
+            // not log it twice and later entries stay readable.
+            let size = state.size;
+            let _ = state.file.set_len(size);
+            return Err(io_error(err));
+        }
+        state.size += frame.len() as u64;
+        state.unsynced |= self.config.fsync == FsyncPolicy::EverySec;
+        if let Some(buffer) = &mut state.rewrite_buffer {
+            let copied: Vec<BatchOp<'_>> = batch
+                .iter()
+                .filter(|op| buffer.copied(database, op.key()))
+                .copied()
+                .collect();
+            if !copied.is_empty() {
+                buffer.entries.extend_from_slice(&encode_entry(database, &copied));
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the entries appended since the last call to disk, without blocking appends
+    /// meanwhile; the `everysec` policy runs it once a second. If it fails, appends fail
+    /// until a later call succeeds.
+    pub fn sync(&self) -> Result<(), StoreError> {
+        let file = {
+            let mut state = lock(&self.state);
+            if !state.unsynced {
+                return Ok(());
+            }
+            state.unsynced = false;
+            state.file.try_clone()
+        };
+        let synced = file.and_then(|file| file.sync_data()).map_err(io_error);
+        let mut state = lock(&self.state);
+        match &synced {
+            Ok(()) => state.sync_error = None,
+            Err(err) => {
+                state.unsynced = true;
+                state.sync_error = Some(err.to_string());
+            }
+        }
+        synced
+    }
+
+    /// Whether the log grew enough since the last rewrite for an automatic one.
+    pub fn needs_rewrite(&self) -> bool {
+        let percentage = self.config.rewrite_percentage;
+        if percentage == 0 || self.is_rewriting() {
+            return false;
+        }
+        let state = lock(&self.state);
+        state.size >= self.config.rewrite_min_size
+            && state.size >= state.base_size + state.base_size * percentage / 100
+    }
+
+    /// Rewrites the log from the current content of `databases`, blocking until it is done.
+    /// Fails if a rewrite is already running.
+    pub fn rewrite(&self, databases: &Databases) -> Result<(), StoreError> {
+        self.begin_rewrite(databases)?;
+        self.finish_rewrite(databases)
+    }
+
+    /// Helper method: Claim the rewrite and start buffering entries for the new file.
+    fn begin_rewrite(&self, databases: &Databases) -> Result<(), StoreError> {
+        if self.rewriting.swap(true, Ordering::AcqRel) {
+            return Err(StoreError::InvalidArgument(
+                "Background append only file rewriting already in progress".into(),
+            ));
+        }
+        lock(&self.state).rewrite_buffer = Some(RewriteBuffer {
+            entries: Vec::new(),
+            shard_counts: databases.all().iter().map(|db| db.shard_count()).collect(),
+            next: (0, 0),
+        });
+        Ok(())
+    }
+
+    /// Helper method: Write the new file and swap it in, then release the rewrite.
+    fn finish_rewrite(&self, databases: &Databases) -> Result<(), StoreError> {
+        let mut path = self.config.path.clone().into_os_string();
+        path.push(".rewrite");
+        let path = PathBuf::from(path);
+        let result = self
+            .write_dataset(&path, databases)
+            .and_then(|file| self.install(file, &path));
+        if result.is_err() {
+            lock(&self.state).rewrite_buffer = None;
+            let _ = fs::remove_file(&path);
+        }
+        self.rewriting.store(false, Ordering::Release);
+        result
+    }
+
+    /// Helper method: Write every live key of `databases` to a new log at `path`, one shard
+    /// at a time so commands keep running.
+    fn write_dataset(&self, path: &Path, databases: &Databases) -> Result<File, StoreError> {
+        let mut out = BufWriter::new(File::create(path).map_err(io_error)?);
+        out.write_all(AOF_MAGIC).map_err(io_error)?;
+        for (index, db) in databases.all().iter().enumerate() {
+            for shard in 0..db.shard_count() {
+                let mut records: Vec<(Vec<u8>, StoreValue, _)> = Vec::new();
+                let mut guard = db.lock_shard(shard);
+                guard.for_each_record(b"", &mut |key, (value, expire_at)| {
+                    records.push((key.to_vec(), value, expire_at));
+                })?;
+                // Writes to the shard are buffered from now on, as the copy has the rest.
+                if let Some(buffer) = &mut lock(&self.state).rewrite_buffer {
+                    buffer.next = (index, shard + 1);
+                }
+                drop(guard);
+                for chunk in records.chunks(REWRITE_BATCH) {
+                    let batch: Vec<BatchOp<'_>> = chunk
+                        .iter()
+                        .map(|(key, value, expire_at)| BatchOp::Put {
+                            key,
+                            value,
+                            expire_at: *expire_at,
+                        })
+                        .collect();
+                    out.write_all(&encode_entry(index, &batch))
+                        .map_err(io_error)?;
+                }
+            }
+        }
+        out.into_inner().map_err(|err| io_error(err.into_error()))
+    }
+
+    /// Helper method: Append the entries logged during the rewrite to the new file and
+    /// atomically replace the log with it.
+    fn install(&self, mut file: File, path: &Path) -> Result<(), StoreError> {
+        let mut state = lock(&self.state);
+        let buffer = state.rewrite_buffer.take().map_or_else(Vec::new, |buffer| buffer.entries);
+        file.write_all(&buffer).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        rename_durably(path, &self.config.path)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.config.path)
+            .map_err(io_error)?;
+        let size = file.metadata().map_err(io_error)?.len();
+        *state = AofState {
+            file,
+            size,
+            base_size: size,
+            unsynced: false,
+            sync_error: None,
+            rewrite_buffer: None,
+        };
+        Ok(())
+    }
+
+    /// Helper method: Replay the entries of database `index` from the file.
+    fn replay(&self, index: usize) -> Result<TTLStore, StoreError> {
+        // Holding the state lock, no entry is half written while the file is read.
+        let _state = lock(&self.state);
+        let bytes = fs::read(&self.config.path).map_err(io_error)?;
+        let mut store = TTLStore::new();
+        read_log(&bytes, &mut |database, writes| {
+            if database == index {
+                apply(&mut store, &writes)?;
+            }
+            Ok(())
+        })?;
+        Ok(store)
+    }
+}
+
+/// The log of one logical database. Writes are appended; reads replay the log, so they are
+/// only meant for loading the keyspace at startup.
+pub struct AofEngine {
+    aof: Arc<Aof>,
+    database: usize,
+}
+
+impl AofEngine {
+    /// Helper method: Run `op` on this database's keyspace as logged: the copy replayed at
+    /// open while it is current, or the file replayed again.
+    fn with_keyspace<R>(&self, op: impl FnOnce(&mut TTLStore) -> R) -> Result<R, StoreError> {
+        if let Some(store) = lock(&self.aof.replayed).get_mut(&self.database) {
+            return Ok(op(store));
+        }
+        Ok(op(&mut self.aof.replay(self.database)?))
+    }
+}
+
+impl StorageEngine for AofEngine {
+    fn read_record(&self, key: &[u8]) -> Result<Option<Record>, StoreError> {
+        self.with_keyspace(|store| store.read_record(key))?
+    }
+
+    fn write_record(
+        &self,
+        key: &[u8],
+        value: &StoreValue,
+        expire_at: Option<SystemTime>,
+    ) -> Result<(), StoreError> {
+        self.write_batch(&[BatchOp::Put {
+            key,
+            value,
+            expire_at,
+        }])
+    }
+
+    fn delete_record(&self, key: &[u8]) -> Result<bool, StoreError> {
+        let existed = self.read_record(key)?.is_some();
+        self.write_batch(&[BatchOp::Delete { key }])?;
+        Ok(existed)
+    }
+
+    fn set_deadline(
+        &self,
+        key: &[u8],
+        expire_at: Option<SystemTime>,
+    ) -> Result<bool, StoreError> {
+        match self.read_record(key)? {
+            Some((value, _)) => self.write_record(key, &value, expire_at).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn for_each_record(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], Record),
+    ) -> Result<(), StoreError> {
+        // Loading the whole keyspace at startup is the last use of the copy replayed at open.
+        let replayed = if prefix.is_empty() {
+            lock(&self.aof.replayed).remove(&self.database)
+        } else {
+            None
+        };
+        match replayed {
+            Some(mut store) => store.for_each_record(prefix, visit),
+            None => self.with_keyspace(|store| store.for_each_record(prefix, visit))?,
+        }
+    }
+
+    fn write_batch(&self, batch: &[BatchOp<'_>]) -> Result<(), StoreError> {
+        // The copy replayed at open no longer matches the log.
+        lock(&self.aof.replayed).remove(&self.database);
+        self.aof.append(self.database, batch)
+    }
+}
+
+/// Starts the background task that syncs the log once a second under the `everysec` policy
+/// and starts automatic rewrites.
+pub fn spawn_background(aof: Arc<Aof>, databases: &'static Databases) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            ticks.tick().await;
+            if aof.config.fsync == FsyncPolicy::EverySec {
+                let syncing = Arc::clone(&aof);
+                if let Ok(Err(err)) = tokio::task::spawn_blocking(move || syncing.sync()).await {
+                    log::error!("Failed to sync the append-only file: {}", err);
+                }
+            }
+            if aof.needs_rewrite() && spawn_rewrite(&aof, databases).is_ok() {
+                log::info!("Started an automatic rewrite of the append-only file");
+            }
+        }
+    })
+}
+
+/// Starts rewriting the log from the current content of `databases` on a blocking thread.
+/// Fails if a rewrite is already running.
+pub fn spawn_rewrite(aof: &Arc<Aof>, databases: &'static Databases) -> Result<(), StoreError> {
+    aof.begin_rewrite(databases)?;
+    let aof = Arc::clone(aof);
+    tokio::task::spawn_blocking(move || match aof.finish_rewrite(databases) {
+        Ok(()) => log::info!("Rewrote the append-only file in {} bytes", aof.size()),
+        Err(err) => log::error!("Failed to rewrite the append-only file: {}", err),
+    });
+    Ok(())
+}
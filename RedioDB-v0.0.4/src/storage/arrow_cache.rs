@@ -2,6 +2,7 @@
 //
 // A stub for an in-memory cache using Apache Arrow.
 // In production, this might cache schema metadata or precomputed query plans.
+#[derive(Default)]
 pub struct ArrowCache;
 
 impl ArrowCache {
@@ -0,0 +1,379 @@
+// src/storage/backup.rs
+//
+// Online backups of the whole dataset to a local directory, and restoring them at startup.
+// A backup is taken at a single point in time. With the RocksDB engine it is a RocksDB
+// checkpoint (hard links to the immutable SST files where the filesystem allows it, so it is
+// cheap even for large datasets), taken while every shard of every database is locked in the
+// usual (database, shard) order, with keys counted afterwards in a RocksDB snapshot taken
+// with it; with the other engines it is a snapshot, in the format SAVE writes, captured
+// without stopping writers (see `snapshot::capture`).
+//
+// Every backup directory holds `MANIFEST_FILENAME`, a JSON file recording the format version,
+// the kind of backup, when it was taken, the number of keys of each database and the size
+// and CRC-32 of every file. The manifest is written last, so a directory without one is an
+// unfinished backup; `verify` checks a backup against its manifest without a server.
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage::codec;
+use crate::storage::databases::Databases;
+use crate::storage::engine::{EngineKind, DEFAULT_DATA_DIR};
+use crate::storage::error::StoreError;
+use crate::storage::sharded::ShardedStore;
+use crate::storage::snapshot::{self, DEFAULT_SNAPSHOT_FILENAME};
+use crate::storage::util::{io_error, read_marker, rename_durably, write_marker};
+
+/// Name of the manifest in every backup directory.
+pub const MANIFEST_FILENAME: &str = "MANIFEST.json";
+
+/// Version of the manifest format written; older versions stay readable.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// Directory of a RocksDB checkpoint within a backup.
+pub const ROCKSDB_DIR: &str = "rocksdb";
+
+/// Name of the marker in the data directory recording when the last backup restored at
+/// startup was taken, so a restart does not restore it again over newer writes.
+pub const RESTORE_MARKER_FILENAME: &str = "backup-restored";
+
+/// Directory in the data directory that backups are written to when `REDIO_BACKUP_DIR` is
+/// not set.
+pub const DEFAULT_BACKUP_DIR: &str = "backups";
+
+/// Where backups requested over the network may be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupConfig {
+    /// Every backup is a directory below this one.
+    pub root: PathBuf,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig { root: Path::new(DEFAULT_DATA_DIR).join(DEFAULT_BACKUP_DIR) }
+    }
+}
+
+impl BackupConfig {
+    /// Reads the backup root from `REDIO_BACKUP_DIR`, falling back to `backups` in
+    /// `REDIO_DATA_DIR`.
+    pub fn from_env() -> Self {
+        let root = env::var("REDIO_BACKUP_DIR").map(PathBuf::from).unwrap_or_else(|_| {
+            let dir = env::var("REDIO_DATA_DIR").unwrap_or_else(|_| DEFAULT_DATA_DIR.to_string());
+            Path::new(&dir).join(DEFAULT_BACKUP_DIR)
+        });
+        BackupConfig { root }
+    }
+
+    /// The directory of the backup named `name`, a relative path that must stay below the
+    /// backup root.
+    pub fn resolve(&self, name: &str) -> Result<PathBuf, StoreError> {
+        let path = Path::new(name);
+        let contained = path.components().all(|part| matches!(part, Component::Normal(_)));
+        if name.is_empty() || !contained {
+            return Err(StoreError::InvalidArgument(format!(
+                "backup directory {} must be a relative path below the backup root",
+                name
+            )));
+        }
+        Ok(self.root.join(path))
+    }
+}
+
+/// What a backup is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupKind {
+    /// A snapshot of every database, restorable with any engine.
+    Snapshot,
+    /// A RocksDB checkpoint, restorable with the RocksDB engine.
+    RocksDb,
+}
+
+/// The number of keys a database held when the backup was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatabaseKeys {
+    pub db: usize,
+    pub keys: usize,
+}
+
+/// A file of a backup, by path relative to the backup directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub path: String,
+    pub size: u64,
+    pub crc32: u32,
+}
+
+/// The manifest of a backup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub kind: BackupKind,
+    /// When the backup was taken, in Unix milliseconds.
+    pub created: u64,
+    /// Key counts of the databases holding keys.
+    pub databases: Vec<DatabaseKeys>,
+    pub files: Vec<BackupFile>,
+}
+
+impl BackupManifest {
+    /// When the backup was taken.
+    pub fn created(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.created)
+    }
+
+    /// Number of keys in the backup.
+    pub fn keys(&self) -> usize {
+        self.databases.iter().map(|db| db.keys).sum()
+    }
+
+    /// Total size of the backup's files, in bytes.
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+}
+
+/// Writes a consistent copy of every database to `dir`, which must be missing or empty,
+/// and returns its manifest.
+pub fn backup(databases: &Databases, dir: &Path) -> Result<BackupManifest, StoreError> {
+    let is_empty = match fs::read_dir(dir) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => true,
+        Err(err) => return Err(io_error(err)),
+    };
+    if !is_empty {
+        return Err(StoreError::InvalidArgument(format!(
+            "backup directory {} is not empty",
+            dir.display()
+        )));
+    }
+    fs::create_dir_all(dir).map_err(io_error)?;
+
+    let created = SystemTime::now();
+    let (kind, counts) = match databases.rocksdb() {
+        Some(rocksdb) => {
+            // Writes are persisted before their shard is unlocked, so with every shard
+            // locked the checkpoint, and a RocksDB snapshot taken with it, hold whole
+            // commands only. Keys are counted in the snapshot once writers can go on.
+            let mut guards: Vec<_> = databases.all().iter().map(ShardedStore::lock_all).collect();
+            for shard in guards.iter_mut().flatten() {
+                shard.commit()?;
+            }
+            let snapshot = rocksdb.snapshot();
+            rocksdb.checkpoint(&dir.join(ROCKSDB_DIR))?;
+            drop(guards);
+            let counts = (0..databases.len())
+                .map(|index| snapshot.count_live(index))
+                .collect::<Result<Vec<usize>, _>>()?;
+            (BackupKind::RocksDb, counts)
+        }
+        None => {
+            let captured = snapshot::capture(databases)?;
+            let counts = captured.iter().map(|(_, records)| records.len()).collect();
+            snapshot::write_snapshot(&dir.join(DEFAULT_SNAPSHOT_FILENAME), &captured, created)?;
+            (BackupKind::Snapshot, counts)
+        }
+    };
+
+    let mut paths = Vec::new();
+    list_files(dir, dir, &mut paths)?;
+    let files = paths
+        .into_iter()
+        .map(|path| {
+            let (size, crc32) = checksum(&dir.join(&path))?;
+            Ok(BackupFile { path, size, crc32 })
+        })
+        .collect::<Result<_, StoreError>>()?;
+    let manifest = BackupManifest {
+        version: BACKUP_FORMAT_VERSION,
+        kind,
+        created: codec::system_time_ms(created),
+        databases: counts
+            .into_iter()
+            .enumerate()
+            .filter(|(_, keys)| *keys > 0)
+            .map(|(db, keys)| DatabaseKeys { db, keys })
+            .collect(),
+        files,
+    };
+    write_manifest(dir, &manifest)?;
+    Ok(manifest)
+}
+
+/// Reads the manifest of the backup in `dir`.
+pub fn read_manifest(dir: &Path) -> Result<BackupManifest, StoreError> {
+    let path = dir.join(MANIFEST_FILENAME);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(StoreError::Storage(format!(
+                "no backup manifest in {}",
+                dir.display()
+            )))
+        }
+        Err(err) => return Err(io_error(err)),
+    };
+    let manifest: BackupManifest = serde_json::from_slice(&bytes)
+        .map_err(|err| StoreError::Storage(format!("invalid backup manifest: {}", err)))?;
+    if manifest.version == 0 || manifest.version > BACKUP_FORMAT_VERSION {
+        return Err(StoreError::Storage(format!(
+            "unsupported backup version {}",
+            manifest.version
+        )));
+    }
+    Ok(manifest)
+}
+
+/// Checks the backup in `dir` against its manifest: every file must be present with its
+/// recorded size and checksum, and a snapshot must hold the recorded number of keys.
+/// Returns the manifest of a sound backup.
+pub fn verify(dir: &Path) -> Result<BackupManifest, StoreError> {
+    let manifest = read_manifest(dir)?;
+    for file in &manifest.files {
+        let path = dir.join(&file.path);
+        let (size, crc32) = match checksum(&path) {
+            Ok(found) => found,
+            Err(_) if !path.exists() => {
+                return Err(StoreError::Storage(format!("backup file {} is missing", file.path)))
+            }
+            Err(err) => return Err(err),
+        };
+        if size != file.size || crc32 != file.crc32 {
+            return Err(StoreError::Storage(format!("backup file {} is damaged", file.path)));
+        }
+    }
+    if manifest.kind == BackupKind::Snapshot {
+        let bytes = fs::read(dir.join(DEFAULT_SNAPSHOT_FILENAME)).map_err(io_error)?;
+        let mut counts: Vec<DatabaseKeys> = Vec::new();
+        snapshot::read_snapshot(&bytes, &mut |db, _, _| {
+            match counts.last_mut() {
+                Some(last) if last.db == db => last.keys += 1,
+                _ => counts.push(DatabaseKeys { db, keys: 1 }),
+            }
+            Ok(())
+        })?;
+        if counts != manifest.databases {
+            return Err(StoreError::Storage(
+                "backup snapshot does not hold the keys its manifest records".into(),
+            ));
+        }
+    }
+    Ok(manifest)
+}
+
+/// First step of restoring the backup in `dir` at startup, before the databases are
+/// opened: verifies it and, for a RocksDB backup, puts the checkpoint in place of the
+/// engine's database, moving the current one aside. Returns the backup's manifest, or None
+/// if the `marker` file records that this backup was restored already.
+pub fn restore_files(
+    dir: &Path,
+    engine: &EngineKind,
+    marker: &Path,
+) -> Result<Option<BackupManifest>, StoreError> {
+    let manifest = verify(dir)?;
+    if read_marker(marker)? == Some(manifest.created.to_string()) {
+        return Ok(None);
+    }
+    if manifest.kind == BackupKind::RocksDb {
+        let EngineKind::RocksDb(config) = engine else {
+            return Err(StoreError::InvalidArgument(
+                "a RocksDB backup can only be restored with the rocksdb engine".into(),
+            ));
+        };
+        if config.path.exists() {
+            let mut aside = config.path.as_os_str().to_owned();
+            aside.push(format!(".before-restore-{}", codec::system_time_ms(SystemTime::now())));
+            fs::rename(&config.path, &aside).map_err(io_error)?;
+            log::warn!("Moved the database at {} aside to {:?}", config.path.display(), aside);
+        }
+        copy_dir(&dir.join(ROCKSDB_DIR), &config.path)?;
+    }
+    Ok(Some(manifest))
+}
+
+/// Second step of restoring the backup in `dir` at startup, once the databases are open:
+/// replaces their keys with a snapshot backup's. A RocksDB backup was already loaded with
+/// the engine's database.
+pub fn restore_keys(
+    dir: &Path,
+    manifest: &BackupManifest,
+    databases: &Databases,
+) -> Result<(), StoreError> {
+    if manifest.kind == BackupKind::Snapshot {
+        databases.flush_all(false)?;
+        snapshot::load(&dir.join(DEFAULT_SNAPSHOT_FILENAME), databases)?;
+    }
+    Ok(())
+}
+
+/// Last step of restoring a backup at startup, once its keys are durable: records in the
+/// `marker` file when the backup was taken, for `restore_files` to skip it from then on.
+pub fn record_restore(marker: &Path, manifest: &BackupManifest) -> Result<(), StoreError> {
+    write_marker(marker, &manifest.created.to_string())
+}
+
+/// Helper function: Write the manifest under a temporary name and rename it into place.
+fn write_manifest(dir: &Path, manifest: &BackupManifest) -> Result<(), StoreError> {
+    let json = serde_json::to_vec_pretty(manifest)
+        .map_err(|err| StoreError::Storage(err.to_string()))?;
+    let temp = dir.join(format!("{}.tmp", MANIFEST_FILENAME));
+    fs::write(&temp, json).map_err(io_error)?;
+    File::open(&temp).and_then(|file| file.sync_all()).map_err(io_error)?;
+    rename_durably(&temp, &dir.join(MANIFEST_FILENAME))
+}
+
+/// Helper function: Collect the paths of the files under `dir`, relative to `root` and
+/// sorted.
+fn list_files(root: &Path, dir: &Path, paths: &mut Vec<String>) -> Result<(), StoreError> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(io_error)?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            list_files(root, &path, paths)?;
+        } else {
+            let relative = path.strip_prefix(root).unwrap();
+            let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+            paths.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// Helper function: The size and CRC-32 of the file at `path`.
+fn checksum(path: &Path) -> Result<(u64, u32), StoreError> {
+    let mut file = File::open(path).map_err(io_error)?;
+    let mut buf = vec![0; 64 * 1024];
+    let (mut size, mut crc) = (0u64, 0u32);
+    loop {
+        let read = file.read(&mut buf).map_err(io_error)?;
+        if read == 0 {
+            return Ok((size, crc));
+        }
+        size += read as u64;
+        crc = codec::crc32_update(crc, &buf[..read]);
+    }
+}
+
+/// Helper function: Copy the directory tree at `from` to `to`.
+fn copy_dir(from: &Path, to: &Path) -> Result<(), StoreError> {
+    fs::create_dir_all(to).map_err(io_error)?;
+    for entry in fs::read_dir(from).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else {
+            fs::copy(&path, &target).map_err(io_error)?;
+        }
+    }
+    Ok(())
+}
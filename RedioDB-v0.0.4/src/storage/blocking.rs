@@ -0,0 +1,385 @@
+// src/storage/blocking.rs
+//
+// Blocking list pops (BLPOP/BRPOP/BLMOVE).
+// Clients that find every list they watch empty are parked here in arrival order. Pushes hand
+// elements directly to the oldest waiter while the shard lock is held, so a newer client can
+// never take an element ahead of one that has been waiting longer. A client watching keys in
+// several shards waits in each of them; the first shard to serve it takes its delivery slot.
+// BLMOVE into a key of another shard is never half done by a push: the client is only woken,
+// and moves the element itself with both shards locked.
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+use crate::storage::error::StoreError;
+use crate::storage::multi_key::MultiKey;
+use crate::storage::sharded::{LockedShard, ShardedStore};
+
+/// What a blocked client does with the first element that becomes available.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockedOp {
+    /// BLPOP (`front`) or BRPOP: pop the element.
+    Pop { front: bool },
+    /// BLMOVE: pop the element and push it onto one end of `destination`.
+    Move {
+        from_front: bool,
+        destination: Vec<u8>,
+        to_front: bool,
+    },
+    /// BLMOVE into a key of another shard: take nothing, only wake the client with an
+    /// empty delivery so it can move the element itself.
+    Wake,
+}
+
+/// An element handed to a blocked client: the key it was popped from and the element.
+pub type Delivery = (Vec<u8>, Vec<u8>);
+
+/// The sending end of a blocked client's delivery, shared by every shard the client waits
+/// on. Serving the client takes the sender out, so no other shard can serve it again.
+pub type DeliverySlot = Arc<Mutex<Option<oneshot::Sender<Delivery>>>>;
+
+#[derive(Debug)]
+struct Waiter {
+    keys: Vec<Vec<u8>>,
+    op: BlockedOp,
+    slot: DeliverySlot,
+    /// Whether this registration counts towards `len`; a client waiting in several shards
+    /// is only counted in one of them.
+    counted: bool,
+}
+
+/// FIFO registry of the clients blocked on list keys.
+#[derive(Debug, Default)]
+pub struct BlockedClients {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    /// Waiter IDs per key, oldest first.
+    queues: HashMap<Vec<u8>, VecDeque<u64>>,
+}
+
+impl BlockedClients {
+    /// Parks a client on `keys`. Returns its ID and the receiving end of its delivery.
+    pub fn register(
+        &mut self,
+        keys: Vec<Vec<u8>>,
+        op: BlockedOp,
+    ) -> (u64, oneshot::Receiver<Delivery>) {
+        let (sender, receiver) = oneshot::channel();
+        let id = self.register_slot(keys, op, Arc::new(Mutex::new(Some(sender))), true);
+        (id, receiver)
+    }
+
+    /// Parks a client that delivers through a slot it may share with other shards.
+    /// Only registrations with `counted` set are included in `len`. Returns its ID.
+    pub fn register_slot(
+        &mut self,
+        keys: Vec<Vec<u8>>,
+        op: BlockedOp,
+        slot: DeliverySlot,
+        counted: bool,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        for key in &keys {
+            let queue = self.queues.entry(key.clone()).or_default();
+            if !queue.contains(&id) {
+                queue.push_back(id);
+            }
+        }
+        self.waiters.insert(id, Waiter { keys, op, slot, counted });
+        id
+    }
+
+    /// Removes a client that timed out or went away.
+    /// Returns false if it was already served.
+    pub fn unregister(&mut self, id: u64) -> bool {
+        self.remove(id).is_some()
+    }
+
+    /// Removes the oldest waiter on `key` whose client is still connected.
+    pub fn pop_oldest(&mut self, key: &[u8]) -> Option<(BlockedOp, oneshot::Sender<Delivery>)> {
+        loop {
+            let id = *self.queues.get(key)?.front()?;
+            let Some(waiter) = self.remove(id) else {
+                // Queued IDs always have a waiter; never spin on a stray one.
+                self.queues.remove(key);
+                return None;
+            };
+            // Another shard may have served the client already.
+            let sender = waiter.slot.lock().unwrap_or_else(PoisonError::into_inner).take();
+            if let Some(sender) = sender.filter(|sender| !sender.is_closed()) {
+                return Some((waiter.op, sender));
+            }
+        }
+    }
+
+    /// Whether any client is blocked on `key`.
+    pub fn is_waiting_on(&self, key: &[u8]) -> bool {
+        self.queues.contains_key(key)
+    }
+
+    /// Keys that at least one client is blocked on.
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        self.queues.keys().cloned().collect()
+    }
+
+    /// Number of blocked clients that are still waiting to be served.
+    pub fn len(&self) -> usize {
+        self.waiters
+            .values()
+            .filter(|waiter| waiter.counted && waiter.slot.lock().is_ok_and(|slot| slot.is_some()))
+            .count()
+    }
+
+    /// Whether no client is blocked.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Helper method: Remove a waiter from every queue it sits in.
+    fn remove(&mut self, id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        for key in &waiter.keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|queued| *queued != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+}
+
+/// Unregisters a waiting client from every shard when its call is dropped, e.g. because the
+/// client disconnected.
+struct WaitGuard {
+    store: &'static ShardedStore,
+    /// Shard index and waiter ID of each registration.
+    registrations: Vec<(usize, u64)>,
+    op: BlockedOp,
+    receiver: oneshot::Receiver<Delivery>,
+    finished: bool,
+}
+
+impl WaitGuard {
+    /// Helper method: Leave every shard; returns an element that was handed over just
+    /// before, if any.
+    fn unregister(&mut self) -> Option<Delivery> {
+        for (index, id) in &self.registrations {
+            self.store.lock_shard(*index).unblock_client_slot(*id);
+        }
+        self.receiver.try_recv().ok()
+    }
+
+    /// Helper method: Stop waiting once a delivery arrived, leaving the queues of the
+    /// other shards too.
+    fn finish(&mut self) {
+        self.finished = true;
+        self.unregister();
+    }
+
+    /// Helper method: Whether a delivery only woke the client to move an element across
+    /// shards itself.
+    fn is_wake(&self, key: &[u8]) -> bool {
+        match &self.op {
+            BlockedOp::Move { destination, .. } => {
+                self.store.shard_of(key) != self.store.shard_of(destination)
+            }
+            _ => false,
+        }
+    }
+
+    /// Helper method: Pass on a wake-up this client will not act on, so the element it was
+    /// woken for goes to the next client waiting.
+    fn pass_on(&self, key: &[u8]) -> Result<(), StoreError> {
+        self.store.write(key, |shard| {
+            shard.wake_blocked(key);
+            Ok(())
+        })
+    }
+
+    /// Stops waiting; returns an element that was handed over just before, if any.
+    fn cancel(&mut self) -> Result<Option<Delivery>, StoreError> {
+        self.finished = true;
+        let Some(delivery) = self.unregister() else {
+            return Ok(None);
+        };
+        if self.is_wake(&delivery.0) {
+            self.pass_on(&delivery.0)?;
+            return Ok(None);
+        }
+        Ok(Some(delivery))
+    }
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Some((key, value)) = self.unregister() {
+            // Nobody is left to receive the element, so it goes back where it came from.
+            // A move within a shard already put it in its destination list. No client is
+            // left to report a failed write to either, so it is logged.
+            let requeued = match self.op {
+                BlockedOp::Pop { front } => self.store.write(&key, |shard| {
+                    shard.requeue(&key, value, front);
+                    Ok(())
+                }),
+                _ if self.is_wake(&key) => self.pass_on(&key),
+                _ => Ok(()),
+            };
+            if let Err(err) = requeued {
+                log::error!("Failed to persist an element a blocked client handed back: {}", err);
+            }
+        }
+    }
+}
+
+/// Pops from the first non-empty list in `keys`, or waits until a push serves this client or
+/// `timeout` elapses (`None` waits forever). Returns None on timeout.
+///
+/// Dropping the returned future unregisters the client; an element already handed to it is
+/// pushed back onto the list, so a disconnecting client never loses data.
+pub async fn blocking_pop(
+    store: &'static ShardedStore,
+    keys: Vec<Vec<u8>>,
+    op: BlockedOp,
+    timeout: Option<Duration>,
+) -> Result<Option<Delivery>, StoreError> {
+    if keys.is_empty() {
+        return Err(StoreError::InvalidArgument("no keys given".into()));
+    }
+    let destination_shard = match &op {
+        BlockedOp::Move { destination, .. } => Some(store.shard_of(destination)),
+        _ => None,
+    };
+    let mut indices: Vec<usize> = keys
+        .iter()
+        .map(|key| store.shard_of(key))
+        .chain(destination_shard)
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+
+    let deadline = match timeout {
+        Some(timeout) => Some(Instant::now().checked_add(timeout).ok_or_else(|| {
+            StoreError::InvalidArgument("timeout is out of range".into())
+        })?),
+        None => None,
+    };
+    loop {
+        let (registrations, receiver) = {
+            let mut shards = store.lock_shards(&indices);
+            let popped = pop_available(store, &mut shards, &keys, &op);
+            store.commit_shards(&mut shards)?;
+            if let Some(delivery) = popped? {
+                return Ok(Some(delivery));
+            }
+            let (sender, receiver) = oneshot::channel();
+            let slot: DeliverySlot = Arc::new(Mutex::new(Some(sender)));
+            let mut registrations = Vec::new();
+            for (index, shard) in shards.iter_mut() {
+                let watched: Vec<Vec<u8>> =
+                    keys.iter().filter(|key| store.shard_of(key) == *index).cloned().collect();
+                if watched.is_empty() {
+                    continue;
+                }
+                // Moves into another shard are made by this call once it is woken.
+                let local_op = match op {
+                    BlockedOp::Move { .. } if destination_shard != Some(*index) => BlockedOp::Wake,
+                    _ => op.clone(),
+                };
+                let counted = registrations.is_empty();
+                let id = shard.block_client_slot(watched, local_op, slot.clone(), counted);
+                registrations.push((*index, id));
+            }
+            (registrations, receiver)
+        };
+        let mut guard =
+            WaitGuard { store, registrations, op: op.clone(), receiver, finished: false };
+        let delivered = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, &mut guard.receiver).await.ok(),
+            None => Some((&mut guard.receiver).await),
+        };
+        match delivered {
+            Some(Ok(delivery)) => {
+                guard.finish();
+                if !guard.is_wake(&delivery.0) {
+                    return Ok(Some(delivery));
+                }
+                // Woken for an element in another shard: take it with both shards locked.
+                // If another client got to it first, wait again.
+            }
+            // Served clients are only dropped without an element when BLMOVE's destination
+            // holds another type.
+            Some(Err(_)) => {
+                guard.finish();
+                return Err(StoreError::WrongType);
+            }
+            None => return guard.cancel(),
+        }
+    }
+}
+
+/// Helper method: Pop from the first non-empty list in `keys` with the shards of every key
+/// (and of BLMOVE's destination) locked, moving the element across shards if needed. The
+/// destination's type is checked before anything is popped.
+fn pop_available(
+    store: &ShardedStore,
+    shards: &mut [LockedShard<'_>],
+    keys: &[Vec<u8>],
+    op: &BlockedOp,
+) -> Result<Option<Delivery>, StoreError> {
+    let position = |key: &[u8]| {
+        let index = store.shard_of(key);
+        shards.iter().position(|(locked, _)| *locked == index)
+    };
+    let positions: Vec<usize> = keys.iter().filter_map(|key| position(key)).collect();
+    let target = match op {
+        BlockedOp::Move { destination, .. } => position(destination),
+        _ => None,
+    };
+    let destination = match op {
+        BlockedOp::Move { destination, .. } => Some(destination),
+        _ => None,
+    };
+    for (key, position) in keys.iter().zip(&positions).chain(destination.zip(target.as_ref())) {
+        store.load_key(&mut shards[*position].1, key)?;
+    }
+    for (key, source) in keys.iter().zip(positions) {
+        if shards[source].1.l_len(key)? == 0 {
+            continue;
+        }
+        let (BlockedOp::Move { from_front, destination, to_front }, Some(target)) = (op, target)
+        else {
+            return shards[source].1.pop_first(std::slice::from_ref(key), op);
+        };
+        if target == source {
+            return shards[source].1.pop_first(std::slice::from_ref(key), op);
+        }
+        // Check the destination first so a wrong type leaves the source untouched.
+        shards[target].1.l_len(destination)?;
+        let pop = BlockedOp::Pop { front: *from_front };
+        let Some((key, value)) = shards[source].1.pop_first(std::slice::from_ref(key), &pop)?
+        else {
+            continue;
+        };
+        let values = std::slice::from_ref(&value);
+        let pushed = if *to_front {
+            shards[target].1.l_push(destination, values)
+        } else {
+            shards[target].1.r_push(destination, values)
+        };
+        if let Err(err) = pushed {
+            shards[source].1.requeue(&key, value, *from_front);
+            return Err(err);
+        }
+        return Ok(Some((key, value)));
+    }
+    Ok(None)
+}
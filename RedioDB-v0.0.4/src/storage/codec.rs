@@ -0,0 +1,323 @@
+// src/storage/codec.rs
+//
+// Binary encoding of values and deadlines for storage engines.
+// A record is a deadline followed by a typed value. Lengths and counts are LEB128 varints,
+// scores are little-endian IEEE 754 doubles, and deadlines are Unix milliseconds (0 for none),
+// since `Instant`s mean nothing once the process exits. Sets, hashes and sorted sets are
+// written in iteration order, so they come back in the order they were written.
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use indexmap::IndexSet;
+
+use crate::storage::error::StoreError;
+use crate::storage::hash::Hash;
+use crate::storage::sorted_set::SortedSet;
+use crate::storage::stream::Stream;
+use crate::storage::string_value::StringValue;
+use crate::storage::ttl_store::StoreValue;
+
+/// Type tags of encoded values.
+const RAW_STRING: u8 = 0;
+const INT_STRING: u8 = 1;
+const LIST: u8 = 2;
+const SET: u8 = 3;
+const HASH: u8 = 4;
+const SORTED_SET: u8 = 5;
+const STREAM: u8 = 6;
+
+/// Converts a deadline to Unix milliseconds; deadlines in the past map to now.
+pub fn to_unix_ms(deadline: Instant) -> u64 {
+    system_time_ms(to_system_time(deadline))
+}
+
+/// Converts a deadline to wall-clock time; deadlines in the past map to now.
+pub fn to_system_time(deadline: Instant) -> SystemTime {
+    SystemTime::now() + deadline.saturating_duration_since(Instant::now())
+}
+
+/// Converts a wall-clock time to a deadline; times in the past map to now.
+pub fn to_instant(at: SystemTime) -> Instant {
+    Instant::now() + at.duration_since(SystemTime::now()).unwrap_or_default()
+}
+
+/// Milliseconds between the Unix epoch and `at`.
+pub fn system_time_ms(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// CRC-32 (IEEE) lookup table, one entry per byte value.
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// Helper function: Build `CRC_TABLE` at compile time.
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+/// CRC-32 checksum of `bytes`, as used by zlib and gzip.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0, bytes)
+}
+
+/// Extends the CRC-32 checksum `crc` of some bytes to the bytes followed by `bytes`.
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Appends a varint.
+pub fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Appends a length-prefixed byte string.
+pub fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Appends a double.
+pub fn put_f64(out: &mut Vec<u8>, value: f64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Appends an optional wall-clock deadline as Unix milliseconds, 0 meaning none.
+pub fn put_deadline(out: &mut Vec<u8>, expire_at: Option<SystemTime>) {
+    put_varint(out, expire_at.map_or(0, |at| system_time_ms(at).max(1)));
+}
+
+/// Reads values written with the `put_*` functions, failing on truncated or malformed input.
+pub struct Decoder<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Starts reading `input` from the beginning.
+    pub fn new(input: &'a [u8]) -> Self {
+        Decoder { input }
+    }
+
+    /// Returns true once every byte was read.
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// Reads one byte.
+    pub fn u8(&mut self) -> Result<u8, StoreError> {
+        let (&byte, rest) = self.input.split_first().ok_or_else(truncated)?;
+        self.input = rest;
+        Ok(byte)
+    }
+
+    /// Reads a varint.
+    pub fn varint(&mut self) -> Result<u64, StoreError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(corrupt("varint is too long"))
+    }
+
+    /// Reads a count or length, checking it against the remaining input so corrupt data
+    /// cannot make the caller allocate huge buffers.
+    pub fn len(&mut self) -> Result<usize, StoreError> {
+        let len = self.varint()?;
+        if len > self.input.len() as u64 {
+            return Err(truncated());
+        }
+        Ok(len as usize)
+    }
+
+    /// Reads a length-prefixed byte string.
+    pub fn bytes(&mut self) -> Result<Vec<u8>, StoreError> {
+        let len = self.len()?;
+        let (bytes, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(bytes.to_vec())
+    }
+
+    /// Reads a length-prefixed UTF-8 string.
+    pub fn string(&mut self) -> Result<String, StoreError> {
+        String::from_utf8(self.bytes()?).map_err(|_| corrupt("invalid UTF-8 name"))
+    }
+
+    /// Reads a little-endian 64-bit integer.
+    pub fn i64(&mut self) -> Result<i64, StoreError> {
+        self.fixed().map(i64::from_le_bytes)
+    }
+
+    /// Reads a double.
+    pub fn f64(&mut self) -> Result<f64, StoreError> {
+        self.fixed().map(f64::from_le_bytes)
+    }
+
+    /// Reads a deadline written by `put_deadline`.
+    pub fn deadline(&mut self) -> Result<Option<SystemTime>, StoreError> {
+        Ok(match self.varint()? {
+            0 => None,
+            ms => Some(UNIX_EPOCH + Duration::from_millis(ms)),
+        })
+    }
+
+    /// Helper method: Read 8 raw bytes.
+    fn fixed(&mut self) -> Result<[u8; 8], StoreError> {
+        if self.input.len() < 8 {
+            return Err(truncated());
+        }
+        let (bytes, rest) = self.input.split_at(8);
+        self.input = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+}
+
+/// Error for data that does not decode.
+pub fn corrupt(reason: &str) -> StoreError {
+    StoreError::Storage(format!("corrupt value: {}", reason))
+}
+
+/// Helper function: Error for input that ends in the middle of a value.
+fn truncated() -> StoreError {
+    corrupt("unexpected end of data")
+}
+
+/// Encodes a value with its optional wall-clock deadline.
+pub fn encode_record(value: &StoreValue, expire_at: Option<SystemTime>) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_deadline(&mut out, expire_at);
+    encode_value(value, &mut out);
+    out
+}
+
+/// Decodes a record written by `encode_record`.
+pub fn decode_record(input: &[u8]) -> Result<(StoreValue, Option<SystemTime>), StoreError> {
+    let mut decoder = Decoder::new(input);
+    let expire_at = decoder.deadline()?;
+    let value = decode_value(&mut decoder)?;
+    if !decoder.is_empty() {
+        return Err(corrupt("trailing bytes"));
+    }
+    Ok((value, expire_at))
+}
+
+/// Appends the encoding of a value.
+pub fn encode_value(value: &StoreValue, out: &mut Vec<u8>) {
+    match value {
+        StoreValue::Simple(StringValue::Raw(raw)) => {
+            out.push(RAW_STRING);
+            put_bytes(out, raw);
+        }
+        StoreValue::Simple(StringValue::Int(num)) => {
+            out.push(INT_STRING);
+            out.extend_from_slice(&num.to_le_bytes());
+        }
+        StoreValue::List(list) => {
+            out.push(LIST);
+            put_varint(out, list.len() as u64);
+            for item in list {
+                put_bytes(out, item);
+            }
+        }
+        StoreValue::Set(set) => {
+            out.push(SET);
+            put_varint(out, set.len() as u64);
+            for member in set {
+                put_bytes(out, member);
+            }
+        }
+        StoreValue::Hash(hash) => {
+            out.push(HASH);
+            put_varint(out, hash.len() as u64);
+            for (field, value) in hash.iter() {
+                put_bytes(out, field);
+                put_bytes(out, value);
+                let deadline = hash.deadline(field).flatten();
+                put_varint(out, deadline.map_or(0, |at| to_unix_ms(at).max(1)));
+            }
+        }
+        StoreValue::SortedSet(zset) => {
+            out.push(SORTED_SET);
+            put_varint(out, zset.len() as u64);
+            for (member, score) in zset.iter() {
+                put_bytes(out, member);
+                put_f64(out, score);
+            }
+        }
+        StoreValue::Stream(stream) => {
+            out.push(STREAM);
+            stream.encode(out);
+        }
+    }
+}
+
+/// Decodes a value written by `encode_value`.
+pub fn decode_value(decoder: &mut Decoder<'_>) -> Result<StoreValue, StoreError> {
+    let value = match decoder.u8()? {
+        RAW_STRING => StoreValue::Simple(StringValue::Raw(decoder.bytes()?)),
+        INT_STRING => StoreValue::Simple(StringValue::Int(decoder.i64()?)),
+        LIST => {
+            let len = decoder.len()?;
+            let mut list = VecDeque::with_capacity(len);
+            for _ in 0..len {
+                list.push_back(decoder.bytes()?);
+            }
+            StoreValue::List(list)
+        }
+        SET => {
+            let len = decoder.len()?;
+            let mut set = IndexSet::with_capacity(len);
+            for _ in 0..len {
+                set.insert(decoder.bytes()?);
+            }
+            StoreValue::Set(set)
+        }
+        HASH => {
+            let mut hash = Hash::new();
+            for _ in 0..decoder.len()? {
+                let (field, value) = (decoder.bytes()?, decoder.bytes()?);
+                hash.insert(&field, &value);
+                if let ms @ 1.. = decoder.varint()? {
+                    hash.expire_at(&field, to_instant(UNIX_EPOCH + Duration::from_millis(ms)));
+                }
+            }
+            StoreValue::Hash(hash)
+        }
+        SORTED_SET => {
+            let mut zset = SortedSet::new();
+            for _ in 0..decoder.len()? {
+                let member = decoder.bytes()?;
+                let score = decoder.f64()?;
+                if score.is_nan() {
+                    return Err(corrupt("NaN score"));
+                }
+                zset.insert(&member, score);
+            }
+            StoreValue::SortedSet(zset)
+        }
+        STREAM => StoreValue::Stream(Stream::decode(decoder)?),
+        tag => return Err(corrupt(&format!("unknown type tag {}", tag))),
+    };
+    Ok(value)
+}
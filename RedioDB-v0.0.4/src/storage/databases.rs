@@ -0,0 +1,274 @@
+// src/storage/databases.rs
+//
+// Logical databases (SELECT/SWAPDB/MOVE/FLUSHALL).
+// The server holds a fixed number of independent sharded keyspaces, addressed by index or by
+// an optional name, so tenants, test fixtures and staging data can share one server.
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+
+use crate::storage::aof::Aof;
+use crate::storage::engine::EngineKind;
+use crate::storage::error::StoreError;
+use crate::storage::eviction::MemoryConfig;
+use crate::storage::rocksdb_store::RocksDBStore;
+use crate::storage::sharded::{ShardGuard, ShardedStore, DEFAULT_SHARDS};
+
+/// Number of databases when `REDIO_DATABASES` is not set, as in Redis.
+pub const DEFAULT_DATABASES: usize = 16;
+
+/// How many databases the server holds and what they are called.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    /// Number of databases, at least 1.
+    pub count: usize,
+    /// Names of the first databases, in index order; the rest are only reachable by index.
+    pub names: Vec<String>,
+    /// Number of lock-striped shards each database's keyspace is split into, at least 1.
+    pub shards: usize,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            count: DEFAULT_DATABASES,
+            names: Vec::new(),
+            shards: DEFAULT_SHARDS,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// Reads the configuration from `REDIO_DATABASES`, `REDIO_DATABASE_NAMES`
+    /// (comma-separated, naming databases 0, 1, ... in order) and `REDIO_SHARDS`.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = DatabaseConfig::default();
+        if let Ok(value) = env::var("REDIO_DATABASES") {
+            config.count = value
+                .parse::<usize>()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| format!("invalid number of databases '{}'", value))?;
+        }
+        if let Ok(value) = env::var("REDIO_DATABASE_NAMES") {
+            config.names = value
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect();
+        }
+        if let Ok(value) = env::var("REDIO_SHARDS") {
+            config.shards = value
+                .parse::<usize>()
+                .ok()
+                .filter(|shards| *shards > 0)
+                .ok_or_else(|| format!("invalid number of shards '{}'", value))?;
+        }
+        Ok(config)
+    }
+}
+
+/// The logical databases of a server, each its own sharded keyspace.
+///
+/// Locks are always taken in (database, shard) index order, so commands spanning shards or
+/// databases cannot deadlock.
+pub struct Databases {
+    stores: Vec<ShardedStore>,
+    names: HashMap<String, usize>,
+    /// The append-only file every change is logged to, if enabled.
+    aof: Option<Arc<Aof>>,
+    /// The RocksDB database every change is written to, if that is the engine.
+    rocksdb: Option<RocksDBStore>,
+}
+
+impl Databases {
+    /// Creates the databases described by `config`, in memory only. Naming more databases
+    /// than `count` creates one per name.
+    pub fn new(config: DatabaseConfig) -> Self {
+        let count = Self::count(&config);
+        let stores = (0..count).map(|_| ShardedStore::new(config.shards)).collect();
+        Self::with_stores(config, stores)
+    }
+
+    /// Creates the databases described by `config` on the given storage engine. The keys
+    /// of an append-only file are all loaded; those of RocksDB as commands need them.
+    pub fn open(config: DatabaseConfig, engine: &EngineKind) -> Result<Self, StoreError> {
+        let count = Self::count(&config);
+        match engine {
+            EngineKind::Memory => Ok(Self::new(config)),
+            EngineKind::Aof(aof_config) => {
+                let aof = Aof::open(aof_config.clone())?;
+                let stores = (0..count)
+                    .map(|index| {
+                        ShardedStore::with_engine(config.shards, Box::new(aof.database(index)))
+                    })
+                    .collect::<Result<_, _>>()?;
+                aof.finish_loading();
+                let mut databases = Self::with_stores(config, stores);
+                databases.aof = Some(aof);
+                Ok(databases)
+            }
+            EngineKind::RocksDb(rocksdb_config) => {
+                let rocksdb = RocksDBStore::open(rocksdb_config)?;
+                let stores = (0..count)
+                    .map(|index| {
+                        ShardedStore::backed_by(config.shards, Box::new(rocksdb.database(index)))
+                    })
+                    .collect();
+                let mut databases = Self::with_stores(config, stores);
+                databases.rocksdb = Some(rocksdb);
+                Ok(databases)
+            }
+        }
+    }
+
+    /// Helper method: Number of databases `config` describes.
+    fn count(config: &DatabaseConfig) -> usize {
+        config.count.max(config.names.len()).max(1)
+    }
+
+    /// Helper method: Name the given stores as `config` describes.
+    fn with_stores(config: DatabaseConfig, stores: Vec<ShardedStore>) -> Self {
+        Databases {
+            stores,
+            names: config
+                .names
+                .into_iter()
+                .enumerate()
+                .map(|(index, name)| (name, index))
+                .collect(),
+            aof: None,
+            rocksdb: None,
+        }
+    }
+
+    /// The append-only file, if enabled.
+    pub fn aof(&self) -> Option<&Arc<Aof>> {
+        self.aof.as_ref()
+    }
+
+    /// The RocksDB database, if that is the engine.
+    pub fn rocksdb(&self) -> Option<&RocksDBStore> {
+        self.rocksdb.as_ref()
+    }
+
+    /// Number of databases.
+    pub fn len(&self) -> usize {
+        self.stores.len()
+    }
+
+    /// Always false: there is at least one database.
+    pub fn is_empty(&self) -> bool {
+        self.stores.is_empty()
+    }
+
+    /// Resolves a database given by index ("3") or by name ("staging").
+    pub fn resolve(&self, selector: &str) -> Result<usize, StoreError> {
+        if let Ok(index) = selector.parse::<usize>() {
+            return if index < self.stores.len() {
+                Ok(index)
+            } else {
+                Err(StoreError::InvalidArgument("DB index is out of range".into()))
+            };
+        }
+        self.names
+            .get(selector)
+            .copied()
+            .ok_or_else(|| StoreError::InvalidArgument(format!("unknown database '{}'", selector)))
+    }
+
+    /// The database at `index`. Panics if it is out of range; use `resolve` first.
+    pub fn get(&self, index: usize) -> &ShardedStore {
+        &self.stores[index]
+    }
+
+    /// Every database, in index order.
+    pub fn all(&self) -> &[ShardedStore] {
+        &self.stores
+    }
+
+    /// Exchange the keys of two databases (SWAPDB). Not supported on RocksDB, where most
+    /// keys are not in memory and would have to be copied across.
+    pub fn swap(&self, first: usize, second: usize) -> Result<(), StoreError> {
+        if self.rocksdb.is_some() {
+            return Err(StoreError::InvalidArgument(
+                "SWAPDB is not supported by the RocksDB engine".into(),
+            ));
+        }
+        if first == second {
+            return Ok(());
+        }
+        let (a, b) = if first < second {
+            (self.stores[first].lock_all(), self.stores[second].lock_all())
+        } else {
+            let b = self.stores[second].lock_all();
+            (self.stores[first].lock_all(), b)
+        };
+        for (mut a, mut b) in a.into_iter().zip(b) {
+            a.swap_keyspace(&mut b);
+            a.commit()?;
+            b.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Move a key with its TTL from one database to another (MOVE).
+    /// Returns false if the key is missing in `from` or already exists in `to`.
+    pub fn move_key(&self, key: &[u8], from: usize, to: usize) -> Result<bool, StoreError> {
+        if from == to {
+            return Err(StoreError::InvalidArgument(
+                "source and destination objects are the same".into(),
+            ));
+        }
+        // Every database has the same number of shards, so the key keeps its shard index.
+        let shard = self.stores[from].shard_of(key);
+        let (mut source, mut target) = self.lock_pair(from, to, shard);
+        self.stores[from].load_key(&mut source, key)?;
+        self.stores[to].load_key(&mut target, key)?;
+        let moved = source.move_to(key, &mut target);
+        target.commit()?;
+        source.commit()?;
+        moved
+    }
+
+    /// Number of key writes and removals in every database since they were created.
+    pub fn changes(&self) -> u64 {
+        self.stores.iter().map(ShardedStore::changes).sum()
+    }
+
+    /// Remove every key of every database (FLUSHALL), optionally freeing them in the
+    /// background.
+    pub fn flush_all(&self, lazy: bool) -> Result<(), StoreError> {
+        for store in &self.stores {
+            store.flush(lazy)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the memory limit and eviction policy to every database.
+    pub fn set_memory_config(&self, memory: MemoryConfig) {
+        for store in &self.stores {
+            store.set_memory_config(memory);
+        }
+    }
+
+    /// Helper method: Lock the same shard of two distinct databases, always in database
+    /// order so concurrent SWAPDB and MOVE calls cannot deadlock. Returns the guards in
+    /// argument order.
+    fn lock_pair(
+        &self,
+        first: usize,
+        second: usize,
+        shard: usize,
+    ) -> (ShardGuard<'_>, ShardGuard<'_>) {
+        if first < second {
+            let a = self.stores[first].lock_shard(shard);
+            let b = self.stores[second].lock_shard(shard);
+            (a, b)
+        } else {
+            let b = self.stores[second].lock_shard(shard);
+            let a = self.stores[first].lock_shard(shard);
+            (a, b)
+        }
+    }
+}
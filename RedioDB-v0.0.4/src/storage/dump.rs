@@ -0,0 +1,55 @@
+// src/storage/dump.rs
+//
+// The serialization format of DUMP and RESTORE.
+// A payload is a record as encoded by `codec` (the key's deadline as wall-clock Unix
+// milliseconds, then its value), followed by the format version (u16 LE) and the CRC-64 of
+// everything before it (u64 LE), the same trailer as Redis's DUMP payloads. Payloads of a
+// newer version or with a wrong checksum are refused, so a damaged or foreign payload never
+// reaches the store.
+use std::time::SystemTime;
+
+use crate::storage::codec;
+use crate::storage::engine::Record;
+use crate::storage::error::StoreError;
+use crate::storage::rdb::crc64;
+use crate::storage::ttl_store::StoreValue;
+
+/// Version of the payloads written by DUMP.
+pub const DUMP_VERSION: u16 = 1;
+
+/// Serializes a value and its deadline (DUMP).
+pub fn serialize(value: &StoreValue, expire_at: Option<SystemTime>) -> Vec<u8> {
+    let mut payload = codec::encode_record(value, expire_at);
+    payload.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    let checksum = crc64(&payload);
+    payload.extend_from_slice(&checksum.to_le_bytes());
+    payload
+}
+
+/// Deserializes a payload written by `serialize`, checking its version and checksum.
+pub fn deserialize(payload: &[u8]) -> Result<Record, StoreError> {
+    let invalid = || StoreError::InvalidArgument("DUMP payload version or checksum are wrong".into());
+    let split = payload.len().checked_sub(2 + 8).ok_or_else(invalid)?;
+    let (body, checksum) = payload.split_at(split + 2);
+    if crc64(body) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+        return Err(invalid());
+    }
+    let (record, version) = body.split_at(split);
+    let version = u16::from_le_bytes(version.try_into().unwrap());
+    if version == 0 || version > DUMP_VERSION {
+        return Err(invalid());
+    }
+    codec::decode_record(record)
+        .map_err(|_| StoreError::InvalidArgument("Bad data format".into()))
+}
+
+/// Options of RESTORE.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RestoreOptions {
+    /// Replace the key if it exists (REPLACE); otherwise RESTORE fails with BUSYKEY.
+    pub replace: bool,
+    /// The TTL is a Unix time in milliseconds rather than a number of milliseconds (ABSTTL).
+    pub abs_ttl: bool,
+    /// Seconds the key counts as idle for, for LRU eviction (IDLETIME).
+    pub idle_time: Option<u64>,
+}
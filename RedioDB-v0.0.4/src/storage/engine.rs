@@ -0,0 +1,193 @@
+// src/storage/engine.rs
+//
+// Pluggable storage engines.
+// A `StorageEngine` stores typed values with optional wall-clock deadlines, iterates over them
+// by key prefix and applies batches of writes atomically. Engines are shared by every shard of
+// a database and take `&self`, so shards write through to them concurrently. A `TTLStore`
+// behind a lock is the in-memory engine; `RocksDBStore` is the durable one. Commands always
+// run against the in-memory shards; with a durable engine configured, every key a command
+// changed is written through to it, in one batch, before the shard locks are released and
+// before the command replies. The shards are loaded from it at startup, or, for an engine
+// too large for memory, one key at a time as commands need them. A key a command created,
+// replaced or removed is written whole; a key it only changed in place (a push onto a list,
+// a field set in a hash) is written as the `Edit`s it went through, so the cost of a write
+// follows the size of the change rather than the size of the value.
+use std::env;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::storage::aof::AofConfig;
+use crate::storage::error::StoreError;
+use crate::storage::rocksdb_store::RocksDBConfig;
+use crate::storage::ttl_store::StoreValue;
+
+/// Directory of the durable files when `REDIO_DATA_DIR` is not set.
+pub const DEFAULT_DATA_DIR: &str = "data";
+
+/// The directory of the durable files: `REDIO_DATA_DIR`, or `DEFAULT_DATA_DIR`.
+pub fn data_dir_from_env() -> PathBuf {
+    PathBuf::from(env::var("REDIO_DATA_DIR").unwrap_or_else(|_| DEFAULT_DATA_DIR.to_string()))
+}
+
+/// A stored value and the wall-clock time it expires at, if any.
+pub type Record = (StoreValue, Option<SystemTime>);
+
+/// A change made in place to a stored value, or to its deadline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// Replace the deadline of the key; None makes it persistent.
+    Deadline(Option<SystemTime>),
+    /// Push values onto the head (`front`) or the tail of a list, one after the other.
+    Push { front: bool, values: Vec<Vec<u8>> },
+    /// Remove the element at the head (`front`) or the tail of a list.
+    Pop { front: bool },
+    /// Add a member to a set.
+    SetAdd(Vec<u8>),
+    /// Remove a member from a set.
+    SetRemove(Vec<u8>),
+    /// Set a hash field, clearing its TTL.
+    HashSet { field: Vec<u8>, value: Vec<u8> },
+    /// Replace the value of a hash field, keeping its TTL.
+    HashUpdate { field: Vec<u8>, value: Vec<u8> },
+    /// Replace the deadline of a hash field; None makes it persistent.
+    HashDeadline {
+        field: Vec<u8>,
+        expire_at: Option<SystemTime>,
+    },
+    /// Remove a hash field.
+    HashRemove(Vec<u8>),
+    /// Add a sorted set member or update its score.
+    ZAdd { member: Vec<u8>, score: f64 },
+    /// Remove a sorted set member.
+    ZRemove(Vec<u8>),
+}
+
+/// One write of an atomic batch.
+#[derive(Debug, Clone, Copy)]
+pub enum BatchOp<'a> {
+    /// Store `value` under `key`, replacing any previous value, expiring at `expire_at`.
+    Put {
+        key: &'a [u8],
+        value: &'a StoreValue,
+        expire_at: Option<SystemTime>,
+    },
+    /// Remove `key`, if present.
+    Delete { key: &'a [u8] },
+    /// Apply `edits` to the value stored under `key`, in order. A missing or expired key is
+    /// left alone; a key holding another type than the edits expect is an error.
+    Update { key: &'a [u8], edits: &'a [Edit] },
+}
+
+impl<'a> BatchOp<'a> {
+    /// The key the write is for.
+    pub fn key(&self) -> &'a [u8] {
+        match *self {
+            BatchOp::Put { key, .. } | BatchOp::Delete { key } | BatchOp::Update { key, .. } => key,
+        }
+    }
+}
+
+/// A keyspace that stores typed values with optional deadlines.
+///
+/// Records whose deadline has passed are never returned, whether or not the engine has
+/// reclaimed them yet.
+pub trait StorageEngine: Send + Sync {
+    /// Reads the value and deadline of `key`.
+    fn read_record(&self, key: &[u8]) -> Result<Option<Record>, StoreError>;
+
+    /// Stores `value` under `key`, replacing any previous value and deadline.
+    fn write_record(
+        &self,
+        key: &[u8],
+        value: &StoreValue,
+        expire_at: Option<SystemTime>,
+    ) -> Result<(), StoreError>;
+
+    /// Removes `key`. Returns false if it did not exist.
+    fn delete_record(&self, key: &[u8]) -> Result<bool, StoreError>;
+
+    /// Replaces the deadline of `key`; `None` makes it persistent.
+    /// Returns false if the key does not exist.
+    fn set_deadline(
+        &self,
+        key: &[u8],
+        expire_at: Option<SystemTime>,
+    ) -> Result<bool, StoreError>;
+
+    /// Calls `visit` with every live record whose key starts with `prefix`.
+    fn for_each_record(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], Record),
+    ) -> Result<(), StoreError>;
+
+    /// Calls `visit` with every live key and the type name of its value, in key order, from
+    /// the first key at or after `start`, until `visit` returns false. Engines that can list
+    /// their keys without reading the values override it.
+    fn for_each_key(
+        &self,
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &'static str) -> bool,
+    ) -> Result<(), StoreError> {
+        let mut keys = Vec::new();
+        self.for_each_record(b"", &mut |key, (value, _)| {
+            if key >= start {
+                keys.push((key.to_vec(), value.type_name()));
+            }
+        })?;
+        keys.sort_unstable();
+        for (key, type_name) in keys {
+            if !visit(&key, type_name) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies every write of `batch`, or none of them.
+    fn write_batch(&self, batch: &[BatchOp<'_>]) -> Result<(), StoreError>;
+}
+
+/// Which engine holds the keyspace.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum EngineKind {
+    /// Memory only; data is lost when the server stops.
+    #[default]
+    Memory,
+    /// Memory, with every change logged to an append-only file that is replayed at startup.
+    Aof(AofConfig),
+    /// Memory, written through to a RocksDB database.
+    RocksDb(RocksDBConfig),
+}
+
+impl EngineKind {
+    /// Reads the engine from `REDIO_STORAGE` (`memory` or `rocksdb`) and `REDIO_APPENDONLY`
+    /// (`yes` logs the memory engine's changes to an append-only file), with the settings
+    /// `AofConfig::from_env` and `RocksDBConfig::from_env` read.
+    pub fn from_env() -> Result<Self, String> {
+        let append_only = match env::var("REDIO_APPENDONLY") {
+            Err(_) => false,
+            Ok(value) => parse_flag(&value)
+                .ok_or_else(|| format!("invalid REDIO_APPENDONLY '{}'", value))?,
+        };
+        let storage = env::var("REDIO_STORAGE").unwrap_or_else(|_| "memory".to_string());
+        match storage.to_ascii_lowercase().as_str() {
+            "memory" if append_only => AofConfig::from_env().map(EngineKind::Aof),
+            "memory" => Ok(EngineKind::Memory),
+            "rocksdb" if append_only => Err(
+                "REDIO_APPENDONLY needs REDIO_STORAGE=memory; RocksDB is durable on its own".into(),
+            ),
+            "rocksdb" => RocksDBConfig::from_env().map(EngineKind::RocksDb),
+            _ => Err(format!("invalid storage engine '{}'", storage)),
+        }
+    }
+}
+
+/// Parses a yes/no setting: `yes`, `true` or `1`, and `no`, `false` or `0`.
+pub fn parse_flag(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" | "true" | "1" => Some(true),
+        "no" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
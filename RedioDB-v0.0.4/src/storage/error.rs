@@ -0,0 +1,58 @@
+// src/storage/error.rs
+//
+// Errors returned by store operations.
+use std::fmt;
+
+/// Error returned by TTLStore operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    /// The write was rejected because `maxmemory` is reached and the eviction
+    /// policy could not free enough memory.
+    OutOfMemory,
+    /// The arguments are invalid for the current state of the key, e.g. a stream ID
+    /// lower than the stream's last ID.
+    InvalidArgument(String),
+    /// The stream or its consumer group does not exist.
+    NoGroup,
+    /// A consumer group with the same name already exists.
+    BusyGroup,
+    /// The key holds a value of another type than the operation works on.
+    WrongType,
+    /// The value, or an argument, is not an integer that fits in 64 bits.
+    NotInteger,
+    /// The value, or an argument, is not a finite float.
+    NotFloat,
+    /// The increment or decrement would overflow a 64-bit integer.
+    Overflow,
+    /// The key the operation needs does not exist (e.g. the source of RENAME).
+    NoSuchKey,
+    /// The key the operation would create already exists (e.g. the target of RESTORE).
+    BusyKey,
+    /// The storage engine failed to read or write, or found data it cannot decode.
+    Storage(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::OutOfMemory => {
+                write!(f, "OOM command not allowed when used memory > 'maxmemory'")
+            }
+            StoreError::InvalidArgument(message) => write!(f, "ERR {}", message),
+            StoreError::NoGroup => write!(f, "NOGROUP No such key or consumer group"),
+            StoreError::BusyGroup => write!(f, "BUSYGROUP Consumer Group name already exists"),
+            StoreError::WrongType => write!(
+                f,
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            ),
+            StoreError::NotInteger => write!(f, "ERR value is not an integer or out of range"),
+            StoreError::NotFloat => write!(f, "ERR value is not a valid float"),
+            StoreError::Overflow => write!(f, "ERR increment or decrement would overflow"),
+            StoreError::NoSuchKey => write!(f, "ERR no such key"),
+            StoreError::BusyKey => write!(f, "BUSYKEY Target key name already exists."),
+            StoreError::Storage(message) => write!(f, "ERR storage engine: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
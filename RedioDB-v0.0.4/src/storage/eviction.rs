@@ -0,0 +1,209 @@
+// src/storage/eviction.rs
+//
+// Memory limits and Redis-style eviction policies for TTLStore.
+// Provides the `maxmemory` configuration, per-entry access metadata used to rank
+// eviction candidates (LRU idle time and an LFU logarithmic counter), and the
+// constants used for approximate memory accounting.
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Approximate bookkeeping cost of one keyspace entry (map slot, tuple and metadata).
+pub const ENTRY_OVERHEAD: usize = 64;
+/// Approximate cost of a heap-allocated string header.
+pub const STRING_OVERHEAD: usize = 24;
+/// Approximate per-element cost of a slot in a hash-based collection.
+pub const SLOT_OVERHEAD: usize = 16;
+
+/// Approximate memory used by a binary string, including its header.
+pub fn string_size(s: &[u8]) -> usize {
+    STRING_OVERHEAD + s.len()
+}
+
+/// Initial LFU counter of a new key, so fresh keys are not evicted immediately.
+const LFU_INIT_VAL: u8 = 5;
+/// Controls how many hits are needed to saturate the LFU counter.
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// Number of seconds after which the LFU counter is decremented by one.
+const LFU_DECAY_SECS: u64 = 60;
+
+/// Policy applied when a write would exceed `maxmemory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Reject writes once the limit is reached.
+    #[default]
+    NoEviction,
+    /// Evict the least recently used key among all keys.
+    AllKeysLru,
+    /// Evict the least frequently used key among all keys.
+    AllKeysLfu,
+    /// Evict the least recently used key among keys with a TTL.
+    VolatileLru,
+    /// Evict the key with the nearest expiry time.
+    VolatileTtl,
+    /// Evict a random key.
+    AllKeysRandom,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "noeviction" => Ok(EvictionPolicy::NoEviction),
+            "allkeys-lru" => Ok(EvictionPolicy::AllKeysLru),
+            "allkeys-lfu" => Ok(EvictionPolicy::AllKeysLfu),
+            "volatile-lru" => Ok(EvictionPolicy::VolatileLru),
+            "volatile-ttl" => Ok(EvictionPolicy::VolatileTtl),
+            "allkeys-random" => Ok(EvictionPolicy::AllKeysRandom),
+            other => Err(format!("unknown eviction policy '{}'", other)),
+        }
+    }
+}
+
+impl fmt::Display for EvictionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Memory limit configuration for a TTLStore.
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryConfig {
+    /// Maximum approximate memory in bytes; 0 disables the limit.
+    pub maxmemory: usize,
+    /// What to do when the limit is reached.
+    pub policy: EvictionPolicy,
+    /// Number of keys sampled when looking for an eviction candidate.
+    pub samples: usize,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        MemoryConfig {
+            maxmemory: 0,
+            policy: EvictionPolicy::NoEviction,
+            samples: 5,
+        }
+    }
+}
+
+impl MemoryConfig {
+    /// Reads the configuration from `REDIO_MAXMEMORY`, `REDIO_MAXMEMORY_POLICY` and
+    /// `REDIO_MAXMEMORY_SAMPLES`, falling back to the defaults for missing values.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = MemoryConfig::default();
+        if let Ok(value) = env::var("REDIO_MAXMEMORY") {
+            config.maxmemory = parse_memory_size(&value)?;
+        }
+        if let Ok(value) = env::var("REDIO_MAXMEMORY_POLICY") {
+            config.policy = value.parse()?;
+        }
+        if let Ok(value) = env::var("REDIO_MAXMEMORY_SAMPLES") {
+            config.samples = value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid maxmemory samples '{}'", value))?
+                .max(1);
+        }
+        Ok(config)
+    }
+}
+
+/// Parses a memory size such as `1048576`, `100kb`, `64mb` or `2gb` into bytes.
+pub fn parse_memory_size(value: &str) -> Result<usize, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let (digits, multiplier) = if let Some(n) = lower.strip_suffix("gb") {
+        (n, 1024 * 1024 * 1024)
+    } else if let Some(n) = lower.strip_suffix("mb") {
+        (n, 1024 * 1024)
+    } else if let Some(n) = lower.strip_suffix("kb") {
+        (n, 1024)
+    } else if let Some(n) = lower.strip_suffix('b') {
+        (n, 1)
+    } else {
+        (lower.as_str(), 1)
+    };
+    digits
+        .trim()
+        .parse::<usize>()
+        .map(|n| n * multiplier)
+        .map_err(|_| format!("invalid memory size '{}'", value))
+}
+
+/// Per-entry access metadata used to rank eviction candidates.
+#[derive(Debug, Clone, Copy)]
+pub struct AccessMeta {
+    /// Time of the last access, used for LRU.
+    last_access: Instant,
+    /// Logarithmic access frequency counter, used for LFU.
+    lfu_counter: u8,
+}
+
+impl AccessMeta {
+    /// Metadata for a freshly created key.
+    pub fn new() -> Self {
+        AccessMeta {
+            last_access: Instant::now(),
+            lfu_counter: LFU_INIT_VAL,
+        }
+    }
+
+    /// Records an access to the key.
+    pub fn touch(&mut self) {
+        let now = Instant::now();
+        let counter = self.decayed_counter(now);
+        self.lfu_counter = lfu_log_incr(counter);
+        self.last_access = now;
+    }
+
+    /// Makes the key look idle for `idle`, as if it was last accessed that long ago.
+    pub fn set_idle(&mut self, idle: Duration) {
+        let now = Instant::now();
+        self.last_access = now.checked_sub(idle).unwrap_or(now);
+    }
+
+    /// Returns how strongly the key should be preferred as an eviction victim under
+    /// `policy`; higher values are evicted first.
+    pub fn eviction_score(&self, policy: EvictionPolicy, now: Instant) -> u64 {
+        match policy {
+            EvictionPolicy::AllKeysLfu => 255 - self.decayed_counter(now) as u64,
+            _ => now.duration_since(self.last_access).as_nanos() as u64,
+        }
+    }
+
+    /// The LFU counter after applying the decay for the time the key was idle.
+    fn decayed_counter(&self, now: Instant) -> u8 {
+        let periods = now.duration_since(self.last_access).as_secs() / LFU_DECAY_SECS;
+        self.lfu_counter.saturating_sub(periods.min(255) as u8)
+    }
+}
+
+impl Default for AccessMeta {
+    fn default() -> Self {
+        AccessMeta::new()
+    }
+}
+
+/// Increments the LFU counter with a probability that shrinks as the counter grows,
+/// so the 8-bit counter can represent access frequencies up to millions of hits.
+fn lfu_log_incr(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+    if rand::random::<f64>() < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
@@ -0,0 +1,152 @@
+// src/storage/expiry.rs
+//
+// Active expiration for TTLStore.
+// Keys with a TTL are normally only removed when a command touches them; this module runs a
+// background task that periodically reclaims expired keys that are never read again.
+use std::env;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+
+use crate::monitoring::EXPIRE_CYCLE_TIME_CAP_COUNTER;
+use crate::storage::ttl_store::TTLStore;
+
+/// Configuration for the active expiry task.
+#[derive(Debug, Clone, Copy)]
+pub struct ActiveExpireConfig {
+    /// How many times per second the expiry cycle runs.
+    pub hz: u32,
+    /// Effort level from 1 (cheapest) to 10 (most aggressive).
+    /// Higher values reclaim more keys per batch and allow a larger share of each tick.
+    pub effort: u32,
+}
+
+impl Default for ActiveExpireConfig {
+    fn default() -> Self {
+        ActiveExpireConfig { hz: 10, effort: 1 }
+    }
+}
+
+impl ActiveExpireConfig {
+    /// Reads the configuration from `REDIO_HZ` and `REDIO_ACTIVE_EXPIRE_EFFORT`,
+    /// falling back to the defaults for missing or invalid values.
+    pub fn from_env() -> Self {
+        let defaults = ActiveExpireConfig::default();
+        let hz = env::var("REDIO_HZ")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.hz);
+        let effort = env::var("REDIO_ACTIVE_EXPIRE_EFFORT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(defaults.effort);
+        ActiveExpireConfig { hz, effort }.normalized()
+    }
+
+    /// Clamps the values into their supported ranges.
+    pub fn normalized(self) -> Self {
+        ActiveExpireConfig {
+            hz: self.hz.clamp(1, 500),
+            effort: self.effort.clamp(1, 10),
+        }
+    }
+
+    /// Maximum number of keys reclaimed while holding the store lock once.
+    pub fn keys_per_batch(&self) -> usize {
+        20 + 5 * (self.effort as usize - 1)
+    }
+
+    /// Interval between two expiry cycles.
+    pub fn period(&self) -> Duration {
+        Duration::from_millis(1000 / self.hz as u64)
+    }
+
+    /// Time budget of a single cycle: a percentage of the period that grows with effort.
+    pub fn time_budget(&self) -> Duration {
+        let percent = 25 + 2 * (self.effort - 1);
+        self.period() * percent / 100
+    }
+}
+
+/// Runs one expiry cycle: reclaims expired keys in batches, releasing the lock between
+/// batches, until no expired keys remain or the time budget is spent.
+/// Returns the number of keys reclaimed.
+pub fn run_expire_cycle(store: &Mutex<TTLStore>, config: &ActiveExpireConfig) -> usize {
+    let config = config.normalized();
+    let started = Instant::now();
+    let batch = config.keys_per_batch();
+    let mut total = 0;
+    loop {
+        let removed = store
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .active_expire_cycle(batch);
+        total += removed;
+        if removed < batch {
+            break;
+        }
+        if started.elapsed() >= config.time_budget() {
+            EXPIRE_CYCLE_TIME_CAP_COUNTER.inc();
+            break;
+        }
+    }
+    total
+}
+
+/// Spawns the background task that actively expires keys in `store`.
+pub fn spawn_active_expiry(
+    store: &'static Mutex<TTLStore>,
+    config: ActiveExpireConfig,
+) -> JoinHandle<()> {
+    let config = config.normalized();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(config.period());
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let removed = run_expire_cycle(store, &config);
+            if removed > 0 {
+                log::debug!("Active expiry reclaimed {} keys", removed);
+            }
+        }
+    })
+}
+
+/// Conditions for the EXPIRE family of commands.
+///
+/// For GT and LT, a key without a TTL counts as having an infinite one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExpireOptions {
+    /// Only set the expiry if the key has no TTL.
+    pub nx: bool,
+    /// Only set the expiry if the key already has a TTL.
+    pub xx: bool,
+    /// Only set the expiry if it is later than the current one.
+    pub gt: bool,
+    /// Only set the expiry if it is earlier than the current one.
+    pub lt: bool,
+}
+
+impl ExpireOptions {
+    /// Rejects combinations of flags that Redis rejects.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.nx && (self.xx || self.gt || self.lt) {
+            return Err("NX and XX, GT or LT options at the same time are not compatible".to_string());
+        }
+        if self.gt && self.lt {
+            return Err("GT and LT options at the same time are not compatible".to_string());
+        }
+        Ok(())
+    }
+
+    /// Returns true if a key whose expiry is `current` may be given the expiry `new`.
+    pub fn allows(&self, current: Option<Instant>, new: Instant) -> bool {
+        match current {
+            None => !self.xx && !self.gt,
+            Some(current) => {
+                !self.nx && (!self.gt || new > current) && (!self.lt || new < current)
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// src/storage/hash.rs
+//
+// Hash value type: binary field names mapped to binary values, with optional per-field expiry.
+// Fields live in an IndexMap so HRANDFIELD can pick one in constant time. Field deadlines are
+// indexed in a B-tree, so expired fields are found without scanning the whole hash.
+use std::collections::{BTreeSet, HashMap};
+use std::time::Instant;
+
+use indexmap::IndexMap;
+
+use crate::storage::eviction::{string_size, SLOT_OVERHEAD, STRING_OVERHEAD};
+
+/// A field of a hash and its value, both binary safe.
+pub type FieldValue = (Vec<u8>, Vec<u8>);
+
+/// A hash of fields to values, some of which may expire on their own.
+#[derive(Debug, Clone, Default)]
+pub struct Hash {
+    fields: IndexMap<Vec<u8>, Vec<u8>>,
+    /// Deadline of every field with a TTL.
+    deadlines: HashMap<Vec<u8>, Instant>,
+    /// The same deadlines, ordered by expiry time.
+    expires: BTreeSet<(Instant, Vec<u8>)>,
+    /// Approximate number of bytes used by all fields.
+    bytes: usize,
+}
+
+impl Hash {
+    /// Creates an empty hash.
+    pub fn new() -> Self {
+        Hash::default()
+    }
+
+    /// Approximate number of bytes used by one field and its value.
+    pub fn field_size(field: &[u8], value: &[u8]) -> usize {
+        string_size(field) + string_size(value) + SLOT_OVERHEAD
+    }
+
+    /// Number of fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns true if the hash has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Value of a field, if present.
+    pub fn get(&self, field: &[u8]) -> Option<&Vec<u8>> {
+        self.fields.get(field)
+    }
+
+    /// Field and value at a position in insertion order (with swap removals).
+    pub fn get_index(&self, index: usize) -> Option<(&Vec<u8>, &Vec<u8>)> {
+        self.fields.get_index(index)
+    }
+
+    /// Iterates over fields and values.
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<u8>)> {
+        self.fields.iter()
+    }
+
+    /// Sets a field, clearing any TTL it had. Returns the previous value.
+    pub fn insert(&mut self, field: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+        self.clear_deadline(field);
+        self.bytes += string_size(value);
+        let old = self.fields.insert(field.to_vec(), value.to_vec());
+        match &old {
+            Some(old) => self.bytes = self.bytes.saturating_sub(string_size(old)),
+            None => self.bytes += string_size(field) + SLOT_OVERHEAD,
+        }
+        old
+    }
+
+    /// Replaces the value of an existing field, keeping its TTL (HINCRBY and friends).
+    pub fn update(&mut self, field: &[u8], value: Vec<u8>) {
+        if let Some(slot) = self.fields.get_mut(field) {
+            self.bytes = (self.bytes + string_size(&value)).saturating_sub(string_size(slot));
+            *slot = value;
+        } else {
+            self.insert(field, &value);
+        }
+    }
+
+    /// Removes a field. Returns its value, if it was present.
+    pub fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        let value = self.fields.swap_remove(field)?;
+        self.clear_deadline(field);
+        self.bytes = self.bytes.saturating_sub(Hash::field_size(field, &value));
+        Some(value)
+    }
+
+    /// Sets the deadline of an existing field. Returns false if the field is missing.
+    pub fn expire_at(&mut self, field: &[u8], deadline: Instant) -> bool {
+        if !self.fields.contains_key(field) {
+            return false;
+        }
+        self.clear_deadline(field);
+        self.deadlines.insert(field.to_vec(), deadline);
+        self.expires.insert((deadline, field.to_vec()));
+        true
+    }
+
+    /// Deadline of a field: None if the field is missing, Some(None) if it never expires.
+    pub fn deadline(&self, field: &[u8]) -> Option<Option<Instant>> {
+        self.fields
+            .contains_key(field)
+            .then(|| self.deadlines.get(field).copied())
+    }
+
+    /// Removes the TTL of a field. Returns false if it had none.
+    pub fn persist(&mut self, field: &[u8]) -> bool {
+        self.clear_deadline(field)
+    }
+
+    /// Earliest field deadline, if any field has a TTL.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.expires.iter().next().map(|(deadline, _)| *deadline)
+    }
+
+    /// Removes every field whose deadline is at or before `now`.
+    /// Returns the number of removed fields.
+    pub fn remove_expired(&mut self, now: Instant) -> usize {
+        let mut removed = 0;
+        while let Some((deadline, field)) = self.expires.iter().next().cloned() {
+            if deadline > now {
+                break;
+            }
+            self.remove(&field);
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Approximate number of bytes used by the hash, for `maxmemory` accounting.
+    pub fn mem_usage(&self) -> usize {
+        STRING_OVERHEAD + self.bytes
+    }
+
+    /// Helper method: Drop the deadline of a field. Returns false if it had none.
+    fn clear_deadline(&mut self, field: &[u8]) -> bool {
+        match self.deadlines.remove(field) {
+            Some(deadline) => self.expires.remove(&(deadline, field.to_vec())),
+            None => false,
+        }
+    }
+}
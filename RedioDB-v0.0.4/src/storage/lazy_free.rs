@@ -0,0 +1,56 @@
+// src/storage/lazy_free.rs
+//
+// Background freeing of values removed by UNLINK and of keyspaces flushed by FLUSHDB ASYNC.
+// Dropping a large list, set, hash, sorted set or stream walks every element; doing it on a
+// dedicated thread keeps that work out of the store lock.
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use lazy_static::lazy_static;
+
+use crate::storage::ttl_store::StoreValue;
+
+/// Values with a free effort above this are handed to the background thread; smaller ones
+/// are cheaper to drop in place than to send.
+pub const LAZY_FREE_THRESHOLD: usize = 64;
+
+lazy_static! {
+    /// Queue of values waiting to be dropped, drained by the lazy free thread.
+    static ref FREE_QUEUE: Sender<Box<dyn Send>> = {
+        let (sender, receiver) = mpsc::channel::<Box<dyn Send>>();
+        thread::Builder::new()
+            .name("lazy-free".to_string())
+            .spawn(move || {
+                for value in receiver {
+                    drop(value);
+                }
+            })
+            .expect("failed to spawn the lazy free thread");
+        sender
+    };
+}
+
+/// Amount of work needed to drop `value`: its number of elements, or 1 for a string.
+pub fn free_effort(value: &StoreValue) -> usize {
+    match value {
+        StoreValue::Simple(_) => 1,
+        StoreValue::List(list) => list.len(),
+        StoreValue::Set(set) => set.len(),
+        StoreValue::Hash(hash) => hash.len(),
+        StoreValue::SortedSet(zset) => zset.len(),
+        StoreValue::Stream(stream) => stream.len(),
+    }
+}
+
+/// Drops `value`, on the background thread if it is large enough to be worth it.
+pub fn free(value: StoreValue) {
+    if free_effort(&value) > LAZY_FREE_THRESHOLD {
+        free_later(value);
+    }
+}
+
+/// Drops `value` on the background thread, whatever its size.
+pub fn free_later<T: Send + 'static>(value: T) {
+    // If the thread is gone the value comes back and is dropped here instead.
+    let _ = FREE_QUEUE.send(Box::new(value));
+}
@@ -4,3 +4,24 @@
 pub mod rocksdb_store;
 pub mod arrow_cache;
 pub mod ttl_store;
+pub mod expiry;
+pub mod eviction;
+pub mod error;
+pub mod sorted_set;
+pub mod hash;
+pub mod string_value;
+pub mod scan;
+pub mod stream;
+pub mod blocking;
+pub mod lazy_free;
+pub mod databases;
+pub mod sharded;
+pub mod codec;
+pub mod engine;
+pub mod aof;
+pub mod snapshot;
+pub mod rdb;
+pub mod dump;
+pub mod backup;
+pub mod util;
+pub mod multi_key;
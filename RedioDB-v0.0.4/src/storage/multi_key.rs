@@ -0,0 +1,550 @@
+// src/storage/multi_key.rs
+//
+// Commands that read or write several keys at once (MSET, DEL, RENAME, LMOVE, SINTERSTORE...).
+// They are written once against `KeyShards`, which routes every key to the store holding it:
+// a plain TTLStore holds all of its keys, and the guard of a multi-key command holds the
+// locked shards of a database. Each step runs against the key's own shard, in place, so no
+// entry is ever moved between stores: untouched keys keep their position (which SCAN relies
+// on) and their access metadata (which eviction relies on), and every write is checked
+// against the memory limit of the shard it lands in.
+use std::collections::HashMap;
+
+use indexmap::IndexSet;
+
+use crate::storage::blocking::{BlockedOp, Delivery};
+use crate::storage::error::StoreError;
+use crate::storage::lazy_free;
+use crate::storage::sorted_set::{Aggregate, SortedSet};
+use crate::storage::stream::{now_ms, GroupReadId, StreamId, StreamRead};
+use crate::storage::ttl_store::{StoreValue, TTLStore};
+
+/// Routes keys to the stores holding them.
+pub trait KeyShards {
+    /// The store holding `key`.
+    fn shard(&self, key: &[u8]) -> &TTLStore;
+
+    /// The store holding `key`, for a command that may change it.
+    fn shard_mut(&mut self, key: &[u8]) -> &mut TTLStore;
+}
+
+/// A single store holds every key.
+impl KeyShards for TTLStore {
+    fn shard(&self, _key: &[u8]) -> &TTLStore {
+        self
+    }
+
+    fn shard_mut(&mut self, _key: &[u8]) -> &mut TTLStore {
+        self
+    }
+}
+
+/// Which combination of sets SINTER/SUNION/SDIFF and their STORE variants compute.
+#[derive(Debug, Clone, Copy)]
+enum SetAlgebra {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Commands spanning several keys, for any `KeyShards`.
+pub trait MultiKey: KeyShards {
+    /// Set several keys at once (MSET).
+    fn m_set(&mut self, pairs: &[(Vec<u8>, Vec<u8>)]) -> Result<(), StoreError> {
+        for (key, value) in pairs {
+            self.shard_mut(key).set(key, value, None)?;
+        }
+        Ok(())
+    }
+
+    /// Set several keys at once, only if none of them exists (MSETNX).
+    /// Returns whether the keys were set.
+    fn m_set_nx(&mut self, pairs: &[(Vec<u8>, Vec<u8>)]) -> Result<bool, StoreError> {
+        for (key, _) in pairs {
+            let shard = self.shard_mut(key);
+            shard.check_expiry(key);
+            if shard.get_entry(key).is_some() {
+                return Ok(false);
+            }
+        }
+        self.m_set(pairs)?;
+        Ok(true)
+    }
+
+    /// Get the values of several keys (MGET); missing keys and keys holding another type
+    /// read as None.
+    fn m_get(&mut self, keys: &[Vec<u8>]) -> Vec<Option<Vec<u8>>> {
+        keys.iter()
+            .map(|key| self.shard_mut(key).get(key).ok().flatten())
+            .collect()
+    }
+
+    /// Delete several keys (DEL). Returns the number of keys that existed.
+    fn del_keys(&mut self, keys: &[Vec<u8>]) -> usize {
+        keys.iter().filter(|key| self.shard_mut(key).del(key)).count()
+    }
+
+    /// Delete several keys, freeing large values on a background thread (UNLINK).
+    /// Returns the number of keys that existed.
+    fn unlink(&mut self, keys: &[Vec<u8>]) -> usize {
+        let mut removed = 0;
+        for key in keys {
+            let shard = self.shard_mut(key);
+            shard.check_expiry(key);
+            if let Some((value, _)) = shard.take_value(key) {
+                lazy_free::free(value);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Count how many of the keys exist (EXISTS); a key given twice is counted twice.
+    fn exists(&mut self, keys: &[Vec<u8>]) -> usize {
+        keys.iter()
+            .filter(|key| {
+                let shard = self.shard_mut(key);
+                shard.check_expiry(key);
+                shard.get_entry(key).is_some()
+            })
+            .count()
+    }
+
+    /// Update the last access time of several keys (TOUCH).
+    /// Returns the number of keys that exist.
+    fn touch(&mut self, keys: &[Vec<u8>]) -> usize {
+        keys.iter()
+            .filter(|key| {
+                let shard = self.shard_mut(key);
+                shard.lookup(key);
+                shard.get_entry(key).is_some()
+            })
+            .count()
+    }
+
+    /// Rename `source` to `destination`, overwriting it and keeping the TTL (RENAME).
+    /// Fails with `StoreError::NoSuchKey` if `source` does not exist.
+    fn rename(&mut self, source: &[u8], destination: &[u8]) -> Result<(), StoreError> {
+        self.shard_mut(source).check_expiry(source);
+        if self.shard(source).get_entry(source).is_none() {
+            return Err(StoreError::NoSuchKey);
+        }
+        if source == destination {
+            return Ok(());
+        }
+        self.shard_mut(destination).ensure_memory()?;
+        let (value, expiry) = self
+            .shard_mut(source)
+            .take_value(source)
+            .ok_or(StoreError::NoSuchKey)?;
+        let shard = self.shard_mut(destination);
+        shard.put_value(destination, value, expiry);
+        shard.serve_blocked(destination);
+        Ok(())
+    }
+
+    /// Rename `source` to `destination` only if `destination` does not exist (RENAMENX).
+    /// Returns whether the key was renamed.
+    /// Fails with `StoreError::NoSuchKey` if `source` does not exist.
+    fn rename_nx(&mut self, source: &[u8], destination: &[u8]) -> Result<bool, StoreError> {
+        self.shard_mut(source).check_expiry(source);
+        self.shard_mut(destination).check_expiry(destination);
+        if self.shard(source).get_entry(source).is_none() {
+            return Err(StoreError::NoSuchKey);
+        }
+        if self.shard(destination).get_entry(destination).is_some() {
+            return Ok(false);
+        }
+        self.rename(source, destination)?;
+        Ok(true)
+    }
+
+    /// Copy the value and TTL of `source` to `destination` (COPY). An existing
+    /// `destination` is only overwritten with `replace`.
+    /// Returns false if `source` does not exist or `destination` was kept.
+    fn copy(&mut self, source: &[u8], destination: &[u8], replace: bool) -> Result<bool, StoreError> {
+        if source == destination {
+            return Err(StoreError::InvalidArgument(
+                "source and destination objects are the same".into(),
+            ));
+        }
+        self.shard_mut(destination).ensure_memory()?;
+        self.shard_mut(source).lookup(source);
+        self.shard_mut(destination).check_expiry(destination);
+        let Some((value, expiry)) = self.shard(source).get_entry(source) else {
+            return Ok(false);
+        };
+        if !replace && self.shard(destination).get_entry(destination).is_some() {
+            return Ok(false);
+        }
+        let value = value.clone();
+        let shard = self.shard_mut(destination);
+        shard.put_value(destination, value, expiry);
+        shard.serve_blocked(destination);
+        Ok(true)
+    }
+
+    /// List operations: atomically pop an element from one end of `source` and push it
+    /// onto one end of `destination` (LMOVE; RPOPLPUSH is the tail-to-head case).
+    /// Returns the moved element, or None if the source is empty.
+    fn l_move(
+        &mut self,
+        source: &[u8],
+        destination: &[u8],
+        from_front: bool,
+        to_front: bool,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        self.shard_mut(destination).ensure_memory()?;
+        self.shard_mut(source).lookup(source);
+        self.shard_mut(destination).lookup(destination);
+        move_value(self, source, destination, from_front, to_front)
+    }
+
+    /// Blocking list operations: pop from the first non-empty list in `keys` the way
+    /// BLPOP/BRPOP/BLMOVE would, without blocking.
+    /// Returns the key and the element, or None if every list is empty.
+    fn pop_first(
+        &mut self,
+        keys: &[Vec<u8>],
+        op: &BlockedOp,
+    ) -> Result<Option<Delivery>, StoreError> {
+        for key in keys {
+            if self.shard_mut(key).l_len(key)? == 0 {
+                continue;
+            }
+            let value = match op {
+                BlockedOp::Pop { front } => self.shard_mut(key).pop_value(key, *front)?,
+                BlockedOp::Move { from_front, destination, to_front } => {
+                    self.l_move(key, destination, *from_front, *to_front)?
+                }
+                BlockedOp::Wake => None,
+            };
+            if let Some(value) = value {
+                return Ok(Some((key.clone(), value)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Set operations: atomically move `member` from one set to another (SMOVE).
+    /// Returns whether the member was moved.
+    fn s_move(
+        &mut self,
+        source: &[u8],
+        destination: &[u8],
+        member: &[u8],
+    ) -> Result<bool, StoreError> {
+        self.shard_mut(destination).ensure_memory()?;
+        self.shard_mut(source).lookup(source);
+        self.shard_mut(destination).lookup(destination);
+        let is_set = |shards: &Self, key: &[u8]| {
+            matches!(shards.shard(key).get_entry(key), None | Some((StoreValue::Set(_), _)))
+        };
+        if !is_set(self, source) || !is_set(self, destination) {
+            return Err(StoreError::WrongType);
+        }
+        if !self.shard_mut(source).s_is_member(source, member)? {
+            return Ok(false);
+        }
+        if source != destination {
+            let member = [member.to_vec()];
+            self.shard_mut(source).s_rem(source, &member)?;
+            self.shard_mut(destination).s_add(destination, &member)?;
+        }
+        Ok(true)
+    }
+
+    /// Set operations: members present in every set (SINTER).
+    fn s_inter(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, StoreError> {
+        set_algebra(self, keys, SetAlgebra::Inter, usize::MAX)
+            .map(|members| members.into_iter().collect())
+    }
+
+    /// Set operations: members present in any set (SUNION).
+    fn s_union(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, StoreError> {
+        set_algebra(self, keys, SetAlgebra::Union, usize::MAX)
+            .map(|members| members.into_iter().collect())
+    }
+
+    /// Set operations: members of the first set that are in none of the others (SDIFF).
+    fn s_diff(&mut self, keys: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, StoreError> {
+        set_algebra(self, keys, SetAlgebra::Diff, usize::MAX)
+            .map(|members| members.into_iter().collect())
+    }
+
+    /// Set operations: number of members in the intersection, counting at most `limit`
+    /// (0 means no limit) (SINTERCARD).
+    fn s_inter_card(&mut self, keys: &[Vec<u8>], limit: usize) -> Result<usize, StoreError> {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        set_algebra(self, keys, SetAlgebra::Inter, limit).map(|members| members.len())
+    }
+
+    /// Set operations: store the intersection of several sets in `destination`
+    /// (SINTERSTORE). Returns the size of the result.
+    fn s_inter_store(&mut self, destination: &[u8], keys: &[Vec<u8>]) -> Result<usize, StoreError> {
+        s_store(self, destination, keys, SetAlgebra::Inter)
+    }
+
+    /// Set operations: store the union of several sets in `destination` (SUNIONSTORE).
+    /// Returns the size of the result.
+    fn s_union_store(&mut self, destination: &[u8], keys: &[Vec<u8>]) -> Result<usize, StoreError> {
+        s_store(self, destination, keys, SetAlgebra::Union)
+    }
+
+    /// Set operations: store the difference of several sets in `destination` (SDIFFSTORE).
+    /// Returns the size of the result.
+    fn s_diff_store(&mut self, destination: &[u8], keys: &[Vec<u8>]) -> Result<usize, StoreError> {
+        s_store(self, destination, keys, SetAlgebra::Diff)
+    }
+
+    /// Sorted set operations: store the union of several sorted sets in `destination`
+    /// (ZUNIONSTORE). Plain sets count as sorted sets with every score equal to 1.
+    /// Returns the size of the result.
+    fn z_union_store(
+        &mut self,
+        destination: &[u8],
+        keys: &[Vec<u8>],
+        weights: &[f64],
+        aggregate: Aggregate,
+    ) -> Result<usize, StoreError> {
+        z_store(self, destination, keys, weights, aggregate, false)
+    }
+
+    /// Sorted set operations: store the intersection of several sorted sets in
+    /// `destination` (ZINTERSTORE). Returns the size of the result.
+    fn z_inter_store(
+        &mut self,
+        destination: &[u8],
+        keys: &[Vec<u8>],
+        weights: &[f64],
+        aggregate: Aggregate,
+    ) -> Result<usize, StoreError> {
+        z_store(self, destination, keys, weights, aggregate, true)
+    }
+
+    /// Stream operations: entries with an ID greater than the matching ID in `after`,
+    /// for each stream in `keys` (XREAD). Streams with no such entries are omitted.
+    fn x_read(
+        &mut self,
+        keys: &[Vec<u8>],
+        after: &[StreamId],
+        count: Option<usize>,
+    ) -> Result<Vec<StreamRead>, StoreError> {
+        let mut result = Vec::new();
+        for (key, id) in keys.iter().zip(after) {
+            if let Some(stream) = self.shard_mut(key).stream_ref(key)? {
+                let entries = stream.read_after(*id, count);
+                if !entries.is_empty() {
+                    result.push((key.clone(), entries));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Stream operations: read entries on behalf of a group consumer (XREADGROUP).
+    /// See `Stream::read_group` for the meaning of each `GroupReadId`. Streams with no
+    /// matching entries are omitted.
+    fn x_read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        keys: &[Vec<u8>],
+        ids: &[GroupReadId],
+        count: Option<usize>,
+        noack: bool,
+    ) -> Result<Vec<StreamRead>, StoreError> {
+        let now = now_ms();
+        let mut result = Vec::new();
+        for (key, id) in keys.iter().zip(ids) {
+            let shard = self.shard_mut(key);
+            shard.ensure_memory()?;
+            let entries = shard.with_group_stream(key, |stream| {
+                stream.read_group(group, consumer, *id, count, noack, now)
+            })?;
+            if !entries.is_empty() {
+                result.push((key.clone(), entries));
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl<T: KeyShards + ?Sized> MultiKey for T {}
+
+/// Helper function: Move an element between lists as LMOVE does.
+/// Returns None if the source is empty; a destination of another type is only
+/// reported when there is something to move.
+pub(crate) fn move_value<S: KeyShards + ?Sized>(
+    shards: &mut S,
+    source: &[u8],
+    destination: &[u8],
+    from_front: bool,
+    to_front: bool,
+) -> Result<Option<Vec<u8>>, StoreError> {
+    if source == destination {
+        return shards.shard_mut(source).rotate_list(source, from_front, to_front);
+    }
+    match shards.shard(source).get_entry(source) {
+        Some((StoreValue::List(_), _)) => {}
+        Some(_) => return Err(StoreError::WrongType),
+        None => return Ok(None),
+    }
+    let target = shards.shard(destination).get_entry(destination);
+    if !matches!(target, None | Some((StoreValue::List(_), _))) {
+        return Err(StoreError::WrongType);
+    }
+    let Some(value) = shards.shard_mut(source).pop_value(source, from_front)? else {
+        return Ok(None);
+    };
+    shards
+        .shard_mut(destination)
+        .push_values(destination, std::slice::from_ref(&value), to_front)?;
+    Ok(Some(value))
+}
+
+/// Helper function: Combine the sets stored at `keys`, keeping at most `limit` members.
+/// Missing keys count as empty sets.
+/// Only the members of the result are copied, never whole source sets.
+fn set_algebra<S: KeyShards + ?Sized>(
+    shards: &mut S,
+    keys: &[Vec<u8>],
+    algebra: SetAlgebra,
+    limit: usize,
+) -> Result<IndexSet<Vec<u8>>, StoreError> {
+    for key in keys {
+        shards.shard_mut(key).lookup(key);
+    }
+    let shards = &*shards;
+    let mut sets = Vec::with_capacity(keys.len());
+    for key in keys {
+        match shards.shard(key).get_entry(key) {
+            Some((StoreValue::Set(set), _)) => sets.push(Some(set)),
+            Some(_) => return Err(StoreError::WrongType),
+            None => sets.push(None),
+        }
+    }
+    let members: Box<dyn Iterator<Item = &Vec<u8>>> = match algebra {
+        // A missing key empties the intersection.
+        SetAlgebra::Inter => match sets.iter().copied().collect::<Option<Vec<_>>>() {
+            Some(present) => match present.iter().min_by_key(|set| set.len()).copied() {
+                // Probe the other sets with the members of the smallest one.
+                Some(smallest) => Box::new(
+                    smallest
+                        .iter()
+                        .filter(move |member| present.iter().all(|set| set.contains(*member))),
+                ),
+                None => Box::new(std::iter::empty()),
+            },
+            None => Box::new(std::iter::empty()),
+        },
+        SetAlgebra::Union => Box::new(sets.iter().flatten().flat_map(|set| set.iter())),
+        SetAlgebra::Diff => {
+            let (first, others) = match sets.split_first() {
+                Some((Some(first), others)) => (*first, others),
+                _ => return Ok(IndexSet::new()),
+            };
+            Box::new(first.iter().filter(move |member| {
+                !others.iter().flatten().any(|set| set.contains(*member))
+            }))
+        }
+    };
+    let mut result = IndexSet::new();
+    for member in members {
+        if result.len() >= limit {
+            break;
+        }
+        result.insert(member.clone());
+    }
+    Ok(result)
+}
+
+/// Helper function: Store a combination of the sets at `keys` in `destination`.
+/// Returns the size of the result.
+fn s_store<S: KeyShards + ?Sized>(
+    shards: &mut S,
+    destination: &[u8],
+    keys: &[Vec<u8>],
+    algebra: SetAlgebra,
+) -> Result<usize, StoreError> {
+    shards.shard_mut(destination).ensure_memory()?;
+    let set = set_algebra(shards, keys, algebra, usize::MAX)?;
+    let len = set.len();
+    let shard = shards.shard_mut(destination);
+    if len == 0 {
+        shard.take_value(destination);
+    } else {
+        shard.put_value(destination, StoreValue::Set(set), None);
+    }
+    Ok(len)
+}
+
+/// Helper function: Store the union or intersection of the sorted sets at `keys` in
+/// `destination`. Returns the size of the result.
+fn z_store<S: KeyShards + ?Sized>(
+    shards: &mut S,
+    destination: &[u8],
+    keys: &[Vec<u8>],
+    weights: &[f64],
+    aggregate: Aggregate,
+    intersect: bool,
+) -> Result<usize, StoreError> {
+    shards.shard_mut(destination).ensure_memory()?;
+    let mut sources: Vec<HashMap<Vec<u8>, f64>> = Vec::with_capacity(keys.len());
+    for (i, key) in keys.iter().enumerate() {
+        let shard = shards.shard_mut(key);
+        shard.lookup(key);
+        let weight = weights.get(i).copied().unwrap_or(1.0);
+        let weighted = |score: f64| {
+            let product = score * weight;
+            if product.is_nan() {
+                0.0
+            } else {
+                product
+            }
+        };
+        let source = match shard.get_entry(key) {
+            Some((StoreValue::SortedSet(zset), _)) => zset
+                .iter()
+                .map(|(member, score)| (member.to_vec(), weighted(score)))
+                .collect(),
+            Some((StoreValue::Set(set), _)) => set
+                .iter()
+                .map(|member| (member.clone(), weighted(1.0)))
+                .collect(),
+            Some(_) => return Err(StoreError::WrongType),
+            None => HashMap::new(),
+        };
+        sources.push(source);
+    }
+
+    let mut sources = sources.into_iter();
+    let mut result = sources.next().unwrap_or_default();
+    for source in sources {
+        if intersect {
+            result.retain(|member, _| source.contains_key(member));
+            for (member, score) in result.iter_mut() {
+                *score = aggregate.combine(*score, source[member]);
+            }
+        } else {
+            for (member, score) in source {
+                result
+                    .entry(member)
+                    .and_modify(|acc| *acc = aggregate.combine(*acc, score))
+                    .or_insert(score);
+            }
+        }
+    }
+
+    let mut zset = SortedSet::new();
+    for (member, score) in &result {
+        zset.insert(member, *score);
+    }
+    let len = zset.len();
+    let shard = shards.shard_mut(destination);
+    if len == 0 {
+        shard.take_value(destination);
+    } else {
+        shard.put_value(destination, StoreValue::SortedSet(zset), None);
+    }
+    Ok(len)
+}
@@ -0,0 +1,795 @@
+// src/storage/rdb.rs
+//
+// Import and export of Redis RDB files, to migrate data from Redis and back.
+// The reader understands RDB versions 1 to 12: strings, lists, sets, hashes and sorted sets
+// in every encoding Redis has written them in (plain, zipmap, ziplist, intset, quicklist and
+// listpack), integer-encoded and LZF-compressed strings, expiry times and the CRC-64
+// trailer. Streams and functions have no counterpart to import into and are skipped; module
+// values and hashes with per-field TTLs are refused rather than silently dropped.
+// The writer emits RDB version 9 (Redis 5.0) with only the plain encodings, which every later
+// Redis loads and re-encodes as it sees fit. Streams cannot be written that way and are
+// skipped, and per-field hash TTLs are dropped.
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use indexmap::IndexSet;
+
+use crate::storage::codec;
+use crate::storage::databases::Databases;
+use crate::storage::engine::Record;
+use crate::storage::error::StoreError;
+use crate::storage::hash::Hash;
+use crate::storage::snapshot::{self, DatabaseRecords};
+use crate::storage::sorted_set::SortedSet;
+use crate::storage::string_value::StringValue;
+use crate::storage::ttl_store::StoreValue;
+
+/// First bytes of every RDB file, followed by the version as four ASCII digits.
+pub const RDB_MAGIC: &[u8] = b"REDIS";
+
+/// Newest RDB version the reader understands (Redis 7.4).
+pub const RDB_MAX_VERSION: u32 = 12;
+
+/// Version of the files written by `export`.
+pub const RDB_EXPORT_VERSION: u32 = 9;
+
+/// Name of the marker in the data directory recording the last RDB file imported at
+/// startup, so a restart does not import it again over newer writes.
+pub const IMPORT_MARKER_FILENAME: &str = "rdb-imported";
+
+/// Value types.
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_MODULE: u8 = 6;
+const TYPE_MODULE_2: u8 = 7;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_SET_LISTPACK: u8 = 20;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+/// Opcodes between keys.
+const OPCODE_SLOT_INFO: u8 = 0xF4;
+const OPCODE_FUNCTION2: u8 = 0xF5;
+const OPCODE_FUNCTION_PRE_GA: u8 = 0xF6;
+const OPCODE_MODULE_AUX: u8 = 0xF7;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+/// Special string encodings, flagged by the top two bits of a length.
+const ENC_INT8: u64 = 0;
+const ENC_INT16: u64 = 1;
+const ENC_INT32: u64 = 2;
+const ENC_LZF: u64 = 3;
+/// Most bytes one byte of LZF data can expand to: a 3-byte back reference copies 264 bytes.
+const LZF_MAX_EXPANSION: usize = 88;
+
+/// Quicklist node containers (RDB version 11).
+const QUICKLIST_PLAIN: u64 = 1;
+const QUICKLIST_PACKED: u64 = 2;
+
+/// CRC-64/Jones table, as used by Redis for the RDB trailer.
+const CRC64_TABLE: [u64; 256] = crc64_table();
+
+/// Helper function: Build the table of the reflected CRC-64/Jones polynomial.
+const fn crc64_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x95ac_9329_ac4b_c9b5 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-64 of `bytes` as Redis computes it for the RDB trailer.
+pub fn crc64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |crc, &byte| {
+        CRC64_TABLE[((crc ^ byte as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Identifies the contents of an RDB file in the import marker: its size and CRC-32. (The
+/// CRC-64 of a file ending in its own CRC-64 is the same for every file.)
+pub fn import_stamp(bytes: &[u8]) -> String {
+    format!("{} {:08x}", bytes.len(), codec::crc32(bytes))
+}
+
+/// What reading an RDB file found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RdbReport {
+    /// Version of the file.
+    pub version: u32,
+    /// Keys read, including the ones skipped or already expired.
+    pub keys: usize,
+    /// Keys that had already expired and were not imported.
+    pub expired: usize,
+    /// Keys that were not imported: streams, or keys of databases that do not exist here.
+    pub skipped: usize,
+}
+
+impl RdbReport {
+    /// Number of keys imported.
+    pub fn imported(&self) -> usize {
+        self.keys - self.expired - self.skipped
+    }
+}
+
+/// Reads an RDB file, calling `visit` with the database, key and record of every key that
+/// can be imported. Streams count as skipped; expired keys are passed on like the others.
+pub fn read_rdb(
+    bytes: &[u8],
+    visit: &mut dyn FnMut(usize, Vec<u8>, Record) -> Result<(), StoreError>,
+) -> Result<RdbReport, StoreError> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(RDB_MAGIC.len()).ok() != Some(RDB_MAGIC) {
+        return Err(StoreError::Storage("not an RDB file".into()));
+    }
+    let version = std::str::from_utf8(reader.take(4)?)
+        .ok()
+        .and_then(|digits| digits.parse::<u32>().ok())
+        .ok_or_else(|| reader.error("invalid RDB version"))?;
+    if version == 0 || version > RDB_MAX_VERSION {
+        return Err(StoreError::Storage(format!("unsupported RDB version {}", version)));
+    }
+
+    let mut report = RdbReport { version, ..RdbReport::default() };
+    let mut database = 0;
+    let mut expire_at = None;
+    loop {
+        match reader.u8()? {
+            OPCODE_EXPIRETIME_MS => {
+                expire_at = Some(UNIX_EPOCH + Duration::from_millis(reader.u64_le()?))
+            }
+            OPCODE_EXPIRETIME => {
+                expire_at = Some(UNIX_EPOCH + Duration::from_secs(reader.u32_le()? as u64))
+            }
+            OPCODE_SELECTDB => database = reader.len()?,
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_AUX => {
+                reader.string()?;
+                reader.string()?;
+            }
+            OPCODE_FREQ => {
+                reader.u8()?;
+            }
+            OPCODE_IDLE => {
+                reader.length()?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.length()?;
+                }
+            }
+            OPCODE_FUNCTION2 => {
+                reader.string()?;
+            }
+            OPCODE_FUNCTION_PRE_GA | OPCODE_MODULE_AUX => {
+                return Err(reader.error("modules and pre-release functions are not supported"));
+            }
+            OPCODE_EOF => {
+                let end = reader.pos;
+                if version >= 5 {
+                    let checksum = reader.u64_le()?;
+                    if checksum != 0 && checksum != crc64(&bytes[..end]) {
+                        return Err(StoreError::Storage("RDB checksum mismatch".into()));
+                    }
+                }
+                return Ok(report);
+            }
+            value_type => {
+                let key = reader.string()?;
+                report.keys += 1;
+                match read_value(&mut reader, value_type)? {
+                    Some(value) => visit(database, key, (value, expire_at))?,
+                    None => report.skipped += 1,
+                }
+                expire_at = None;
+            }
+        }
+    }
+}
+
+/// Imports an RDB file into `databases`, replacing keys with the same names. Keys that have
+/// already expired and keys of databases that are not configured are skipped. Nothing is
+/// written unless the whole file reads back and its checksum matches.
+pub fn import(bytes: &[u8], databases: &Databases) -> Result<RdbReport, StoreError> {
+    let now = SystemTime::now();
+    let (mut expired, mut missing) = (0, 0);
+    let mut records = Vec::new();
+    let mut report = read_rdb(bytes, &mut |index, key, (value, expire_at)| {
+        if index >= databases.len() {
+            missing += 1;
+        } else if expire_at.is_some_and(|at| at <= now) {
+            expired += 1;
+        } else {
+            records.push((index, key, value, expire_at));
+        }
+        Ok(())
+    })?;
+    for (index, key, value, expire_at) in records {
+        databases
+            .get(index)
+            .write(&key, |store| store.write_record(&key, &value, expire_at))?;
+    }
+    if missing > 0 {
+        log::warn!("Skipped {} keys of databases that are not configured", missing);
+    }
+    report.expired = expired;
+    report.skipped += missing;
+    Ok(report)
+}
+
+/// What exporting an RDB file wrote.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RdbExport {
+    /// The RDB file.
+    pub bytes: Vec<u8>,
+    /// Keys written.
+    pub keys: usize,
+    /// Streams, which RDB's plain encodings cannot express.
+    pub skipped: usize,
+}
+
+/// Exports a point-in-time copy of every database as an RDB file.
+pub fn export(databases: &Databases) -> Result<RdbExport, StoreError> {
+    Ok(write_rdb(&snapshot::capture(databases)?))
+}
+
+/// Writes the records of each database as an RDB file.
+pub fn write_rdb(snapshot: &[DatabaseRecords]) -> RdbExport {
+    let mut out = RDB_MAGIC.to_vec();
+    out.extend_from_slice(format!("{:04}", RDB_EXPORT_VERSION).as_bytes());
+    put_aux(&mut out, b"rediodb-ver", env!("CARGO_PKG_VERSION").as_bytes());
+    let (mut keys, mut skipped) = (0, 0);
+    for (index, records) in snapshot {
+        let exported: Vec<_> = records
+            .iter()
+            .filter(|(_, (value, _))| !matches!(value, StoreValue::Stream(_)))
+            .collect();
+        skipped += records.len() - exported.len();
+        if exported.is_empty() {
+            continue;
+        }
+        out.push(OPCODE_SELECTDB);
+        put_length(&mut out, *index as u64);
+        out.push(OPCODE_RESIZEDB);
+        put_length(&mut out, exported.len() as u64);
+        let volatile = exported.iter().filter(|(_, (_, at))| at.is_some()).count();
+        put_length(&mut out, volatile as u64);
+        for (key, (value, expire_at)) in exported {
+            if let Some(at) = expire_at {
+                out.push(OPCODE_EXPIRETIME_MS);
+                out.extend_from_slice(&codec::system_time_ms(*at).to_le_bytes());
+            }
+            put_value(&mut out, key, value);
+            keys += 1;
+        }
+    }
+    out.push(OPCODE_EOF);
+    let checksum = crc64(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    RdbExport { bytes: out, keys, skipped }
+}
+
+/// Helper function: Append an auxiliary field.
+fn put_aux(out: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    out.push(OPCODE_AUX);
+    put_string(out, name);
+    put_string(out, value);
+}
+
+/// Helper function: Append a key and its value in the plain encoding of its type.
+fn put_value(out: &mut Vec<u8>, key: &[u8], value: &StoreValue) {
+    match value {
+        StoreValue::Simple(string) => {
+            out.push(TYPE_STRING);
+            put_string(out, key);
+            put_string(out, &string.to_bytes());
+        }
+        StoreValue::List(list) => {
+            out.push(TYPE_LIST);
+            put_string(out, key);
+            put_length(out, list.len() as u64);
+            for item in list {
+                put_string(out, item);
+            }
+        }
+        StoreValue::Set(set) => {
+            out.push(TYPE_SET);
+            put_string(out, key);
+            put_length(out, set.len() as u64);
+            for member in set {
+                put_string(out, member);
+            }
+        }
+        StoreValue::Hash(hash) => {
+            out.push(TYPE_HASH);
+            put_string(out, key);
+            put_length(out, hash.len() as u64);
+            for (field, value) in hash.iter() {
+                put_string(out, field);
+                put_string(out, value);
+            }
+        }
+        StoreValue::SortedSet(zset) => {
+            out.push(TYPE_ZSET_2);
+            put_string(out, key);
+            put_length(out, zset.len() as u64);
+            for (member, score) in zset.iter() {
+                put_string(out, member);
+                out.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+        StoreValue::Stream(_) => unreachable!("streams are filtered out before writing"),
+    }
+}
+
+/// Helper function: Append a length in RDB's variable-size encoding.
+fn put_length(out: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.extend_from_slice(&(0x4000 | len as u16).to_be_bytes());
+    } else if len <= u32::MAX as u64 {
+        out.push(0x80);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+/// Helper function: Append a length-prefixed string.
+fn put_string(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_length(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Helper function: Read a value of the given type; None for a skipped stream.
+fn read_value(reader: &mut Reader<'_>, value_type: u8) -> Result<Option<StoreValue>, StoreError> {
+    let value = match value_type {
+        TYPE_STRING => StoreValue::Simple(StringValue::new(&reader.string()?)),
+        TYPE_LIST => {
+            let len = reader.len()?;
+            let mut list = VecDeque::with_capacity(len.min(1024));
+            for _ in 0..len {
+                list.push_back(reader.string()?);
+            }
+            StoreValue::List(list)
+        }
+        TYPE_LIST_ZIPLIST => StoreValue::List(ziplist(&reader.string()?)?.into()),
+        TYPE_LIST_QUICKLIST => {
+            let mut list = VecDeque::new();
+            for _ in 0..reader.len()? {
+                list.extend(ziplist(&reader.string()?)?);
+            }
+            StoreValue::List(list)
+        }
+        TYPE_LIST_QUICKLIST_2 => {
+            let mut list = VecDeque::new();
+            for _ in 0..reader.len()? {
+                match reader.length()? {
+                    QUICKLIST_PLAIN => list.push_back(reader.string()?),
+                    QUICKLIST_PACKED => list.extend(listpack(&reader.string()?)?),
+                    container => {
+                        return Err(reader.error(&format!("unknown quicklist container {}", container)))
+                    }
+                }
+            }
+            StoreValue::List(list)
+        }
+        TYPE_SET => {
+            let len = reader.len()?;
+            let mut set = IndexSet::with_capacity(len.min(1024));
+            for _ in 0..len {
+                set.insert(reader.string()?);
+            }
+            StoreValue::Set(set)
+        }
+        TYPE_SET_INTSET => StoreValue::Set(intset(&reader.string()?)?.into_iter().collect()),
+        TYPE_SET_LISTPACK => StoreValue::Set(listpack(&reader.string()?)?.into_iter().collect()),
+        TYPE_HASH => {
+            let mut hash = Hash::new();
+            for _ in 0..reader.len()? {
+                let (field, value) = (reader.string()?, reader.string()?);
+                hash.insert(&field, &value);
+            }
+            StoreValue::Hash(hash)
+        }
+        TYPE_HASH_ZIPMAP => StoreValue::Hash(to_hash(zipmap(&reader.string()?)?)?),
+        TYPE_HASH_ZIPLIST => StoreValue::Hash(to_hash(ziplist(&reader.string()?)?)?),
+        TYPE_HASH_LISTPACK => StoreValue::Hash(to_hash(listpack(&reader.string()?)?)?),
+        TYPE_ZSET | TYPE_ZSET_2 => {
+            let mut zset = SortedSet::new();
+            for _ in 0..reader.len()? {
+                let member = reader.string()?;
+                let score = if value_type == TYPE_ZSET {
+                    reader.text_score()?
+                } else {
+                    f64::from_le_bytes(reader.take(8)?.try_into().unwrap())
+                };
+                if score.is_nan() {
+                    return Err(reader.error("NaN score"));
+                }
+                zset.insert(&member, score);
+            }
+            StoreValue::SortedSet(zset)
+        }
+        TYPE_ZSET_ZIPLIST => StoreValue::SortedSet(to_sorted_set(ziplist(&reader.string()?)?)?),
+        TYPE_ZSET_LISTPACK => StoreValue::SortedSet(to_sorted_set(listpack(&reader.string()?)?)?),
+        TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+            skip_stream(reader, value_type)?;
+            return Ok(None);
+        }
+        TYPE_MODULE | TYPE_MODULE_2 => return Err(reader.error("module values are not supported")),
+        other => return Err(reader.error(&format!("unsupported value type {}", other))),
+    };
+    Ok(Some(value))
+}
+
+/// Helper function: Skip over a stream, which has no counterpart to import into.
+fn skip_stream(reader: &mut Reader<'_>, value_type: u8) -> Result<(), StoreError> {
+    for _ in 0..reader.len()? {
+        reader.string()?; // Master entry ID.
+        reader.string()?; // Listpack of entries.
+    }
+    // Length and last ID, then the first ID, max deleted ID and entries added.
+    let lengths = if value_type >= TYPE_STREAM_LISTPACKS_2 { 8 } else { 3 };
+    for _ in 0..lengths {
+        reader.length()?;
+    }
+    for _ in 0..reader.len()? {
+        reader.string()?; // Group name.
+        reader.length()?; // Last delivered ID.
+        reader.length()?;
+        if value_type >= TYPE_STREAM_LISTPACKS_2 {
+            reader.length()?; // Entries read.
+        }
+        for _ in 0..reader.len()? {
+            reader.take(16 + 8)?; // Pending ID and delivery time.
+            reader.length()?; // Delivery count.
+        }
+        for _ in 0..reader.len()? {
+            reader.string()?; // Consumer name.
+            let times = if value_type >= TYPE_STREAM_LISTPACKS_3 { 2 } else { 1 };
+            reader.take(8 * times)?;
+            for _ in 0..reader.len()? {
+                reader.take(16)?; // Pending ID.
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Helper function: Pair up fields and values read from a flat encoding.
+fn to_hash(entries: Vec<Vec<u8>>) -> Result<Hash, StoreError> {
+    if !entries.len().is_multiple_of(2) {
+        return Err(StoreError::Storage("invalid RDB file: odd number of hash entries".into()));
+    }
+    let mut hash = Hash::new();
+    for pair in entries.chunks(2) {
+        hash.insert(&pair[0], &pair[1]);
+    }
+    Ok(hash)
+}
+
+/// Helper function: Pair up members and scores read from a flat encoding.
+fn to_sorted_set(entries: Vec<Vec<u8>>) -> Result<SortedSet, StoreError> {
+    if !entries.len().is_multiple_of(2) {
+        return Err(StoreError::Storage("invalid RDB file: odd number of sorted set entries".into()));
+    }
+    let mut zset = SortedSet::new();
+    for pair in entries.chunks(2) {
+        let score = parse_score(&pair[1])
+            .ok_or_else(|| StoreError::Storage("invalid RDB file: invalid score".into()))?;
+        zset.insert(&pair[0], score);
+    }
+    Ok(zset)
+}
+
+/// Helper function: Parse a score written as text; Redis writes infinities as `inf`.
+fn parse_score(text: &[u8]) -> Option<f64> {
+    let score = std::str::from_utf8(text).ok()?.parse::<f64>().ok()?;
+    (!score.is_nan()).then_some(score)
+}
+
+/// Helper function: Read the entries of a ziplist.
+fn ziplist(bytes: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+    let mut reader = Reader::new(bytes);
+    reader.take(4 + 4 + 2)?; // Total bytes, offset of the last entry and entry count.
+    let mut entries = Vec::new();
+    loop {
+        match reader.u8()? {
+            0xFF => return Ok(entries),
+            0xFE => {
+                reader.take(4)?; // Long length of the previous entry.
+            }
+            _ => {}
+        }
+        let encoding = reader.u8()?;
+        let entry = match encoding >> 6 {
+            0 => reader.take((encoding & 0x3F) as usize)?.to_vec(),
+            1 => {
+                let len = ((encoding as usize & 0x3F) << 8) | reader.u8()? as usize;
+                reader.take(len)?.to_vec()
+            }
+            2 => {
+                let len = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+                reader.take(len as usize)?.to_vec()
+            }
+            _ => {
+                let num = match encoding {
+                    0xC0 => reader.int_le(2)?,
+                    0xD0 => reader.int_le(4)?,
+                    0xE0 => reader.int_le(8)?,
+                    0xF0 => reader.int_le(3)?,
+                    0xFE => reader.int_le(1)?,
+                    0xF1..=0xFD => (encoding & 0x0F) as i64 - 1,
+                    _ => return Err(reader.error("invalid ziplist entry")),
+                };
+                num.to_string().into_bytes()
+            }
+        };
+        entries.push(entry);
+    }
+}
+
+/// Helper function: Read the entries of a listpack.
+fn listpack(bytes: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+    let mut reader = Reader::new(bytes);
+    reader.take(4 + 2)?; // Total bytes and entry count.
+    let mut entries = Vec::new();
+    loop {
+        let start = reader.pos;
+        let encoding = reader.u8()?;
+        let entry = if encoding == 0xFF {
+            return Ok(entries);
+        } else if encoding & 0x80 == 0 {
+            (encoding as i64).to_string().into_bytes()
+        } else if encoding & 0xC0 == 0x80 {
+            reader.take((encoding & 0x3F) as usize)?.to_vec()
+        } else if encoding & 0xE0 == 0xC0 {
+            let num = ((encoding as i64 & 0x1F) << 8) | reader.u8()? as i64;
+            let num = if num >= 1 << 12 { num - (1 << 13) } else { num };
+            num.to_string().into_bytes()
+        } else if encoding & 0xF0 == 0xE0 {
+            let len = ((encoding as usize & 0x0F) << 8) | reader.u8()? as usize;
+            reader.take(len)?.to_vec()
+        } else {
+            match encoding {
+                0xF0 => {
+                    let len = reader.u32_le()?;
+                    reader.take(len as usize)?.to_vec()
+                }
+                0xF1 => reader.int_le(2)?.to_string().into_bytes(),
+                0xF2 => reader.int_le(3)?.to_string().into_bytes(),
+                0xF3 => reader.int_le(4)?.to_string().into_bytes(),
+                0xF4 => reader.int_le(8)?.to_string().into_bytes(),
+                _ => return Err(reader.error("invalid listpack entry")),
+            }
+        };
+        // Each entry ends with its own size, so listpacks can be walked backwards.
+        let size = reader.pos - start;
+        let backlen = match size {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        };
+        reader.take(backlen)?;
+        entries.push(entry);
+    }
+}
+
+/// Helper function: Read the members of an intset.
+fn intset(bytes: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+    let mut reader = Reader::new(bytes);
+    let width = reader.u32_le()? as usize;
+    if !matches!(width, 2 | 4 | 8) {
+        return Err(reader.error("invalid intset encoding"));
+    }
+    (0..reader.u32_le()?)
+        .map(|_| Ok(reader.int_le(width)?.to_string().into_bytes()))
+        .collect()
+}
+
+/// Helper function: Read the fields and values of a zipmap, flattened.
+fn zipmap(bytes: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+    let mut reader = Reader::new(bytes);
+    reader.u8()?; // Entry count, unreliable past 253.
+    let mut entries = Vec::new();
+    loop {
+        let Some(len) = reader.zipmap_len()? else {
+            return Ok(entries);
+        };
+        entries.push(reader.take(len)?.to_vec());
+        let len = reader.zipmap_len()?.ok_or_else(|| reader.error("zipmap field without value"))?;
+        let free = reader.u8()? as usize;
+        entries.push(reader.take(len)?.to_vec());
+        reader.take(free)?;
+    }
+}
+
+/// Helper function: Decompress LZF data into `len` bytes.
+fn lzf_decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    if len > input.len().saturating_mul(LZF_MAX_EXPANSION) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(len.min(1 << 20));
+    let mut i = 0;
+    while i < input.len() {
+        if out.len() > len {
+            return None;
+        }
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            // A run of literal bytes.
+            out.extend_from_slice(input.get(i..i + ctrl + 1)?);
+            i += ctrl + 1;
+        } else {
+            // A back reference into the output.
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(i)? as usize;
+                i += 1;
+            }
+            let offset = ((ctrl & 0x1F) << 8) + *input.get(i)? as usize + 1;
+            i += 1;
+            let start = out.len().checked_sub(offset)?;
+            for k in 0..run + 2 {
+                out.push(out[start + k]);
+            }
+        }
+    }
+    (out.len() == len).then_some(out)
+}
+
+/// Cursor over the bytes of an RDB file or of one of its encoded values.
+struct Reader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Reader { input, pos: 0 }
+    }
+
+    /// Helper method: Error for the data at the current position.
+    fn error(&self, reason: &str) -> StoreError {
+        StoreError::Storage(format!("invalid RDB file at offset {}: {}", self.pos, reason))
+    }
+
+    /// Helper method: Take the next `len` bytes.
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StoreError> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.input.len());
+        let end = end.ok_or_else(|| self.error("unexpected end of data"))?;
+        let bytes = &self.input[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StoreError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32_le(&mut self) -> Result<u32, StoreError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64_le(&mut self) -> Result<u64, StoreError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Helper method: Read a little-endian signed integer of `width` bytes.
+    fn int_le(&mut self, width: usize) -> Result<i64, StoreError> {
+        let bytes = self.take(width)?;
+        let mut buf = [0u8; 8];
+        buf[..width].copy_from_slice(bytes);
+        // Shift the sign bit into place, then back, to sign-extend.
+        let shift = 64 - 8 * width as u32;
+        Ok((i64::from_le_bytes(buf) << shift) >> shift)
+    }
+
+    /// Helper method: Read a length, or a special string encoding (flagged true).
+    fn length_or_encoding(&mut self) -> Result<(u64, bool), StoreError> {
+        let first = self.u8()?;
+        let value = first as u64 & 0x3F;
+        match first >> 6 {
+            0 => Ok((value, false)),
+            1 => Ok((value << 8 | self.u8()? as u64, false)),
+            2 => match first {
+                0x80 => Ok((u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64, false)),
+                0x81 => Ok((u64::from_be_bytes(self.take(8)?.try_into().unwrap()), false)),
+                _ => Err(self.error("invalid length")),
+            },
+            _ => Ok((value, true)),
+        }
+    }
+
+    fn length(&mut self) -> Result<u64, StoreError> {
+        match self.length_or_encoding()? {
+            (len, false) => Ok(len),
+            (_, true) => Err(self.error("expected a length")),
+        }
+    }
+
+    fn len(&mut self) -> Result<usize, StoreError> {
+        let len = self.length()?;
+        usize::try_from(len).map_err(|_| self.error("length out of range"))
+    }
+
+    /// Helper method: Read a string, expanding integer and LZF encodings.
+    fn string(&mut self) -> Result<Vec<u8>, StoreError> {
+        let (len, encoded) = self.length_or_encoding()?;
+        if !encoded {
+            let len = usize::try_from(len).map_err(|_| self.error("length out of range"))?;
+            return Ok(self.take(len)?.to_vec());
+        }
+        let num = match len {
+            ENC_INT8 => self.int_le(1)?,
+            ENC_INT16 => self.int_le(2)?,
+            ENC_INT32 => self.int_le(4)?,
+            ENC_LZF => {
+                let compressed = self.len()?;
+                let len = self.len()?;
+                let input = self.take(compressed)?;
+                return lzf_decompress(input, len).ok_or_else(|| self.error("invalid LZF data"));
+            }
+            _ => return Err(self.error("unknown string encoding")),
+        };
+        Ok(num.to_string().into_bytes())
+    }
+
+    /// Helper method: Read a score of the original sorted set type, written as text.
+    fn text_score(&mut self) -> Result<f64, StoreError> {
+        match self.u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => {
+                let text = self.take(len as usize)?;
+                parse_score(text).ok_or_else(|| self.error("invalid score"))
+            }
+        }
+    }
+
+    /// Helper method: Read a zipmap length; None at the end of the zipmap.
+    fn zipmap_len(&mut self) -> Result<Option<usize>, StoreError> {
+        match self.u8()? {
+            255 => Ok(None),
+            254 => Ok(Some(self.u32_le()? as usize)),
+            len => Ok(Some(len as usize)),
+        }
+    }
+}
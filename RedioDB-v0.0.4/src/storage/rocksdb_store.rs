@@ -1,32 +1,791 @@
 // src/storage/rocksdb_store.rs
 //
-// A simple wrapper around RocksDB for persistent key–value storage.
-use rocksdb::{Options, DB};
+// RocksDB-backed storage engine.
+// Every key has a metadata record in the default column family: its type and, for strings and
+// streams, the value itself. The elements of lists, sets, hashes and sorted sets are stored
+// one per RocksDB key in a column family per type, under a composite key
+// `<database><key length><key><element>`, so a container is read with one prefix scan and
+// removed with one range delete. A change made in place writes only the elements it touched:
+// list elements are keyed by a sequence number that pushes extend at either end, with the
+// bounds kept in the metadata record, so no element moves when the list grows or shrinks.
+// Every record and element starts with the key's wall-clock deadline, so a compaction filter
+// can drop expired data without looking anything up; changing the deadline of a container
+// rewrites its elements. All logical databases share one RocksDB instance, each under its
+// own 4-byte prefix. Keys are read when a command first needs them (see
+// `ShardedStore::backed_by`), so the dataset does not have to fit in memory.
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use indexmap::IndexSet;
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{
+    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, CompactionDecision,
+    DBCompressionType, Direction, IteratorMode, Options, SliceTransform, Snapshot, WriteBatch,
+    WriteOptions, DB,
+};
+
+use crate::storage::codec::{self, corrupt, put_bytes, put_deadline, put_f64, put_varint, Decoder};
+use crate::storage::engine::{
+    parse_flag, BatchOp, Edit, Record, StorageEngine, DEFAULT_DATA_DIR,
+};
+use crate::storage::error::StoreError;
+use crate::storage::eviction::parse_memory_size;
+use crate::storage::hash::Hash;
+use crate::storage::sorted_set::SortedSet;
+use crate::storage::ttl_store::StoreValue;
+
+/// Size of the block cache when `REDIO_ROCKSDB_BLOCK_CACHE` is not set.
+pub const DEFAULT_BLOCK_CACHE: usize = 64 * 1024 * 1024;
+
+/// Column families holding the elements of each container type.
+const LIST_FAMILY: &str = "list";
+const SET_FAMILY: &str = "set";
+const HASH_FAMILY: &str = "hash";
+const ZSET_FAMILY: &str = "zset";
+const ELEMENT_FAMILIES: [&str; 4] = [LIST_FAMILY, SET_FAMILY, HASH_FAMILY, ZSET_FAMILY];
+
+/// Length of the logical database prefix of every RocksDB key.
+const DATABASE_PREFIX_LEN: usize = 4;
+
+/// Sequence number of the first element of a list written whole. Pushes onto the head count
+/// down from it and pushes onto the tail count up, so there is room both ways.
+pub const LIST_ORIGIN: u64 = 1 << 63;
+
+/// A hash field's own deadline and value.
+type HashField = (Option<SystemTime>, Vec<u8>);
+
+/// The hash fields a batch of edits set (Some) or removed (None), by name.
+type FieldChanges<'a> = HashMap<&'a [u8], Option<HashField>>;
+
+/// Block compression of the database files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "snappy" => Ok(Compression::Snappy),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("invalid compression '{}'", s)),
+        }
+    }
+}
+
+/// Where the database lives and how RocksDB is tuned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RocksDBConfig {
+    /// Directory of the database; created if missing.
+    pub path: PathBuf,
+    /// Size of the LRU cache of uncompressed blocks shared by every column family, in bytes.
+    pub block_cache: usize,
+    /// Compression of the database files.
+    pub compression: Compression,
+    /// Whether every write waits for the write-ahead log to reach the disk. Without it, a
+    /// machine crash can lose the last writes; a crash of the server alone cannot.
+    pub sync_writes: bool,
+}
+
+impl RocksDBConfig {
+    /// A configuration with the default tuning for the database in `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        RocksDBConfig {
+            path: path.into(),
+            block_cache: DEFAULT_BLOCK_CACHE,
+            compression: Compression::Lz4,
+            sync_writes: false,
+        }
+    }
+
+    /// Reads the configuration from `REDIO_DATA_DIR`, `REDIO_ROCKSDB_BLOCK_CACHE` (a size
+    /// such as `256mb`), `REDIO_ROCKSDB_COMPRESSION` (`none`, `snappy`, `lz4` or `zstd`) and
+    /// `REDIO_ROCKSDB_SYNC` (`yes` or `no`), falling back to the defaults for missing values.
+    pub fn from_env() -> Result<Self, String> {
+        let path = env::var("REDIO_DATA_DIR").unwrap_or_else(|_| DEFAULT_DATA_DIR.to_string());
+        let mut config = RocksDBConfig::new(path);
+        if let Ok(value) = env::var("REDIO_ROCKSDB_BLOCK_CACHE") {
+            config.block_cache = parse_memory_size(&value)?;
+        }
+        if let Ok(value) = env::var("REDIO_ROCKSDB_COMPRESSION") {
+            config.compression = value.parse()?;
+        }
+        if let Ok(value) = env::var("REDIO_ROCKSDB_SYNC") {
+            config.sync_writes = parse_flag(&value)
+                .ok_or_else(|| format!("invalid REDIO_ROCKSDB_SYNC '{}'", value))?;
+        }
+        Ok(config)
+    }
+}
+
+/// A handle on a RocksDB database, scoped to one logical database's keys.
 pub struct RocksDBStore {
-    db: DB,
+    db: Arc<DB>,
+    /// Prefix of every key written through this handle.
+    prefix: Vec<u8>,
+    /// Whether writes wait for the write-ahead log to be synced.
+    sync_writes: bool,
 }
 
 impl RocksDBStore {
-    /// Opens a RocksDB database at the specified path.
-    pub fn new(path: &str) -> Self {
-        let mut opts = Options::default();
+    /// Opens the database described by `config`, creating it if needed.
+    /// The handle covers database 0; use `database` for the others.
+    pub fn open(config: &RocksDBConfig) -> Result<Self, StoreError> {
+        let cache = Cache::new_lru_cache(config.block_cache).map_err(storage_error)?;
+        let mut opts = family_options(
+            config,
+            &cache,
+            SliceTransform::create_fixed_prefix(DATABASE_PREFIX_LEN),
+        );
         opts.create_if_missing(true);
-        let db = DB::open(&opts, path).expect("Failed to open RocksDB");
-        RocksDBStore { db }
+        opts.create_missing_column_families(true);
+        let families = ELEMENT_FAMILIES.iter().map(|name| {
+            let prefix =
+                SliceTransform::create("redio-element", element_prefix, Some(is_element_key));
+            ColumnFamilyDescriptor::new(*name, family_options(config, &cache, prefix))
+        });
+        let db = DB::open_cf_descriptors(&opts, &config.path, families).map_err(storage_error)?;
+        Ok(RocksDBStore {
+            db: Arc::new(db),
+            prefix: database_prefix(0),
+            sync_writes: config.sync_writes,
+        })
+    }
+
+    /// A handle on the same RocksDB database for logical database `index`.
+    pub fn database(&self, index: usize) -> RocksDBStore {
+        RocksDBStore {
+            db: Arc::clone(&self.db),
+            prefix: database_prefix(index),
+            sync_writes: self.sync_writes,
+        }
+    }
+
+    /// Writes a checkpoint of the whole RocksDB database, every logical database included,
+    /// to `dir`, which must not exist yet. SST files are hard-linked when `dir` is on the
+    /// same filesystem.
+    pub fn checkpoint(&self, dir: &Path) -> Result<(), StoreError> {
+        let checkpoint = Checkpoint::new(&self.db).map_err(storage_error)?;
+        checkpoint.create_checkpoint(dir).map_err(storage_error)
+    }
+
+    /// A consistent view of the whole RocksDB database, every logical database included, as
+    /// of now. Writes made later are not seen through it.
+    pub fn snapshot(&self) -> RocksDBSnapshot<'_> {
+        RocksDBSnapshot { snapshot: self.db.snapshot() }
+    }
+
+    /// Helper method: The metadata key of `key`.
+    fn meta_key(&self, key: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), key].concat()
+    }
+
+    /// Helper method: The prefix shared by every element of `key`.
+    fn element_prefix(&self, key: &[u8]) -> Vec<u8> {
+        let mut prefix = self.prefix.clone();
+        prefix.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        prefix.extend_from_slice(key);
+        prefix
+    }
+
+    /// Helper method: The handle of a column family created by `open`.
+    fn family(&self, name: &str) -> Result<&ColumnFamily, StoreError> {
+        self.db
+            .cf_handle(name)
+            .ok_or_else(|| StoreError::Storage(format!("missing column family '{}'", name)))
+    }
+
+    /// Helper method: Assemble the record stored under metadata `bytes` for `key`, or None
+    /// if it expired.
+    fn load(&self, key: &[u8], bytes: &[u8]) -> Result<Option<Record>, StoreError> {
+        let Meta {
+            expire_at,
+            mut value,
+            bounds: (head, tail),
+        } = decode_meta(bytes)?;
+        if !is_live(expire_at) {
+            return Ok(None);
+        }
+        if let Some(family) = element_family(&value) {
+            let prefix = self.element_prefix(key);
+            for (element_key, bytes) in self.db.prefix_iterator_cf(self.family(family)?, &prefix) {
+                if !element_key.starts_with(&prefix) {
+                    break;
+                }
+                let element = &element_key[prefix.len()..];
+                if matches!(value, StoreValue::List(_))
+                    && !(head..tail).contains(&sequence(element)?)
+                {
+                    continue;
+                }
+                // Elements left over from an earlier value of the key that already expired.
+                let (element_expire_at, payload) = split_deadline(&bytes)?;
+                if is_live(element_expire_at) {
+                    add_element(&mut value, element, payload)?;
+                }
+            }
+        }
+        Ok(Some((value, expire_at)))
+    }
+
+    /// Helper method: The metadata record of `key`, expired or not.
+    fn meta(&self, key: &[u8]) -> Result<Option<Meta>, StoreError> {
+        match self.db.get(self.meta_key(key)).map_err(storage_error)? {
+            Some(bytes) => decode_meta(&bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Helper method: Add the writes storing (or, without a value, removing) `key` whole
+    /// to `writes`.
+    fn stage(
+        &self,
+        writes: &mut WriteBatch,
+        key: &[u8],
+        record: Option<(&StoreValue, Option<SystemTime>)>,
+    ) -> Result<(), StoreError> {
+        let meta_key = self.meta_key(key);
+        let prefix = self.element_prefix(key);
+        // Clear the elements of the previous value, whatever its type, and any stale ones
+        // of the new type.
+        let mut stale = Vec::new();
+        if let Some(meta) = self.meta(key)? {
+            stale.extend(element_family(&meta.value));
+        }
+        stale.extend(record.and_then(|(value, _)| element_family(value)));
+        stale.dedup();
+        for family in stale {
+            writes.delete_range_cf(self.family(family)?, &prefix, &prefix_end(&prefix));
+        }
+
+        let Some((value, expire_at)) = record else {
+            writes.delete(&meta_key);
+            return Ok(());
+        };
+        let meta = Meta {
+            expire_at,
+            value: header(value),
+            bounds: match value {
+                StoreValue::List(list) => (LIST_ORIGIN, LIST_ORIGIN + list.len() as u64),
+                _ => (0, 0),
+            },
+        };
+        writes.put(&meta_key, meta.encode());
+        if let Some(family) = element_family(value) {
+            let family = self.family(family)?;
+            for (element, payload) in elements(value) {
+                let element_key = [prefix.as_slice(), &element].concat();
+                writes.put_cf(family, element_key, with_deadline(expire_at, &payload));
+            }
+        }
+        Ok(())
+    }
+
+    /// Helper method: Add the writes applying `edits` to `key` in place to `writes`: one
+    /// per element added or removed, plus the metadata record if the deadline or the list
+    /// bounds moved. A missing or expired key is left alone.
+    fn update(&self, writes: &mut WriteBatch, key: &[u8], edits: &[Edit]) -> Result<(), StoreError> {
+        let Some(mut meta) = self.meta(key)? else {
+            return Ok(());
+        };
+        if !is_live(meta.expire_at) {
+            return Ok(());
+        }
+        let prefix = self.element_prefix(key);
+        let family = element_family(&meta.value);
+        let (old_expire_at, old_bounds) = (meta.expire_at, meta.bounds);
+        // Every element written carries the deadline the key ends up with.
+        for edit in edits {
+            if let Edit::Deadline(expire_at) = edit {
+                meta.expire_at = *expire_at;
+            }
+        }
+        let expire_at = meta.expire_at;
+        if let Some(family) = family.filter(|_| expire_at != old_expire_at) {
+            let family = self.family(family)?;
+            for (element_key, bytes) in self.db.prefix_iterator_cf(family, &prefix) {
+                if !element_key.starts_with(&prefix) {
+                    break;
+                }
+                let (_, payload) = split_deadline(&bytes)?;
+                writes.put_cf(family, &element_key, with_deadline(expire_at, payload));
+            }
+        }
+
+        // Hash fields written by earlier edits, for the edits that keep part of a field.
+        let mut fields: FieldChanges<'_> = HashMap::new();
+        for edit in edits {
+            let element_key = |element: &[u8]| [prefix.as_slice(), element].concat();
+            match (edit, &meta.value) {
+                (Edit::Deadline(_), _) => {}
+                (Edit::Push { front, values }, StoreValue::List(_)) => {
+                    let family = self.family(LIST_FAMILY)?;
+                    for value in values {
+                        let position = if *front {
+                            meta.bounds.0 -= 1;
+                            meta.bounds.0
+                        } else {
+                            meta.bounds.1 += 1;
+                            meta.bounds.1 - 1
+                        };
+                        let element = element_key(&position.to_be_bytes());
+                        writes.put_cf(family, element, with_deadline(expire_at, value));
+                    }
+                }
+                (Edit::Pop { front }, StoreValue::List(_)) => {
+                    let (head, tail) = &mut meta.bounds;
+                    if head == tail {
+                        continue;
+                    }
+                    let position = if *front {
+                        *head += 1;
+                        *head - 1
+                    } else {
+                        *tail -= 1;
+                        *tail
+                    };
+                    let element = element_key(&position.to_be_bytes());
+                    writes.delete_cf(self.family(LIST_FAMILY)?, element);
+                }
+                (Edit::SetAdd(member), StoreValue::Set(_)) => {
+                    let element = element_key(member);
+                    writes.put_cf(self.family(SET_FAMILY)?, element, with_deadline(expire_at, b""));
+                }
+                (Edit::SetRemove(member), StoreValue::Set(_)) => {
+                    writes.delete_cf(self.family(SET_FAMILY)?, element_key(member));
+                }
+                (Edit::HashSet { field, value }, StoreValue::Hash(_)) => {
+                    fields.insert(field, Some((None, value.clone())));
+                }
+                (Edit::HashUpdate { field, value }, StoreValue::Hash(_)) => {
+                    let deadline = self
+                        .hash_field(&fields, &prefix, field)?
+                        .and_then(|(deadline, _)| deadline);
+                    fields.insert(field, Some((deadline, value.clone())));
+                }
+                (Edit::HashDeadline { field, expire_at }, StoreValue::Hash(_)) => {
+                    if let Some((_, value)) = self.hash_field(&fields, &prefix, field)? {
+                        fields.insert(field, Some((*expire_at, value)));
+                    }
+                }
+                (Edit::HashRemove(field), StoreValue::Hash(_)) => {
+                    fields.insert(field, None);
+                }
+                (Edit::ZAdd { member, score }, StoreValue::SortedSet(_)) => {
+                    let mut payload = Vec::new();
+                    put_f64(&mut payload, *score);
+                    let element = element_key(member);
+                    writes.put_cf(self.family(ZSET_FAMILY)?, element, with_deadline(expire_at, &payload));
+                }
+                (Edit::ZRemove(member), StoreValue::SortedSet(_)) => {
+                    writes.delete_cf(self.family(ZSET_FAMILY)?, element_key(member));
+                }
+                _ => return Err(StoreError::WrongType),
+            }
+        }
+        if !fields.is_empty() {
+            let family = self.family(HASH_FAMILY)?;
+            for (field, change) in fields {
+                let element = [prefix.as_slice(), field].concat();
+                match change {
+                    Some((deadline, value)) => {
+                        let payload = hash_payload(deadline, &value);
+                        writes.put_cf(family, element, with_deadline(expire_at, &payload));
+                    }
+                    None => writes.delete_cf(family, element),
+                }
+            }
+        }
+        if (meta.expire_at, meta.bounds) != (old_expire_at, old_bounds) {
+            writes.put(self.meta_key(key), meta.encode());
+        }
+        Ok(())
+    }
+
+    /// Helper method: The deadline and value of hash field `field` of the key with element
+    /// prefix `prefix`, as left by the edits recorded in `fields` or else as stored.
+    fn hash_field(
+        &self,
+        fields: &FieldChanges<'_>,
+        prefix: &[u8],
+        field: &[u8],
+    ) -> Result<Option<HashField>, StoreError> {
+        if let Some(change) = fields.get(field) {
+            return Ok(change.clone());
+        }
+        let element_key = [prefix, field].concat();
+        let family = self.family(HASH_FAMILY)?;
+        match self.db.get_cf(family, element_key).map_err(storage_error)? {
+            Some(bytes) => {
+                let mut decoder = Decoder::new(split_deadline(&bytes)?.1);
+                Ok(Some((decoder.deadline()?, decoder.bytes()?)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Helper method: Apply `writes` atomically.
+    fn commit(&self, writes: WriteBatch) -> Result<(), StoreError> {
+        let mut opts = WriteOptions::default();
+        opts.set_sync(self.sync_writes);
+        self.db.write_opt(writes, &opts).map_err(storage_error)
+    }
+}
+
+/// A point-in-time view of a RocksDB database, taken by `RocksDBStore::snapshot`.
+pub struct RocksDBSnapshot<'a> {
+    snapshot: Snapshot<'a>,
+}
+
+impl RocksDBSnapshot<'_> {
+    /// Number of live keys logical database `index` held when the snapshot was taken.
+    pub fn count_live(&self, index: usize) -> Result<usize, StoreError> {
+        let prefix = database_prefix(index);
+        let mode = IteratorMode::From(&prefix, Direction::Forward);
+        let mut count = 0;
+        for (meta_key, bytes) in self.snapshot.iterator(mode) {
+            if !meta_key.starts_with(&prefix) {
+                break;
+            }
+            if is_live(decode_meta(&bytes)?.expire_at) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl StorageEngine for RocksDBStore {
+    fn read_record(&self, key: &[u8]) -> Result<Option<Record>, StoreError> {
+        match self.db.get(self.meta_key(key)).map_err(storage_error)? {
+            Some(bytes) => self.load(key, &bytes),
+            None => Ok(None),
+        }
+    }
+
+    fn write_record(
+        &self,
+        key: &[u8],
+        value: &StoreValue,
+        expire_at: Option<SystemTime>,
+    ) -> Result<(), StoreError> {
+        self.write_batch(&[BatchOp::Put {
+            key,
+            value,
+            expire_at,
+        }])
+    }
+
+    fn delete_record(&self, key: &[u8]) -> Result<bool, StoreError> {
+        let existed = self.meta(key)?.is_some_and(|meta| is_live(meta.expire_at));
+        self.write_batch(&[BatchOp::Delete { key }])?;
+        Ok(existed)
+    }
+
+    fn set_deadline(
+        &self,
+        key: &[u8],
+        expire_at: Option<SystemTime>,
+    ) -> Result<bool, StoreError> {
+        if !self.meta(key)?.is_some_and(|meta| is_live(meta.expire_at)) {
+            return Ok(false);
+        }
+        let edits = [Edit::Deadline(expire_at)];
+        self.write_batch(&[BatchOp::Update { key, edits: &edits }]).map(|_| true)
+    }
+
+    fn for_each_record(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], Record),
+    ) -> Result<(), StoreError> {
+        let start = self.meta_key(prefix);
+        let mode = IteratorMode::From(&start, Direction::Forward);
+        for (meta_key, bytes) in self.db.iterator(mode) {
+            if !meta_key.starts_with(&start) {
+                break;
+            }
+            let key = &meta_key[self.prefix.len()..];
+            if let Some(record) = self.load(key, &bytes)? {
+                visit(key, record);
+            }
+        }
+        Ok(())
+    }
+
+    fn for_each_key(
+        &self,
+        start: &[u8],
+        visit: &mut dyn FnMut(&[u8], &'static str) -> bool,
+    ) -> Result<(), StoreError> {
+        // Only the metadata records are read, never the elements.
+        let start = self.meta_key(start);
+        let mode = IteratorMode::From(&start, Direction::Forward);
+        for (meta_key, bytes) in self.db.iterator(mode) {
+            if !meta_key.starts_with(&self.prefix) {
+                break;
+            }
+            let meta = decode_meta(&bytes)?;
+            if is_live(meta.expire_at) && !visit(&meta_key[self.prefix.len()..], meta.value.type_name()) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_batch(&self, batch: &[BatchOp<'_>]) -> Result<(), StoreError> {
+        let mut writes = WriteBatch::default();
+        for op in batch {
+            match *op {
+                BatchOp::Put {
+                    key,
+                    value,
+                    expire_at,
+                } => self.stage(&mut writes, key, Some((value, expire_at)))?,
+                BatchOp::Delete { key } => self.stage(&mut writes, key, None)?,
+                BatchOp::Update { key, edits } => self.update(&mut writes, key, edits)?,
+            }
+        }
+        self.commit(writes)
+    }
+}
+
+/// A decoded metadata record.
+struct Meta {
+    expire_at: Option<SystemTime>,
+    /// The value itself, or an empty value of its type if its elements are stored apart.
+    value: StoreValue,
+    /// For a list, the sequence numbers of its first element and of the one after its last.
+    bounds: (u64, u64),
+}
+
+impl Meta {
+    /// Encodes the record, deadline first.
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        codec::encode_value(&self.value, &mut bytes);
+        if let StoreValue::List(_) = self.value {
+            put_varint(&mut bytes, self.bounds.0);
+            put_varint(&mut bytes, self.bounds.1);
+        }
+        with_deadline(self.expire_at, &bytes)
+    }
+}
+
+/// Helper function: Decode a metadata record written by `Meta::encode`.
+fn decode_meta(bytes: &[u8]) -> Result<Meta, StoreError> {
+    let (expire_at, payload) = split_deadline(bytes)?;
+    let mut decoder = Decoder::new(payload);
+    let value = codec::decode_value(&mut decoder)?;
+    let bounds = match value {
+        StoreValue::List(_) => (decoder.varint()?, decoder.varint()?),
+        _ => (0, 0),
+    };
+    Ok(Meta {
+        expire_at,
+        value,
+        bounds,
+    })
+}
+
+/// Helper function: The sequence number of a list element, from its element key.
+fn sequence(element: &[u8]) -> Result<u64, StoreError> {
+    element
+        .try_into()
+        .map(u64::from_be_bytes)
+        .map_err(|_| corrupt("list element key"))
+}
+
+/// Helper function: The payload of a hash field: its own deadline, then its value.
+fn hash_payload(expire_at: Option<SystemTime>, value: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    put_deadline(&mut payload, expire_at);
+    put_bytes(&mut payload, value);
+    payload
+}
+
+/// Helper function: Options shared by every column family.
+fn family_options(config: &RocksDBConfig, cache: &Cache, prefix: SliceTransform) -> Options {
+    let mut table = BlockBasedOptions::default();
+    table.set_block_cache(cache);
+    table.set_bloom_filter(10, false);
+    let mut opts = Options::default();
+    opts.set_block_based_table_factory(&table);
+    opts.set_prefix_extractor(prefix);
+    opts.set_memtable_prefix_bloom_ratio(0.1);
+    opts.set_compression_type(match config.compression {
+        Compression::None => DBCompressionType::None,
+        Compression::Snappy => DBCompressionType::Snappy,
+        Compression::Lz4 => DBCompressionType::Lz4,
+        Compression::Zstd => DBCompressionType::Zstd,
+    });
+    opts.set_compaction_filter("redio-expired", drop_expired);
+    opts
+}
+
+/// Compaction filter dropping records and elements whose deadline passed.
+pub fn drop_expired(_level: u32, _key: &[u8], value: &[u8]) -> CompactionDecision {
+    match split_deadline(value) {
+        Ok((expire_at, _)) if !is_live(expire_at) => CompactionDecision::Remove,
+        _ => CompactionDecision::Keep,
     }
+}
+
+/// Helper function: Prefix extractor of element keys: everything up to the element itself.
+fn element_prefix(element_key: &[u8]) -> &[u8] {
+    let start = DATABASE_PREFIX_LEN + 4;
+    let len = u32::from_be_bytes(element_key[DATABASE_PREFIX_LEN..start].try_into().unwrap());
+    &element_key[..start + len as usize]
+}
+
+/// Helper function: Whether `element_key` is long enough for `element_prefix`.
+fn is_element_key(element_key: &[u8]) -> bool {
+    let start = DATABASE_PREFIX_LEN + 4;
+    element_key.len() >= start
+        && element_key.len()
+            >= start
+                + u32::from_be_bytes(element_key[DATABASE_PREFIX_LEN..start].try_into().unwrap())
+                    as usize
+}
+
+/// Helper function: Key prefix of logical database `index`.
+fn database_prefix(index: usize) -> Vec<u8> {
+    (index as u32).to_be_bytes().to_vec()
+}
 
-    /// Sets a key-value pair in the database.
-    pub fn set(&self, key: &str, value: &str) {
-        self.db.put(key.as_bytes(), value.as_bytes())
-            .expect("Failed to write to RocksDB");
+/// Helper function: The smallest key greater than every key starting with `prefix`.
+/// Prefixes always start with a database index far below `u32::MAX`, so one exists.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            break;
+        }
     }
+    end
+}
 
-    /// Retrieves a value for a key from the database.
-    pub fn get(&self, key: &str) -> Option<String> {
-        match self.db.get(key.as_bytes()) {
-            Ok(Some(value)) => Some(String::from_utf8(value).unwrap_or_default()),
-            _ => None,
+/// Prepends a deadline, as big-endian Unix milliseconds (0 for none).
+pub fn with_deadline(expire_at: Option<SystemTime>, payload: &[u8]) -> Vec<u8> {
+    let ms = expire_at.map_or(0, |at| codec::system_time_ms(at).max(1));
+    [&ms.to_be_bytes()[..], payload].concat()
+}
+
+/// Splits the deadline written by `with_deadline` from the payload.
+pub fn split_deadline(bytes: &[u8]) -> Result<(Option<SystemTime>, &[u8]), StoreError> {
+    if bytes.len() < 8 {
+        return Err(corrupt("missing deadline"));
+    }
+    let (deadline, payload) = bytes.split_at(8);
+    let expire_at = match u64::from_be_bytes(deadline.try_into().unwrap()) {
+        0 => None,
+        ms => Some(UNIX_EPOCH + Duration::from_millis(ms)),
+    };
+    Ok((expire_at, payload))
+}
+
+/// Helper function: Whether a record with deadline `expire_at` has not expired yet.
+fn is_live(expire_at: Option<SystemTime>) -> bool {
+    expire_at.is_none_or(|at| at > SystemTime::now())
+}
+
+/// Helper function: Column family of the elements of `value`, None for strings and streams,
+/// which are stored whole in their metadata record.
+fn element_family(value: &StoreValue) -> Option<&'static str> {
+    match value {
+        StoreValue::List(_) => Some(LIST_FAMILY),
+        StoreValue::Set(_) => Some(SET_FAMILY),
+        StoreValue::Hash(_) => Some(HASH_FAMILY),
+        StoreValue::SortedSet(_) => Some(ZSET_FAMILY),
+        StoreValue::Simple(_) | StoreValue::Stream(_) => None,
+    }
+}
+
+/// What the metadata record stores: the value itself, or an empty value of its type when
+/// the elements are stored separately.
+pub fn header(value: &StoreValue) -> StoreValue {
+    match value {
+        StoreValue::List(_) => StoreValue::List(VecDeque::new()),
+        StoreValue::Set(_) => StoreValue::Set(IndexSet::new()),
+        StoreValue::Hash(_) => StoreValue::Hash(Hash::new()),
+        StoreValue::SortedSet(_) => StoreValue::SortedSet(SortedSet::new()),
+        other => other.clone(),
+    }
+}
+
+/// The elements of a container as (element key, payload) pairs. List elements are keyed by
+/// big-endian sequence number from `LIST_ORIGIN` up, so a prefix scan returns them in order.
+pub fn elements(value: &StoreValue) -> Vec<(Vec<u8>, Vec<u8>)> {
+    match value {
+        StoreValue::List(list) => (LIST_ORIGIN..)
+            .zip(list)
+            .map(|(position, item)| (position.to_be_bytes().to_vec(), item.clone()))
+            .collect(),
+        StoreValue::Set(set) => set
+            .iter()
+            .map(|member| (member.clone(), Vec::new()))
+            .collect(),
+        StoreValue::Hash(hash) => hash
+            .iter()
+            .map(|(field, value)| {
+                let deadline = hash.deadline(field).flatten();
+                (field.clone(), hash_payload(deadline.map(codec::to_system_time), value))
+            })
+            .collect(),
+        StoreValue::SortedSet(zset) => zset
+            .iter()
+            .map(|(member, score)| {
+                let mut payload = Vec::new();
+                put_f64(&mut payload, score);
+                (member.to_vec(), payload)
+            })
+            .collect(),
+        StoreValue::Simple(_) | StoreValue::Stream(_) => Vec::new(),
+    }
+}
+
+/// Adds an element read back from its column family to a container. List elements must be
+/// added in sequence order.
+pub fn add_element(
+    value: &mut StoreValue,
+    element: &[u8],
+    payload: &[u8],
+) -> Result<(), StoreError> {
+    let mut decoder = Decoder::new(payload);
+    match value {
+        StoreValue::List(list) => list.push_back(payload.to_vec()),
+        StoreValue::Set(set) => {
+            set.insert(element.to_vec());
+        }
+        StoreValue::Hash(hash) => {
+            let deadline = decoder.deadline()?;
+            hash.insert(element, &decoder.bytes()?);
+            if let Some(at) = deadline {
+                hash.expire_at(element, codec::to_instant(at));
+            }
+        }
+        StoreValue::SortedSet(zset) => {
+            zset.insert(element, decoder.f64()?);
+        }
+        StoreValue::Simple(_) | StoreValue::Stream(_) => {
+            return Err(corrupt("element of a value without elements"))
         }
     }
+    Ok(())
+}
+
+/// Helper function: Convert a RocksDB error.
+fn storage_error(err: rocksdb::Error) -> StoreError {
+    StoreError::Storage(err.into_string())
 }
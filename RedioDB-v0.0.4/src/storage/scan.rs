@@ -0,0 +1,58 @@
+// src/storage/scan.rs
+//
+// Cursor-based iteration for SCAN, SSCAN, HSCAN and ZSCAN.
+//
+// The keyspace and the set, hash and sorted set types are all IndexMap/IndexSet backed. Their
+// entries only ever move when an entry is swap-removed, and then the last entry moves down
+// into the hole. A cursor therefore walks positions from the end towards the front: an entry
+// moved down from an already visited position may be returned twice, but an entry that is
+// present for the whole iteration is never missed. Entries added meanwhile land at the end and
+// may or may not be returned, as in Redis.
+use std::ops::Range;
+
+use crate::glob::glob_match;
+
+/// Number of positions visited per call when no COUNT is given.
+pub const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// MATCH and COUNT hints shared by the SCAN family.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Only return entries matching this glob pattern.
+    pub pattern: Option<Vec<u8>>,
+    /// Number of positions to visit; 0 means `DEFAULT_SCAN_COUNT`.
+    pub count: usize,
+}
+
+impl ScanOptions {
+    /// Returns true if `value` passes the MATCH filter.
+    pub fn matches(&self, value: &[u8]) -> bool {
+        match &self.pattern {
+            Some(pattern) => glob_match(pattern, value),
+            None => true,
+        }
+    }
+}
+
+/// One page of a scan: the cursor to resume from (0 once the iteration is complete) and the
+/// entries found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanPage<T> {
+    pub cursor: u64,
+    pub items: Vec<T>,
+}
+
+/// Computes the positions to visit for `cursor` in a collection of `len` entries, and the
+/// cursor to return. Positions are meant to be visited from the end of the range down.
+///
+/// Cursor 0 starts a new iteration; any other cursor is the number of positions left to
+/// visit, clamped to the current length.
+pub fn scan_window(len: usize, cursor: u64, count: usize) -> (Range<usize>, u64) {
+    let count = if count == 0 { DEFAULT_SCAN_COUNT } else { count };
+    let end = match cursor {
+        0 => len,
+        cursor => usize::try_from(cursor).unwrap_or(usize::MAX).min(len),
+    };
+    let start = end.saturating_sub(count);
+    (start..end, start as u64)
+}
@@ -0,0 +1,668 @@
+// src/storage/sharded.rs
+//
+// Lock-striped keyspace.
+// Each database splits its keys across a fixed number of TTLStore shards, each behind its own
+// lock, so commands on different keys run in parallel instead of queueing on one global lock.
+// Keys are routed by hash; like Redis Cluster, only the part between the first `{` and the
+// next `}` is hashed when it is not empty, so `{user:1}:name` and `{user:1}:email` always
+// share a shard. Commands touching several shards lock them in index order, so they can never
+// deadlock with each other.
+//
+// With a durable storage engine, the keys a command changed are written through to it, in one
+// batch, before its shard locks are released. Writes run through `write` and `write_keys`,
+// which commit the changes and fail the command if the engine could not store them, so a
+// client is never told a lost write succeeded. The shards are either loaded from the engine
+// on creation, or, with an engine loaded on demand, act as a cache in front of it: `read`,
+// `write` and their multi-key forms read each key the command names from the engine the
+// first time it is needed, and keyspace-wide commands (KEYS, SCAN, DBSIZE, FLUSHDB) go to
+// the engine itself.
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use indexmap::IndexMap;
+
+use crate::glob::glob_match;
+use crate::storage::codec;
+use crate::storage::engine::{BatchOp, Record, StorageEngine};
+use crate::storage::error::StoreError;
+use crate::storage::eviction::MemoryConfig;
+use crate::storage::multi_key::KeyShards;
+use crate::storage::scan::{ScanOptions, ScanPage, DEFAULT_SCAN_COUNT};
+use crate::storage::ttl_store::{KeyChange, TTLStore};
+use crate::storage::util::lock;
+
+/// Number of shards per database when `REDIO_SHARDS` is not set.
+pub const DEFAULT_SHARDS: usize = 16;
+
+/// Number of SCAN cursors over an on-demand engine remembered at once; older ones expire.
+const MAX_SCAN_CURSORS: usize = 1024;
+
+/// Number of keys FLUSHDB removes from an on-demand engine per batch.
+const FLUSH_BATCH: usize = 1024;
+
+/// A locked shard and its index.
+pub type LockedShard<'a> = (usize, ShardGuard<'a>);
+
+/// Returns the part of `key` that decides its shard: the first non-empty `{...}` hash tag,
+/// or the whole key.
+pub fn hash_tag(key: &[u8]) -> &[u8] {
+    if let Some(open) = key.iter().position(|&byte| byte == b'{') {
+        let rest = &key[open + 1..];
+        if let Some(close) = rest.iter().position(|&byte| byte == b'}') {
+            if close > 0 {
+                return &rest[..close];
+            }
+        }
+    }
+    key
+}
+
+/// Index of the shard holding `key` among `count` shards.
+pub(crate) fn shard_index(key: &[u8], count: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    hash_tag(key).hash(&mut hasher);
+    (hasher.finish() % count as u64) as usize
+}
+
+/// One database's keyspace, split across lock-striped TTLStore shards.
+pub struct ShardedStore {
+    shards: Vec<Mutex<TTLStore>>,
+    /// Durable engine every change is written through to; None for a memory-only keyspace.
+    /// Engines synchronize internally, so shards write through to it in parallel.
+    engine: Option<Box<dyn StorageEngine>>,
+    /// Whether keys are read from the engine when a command first needs them, instead of
+    /// all being loaded on creation.
+    on_demand: bool,
+    /// Where the SCANs over an on-demand engine resume.
+    cursors: Mutex<ScanCursors>,
+}
+
+impl ShardedStore {
+    /// Creates an empty keyspace with `shards` shards (at least 1).
+    pub fn new(shards: usize) -> Self {
+        ShardedStore {
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(TTLStore::new()))
+                .collect(),
+            engine: None,
+            on_demand: false,
+            cursors: Mutex::new(ScanCursors::default()),
+        }
+    }
+
+    /// Creates a keyspace with `shards` shards backed by a durable engine, loading every
+    /// live key it holds. The engine is never read again, so evicted keys are removed from
+    /// it as well.
+    pub fn with_engine(
+        shards: usize,
+        engine: Box<dyn StorageEngine>,
+    ) -> Result<Self, StoreError> {
+        let mut store = ShardedStore::new(shards);
+        let count = store.shards.len();
+        let stores = &mut store.shards;
+        engine.for_each_record(b"", &mut |key, (value, expire_at)| {
+            let shard = stores[shard_index(key, count)]
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner);
+            shard.restore_entry(key, value, expire_at.map(codec::to_instant));
+        })?;
+        for shard in &mut store.shards {
+            let shard = shard.get_mut().unwrap_or_else(PoisonError::into_inner);
+            shard.track_writes();
+            shard.log_evictions();
+        }
+        store.engine = Some(engine);
+        Ok(store)
+    }
+
+    /// Creates a keyspace with `shards` shards in front of a durable engine, loading each
+    /// key from it the first time a command needs it. Memory then only holds the keys in
+    /// use, and a memory limit evicts them back to the engine alone.
+    pub fn backed_by(shards: usize, engine: Box<dyn StorageEngine>) -> Self {
+        let mut store = ShardedStore::new(shards);
+        for shard in &mut store.shards {
+            shard
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .track_writes();
+        }
+        store.engine = Some(engine);
+        store.on_demand = true;
+        store
+    }
+
+    /// Number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Index of the shard holding `key`.
+    pub fn shard_of(&self, key: &[u8]) -> usize {
+        shard_index(key, self.shards.len())
+    }
+
+    /// Every shard, in index order.
+    pub fn shards(&self) -> &[Mutex<TTLStore>] {
+        &self.shards
+    }
+
+    /// Locks the shard at `index`. A shard poisoned by a panicking command stays usable, so
+    /// one failed command cannot take the whole server down.
+    pub fn lock_shard(&self, index: usize) -> ShardGuard<'_> {
+        ShardGuard {
+            store: self,
+            guard: lock(&self.shards[index]),
+        }
+    }
+
+    /// Locks the shard holding `key`, for commands that touch a single key.
+    pub fn lock_key(&self, key: &[u8]) -> ShardGuard<'_> {
+        self.lock_shard(self.shard_of(key))
+    }
+
+    /// Locks the given shards in index order. `indices` must be sorted and free of duplicates.
+    pub fn lock_shards(&self, indices: &[usize]) -> Vec<LockedShard<'_>> {
+        debug_assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        indices
+            .iter()
+            .map(|&index| (index, self.lock_shard(index)))
+            .collect()
+    }
+
+    /// Locks every shard, in index order.
+    pub fn lock_all(&self) -> Vec<ShardGuard<'_>> {
+        (0..self.shards.len())
+            .map(|index| self.lock_shard(index))
+            .collect()
+    }
+
+    /// Locks the shards holding `keys`, for commands that touch several keys at once.
+    ///
+    /// The guard routes each key to its own locked shard (see `KeyShards`), so multi-key
+    /// commands run in place. Every key the command reads or writes must be listed.
+    pub fn lock_keys<I, K>(&self, keys: I) -> KeysGuard<'_>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let mut indices: Vec<usize> = keys
+            .into_iter()
+            .map(|key| self.shard_of(key.as_ref()))
+            .collect();
+        indices.sort_unstable();
+        indices.dedup();
+        KeysGuard {
+            store: self,
+            shards: self.lock_shards(&indices),
+        }
+    }
+
+    /// Loads `key` into `shard`, the shard holding it, from an on-demand engine, unless
+    /// the shard already holds it or a change to it. Does nothing for other keyspaces.
+    pub fn load_key(&self, shard: &mut TTLStore, key: &[u8]) -> Result<(), StoreError> {
+        let Some(engine) = self.on_demand_engine() else {
+            return Ok(());
+        };
+        if shard.is_loaded(key) {
+            return Ok(());
+        }
+        if let Some((value, expire_at)) = engine.read_record(key)? {
+            shard.restore_entry(key, value, expire_at.map(codec::to_instant));
+        }
+        Ok(())
+    }
+
+    /// Runs a read-only command on the shard holding `key`, loading the key first.
+    pub fn read<T>(
+        &self,
+        key: &[u8],
+        command: impl FnOnce(&mut TTLStore) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError> {
+        let mut guard = self.lock_key(key);
+        self.load_key(&mut guard, key)?;
+        command(&mut guard)
+    }
+
+    /// Runs a read-only command on the shards holding `keys` (see `lock_keys`), loading
+    /// every key first.
+    pub fn read_keys<I, K, T>(
+        &self,
+        keys: I,
+        command: impl FnOnce(&mut KeysGuard<'_>) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let mut guard = self.load_keys(keys)?;
+        command(&mut guard)
+    }
+
+    /// Runs a write command on the shard holding `key`, loading the key first, and commits
+    /// its changes: the command fails if the engine could not store them.
+    pub fn write<T>(
+        &self,
+        key: &[u8],
+        command: impl FnOnce(&mut TTLStore) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError> {
+        let mut guard = self.lock_key(key);
+        self.load_key(&mut guard, key)?;
+        let result = command(&mut guard);
+        guard.commit()?;
+        result
+    }
+
+    /// Runs a write command on the shards holding `keys` (see `lock_keys`), loading every
+    /// key first, and commits its changes in one batch: the command fails if the engine
+    /// could not store them.
+    pub fn write_keys<I, K, T>(
+        &self,
+        keys: I,
+        command: impl FnOnce(&mut KeysGuard<'_>) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let mut guard = self.load_keys(keys)?;
+        let result = command(&mut guard);
+        guard.commit()?;
+        result
+    }
+
+    /// Helper method: Lock the shards holding `keys` and load every key.
+    fn load_keys<I, K>(&self, keys: I) -> Result<KeysGuard<'_>, StoreError>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let keys: Vec<K> = keys.into_iter().collect();
+        let mut guard = self.lock_keys(&keys);
+        for key in &keys {
+            let key = key.as_ref();
+            self.load_key(guard.shard_mut(key), key)?;
+        }
+        Ok(guard)
+    }
+
+    /// Number of keys in every shard, including expired keys not yet reclaimed. With an
+    /// on-demand engine, only the keys loaded count; see `count_keys`.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).len()).sum()
+    }
+
+    /// Number of keys in the keyspace (DBSIZE): those of an on-demand engine, or else those
+    /// in every shard, including expired keys not yet reclaimed.
+    pub fn count_keys(&self) -> Result<usize, StoreError> {
+        let Some(engine) = self.on_demand_engine() else {
+            return Ok(self.len());
+        };
+        self.write_pending()?;
+        let mut count = 0;
+        engine.for_each_key(b"", &mut |_, _| {
+            count += 1;
+            true
+        })?;
+        Ok(count)
+    }
+
+    /// Number of key writes and removals in every shard since the keyspace was created.
+    pub fn changes(&self) -> u64 {
+        self.shards.iter().map(|shard| lock(shard).changes()).sum()
+    }
+
+    /// Returns true if no shard holds a key.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| lock(shard).is_empty())
+    }
+
+    /// Approximate number of bytes used by every shard.
+    pub fn used_memory(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| lock(shard).used_memory())
+            .sum()
+    }
+
+    /// Number of clients blocked on lists in this keyspace.
+    pub fn blocked_clients(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| lock(shard).blocked_clients())
+            .sum()
+    }
+
+    /// Keys matching a glob pattern (KEYS), locking one shard at a time.
+    pub fn keys(&self, pattern: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+        let Some(engine) = self.on_demand_engine() else {
+            return Ok(self
+                .shards
+                .iter()
+                .flat_map(|shard| lock(shard).keys(pattern))
+                .collect());
+        };
+        self.write_pending()?;
+        let mut keys = Vec::new();
+        engine.for_each_key(b"", &mut |key, _| {
+            if glob_match(pattern, key) {
+                keys.push(key.to_vec());
+            }
+            true
+        })?;
+        Ok(keys)
+    }
+
+    /// Incrementally iterate over every shard (SCAN), locking one shard at a time.
+    ///
+    /// The cursor packs the shard being visited with the cursor within it, so it stays 0 at
+    /// the start and the end of an iteration. Pages continue into the next shard until
+    /// `opts.count` keys were found or a shard has positions left. Over an on-demand engine,
+    /// keys are visited in order and the cursor names the key to resume from.
+    pub fn scan(
+        &self,
+        cursor: u64,
+        opts: &ScanOptions,
+        type_name: Option<&str>,
+    ) -> Result<ScanPage<Vec<u8>>, StoreError> {
+        let wanted = if opts.count == 0 {
+            DEFAULT_SCAN_COUNT
+        } else {
+            opts.count
+        };
+        if let Some(engine) = self.on_demand_engine() {
+            return self.scan_engine(engine, cursor, wanted, opts, type_name);
+        }
+        let count = self.shards.len() as u64;
+        let (mut shard, mut inner) = ((cursor % count) as usize, cursor / count);
+        let mut items = Vec::new();
+        loop {
+            let page = self.lock_shard(shard).scan(inner, opts, type_name);
+            items.extend(page.items);
+            if page.cursor != 0 {
+                let cursor = page.cursor.saturating_mul(count) + shard as u64;
+                return Ok(ScanPage { cursor, items });
+            }
+            shard += 1;
+            inner = 0;
+            if shard == self.shards.len() {
+                return Ok(ScanPage { cursor: 0, items });
+            }
+            if items.len() >= wanted {
+                return Ok(ScanPage {
+                    cursor: shard as u64,
+                    items,
+                });
+            }
+        }
+    }
+
+    /// Helper method: One SCAN page over an on-demand engine, visiting `wanted` keys from
+    /// the one `cursor` resumes at.
+    fn scan_engine(
+        &self,
+        engine: &dyn StorageEngine,
+        cursor: u64,
+        wanted: usize,
+        opts: &ScanOptions,
+        type_name: Option<&str>,
+    ) -> Result<ScanPage<Vec<u8>>, StoreError> {
+        let start = match cursor {
+            0 => Vec::new(),
+            cursor => lock(&self.cursors)
+                .positions
+                .get(&cursor)
+                .cloned()
+                .ok_or_else(|| StoreError::InvalidArgument("invalid cursor".into()))?,
+        };
+        self.write_pending()?;
+        let (mut visited, mut items, mut next) = (0, Vec::new(), None);
+        engine.for_each_key(&start, &mut |key, key_type| {
+            if visited == wanted {
+                next = Some(key.to_vec());
+                return false;
+            }
+            visited += 1;
+            if type_name.is_none_or(|name| name == key_type) && opts.matches(key) {
+                items.push(key.to_vec());
+            }
+            true
+        })?;
+        let cursor = match next {
+            Some(key) => lock(&self.cursors).insert(key),
+            None => 0,
+        };
+        Ok(ScanPage { cursor, items })
+    }
+
+    /// Remove every key (FLUSHDB), optionally freeing them in the background.
+    pub fn flush(&self, lazy: bool) -> Result<(), StoreError> {
+        let mut shards = self.lock_all();
+        for shard in &mut shards {
+            shard.flush(lazy);
+            shard.commit()?;
+        }
+        let Some(engine) = self.on_demand_engine() else {
+            return Ok(());
+        };
+        // The keys never loaded are removed from the engine directly, with every shard
+        // still locked so none of them is loaded meanwhile.
+        loop {
+            let mut keys = Vec::new();
+            engine.for_each_key(b"", &mut |key, _| {
+                keys.push(key.to_vec());
+                keys.len() < FLUSH_BATCH
+            })?;
+            if keys.is_empty() {
+                return Ok(());
+            }
+            let batch: Vec<BatchOp<'_>> = keys.iter().map(|key| BatchOp::Delete { key }).collect();
+            engine.write_batch(&batch)?;
+        }
+    }
+
+    /// Calls `visit` with every live record of the keyspace, given all of its shards locked
+    /// (see `lock_all`). With an on-demand engine the records are read from it, once the
+    /// changes still pending are written through.
+    pub fn for_each_record(
+        &self,
+        shards: &mut [ShardGuard<'_>],
+        visit: &mut dyn FnMut(&[u8], Record),
+    ) -> Result<(), StoreError> {
+        match self.on_demand_engine() {
+            Some(engine) => {
+                for shard in shards.iter_mut() {
+                    shard.commit()?;
+                }
+                engine.for_each_record(b"", visit)
+            }
+            None => {
+                for shard in shards.iter_mut() {
+                    shard.for_each_record(b"", visit)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Freezes the keyspace for a snapshot, given all of its shards locked (see `lock_all`):
+    /// `take_frozen` then copies it as of this call, one shard at a time, while commands
+    /// keep running. Returns false for an on-demand engine, whose keyspace is not held in
+    /// memory; it has to be copied with `for_each_record` before the shards are unlocked.
+    pub fn freeze(&self, shards: &mut [ShardGuard<'_>]) -> bool {
+        if self.on_demand_engine().is_some() {
+            return false;
+        }
+        for shard in shards.iter_mut() {
+            shard.freeze();
+        }
+        true
+    }
+
+    /// Visits every live record as of the last `freeze`, locking one shard at a time.
+    pub fn take_frozen(&self, visit: &mut dyn FnMut(&[u8], Record)) {
+        for index in 0..self.shards.len() {
+            self.lock_shard(index).take_frozen(visit);
+        }
+    }
+
+    /// Applies the memory limit and eviction policy, splitting the limit evenly between
+    /// the shards. Each shard evicts on its own once it reaches its share.
+    pub fn set_memory_config(&self, memory: MemoryConfig) {
+        let maxmemory = match memory.maxmemory {
+            0 => 0,
+            limit => (limit / self.shards.len()).max(1),
+        };
+        for index in 0..self.shards.len() {
+            self.lock_shard(index).set_memory_config(MemoryConfig {
+                maxmemory,
+                ..memory
+            });
+        }
+    }
+
+    /// Write the keys changed in the given locked shards through to the durable engine, in
+    /// a single batch.
+    pub fn commit_shards(&self, shards: &mut [LockedShard<'_>]) -> Result<(), StoreError> {
+        let mut stores: Vec<&mut TTLStore> =
+            shards.iter_mut().map(|(_, shard)| &mut *shard.guard).collect();
+        self.persist(&mut stores)
+    }
+
+    /// Helper method: The engine keys are loaded from on demand, if any.
+    fn on_demand_engine(&self) -> Option<&dyn StorageEngine> {
+        self.engine.as_deref().filter(|_| self.on_demand)
+    }
+
+    /// Helper method: Write the changes every shard still holds through to the engine, one
+    /// shard at a time, before reading the whole keyspace from it.
+    fn write_pending(&self) -> Result<(), StoreError> {
+        for index in 0..self.shards.len() {
+            self.lock_shard(index).commit()?;
+        }
+        Ok(())
+    }
+
+    /// Helper method: Write the keys the given shards marked dirty through to the engine, in
+    /// one batch: whole if they were rewritten, as their edits if they were only changed in
+    /// place. If the engine fails, the keys stay dirty for the next commit to retry.
+    fn persist(&self, stores: &mut [&mut TTLStore]) -> Result<(), StoreError> {
+        let Some(engine) = &self.engine else {
+            return Ok(());
+        };
+        let dirty: Vec<Vec<(Vec<u8>, KeyChange)>> =
+            stores.iter_mut().map(|store| store.take_dirty()).collect();
+        if dirty.iter().all(Vec::is_empty) {
+            return Ok(());
+        }
+        let batch: Vec<BatchOp<'_>> = stores
+            .iter()
+            .zip(&dirty)
+            .flat_map(|(store, changes)| {
+                changes.iter().map(move |(key, change)| match change {
+                    KeyChange::Edits(edits) => BatchOp::Update { key, edits },
+                    KeyChange::Rewrite => match store.peek(key) {
+                        Some((value, expire_at)) => BatchOp::Put {
+                            key,
+                            value,
+                            expire_at,
+                        },
+                        None => BatchOp::Delete { key },
+                    },
+                })
+            })
+            .collect();
+        let written = engine.write_batch(&batch);
+        if written.is_err() {
+            for (store, changes) in stores.iter_mut().zip(dirty) {
+                store.restore_dirty(changes);
+            }
+        }
+        written
+    }
+}
+
+/// A locked shard; dereferences to its store. With a durable engine, the keys written while
+/// it is held are persisted by `commit`; writers that skip it leave them for the next commit.
+pub struct ShardGuard<'a> {
+    store: &'a ShardedStore,
+    guard: MutexGuard<'a, TTLStore>,
+}
+
+impl Deref for ShardGuard<'_> {
+    type Target = TTLStore;
+
+    fn deref(&self) -> &TTLStore {
+        &self.guard
+    }
+}
+
+impl DerefMut for ShardGuard<'_> {
+    fn deref_mut(&mut self) -> &mut TTLStore {
+        &mut self.guard
+    }
+}
+
+impl ShardGuard<'_> {
+    /// Write the keys changed in this shard through to the durable engine.
+    pub fn commit(&mut self) -> Result<(), StoreError> {
+        self.store.persist(&mut [&mut *self.guard])
+    }
+}
+
+/// The shards locked for a multi-key command. With a durable engine, the keys written in
+/// any of them are persisted, in one batch, by `commit`.
+pub struct KeysGuard<'a> {
+    store: &'a ShardedStore,
+    shards: Vec<LockedShard<'a>>,
+}
+
+impl KeysGuard<'_> {
+    /// Write the keys changed in every locked shard through to the durable engine, in a
+    /// single batch.
+    pub fn commit(&mut self) -> Result<(), StoreError> {
+        self.store.commit_shards(&mut self.shards)
+    }
+
+    /// Helper method: Position in `shards` of the shard holding `key`.
+    /// Panics if the key was not listed when the guard was taken.
+    fn position(&self, key: &[u8]) -> usize {
+        let index = self.store.shard_of(key);
+        self.shards
+            .binary_search_by_key(&index, |(locked, _)| *locked)
+            .expect("a multi-key command used a key it did not lock")
+    }
+}
+
+impl KeyShards for KeysGuard<'_> {
+    fn shard(&self, key: &[u8]) -> &TTLStore {
+        &self.shards[self.position(key)].1
+    }
+
+    fn shard_mut(&mut self, key: &[u8]) -> &mut TTLStore {
+        let position = self.position(key);
+        &mut self.shards[position].1
+    }
+}
+
+/// The SCANs in progress over an on-demand engine: for each cursor handed out, the first key
+/// it has not visited yet. Only the `MAX_SCAN_CURSORS` most recent are kept.
+#[derive(Default)]
+struct ScanCursors {
+    /// The last cursor handed out; cursors count up from 1, as 0 starts an iteration.
+    last: u64,
+    positions: IndexMap<u64, Vec<u8>>,
+}
+
+impl ScanCursors {
+    /// Remembers where an iteration resumes and returns its cursor.
+    fn insert(&mut self, key: Vec<u8>) -> u64 {
+        self.last += 1;
+        self.positions.insert(self.last, key);
+        if self.positions.len() > MAX_SCAN_CURSORS {
+            self.positions.shift_remove_index(0);
+        }
+        self.last
+    }
+}
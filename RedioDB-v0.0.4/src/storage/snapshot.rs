@@ -0,0 +1,443 @@
+// src/storage/snapshot.rs
+//
+// Point-in-time snapshots of every database (SAVE/BGSAVE).
+// A snapshot locks every shard of every database, in the usual (database, shard) order, just
+// long enough to freeze them; the shards are then copied one at a time while commands keep
+// running, and a key changed before its shard is copied saves its old record first, so only
+// the keys that change during the copy are held twice. Encoding, checksumming and writing
+// the file happen after the locks are released. Keyspaces read on demand from RocksDB are
+// not held in memory; they are read from the engine before the locks are released.
+// Save points work like Redis's `save <seconds> <changes>`: a background save starts once a
+// point's number of changes happened and its number of seconds passed since the last
+// successful save.
+//
+// File format, version 1: `SNAPSHOT_MAGIC`, the version byte and the creation time in Unix
+// milliseconds; then, for every database holding keys, `SELECT_DB`, its index and its number
+// of keys, followed by each key and its record as encoded by `codec` (deadlines are
+// wall-clock Unix milliseconds, since `Instant`s mean nothing after a restart); then `EOF`
+// and the CRC-32 of every byte before it. The file is written under a temporary name and
+// renamed over the previous snapshot, so a crash never leaves a half-written one behind.
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use tokio::task::JoinHandle;
+
+use crate::storage::codec::{self, put_bytes, put_varint, Decoder};
+use crate::storage::databases::Databases;
+use crate::storage::engine::{Record, DEFAULT_DATA_DIR};
+use crate::storage::error::StoreError;
+use crate::storage::sharded::ShardedStore;
+use crate::storage::util::{io_error, lock, rename_durably};
+
+/// Name of the snapshot in the data directory when `REDIO_DBFILENAME` is not set.
+pub const DEFAULT_SNAPSHOT_FILENAME: &str = "dump.rdio";
+
+/// Save points when `REDIO_SAVE` is not set: the Redis defaults.
+pub const DEFAULT_SAVE_POINTS: &str = "3600 1 300 100 60 10000";
+
+/// First bytes of every snapshot.
+pub const SNAPSHOT_MAGIC: &[u8] = b"REDIOSNAP";
+
+/// Version of the format written; older versions stay readable.
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// Opcodes of the snapshot body.
+const SELECT_DB: u8 = 0xFE;
+const EOF: u8 = 0xFF;
+
+/// Delay before a failed background save is retried, so a full disk is not hammered.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Held while a snapshot is captured.
+static CAPTURING: Mutex<()> = Mutex::new(());
+
+/// Save the snapshot once `changes` changes happened and `seconds` passed since the last save.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavePoint {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+/// Parses save points written as `<seconds> <changes>` pairs, e.g. `3600 1 300 100`.
+/// An empty string means no save points.
+pub fn parse_save_points(value: &str) -> Result<Vec<SavePoint>, String> {
+    let numbers = value
+        .split_whitespace()
+        .map(|number| number.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid save points '{}'", value))?;
+    if !numbers.len().is_multiple_of(2) {
+        return Err(format!("invalid save points '{}'", value));
+    }
+    Ok(numbers
+        .chunks(2)
+        .map(|pair| SavePoint {
+            seconds: pair[0],
+            changes: pair[1],
+        })
+        .collect())
+}
+
+/// Where snapshots are saved, and when.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotConfig {
+    /// Path of the snapshot; its directory is created if missing.
+    pub path: PathBuf,
+    /// When background saves start on their own; empty to only save on request.
+    pub save_points: Vec<SavePoint>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig::new(Path::new(DEFAULT_DATA_DIR).join(DEFAULT_SNAPSHOT_FILENAME))
+    }
+}
+
+impl SnapshotConfig {
+    /// A configuration with the default save points for the snapshot at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        SnapshotConfig {
+            path: path.into(),
+            save_points: parse_save_points(DEFAULT_SAVE_POINTS).unwrap(),
+        }
+    }
+
+    /// Reads the configuration from `REDIO_DATA_DIR` and `REDIO_DBFILENAME` (the snapshot's
+    /// directory and name) and `REDIO_SAVE` (save points, `""` to disable them), falling
+    /// back to the defaults for missing values.
+    pub fn from_env() -> Result<Self, String> {
+        let dir = env::var("REDIO_DATA_DIR").unwrap_or_else(|_| DEFAULT_DATA_DIR.to_string());
+        let name =
+            env::var("REDIO_DBFILENAME").unwrap_or_else(|_| DEFAULT_SNAPSHOT_FILENAME.to_string());
+        let mut config = SnapshotConfig::new(Path::new(&dir).join(name));
+        if let Ok(value) = env::var("REDIO_SAVE") {
+            config.save_points = parse_save_points(&value)?;
+        }
+        Ok(config)
+    }
+}
+
+/// The live records of one database, by index.
+pub type DatabaseRecords = (usize, Vec<(Vec<u8>, Record)>);
+
+/// What a snapshot holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    /// When the snapshot was taken.
+    pub created: SystemTime,
+    /// Number of keys it holds.
+    pub keys: usize,
+}
+
+/// Copies the live records of every database at a single point in time.
+pub fn capture(databases: &Databases) -> Result<Vec<DatabaseRecords>, StoreError> {
+    // One capture at a time: a second freeze would discard the keys the first one saved.
+    let _capturing = lock(&CAPTURING);
+    let mut snapshot: Vec<DatabaseRecords> =
+        (0..databases.len()).map(|index| (index, Vec::new())).collect();
+    let mut frozen = vec![false; databases.len()];
+    {
+        // With every shard locked at once, no command is half applied in the copy.
+        let mut guards: Vec<_> = databases.all().iter().map(ShardedStore::lock_all).collect();
+        for (index, (store, shards)) in databases.all().iter().zip(&mut guards).enumerate() {
+            frozen[index] = store.freeze(shards);
+            if !frozen[index] {
+                let records = &mut snapshot[index].1;
+                store.for_each_record(shards, &mut |key, record| {
+                    records.push((key.to_vec(), record))
+                })?;
+            }
+        }
+    }
+    for (index, store) in databases.all().iter().enumerate().filter(|(index, _)| frozen[*index]) {
+        let records = &mut snapshot[index].1;
+        store.take_frozen(&mut |key, record| records.push((key.to_vec(), record)));
+    }
+    Ok(snapshot)
+}
+
+/// A writer keeping the CRC-32 of everything written through it.
+struct ChecksumWriter<W> {
+    inner: W,
+    crc: u32,
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc = codec::crc32_update(self.crc, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes `snapshot`, taken at `created`, to `path`, atomically replacing any previous file.
+pub fn write_snapshot(
+    path: &Path,
+    snapshot: &[DatabaseRecords],
+    created: SystemTime,
+) -> Result<(), StoreError> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let result = write_file(&temp, snapshot, created).and_then(|()| {
+        rename_durably(&temp, path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Helper function: Write and sync the snapshot file at `path`.
+fn write_file(
+    path: &Path,
+    snapshot: &[DatabaseRecords],
+    created: SystemTime,
+) -> Result<(), StoreError> {
+    let file = File::create(path).map_err(io_error)?;
+    let mut out = ChecksumWriter {
+        inner: BufWriter::new(file),
+        crc: 0,
+    };
+    let mut buf = SNAPSHOT_MAGIC.to_vec();
+    buf.push(SNAPSHOT_VERSION);
+    put_varint(&mut buf, codec::system_time_ms(created));
+    out.write_all(&buf).map_err(io_error)?;
+    for (index, records) in snapshot.iter().filter(|(_, records)| !records.is_empty()) {
+        buf.clear();
+        buf.push(SELECT_DB);
+        put_varint(&mut buf, *index as u64);
+        put_varint(&mut buf, records.len() as u64);
+        out.write_all(&buf).map_err(io_error)?;
+        for (key, (value, expire_at)) in records {
+            buf.clear();
+            put_bytes(&mut buf, key);
+            put_bytes(&mut buf, &codec::encode_record(value, *expire_at));
+            out.write_all(&buf).map_err(io_error)?;
+        }
+    }
+    out.write_all(&[EOF]).map_err(io_error)?;
+    let crc = out.crc;
+    let mut out = out.inner;
+    out.write_all(&crc.to_le_bytes()).map_err(io_error)?;
+    let file = out.into_inner().map_err(|err| io_error(err.into_error()))?;
+    file.sync_all().map_err(io_error)
+}
+
+/// Reads a snapshot, calling `visit` with the database, key and record of every key.
+/// The checksum is verified before anything is visited.
+pub fn read_snapshot(
+    bytes: &[u8],
+    visit: &mut dyn FnMut(usize, Vec<u8>, Record) -> Result<(), StoreError>,
+) -> Result<SnapshotInfo, StoreError> {
+    if !bytes.starts_with(SNAPSHOT_MAGIC) {
+        return Err(StoreError::Storage("not a snapshot file".into()));
+    }
+    let version = bytes.get(SNAPSHOT_MAGIC.len()).copied().unwrap_or_default();
+    if version == 0 || version > SNAPSHOT_VERSION {
+        return Err(StoreError::Storage(format!(
+            "unsupported snapshot version {}",
+            version
+        )));
+    }
+    if bytes.len() < SNAPSHOT_MAGIC.len() + 1 + 4 {
+        return Err(StoreError::Storage("snapshot is truncated".into()));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    if codec::crc32(body) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+        return Err(StoreError::Storage("snapshot checksum mismatch".into()));
+    }
+
+    let mut decoder = Decoder::new(&body[SNAPSHOT_MAGIC.len() + 1..]);
+    let created = UNIX_EPOCH + Duration::from_millis(decoder.varint()?);
+    let mut keys = 0;
+    loop {
+        match decoder.u8()? {
+            SELECT_DB => {
+                let index = decoder.varint()? as usize;
+                for _ in 0..decoder.varint()? {
+                    let key = decoder.bytes()?;
+                    let record = codec::decode_record(&decoder.bytes()?)?;
+                    visit(index, key, record)?;
+                    keys += 1;
+                }
+            }
+            EOF if decoder.is_empty() => return Ok(SnapshotInfo { created, keys }),
+            EOF => return Err(codec::corrupt("data after the end of the snapshot")),
+            opcode => return Err(codec::corrupt(&format!("unknown snapshot opcode {}", opcode))),
+        }
+    }
+}
+
+/// Loads the snapshot at `path` into `databases`, replacing keys with the same names.
+/// Returns None if there is no snapshot; keys of databases that are not configured are
+/// skipped.
+pub fn load(path: &Path, databases: &Databases) -> Result<Option<SnapshotInfo>, StoreError> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(io_error(err)),
+    };
+    let now = SystemTime::now();
+    let info = read_snapshot(&bytes, &mut |index, key, (value, expire_at)| {
+        if index >= databases.len() {
+            log::warn!("Skipping a key of database {}, which is not configured", index);
+            return Ok(());
+        }
+        if expire_at.is_some_and(|at| at <= now) {
+            return Ok(());
+        }
+        databases
+            .get(index)
+            .write(&key, |store| store.write_record(&key, &value, expire_at))
+    })?;
+    Ok(Some(info))
+}
+
+/// Saves snapshots on request and at the configured save points.
+pub struct Snapshotter {
+    config: SnapshotConfig,
+    state: Mutex<SaveState>,
+    saving: AtomicBool,
+}
+
+/// Outcome of the last saves.
+struct SaveState {
+    /// When the last successful snapshot was taken, or the snapshotter created.
+    last_save: SystemTime,
+    /// Changes of the databases when the last successful snapshot was taken.
+    changes_at_save: u64,
+    /// When the last save failed, if it did.
+    last_failure: Option<Instant>,
+}
+
+impl Snapshotter {
+    /// A snapshotter for `databases` with nothing to save yet.
+    pub fn new(config: SnapshotConfig, databases: &Databases) -> Self {
+        Snapshotter {
+            config,
+            state: Mutex::new(SaveState {
+                last_save: SystemTime::now(),
+                changes_at_save: databases.changes(),
+                last_failure: None,
+            }),
+            saving: AtomicBool::new(false),
+        }
+    }
+
+    /// The configuration the snapshotter was created with.
+    pub fn config(&self) -> &SnapshotConfig {
+        &self.config
+    }
+
+    /// When the last successful snapshot was taken (LASTSAVE), or the snapshotter created.
+    pub fn last_save(&self) -> SystemTime {
+        lock(&self.state).last_save
+    }
+
+    /// Whether a save is running.
+    pub fn is_saving(&self) -> bool {
+        self.saving.load(Ordering::Acquire)
+    }
+
+    /// Saves a snapshot of `databases`, blocking until it is written (SAVE).
+    /// Fails if a save is already running.
+    pub fn save(&self, databases: &Databases) -> Result<(), StoreError> {
+        self.begin_save()?;
+        self.finish_save(databases)
+    }
+
+    /// Whether a save point is reached: enough changes happened and enough time passed
+    /// since the last successful save. A failed save is retried after a short delay.
+    pub fn save_due(&self, databases: &Databases) -> bool {
+        if self.config.save_points.is_empty() || self.is_saving() {
+            return false;
+        }
+        let changes = databases.changes();
+        let state = lock(&self.state);
+        if state.last_failure.is_some_and(|at| at.elapsed() < RETRY_DELAY) {
+            return false;
+        }
+        let elapsed = state.last_save.elapsed().unwrap_or_default().as_secs();
+        let changed = changes.saturating_sub(state.changes_at_save);
+        self.config
+            .save_points
+            .iter()
+            .any(|point| changed >= point.changes && elapsed >= point.seconds)
+    }
+
+    /// Helper method: Claim the save.
+    fn begin_save(&self) -> Result<(), StoreError> {
+        if self.saving.swap(true, Ordering::AcqRel) {
+            return Err(StoreError::InvalidArgument(
+                "Background save already in progress".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Helper method: Take and write the snapshot, record the outcome and release the save.
+    fn finish_save(&self, databases: &Databases) -> Result<(), StoreError> {
+        // Changes made while the snapshot is taken count towards the next one.
+        let changes = databases.changes();
+        let created = SystemTime::now();
+        let result = capture(databases)
+            .and_then(|snapshot| write_snapshot(&self.config.path, &snapshot, created));
+        {
+            let mut state = lock(&self.state);
+            match &result {
+                Ok(()) => {
+                    state.last_save = created;
+                    state.changes_at_save = changes;
+                    state.last_failure = None;
+                }
+                Err(_) => state.last_failure = Some(Instant::now()),
+            }
+        }
+        self.saving.store(false, Ordering::Release);
+        result
+    }
+}
+
+/// Starts saving a snapshot of `databases` on a blocking thread (BGSAVE).
+/// Fails if a save is already running.
+pub fn spawn_save(
+    snapshotter: &Arc<Snapshotter>,
+    databases: &'static Databases,
+) -> Result<(), StoreError> {
+    snapshotter.begin_save()?;
+    let snapshotter = Arc::clone(snapshotter);
+    tokio::task::spawn_blocking(move || match snapshotter.finish_save(databases) {
+        Ok(()) => log::info!("Saved a snapshot to {}", snapshotter.config.path.display()),
+        Err(err) => log::error!("Failed to save a snapshot: {}", err),
+    });
+    Ok(())
+}
+
+/// Starts the background task that checks the save points once a second.
+pub fn spawn_background(
+    snapshotter: Arc<Snapshotter>,
+    databases: &'static Databases,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            ticks.tick().await;
+            if snapshotter.save_due(databases) {
+                let _ = spawn_save(&snapshotter, databases);
+            }
+        }
+    })
+}
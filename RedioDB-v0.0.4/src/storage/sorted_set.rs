@@ -0,0 +1,387 @@
+// src/storage/sorted_set.rs
+//
+// Sorted set value type: unique binary members ordered by a floating point score.
+// Members are kept in a B-tree ordered by (score, member) for range queries, plus a
+// hash index from member to score for constant-time score lookups. The hash index is an
+// IndexMap, so ZSCAN can walk it by position.
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+use indexmap::IndexMap;
+
+use crate::storage::eviction::{string_size, SLOT_OVERHEAD, STRING_OVERHEAD};
+
+/// A score with a total order, so it can be used as a B-tree key.
+/// NaN is never stored; -0.0 is normalized to 0.0.
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A set of unique members ordered by score, then lexicographically by member.
+#[derive(Debug, Clone, Default)]
+pub struct SortedSet {
+    scores: IndexMap<Vec<u8>, f64>,
+    ordered: BTreeSet<(Score, Vec<u8>)>,
+}
+
+impl SortedSet {
+    /// Creates an empty sorted set.
+    pub fn new() -> Self {
+        SortedSet::default()
+    }
+
+    /// Number of members.
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Returns true if the set has no members.
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Score of a member, if present.
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Inserts or updates a member, returning its previous score.
+    /// The score must not be NaN.
+    pub fn insert(&mut self, member: &[u8], score: f64) -> Option<f64> {
+        debug_assert!(!score.is_nan());
+        let score = score + 0.0;
+        let old = self.scores.insert(member.to_vec(), score);
+        if let Some(old_score) = old {
+            self.ordered.remove(&(Score(old_score), member.to_vec()));
+        }
+        self.ordered.insert((Score(score), member.to_vec()));
+        old
+    }
+
+    /// Removes a member, returning its score.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let score = self.scores.swap_remove(member)?;
+        self.ordered.remove(&(Score(score), member.to_vec()));
+        Some(score)
+    }
+
+    /// Member and score at a position of the hash index, for ZSCAN.
+    pub fn get_index(&self, index: usize) -> Option<(&Vec<u8>, f64)> {
+        self.scores.get_index(index).map(|(member, score)| (member, *score))
+    }
+
+    /// Zero-based rank of a member in ascending score order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(
+            self.ordered
+                .range(..(Score(score), member.to_vec()))
+                .count(),
+        )
+    }
+
+    /// Iterates over all members in ascending order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&[u8], f64)> + '_ {
+        self.ordered
+            .iter()
+            .map(|(score, member)| (member.as_slice(), score.0))
+    }
+
+    /// Members whose rank lies in `start..=stop`; negative indexes count from the end.
+    /// With `rev`, ranks are taken in descending score order.
+    pub fn range_by_index(&self, start: i64, stop: i64, rev: bool) -> Vec<(Vec<u8>, f64)> {
+        let len = self.len() as i64;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop || start >= len {
+            return Vec::new();
+        }
+        let take = (stop - start + 1) as usize;
+        let collect = |(member, score): (&[u8], f64)| (member.to_vec(), score);
+        if rev {
+            self.iter().rev().skip(start as usize).take(take).map(collect).collect()
+        } else {
+            self.iter().skip(start as usize).take(take).map(collect).collect()
+        }
+    }
+
+    /// Members with a score between `min` and `max`, in ascending order
+    /// (descending with `rev`), after applying an optional `(offset, count)` limit.
+    pub fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+        rev: bool,
+        limit: Option<(usize, Option<usize>)>,
+    ) -> Vec<(Vec<u8>, f64)> {
+        let lower = Bound::Included((Score(min.value()), Vec::new()));
+        let matching = self
+            .ordered
+            .range((lower, Bound::Unbounded))
+            .skip_while(|(score, _)| !min.admits_as_min(score.0))
+            .take_while(|(score, _)| max.admits_as_max(score.0))
+            .map(|(score, member)| (member.clone(), score.0));
+        apply_limit(matching, rev, limit)
+    }
+
+    /// Members between `min` and `max` in lexicographical order (descending with `rev`).
+    /// Only meaningful when all members share the same score.
+    pub fn range_by_lex(
+        &self,
+        min: &LexBound,
+        max: &LexBound,
+        rev: bool,
+        limit: Option<(usize, Option<usize>)>,
+    ) -> Vec<(Vec<u8>, f64)> {
+        let matching = self
+            .ordered
+            .iter()
+            .filter(|(_, member)| min.admits_as_min(member) && max.admits_as_max(member))
+            .map(|(score, member)| (member.clone(), score.0));
+        apply_limit(matching, rev, limit)
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores
+    /// (or the highest, with `max`).
+    pub fn pop(&mut self, count: usize, max: bool) -> Vec<(Vec<u8>, f64)> {
+        let mut popped = Vec::with_capacity(count.min(self.len()));
+        while popped.len() < count {
+            let entry = if max {
+                self.ordered.pop_last()
+            } else {
+                self.ordered.pop_first()
+            };
+            match entry {
+                Some((score, member)) => {
+                    self.scores.swap_remove(&member);
+                    popped.push((member, score.0));
+                }
+                None => break,
+            }
+        }
+        popped
+    }
+
+    /// Approximate number of bytes used by the set, for `maxmemory` accounting.
+    pub fn mem_usage(&self) -> usize {
+        STRING_OVERHEAD
+            + self
+                .scores
+                .keys()
+                .map(|member| Self::member_size(member))
+                .sum::<usize>()
+    }
+
+    /// Approximate number of bytes used by one member: it is stored in both indexes.
+    pub fn member_size(member: &[u8]) -> usize {
+        2 * (string_size(member) + SLOT_OVERHEAD + std::mem::size_of::<f64>())
+    }
+}
+
+/// Collects an ascending iterator, reversing it and applying `(offset, count)` if requested.
+fn apply_limit(
+    matching: impl Iterator<Item = (Vec<u8>, f64)>,
+    rev: bool,
+    limit: Option<(usize, Option<usize>)>,
+) -> Vec<(Vec<u8>, f64)> {
+    let mut items: Vec<(Vec<u8>, f64)> = matching.collect();
+    if rev {
+        items.reverse();
+    }
+    match limit {
+        Some((offset, count)) => items
+            .into_iter()
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .collect(),
+        None => items,
+    }
+}
+
+/// One end of a score range, e.g. `1.5`, `(1.5`, `-inf` or `+inf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+impl ScoreBound {
+    /// Parses a Redis-style score bound.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (exclusive, number) = match s.strip_prefix('(') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let value = parse_score(number).map_err(|_| "min or max is not a float".to_string())?;
+        Ok(if exclusive {
+            ScoreBound::Exclusive(value)
+        } else {
+            ScoreBound::Inclusive(value)
+        })
+    }
+
+    fn value(&self) -> f64 {
+        match self {
+            ScoreBound::Inclusive(v) | ScoreBound::Exclusive(v) => *v,
+        }
+    }
+
+    fn admits_as_min(&self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(v) => score >= *v,
+            ScoreBound::Exclusive(v) => score > *v,
+        }
+    }
+
+    fn admits_as_max(&self, score: f64) -> bool {
+        match self {
+            ScoreBound::Inclusive(v) => score <= *v,
+            ScoreBound::Exclusive(v) => score < *v,
+        }
+    }
+}
+
+/// One end of a lexicographical range, e.g. `[a`, `(a`, `-` or `+`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    /// Parses a Redis-style lexicographical bound.
+    pub fn parse(s: &[u8]) -> Result<Self, String> {
+        match s {
+            b"-" => Ok(LexBound::Min),
+            b"+" => Ok(LexBound::Max),
+            _ => {
+                if let Some(rest) = s.strip_prefix(b"[") {
+                    Ok(LexBound::Inclusive(rest.to_vec()))
+                } else if let Some(rest) = s.strip_prefix(b"(") {
+                    Ok(LexBound::Exclusive(rest.to_vec()))
+                } else {
+                    Err("min or max not valid string range item".to_string())
+                }
+            }
+        }
+    }
+
+    fn admits_as_min(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(v) => member >= v.as_slice(),
+            LexBound::Exclusive(v) => member > v.as_slice(),
+        }
+    }
+
+    fn admits_as_max(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(v) => member <= v.as_slice(),
+            LexBound::Exclusive(v) => member < v.as_slice(),
+        }
+    }
+}
+
+/// Parses a score, accepting `inf`, `+inf` and `-inf`. NaN is rejected.
+pub fn parse_score(s: &str) -> Result<f64, String> {
+    let value = match s.to_ascii_lowercase().as_str() {
+        "inf" | "+inf" => f64::INFINITY,
+        "-inf" => f64::NEG_INFINITY,
+        other => other
+            .parse::<f64>()
+            .map_err(|_| "value is not a valid float".to_string())?,
+    };
+    if value.is_nan() {
+        return Err("value is not a valid float".to_string());
+    }
+    Ok(value)
+}
+
+/// The kind of range queried by ZRANGE.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZRangeBy {
+    /// Ranks `start..=stop`; negative indexes count from the end.
+    Index(i64, i64),
+    /// Scores between `min` and `max`.
+    Score(ScoreBound, ScoreBound),
+    /// Members between `min` and `max` in lexicographical order.
+    Lex(LexBound, LexBound),
+}
+
+/// Conditions for ZADD.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZAddOptions {
+    /// Only add new members; never update existing ones.
+    pub nx: bool,
+    /// Only update existing members; never add new ones.
+    pub xx: bool,
+    /// Only update when the new score is greater than the current one.
+    pub gt: bool,
+    /// Only update when the new score is less than the current one.
+    pub lt: bool,
+    /// Count changed members as well as added ones in the reply.
+    pub ch: bool,
+}
+
+impl ZAddOptions {
+    /// Rejects combinations of flags that Redis rejects.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.nx && self.xx {
+            return Err("XX and NX options at the same time are not compatible".to_string());
+        }
+        if (self.gt && self.lt) || (self.nx && (self.gt || self.lt)) {
+            return Err("GT, LT, and/or NX options at the same time are not compatible".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How scores of the same member are combined by ZUNIONSTORE and ZINTERSTORE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    /// Combines two weighted scores. NaN results (e.g. `inf + -inf`) become 0.
+    pub fn combine(&self, a: f64, b: f64) -> f64 {
+        let combined = match self {
+            Aggregate::Sum => a + b,
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        };
+        if combined.is_nan() {
+            0.0
+        } else {
+            combined
+        }
+    }
+}
@@ -0,0 +1,847 @@
+// src/storage/stream.rs
+//
+// Append-only stream value type with consumer groups.
+// Entries are kept in a B-tree keyed by monotonically increasing `<ms>-<seq>` IDs.
+// Each consumer group tracks the last delivered ID and a pending entries list (PEL)
+// of entries delivered to a consumer but not yet acknowledged.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::storage::codec::{corrupt, put_bytes, put_varint, Decoder};
+use crate::storage::error::StoreError;
+use crate::storage::eviction::{string_size, SLOT_OVERHEAD, STRING_OVERHEAD};
+
+/// Approximate bookkeeping cost of one pending entry (in the group and the consumer PEL).
+const PENDING_ENTRY_SIZE: usize = 64;
+/// Approximate bookkeeping cost of a consumer group or consumer, besides its name.
+const GROUP_OVERHEAD: usize = 64;
+
+/// The field/value pairs of a stream entry, both binary safe.
+pub type StreamFields = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// A stream entry: its ID and its fields.
+pub type StreamEntry = (StreamId, StreamFields);
+
+/// Entries read from one stream, tagged with the stream's key (XREAD, XREADGROUP).
+pub type StreamRead = (Vec<u8>, Vec<StreamEntry>);
+
+/// Reply of XAUTOCLAIM: the cursor to continue from, the claimed entries and the IDs
+/// of deleted entries dropped from the pending list.
+pub type AutoClaimReply = (StreamId, Vec<StreamEntry>, Vec<StreamId>);
+
+/// Milliseconds since the Unix epoch, used for IDs and idle times.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// A stream entry ID: a millisecond timestamp and a sequence number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    /// The smallest possible ID, `0-0`.
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    /// The largest possible ID.
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    /// The ID immediately after this one, if any.
+    pub fn next(self) -> Option<StreamId> {
+        if self.seq < u64::MAX {
+            Some(StreamId::new(self.ms, self.seq + 1))
+        } else if self.ms < u64::MAX {
+            Some(StreamId::new(self.ms + 1, 0))
+        } else {
+            None
+        }
+    }
+
+    /// The ID immediately before this one, if any.
+    pub fn prev(self) -> Option<StreamId> {
+        if self.seq > 0 {
+            Some(StreamId::new(self.ms, self.seq - 1))
+        } else if self.ms > 0 {
+            Some(StreamId::new(self.ms - 1, u64::MAX))
+        } else {
+            None
+        }
+    }
+
+    /// Parses `<ms>-<seq>`, or `<ms>` with `missing_seq` as the sequence number.
+    fn parse_with_default(s: &str, missing_seq: u64) -> Result<Self, String> {
+        let invalid = || "Invalid stream ID specified as stream command argument".to_string();
+        match s.split_once('-') {
+            Some((ms, seq)) => Ok(StreamId::new(
+                ms.parse().map_err(|_| invalid())?,
+                seq.parse().map_err(|_| invalid())?,
+            )),
+            None => Ok(StreamId::new(s.parse().map_err(|_| invalid())?, missing_seq)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl FromStr for StreamId {
+    type Err = String;
+
+    /// Parses `<ms>-<seq>`; a bare `<ms>` means `<ms>-0`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StreamId::parse_with_default(s, 0)
+    }
+}
+
+/// Parses the start of an XRANGE-style range: `-`, `<id>`, `<ms>` or exclusive `(<id>`.
+/// Returns None if the range is empty (an exclusive start at the largest ID).
+pub fn parse_range_start(s: &str) -> Result<Option<StreamId>, String> {
+    if s == "-" {
+        return Ok(Some(StreamId::MIN));
+    }
+    if s == "+" {
+        return Ok(Some(StreamId::MAX));
+    }
+    match s.strip_prefix('(') {
+        Some(rest) => Ok(StreamId::parse_with_default(rest, 0)?.next()),
+        None => StreamId::parse_with_default(s, 0).map(Some),
+    }
+}
+
+/// Parses the end of an XRANGE-style range: `+`, `<id>`, `<ms>` or exclusive `(<id>`.
+/// Returns None if the range is empty (an exclusive end at the smallest ID).
+pub fn parse_range_end(s: &str) -> Result<Option<StreamId>, String> {
+    if s == "+" {
+        return Ok(Some(StreamId::MAX));
+    }
+    if s == "-" {
+        return Ok(Some(StreamId::MIN));
+    }
+    match s.strip_prefix('(') {
+        Some(rest) => Ok(StreamId::parse_with_default(rest, u64::MAX)?.prev()),
+        None => StreamId::parse_with_default(s, u64::MAX).map(Some),
+    }
+}
+
+/// The ID requested by XADD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XAddId {
+    /// `*`: generate the whole ID.
+    Auto,
+    /// `<ms>-*`: use the given time and generate the sequence number.
+    AutoSeq(u64),
+    /// An explicit ID.
+    Explicit(StreamId),
+}
+
+impl FromStr for XAddId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s == "*" {
+            return Ok(XAddId::Auto);
+        }
+        if let Some(ms) = s.strip_suffix("-*") {
+            return ms
+                .parse()
+                .map(XAddId::AutoSeq)
+                .map_err(|_| "Invalid stream ID specified as stream command argument".to_string());
+        }
+        s.parse().map(XAddId::Explicit)
+    }
+}
+
+/// How XADD and XTRIM trim a stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    /// Keep at most this many entries.
+    MaxLen(usize),
+    /// Remove entries with an ID lower than this one.
+    MinId(StreamId),
+}
+
+/// A trimming request. With `approximate`, at most `limit` entries are removed
+/// (0 means no limit); exact trimming always removes everything required.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrimSpec {
+    pub strategy: TrimStrategy,
+    pub approximate: bool,
+    pub limit: usize,
+}
+
+/// The ID requested by XREADGROUP for one stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupReadId {
+    /// `>`: entries never delivered to any consumer of the group.
+    New,
+    /// Entries in the consumer's own pending list with an ID greater than this one.
+    After(StreamId),
+}
+
+/// Options of XCLAIM.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClaimOptions {
+    /// Set the idle time of claimed entries instead of resetting it to 0.
+    pub idle_ms: Option<u64>,
+    /// Set the delivery count instead of incrementing it.
+    pub retry_count: Option<u64>,
+    /// Create pending entries for IDs that are not pending yet.
+    pub force: bool,
+    /// Return only IDs and do not increment the delivery count.
+    pub justid: bool,
+}
+
+/// Summary form of XPENDING.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PendingSummary {
+    pub count: usize,
+    pub min: Option<StreamId>,
+    pub max: Option<StreamId>,
+    pub consumers: Vec<(String, usize)>,
+}
+
+/// One entry of the extended form of XPENDING.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingInfo {
+    pub id: StreamId,
+    pub consumer: String,
+    pub idle_ms: u64,
+    pub deliveries: u64,
+}
+
+/// A delivered but not yet acknowledged entry.
+#[derive(Debug, Clone)]
+struct PendingEntry {
+    consumer: String,
+    delivery_time: u64,
+    delivery_count: u64,
+}
+
+/// A consumer of a group and the IDs pending for it.
+#[derive(Debug, Clone, Default)]
+struct Consumer {
+    seen_time: u64,
+    pending: BTreeSet<StreamId>,
+}
+
+/// A consumer group.
+#[derive(Debug, Clone, Default)]
+struct ConsumerGroup {
+    last_delivered: StreamId,
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<String, Consumer>,
+}
+
+impl ConsumerGroup {
+    /// Returns the consumer, creating it if needed; reports whether it was created.
+    fn consumer(&mut self, name: &str, now: u64) -> (&mut Consumer, bool) {
+        let created = !self.consumers.contains_key(name);
+        let consumer = self.consumers.entry(name.to_string()).or_default();
+        consumer.seen_time = now;
+        (consumer, created)
+    }
+
+    /// Assigns a pending entry to `consumer`, removing it from its previous owner.
+    fn assign(&mut self, id: StreamId, consumer: &str) {
+        if let Some(entry) = self.pending.get_mut(&id) {
+            if entry.consumer != consumer {
+                if let Some(previous) = self.consumers.get_mut(&entry.consumer) {
+                    previous.pending.remove(&id);
+                }
+                entry.consumer = consumer.to_string();
+            }
+        }
+        if let Some(owner) = self.consumers.get_mut(consumer) {
+            owner.pending.insert(id);
+        }
+    }
+}
+
+/// An append-only log of field/value entries with consumer groups.
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, StreamFields>,
+    last_id: StreamId,
+    groups: BTreeMap<String, ConsumerGroup>,
+    /// Approximate number of bytes used by entries, groups and pending lists.
+    bytes: usize,
+}
+
+impl Stream {
+    /// Creates an empty stream.
+    pub fn new() -> Self {
+        Stream::default()
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the stream has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The ID of the last entry ever added (entries may have been trimmed since).
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// Approximate number of bytes used by the stream, for `maxmemory` accounting.
+    pub fn mem_usage(&self) -> usize {
+        STRING_OVERHEAD + self.bytes
+    }
+
+    fn entry_size(fields: &StreamFields) -> usize {
+        SLOT_OVERHEAD
+            + std::mem::size_of::<StreamId>()
+            + fields
+                .iter()
+                .map(|(field, value)| string_size(field) + string_size(value))
+                .sum::<usize>()
+    }
+
+    /// Appends an entry (XADD) and returns its ID.
+    pub fn add(&mut self, id: XAddId, fields: StreamFields, now: u64) -> Result<StreamId, StoreError> {
+        let id = match id {
+            XAddId::Auto => {
+                if now > self.last_id.ms {
+                    StreamId::new(now, 0)
+                } else {
+                    self.last_id.next().ok_or_else(Self::id_too_small)?
+                }
+            }
+            XAddId::AutoSeq(ms) => {
+                if ms > self.last_id.ms {
+                    StreamId::new(ms, 0)
+                } else if ms == self.last_id.ms {
+                    self.last_id.next().ok_or_else(Self::id_too_small)?
+                } else {
+                    return Err(Self::id_too_small());
+                }
+            }
+            XAddId::Explicit(id) => {
+                if id == StreamId::MIN {
+                    return Err(StoreError::InvalidArgument(
+                        "The ID specified in XADD must be greater than 0-0".to_string(),
+                    ));
+                }
+                if id <= self.last_id {
+                    return Err(Self::id_too_small());
+                }
+                id
+            }
+        };
+        self.bytes += Self::entry_size(&fields);
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        Ok(id)
+    }
+
+    fn id_too_small() -> StoreError {
+        StoreError::InvalidArgument(
+            "The ID specified in XADD is equal or smaller than the target stream top item"
+                .to_string(),
+        )
+    }
+
+    /// Trims the stream (XTRIM) and returns the number of removed entries.
+    pub fn trim(&mut self, spec: TrimSpec) -> usize {
+        let limit = if spec.approximate && spec.limit > 0 {
+            spec.limit
+        } else {
+            usize::MAX
+        };
+        let mut removed = 0;
+        while removed < limit {
+            let first = match self.entries.keys().next() {
+                Some(first) => *first,
+                None => break,
+            };
+            let excess = match spec.strategy {
+                TrimStrategy::MaxLen(max) => self.entries.len() > max,
+                TrimStrategy::MinId(min) => first < min,
+            };
+            if !excess {
+                break;
+            }
+            if let Some(fields) = self.entries.remove(&first) {
+                self.bytes = self.bytes.saturating_sub(Self::entry_size(&fields));
+            }
+            removed += 1;
+        }
+        removed
+    }
+
+    /// Entries with IDs in `start..=end` (XRANGE), or in descending order (XREVRANGE).
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Vec<StreamEntry> {
+        if start > end {
+            return Vec::new();
+        }
+        let count = count.unwrap_or(usize::MAX);
+        let range = self.entries.range(start..=end);
+        let clone = |(id, fields): (&StreamId, &StreamFields)| (*id, fields.clone());
+        if rev {
+            range.rev().take(count).map(clone).collect()
+        } else {
+            range.take(count).map(clone).collect()
+        }
+    }
+
+    /// Entries with an ID greater than `after` (XREAD).
+    pub fn read_after(&self, after: StreamId, count: Option<usize>) -> Vec<StreamEntry> {
+        match after.next() {
+            Some(start) => self.range(start, StreamId::MAX, count, false),
+            None => Vec::new(),
+        }
+    }
+
+    fn group_mut(&mut self, group: &str) -> Result<&mut ConsumerGroup, StoreError> {
+        self.groups.get_mut(group).ok_or(StoreError::NoGroup)
+    }
+
+    /// Creates a consumer group (XGROUP CREATE) that delivers entries after `last_delivered`.
+    pub fn create_group(&mut self, group: &str, last_delivered: StreamId) -> Result<(), StoreError> {
+        if self.groups.contains_key(group) {
+            return Err(StoreError::BusyGroup);
+        }
+        self.groups.insert(
+            group.to_string(),
+            ConsumerGroup {
+                last_delivered,
+                ..ConsumerGroup::default()
+            },
+        );
+        self.bytes += string_size(group.as_bytes()) + GROUP_OVERHEAD;
+        Ok(())
+    }
+
+    /// Destroys a consumer group (XGROUP DESTROY); returns whether it existed.
+    pub fn destroy_group(&mut self, group: &str) -> bool {
+        match self.groups.remove(group) {
+            Some(removed) => {
+                let consumers: usize = removed
+                    .consumers
+                    .keys()
+                    .map(|name| string_size(name.as_bytes()) + GROUP_OVERHEAD)
+                    .sum();
+                self.bytes = self.bytes.saturating_sub(
+                    string_size(group.as_bytes())
+                        + GROUP_OVERHEAD
+                        + consumers
+                        + removed.pending.len() * PENDING_ENTRY_SIZE,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Creates a consumer (XGROUP CREATECONSUMER); returns whether it was created.
+    pub fn create_consumer(&mut self, group: &str, consumer: &str, now: u64) -> Result<bool, StoreError> {
+        let (_, created) = self.group_mut(group)?.consumer(consumer, now);
+        if created {
+            self.bytes += string_size(consumer.as_bytes()) + GROUP_OVERHEAD;
+        }
+        Ok(created)
+    }
+
+    /// Deletes a consumer and its pending entries (XGROUP DELCONSUMER);
+    /// returns how many pending entries it had.
+    pub fn delete_consumer(&mut self, group: &str, consumer: &str) -> Result<usize, StoreError> {
+        let cg = self.group_mut(group)?;
+        let removed = match cg.consumers.remove(consumer) {
+            Some(removed) => removed,
+            None => return Ok(0),
+        };
+        for id in &removed.pending {
+            cg.pending.remove(id);
+        }
+        let pending = removed.pending.len();
+        self.bytes = self.bytes.saturating_sub(
+            string_size(consumer.as_bytes()) + GROUP_OVERHEAD + pending * PENDING_ENTRY_SIZE,
+        );
+        Ok(pending)
+    }
+
+    /// Reads entries on behalf of a consumer (XREADGROUP).
+    ///
+    /// With `GroupReadId::New`, delivers entries never delivered to the group and adds
+    /// them to the consumer's pending list unless `noack` is set. With
+    /// `GroupReadId::After`, replays the consumer's own pending entries; entries deleted
+    /// from the stream since are returned with no fields.
+    pub fn read_group(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        id: GroupReadId,
+        count: Option<usize>,
+        noack: bool,
+        now: u64,
+    ) -> Result<Vec<StreamEntry>, StoreError> {
+        if !self.groups.contains_key(group) {
+            return Err(StoreError::NoGroup);
+        }
+        self.create_consumer(group, consumer, now)?;
+        match id {
+            GroupReadId::New => {
+                let after = self.groups[group].last_delivered;
+                let entries = self.read_after(after, count);
+                let mut added = 0;
+                let cg = self.group_mut(group)?;
+                for (id, _) in &entries {
+                    cg.last_delivered = *id;
+                    if noack {
+                        continue;
+                    }
+                    let fresh = cg
+                        .pending
+                        .insert(
+                            *id,
+                            PendingEntry {
+                                consumer: consumer.to_string(),
+                                delivery_time: now,
+                                delivery_count: 1,
+                            },
+                        )
+                        .is_none();
+                    if fresh {
+                        added += 1;
+                    }
+                    cg.assign(*id, consumer);
+                }
+                self.bytes += added * PENDING_ENTRY_SIZE;
+                Ok(entries)
+            }
+            GroupReadId::After(after) => {
+                let count = count.unwrap_or(usize::MAX);
+                let cg = &self.groups[group];
+                let ids: Vec<StreamId> = match after.next() {
+                    Some(start) => cg.consumers[consumer]
+                        .pending
+                        .range(start..)
+                        .take(count)
+                        .copied()
+                        .collect(),
+                    None => Vec::new(),
+                };
+                Ok(ids
+                    .into_iter()
+                    .map(|id| (id, self.entries.get(&id).cloned().unwrap_or_default()))
+                    .collect())
+            }
+        }
+    }
+
+    /// Acknowledges entries (XACK); returns how many were pending.
+    pub fn ack(&mut self, group: &str, ids: &[StreamId]) -> usize {
+        let cg = match self.groups.get_mut(group) {
+            Some(cg) => cg,
+            None => return 0,
+        };
+        let mut acked = 0;
+        for id in ids {
+            if let Some(entry) = cg.pending.remove(id) {
+                if let Some(owner) = cg.consumers.get_mut(&entry.consumer) {
+                    owner.pending.remove(id);
+                }
+                acked += 1;
+            }
+        }
+        self.bytes = self.bytes.saturating_sub(acked * PENDING_ENTRY_SIZE);
+        acked
+    }
+
+    /// Summary form of XPENDING.
+    pub fn pending_summary(&self, group: &str) -> Result<PendingSummary, StoreError> {
+        let cg = self.groups.get(group).ok_or(StoreError::NoGroup)?;
+        Ok(PendingSummary {
+            count: cg.pending.len(),
+            min: cg.pending.keys().next().copied(),
+            max: cg.pending.keys().next_back().copied(),
+            consumers: cg
+                .consumers
+                .iter()
+                .filter(|(_, c)| !c.pending.is_empty())
+                .map(|(name, c)| (name.clone(), c.pending.len()))
+                .collect(),
+        })
+    }
+
+    /// Extended form of XPENDING: pending entries with IDs in `start..=end`, optionally
+    /// only those of one consumer and idle for at least `min_idle_ms`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn pending_range(
+        &self,
+        group: &str,
+        start: StreamId,
+        end: StreamId,
+        count: usize,
+        consumer: Option<&str>,
+        min_idle_ms: u64,
+        now: u64,
+    ) -> Result<Vec<PendingInfo>, StoreError> {
+        let cg = self.groups.get(group).ok_or(StoreError::NoGroup)?;
+        if start > end {
+            return Ok(Vec::new());
+        }
+        Ok(cg
+            .pending
+            .range(start..=end)
+            .filter(|(_, entry)| consumer.is_none_or(|c| entry.consumer == c))
+            .map(|(id, entry)| PendingInfo {
+                id: *id,
+                consumer: entry.consumer.clone(),
+                idle_ms: now.saturating_sub(entry.delivery_time),
+                deliveries: entry.delivery_count,
+            })
+            .filter(|info| info.idle_ms >= min_idle_ms)
+            .take(count)
+            .collect())
+    }
+
+    /// Transfers ownership of pending entries idle for at least `min_idle_ms` to
+    /// `consumer` (XCLAIM). Entries deleted from the stream are dropped from the
+    /// pending list and not returned. With `justid`, returned entries carry no fields.
+    pub fn claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle_ms: u64,
+        ids: &[StreamId],
+        opts: ClaimOptions,
+        now: u64,
+    ) -> Result<Vec<StreamEntry>, StoreError> {
+        if !self.groups.contains_key(group) {
+            return Err(StoreError::NoGroup);
+        }
+        self.create_consumer(group, consumer, now)?;
+        let mut claimed = Vec::new();
+        for id in ids {
+            let fields = self.entries.get(id).cloned();
+            let cg = self.group_mut(group)?;
+            if !cg.pending.contains_key(id) {
+                if !opts.force || fields.is_none() {
+                    continue;
+                }
+                cg.pending.insert(
+                    *id,
+                    PendingEntry {
+                        consumer: consumer.to_string(),
+                        delivery_time: now,
+                        delivery_count: 0,
+                    },
+                );
+                self.bytes += PENDING_ENTRY_SIZE;
+            }
+            if self.claim_one(group, consumer, *id, fields.is_some(), min_idle_ms, opts, now)? {
+                let fields = if opts.justid { Vec::new() } else { fields.unwrap_or_default() };
+                claimed.push((*id, fields));
+            }
+        }
+        Ok(claimed)
+    }
+
+    /// Scans the pending list from `start` and claims up to `count` entries idle for at
+    /// least `min_idle_ms` (XAUTOCLAIM). Returns the ID to continue scanning from
+    /// (`0-0` when the scan is complete), the claimed entries and the IDs of pending
+    /// entries that were deleted from the stream and dropped from the pending list.
+    #[allow(clippy::too_many_arguments)]
+    pub fn auto_claim(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        min_idle_ms: u64,
+        start: StreamId,
+        count: usize,
+        justid: bool,
+        now: u64,
+    ) -> Result<AutoClaimReply, StoreError> {
+        if !self.groups.contains_key(group) {
+            return Err(StoreError::NoGroup);
+        }
+        self.create_consumer(group, consumer, now)?;
+        let opts = ClaimOptions {
+            justid,
+            ..ClaimOptions::default()
+        };
+        let max_attempts = count.saturating_mul(10);
+        let candidates: Vec<StreamId> = self.groups[group]
+            .pending
+            .range(start..)
+            .map(|(id, _)| *id)
+            .take(max_attempts + 1)
+            .collect();
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        let mut next = StreamId::MIN;
+        for (scanned, id) in candidates.iter().enumerate() {
+            if scanned == max_attempts || claimed.len() == count {
+                next = *id;
+                break;
+            }
+            let fields = self.entries.get(id).cloned();
+            if fields.is_none() {
+                deleted.push(*id);
+            }
+            if self.claim_one(group, consumer, *id, fields.is_some(), min_idle_ms, opts, now)? {
+                let fields = if justid { Vec::new() } else { fields.unwrap_or_default() };
+                claimed.push((*id, fields));
+            }
+        }
+        Ok((next, claimed, deleted))
+    }
+
+    /// Claims one pending entry; returns whether it was claimed. Pending entries whose
+    /// stream entry no longer exists are removed instead.
+    #[allow(clippy::too_many_arguments)]
+    fn claim_one(
+        &mut self,
+        group: &str,
+        consumer: &str,
+        id: StreamId,
+        exists: bool,
+        min_idle_ms: u64,
+        opts: ClaimOptions,
+        now: u64,
+    ) -> Result<bool, StoreError> {
+        let cg = self.group_mut(group)?;
+        if !exists {
+            if let Some(entry) = cg.pending.remove(&id) {
+                if let Some(owner) = cg.consumers.get_mut(&entry.consumer) {
+                    owner.pending.remove(&id);
+                }
+                self.bytes = self.bytes.saturating_sub(PENDING_ENTRY_SIZE);
+            }
+            return Ok(false);
+        }
+        let entry = match cg.pending.get_mut(&id) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        if now.saturating_sub(entry.delivery_time) < min_idle_ms {
+            return Ok(false);
+        }
+        entry.delivery_time = now.saturating_sub(opts.idle_ms.unwrap_or(0));
+        match opts.retry_count {
+            Some(retries) => entry.delivery_count = retries,
+            None if !opts.justid => entry.delivery_count += 1,
+            None => {}
+        }
+        cg.assign(id, consumer);
+        Ok(true)
+    }
+
+    /// Appends the binary encoding of the stream: entries, last ID and consumer groups with
+    /// their consumers and pending entries.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        put_varint(out, self.entries.len() as u64);
+        for (id, fields) in &self.entries {
+            put_id(out, *id);
+            put_varint(out, fields.len() as u64);
+            for (field, value) in fields {
+                put_bytes(out, field);
+                put_bytes(out, value);
+            }
+        }
+        put_id(out, self.last_id);
+        put_varint(out, self.groups.len() as u64);
+        for (name, group) in &self.groups {
+            put_bytes(out, name.as_bytes());
+            put_id(out, group.last_delivered);
+            put_varint(out, group.consumers.len() as u64);
+            for (consumer, state) in &group.consumers {
+                put_bytes(out, consumer.as_bytes());
+                put_varint(out, state.seen_time);
+            }
+            put_varint(out, group.pending.len() as u64);
+            for (id, entry) in &group.pending {
+                put_id(out, *id);
+                put_bytes(out, entry.consumer.as_bytes());
+                put_varint(out, entry.delivery_time);
+                put_varint(out, entry.delivery_count);
+            }
+        }
+    }
+
+    /// Decodes a stream written by `encode`.
+    pub fn decode(decoder: &mut Decoder<'_>) -> Result<Stream, StoreError> {
+        let mut stream = Stream::new();
+        for _ in 0..decoder.len()? {
+            let id = read_id(decoder)?;
+            let mut fields = Vec::new();
+            for _ in 0..decoder.len()? {
+                fields.push((decoder.bytes()?, decoder.bytes()?));
+            }
+            stream.bytes += Self::entry_size(&fields);
+            stream.entries.insert(id, fields);
+        }
+        stream.last_id = read_id(decoder)?;
+        for _ in 0..decoder.len()? {
+            let name = decoder.string()?;
+            let mut group = ConsumerGroup {
+                last_delivered: read_id(decoder)?,
+                ..ConsumerGroup::default()
+            };
+            stream.bytes += string_size(name.as_bytes()) + GROUP_OVERHEAD;
+            for _ in 0..decoder.len()? {
+                let consumer = decoder.string()?;
+                let seen_time = decoder.varint()?;
+                stream.bytes += string_size(consumer.as_bytes()) + GROUP_OVERHEAD;
+                group.consumers.insert(consumer, Consumer { seen_time, ..Consumer::default() });
+            }
+            for _ in 0..decoder.len()? {
+                let id = read_id(decoder)?;
+                let consumer = decoder.string()?;
+                let owner = group
+                    .consumers
+                    .get_mut(&consumer)
+                    .ok_or_else(|| corrupt("pending entry of an unknown consumer"))?;
+                owner.pending.insert(id);
+                let entry = PendingEntry {
+                    consumer,
+                    delivery_time: decoder.varint()?,
+                    delivery_count: decoder.varint()?,
+                };
+                group.pending.insert(id, entry);
+                stream.bytes += PENDING_ENTRY_SIZE;
+            }
+            stream.groups.insert(name, group);
+        }
+        Ok(stream)
+    }
+}
+
+/// Helper function: Append a stream ID.
+fn put_id(out: &mut Vec<u8>, id: StreamId) {
+    put_varint(out, id.ms);
+    put_varint(out, id.seq);
+}
+
+/// Helper function: Read a stream ID written by `put_id`.
+fn read_id(decoder: &mut Decoder<'_>) -> Result<StreamId, StoreError> {
+    Ok(StreamId::new(decoder.varint()?, decoder.varint()?))
+}
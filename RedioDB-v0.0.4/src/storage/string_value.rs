@@ -0,0 +1,169 @@
+// src/storage/string_value.rs
+//
+// String value type. Strings are binary safe. Strings that are the canonical form of a 64-bit
+// integer are kept as an `i64`, so INCR/DECR do not re-parse and re-format the value on every
+// call. Every other string is kept as raw bytes. Also holds the options of SET and its variants.
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::storage::error::StoreError;
+use crate::storage::eviction::{string_size, STRING_OVERHEAD};
+
+/// A string value, integer-encoded when possible.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringValue {
+    /// The canonical decimal form of an `i64` (no sign prefix, padding or leading zeros).
+    Int(i64),
+    /// Any other string, as raw bytes.
+    Raw(Vec<u8>),
+}
+
+impl StringValue {
+    /// Creates a value, picking the integer encoding if `value` round-trips through `i64`.
+    pub fn new(value: &[u8]) -> Self {
+        match parse_canonical_int(value) {
+            Some(num) => StringValue::Int(num),
+            None => StringValue::Raw(value.to_vec()),
+        }
+    }
+
+    /// Returns the value as an integer, or `StoreError::NotInteger` if it is not one.
+    pub fn as_int(&self) -> Result<i64, StoreError> {
+        match self {
+            StringValue::Int(num) => Ok(*num),
+            // An APPEND can turn a raw string into a number again.
+            StringValue::Raw(val) => parse_canonical_int(val).ok_or(StoreError::NotInteger),
+        }
+    }
+
+    /// Returns the value as a finite float, or `StoreError::NotFloat` if it is not one.
+    pub fn as_float(&self) -> Result<f64, StoreError> {
+        match self {
+            StringValue::Int(num) => Ok(*num as f64),
+            StringValue::Raw(val) => parse_float(val),
+        }
+    }
+
+    /// Appends `suffix`, switching to the raw encoding.
+    pub fn append(&mut self, suffix: &[u8]) {
+        let mut raw = self.to_bytes();
+        raw.extend_from_slice(suffix);
+        *self = StringValue::Raw(raw);
+    }
+
+    /// The value as bytes, formatting the integer encoding back to decimal.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            StringValue::Int(num) => num.to_string().into_bytes(),
+            StringValue::Raw(val) => val.clone(),
+        }
+    }
+
+    /// Length of the value in bytes (STRLEN).
+    pub fn len(&self) -> usize {
+        match self {
+            StringValue::Int(num) => num.to_string().len(),
+            StringValue::Raw(val) => val.len(),
+        }
+    }
+
+    /// Returns true for the empty string.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Approximate number of bytes used by the value, for `maxmemory` accounting.
+    pub fn mem_usage(&self) -> usize {
+        match self {
+            StringValue::Int(_) => STRING_OVERHEAD,
+            StringValue::Raw(val) => string_size(val),
+        }
+    }
+}
+
+/// When a key written by SET or GETEX, or given a TTL by the EXPIRE family, expires.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetExpiry {
+    /// After a relative TTL (EX/PX, EXPIRE/PEXPIRE).
+    In(Duration),
+    /// At an absolute wall-clock time (EXAT/PXAT, EXPIREAT/PEXPIREAT).
+    At(SystemTime),
+}
+
+impl SetExpiry {
+    /// The deadline as an `Instant`; times in the past map to now, so the key expires at once.
+    pub fn deadline(&self) -> Instant {
+        let now = Instant::now();
+        match self {
+            SetExpiry::In(ttl) => now + *ttl,
+            SetExpiry::At(at) => now + at.duration_since(SystemTime::now()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Conditions and expiry for SET.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SetOptions {
+    /// Only set the key if it does not exist.
+    pub nx: bool,
+    /// Only set the key if it already exists.
+    pub xx: bool,
+    /// Reply with the old value; fails if the key holds another type.
+    pub get: bool,
+    /// Retain the TTL of the existing key.
+    pub keep_ttl: bool,
+    /// New expiry; `None` clears the TTL unless `keep_ttl` is set.
+    pub expiry: Option<SetExpiry>,
+}
+
+impl SetOptions {
+    /// Rejects combinations of flags that Redis rejects.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.nx && self.xx {
+            return Err("XX and NX options at the same time are not compatible".to_string());
+        }
+        if self.keep_ttl && self.expiry.is_some() {
+            return Err("KEEPTTL and an expiration at the same time are not compatible".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Result of SET.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetOutcome {
+    /// Whether the value was written; false if an NX/XX condition was not met.
+    pub written: bool,
+    /// The previous string value, filled in only for SET ... GET.
+    pub old_value: Option<Vec<u8>>,
+}
+
+/// Helper function: Parses `value` as an `i64` only if formatting it back gives `value`.
+fn parse_canonical_int(value: &[u8]) -> Option<i64> {
+    // 20 bytes fit "-9223372036854775808".
+    if value.is_empty() || value.len() > 20 {
+        return None;
+    }
+    let num = std::str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+    (num.to_string().as_bytes() == value).then_some(num)
+}
+
+/// Parses a stored value for INCRBYFLOAT/HINCRBYFLOAT, rejecting NaN and infinities.
+pub fn parse_float(value: &[u8]) -> Result<f64, StoreError> {
+    std::str::from_utf8(value)
+        .map_err(|_| StoreError::NotFloat)?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or(StoreError::NotFloat)
+}
+
+/// Formats a float the way INCRBYFLOAT/HINCRBYFLOAT reply: the shortest decimal that
+/// round-trips, without an exponent or a trailing ".0".
+pub fn format_float(value: f64) -> String {
+    if value == 0.0 {
+        // Also normalizes -0.
+        return "0".to_string();
+    }
+    format!("{}", value)
+}
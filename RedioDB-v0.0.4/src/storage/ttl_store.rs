@@ -1,229 +1,2628 @@
 // src/storage/ttl_store.rs
 
-use std::collections::{HashMap, HashSet};
-use std::time::{Duration, Instant};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use indexmap::{IndexMap, IndexSet};
+use rand::Rng;
+
+use tokio::sync::oneshot;
+
+use crate::glob::glob_match;
+use crate::monitoring::{EVICTED_KEYS_COUNTER, EXPIRED_KEYS_COUNTER};
+use crate::storage::blocking::{BlockedClients, BlockedOp, Delivery, DeliverySlot};
+use crate::storage::codec;
+use crate::storage::dump::{self, RestoreOptions};
+use crate::storage::engine::{BatchOp, Edit, Record, StorageEngine};
+use crate::storage::error::StoreError;
+use crate::storage::expiry::ExpireOptions;
+use crate::storage::hash::{FieldValue, Hash};
+use crate::storage::lazy_free;
+use crate::storage::multi_key;
+use crate::storage::eviction::{
+    string_size, AccessMeta, EvictionPolicy, MemoryConfig, ENTRY_OVERHEAD, SLOT_OVERHEAD,
+    STRING_OVERHEAD,
+};
+use crate::storage::scan::{scan_window, ScanOptions, ScanPage};
+use crate::storage::sorted_set::{SortedSet, ZAddOptions, ZRangeBy};
+use crate::storage::string_value::{
+    format_float, parse_float, SetExpiry, SetOptions, SetOutcome, StringValue,
+};
+use crate::storage::stream::{
+    now_ms, AutoClaimReply, ClaimOptions, PendingInfo, PendingSummary, Stream,
+    StreamEntry, StreamFields, StreamId, TrimSpec, XAddId,
+};
+use crate::storage::util;
+
+/// Largest number of members a negative SRANDMEMBER (or HRANDFIELD) count may ask for.
+/// The picks may repeat, so the reply is not bounded by the size of the key, and it is
+/// built while the key's shard is locked.
+pub const MAX_RANDOM_REPEATS: u64 = 1 << 24;
 
 /// Represents the different types of values our store can hold.
 #[derive(Debug, Clone)]
 pub enum StoreValue {
-    /// A simple string value.
-    Simple(String),
-    /// A list of string values.
-    List(Vec<String>),
-    /// A set of unique string values.
-    Set(HashSet<String>),
-    /// A hash mapping field names to string values.
-    Hash(HashMap<String, String>),
+    /// A simple string value, integer-encoded when it holds a number.
+    Simple(StringValue),
+    /// A list of string values, with constant-time pushes and pops at both ends.
+    List(VecDeque<Vec<u8>>),
+    /// A set of unique string values; an IndexSet so SPOP/SRANDMEMBER pick members in
+    /// constant time.
+    Set(IndexSet<Vec<u8>>),
+    /// A hash mapping field names to string values, with optional per-field TTLs.
+    Hash(Hash),
+    /// A set of unique string members ordered by score.
+    SortedSet(SortedSet),
+    /// An append-only log of entries with consumer groups.
+    Stream(Stream),
+}
+
+impl StoreValue {
+    /// Name of the value's type, as reported by TYPE and matched by SCAN's TYPE filter.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            StoreValue::Simple(_) => "string",
+            StoreValue::List(_) => "list",
+            StoreValue::Set(_) => "set",
+            StoreValue::Hash(_) => "hash",
+            StoreValue::SortedSet(_) => "zset",
+            StoreValue::Stream(_) => "stream",
+        }
+    }
+
+    /// Approximate number of bytes used by the value, for `maxmemory` accounting.
+    pub fn mem_usage(&self) -> usize {
+        match self {
+            StoreValue::Simple(val) => val.mem_usage(),
+            StoreValue::List(list) => {
+                STRING_OVERHEAD + list.iter().map(|item| string_size(item)).sum::<usize>()
+            }
+            StoreValue::Set(set) => {
+                STRING_OVERHEAD
+                    + set
+                        .iter()
+                        .map(|member| string_size(member) + SLOT_OVERHEAD)
+                        .sum::<usize>()
+            }
+            StoreValue::Hash(hash) => hash.mem_usage(),
+            StoreValue::SortedSet(zset) => zset.mem_usage(),
+            StoreValue::Stream(stream) => stream.mem_usage(),
+        }
+    }
+}
+
+/// How a key changed since the last `take_dirty`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyChange {
+    /// The key was created, replaced or removed: write it whole, or delete it.
+    Rewrite,
+    /// The key was only changed in place, by these edits in order.
+    Edits(Vec<Edit>),
+}
+
+/// A keyspace entry: the value, its optional expiry and its access metadata.
+type Entry = (StoreValue, Option<Instant>, AccessMeta);
+
+/// The keyspace as it was when a snapshot froze the store (see `TTLStore::freeze`).
+struct Frozen {
+    /// When the store was frozen; a key is in the snapshot if it was live then.
+    at: Instant,
+    /// Record of every key changed since, as it was at `at`; None if it did not exist.
+    saved: HashMap<Vec<u8>, Option<Record>>,
+    /// Whether `saved` holds every key, because the whole keyspace was replaced since.
+    complete: bool,
+}
+
+/// Approximate number of bytes used by a whole keyspace entry.
+fn entry_size(key: &[u8], value: &StoreValue) -> usize {
+    ENTRY_OVERHEAD + string_size(key) + value.mem_usage()
 }
 
 /// TTLStore is an in-memory key–value store that supports TTLs and multiple data types.
+#[derive(Default)]
 pub struct TTLStore {
-    store: HashMap<String, (StoreValue, Option<Instant>)>,
+    /// Keyspace; an IndexMap so eviction can sample random keys in constant time.
+    store: IndexMap<Vec<u8>, Entry>,
+    /// Deadline index of every key with a TTL, ordered by expiry time.
+    /// Used by the active expiry cycle to find expired keys without scanning the whole map.
+    expires: BTreeSet<(Instant, Vec<u8>)>,
+    /// Number of keys removed because their TTL elapsed (lazily or actively).
+    expired_keys: u64,
+    /// Memory limit and eviction policy.
+    memory: MemoryConfig,
+    /// Approximate number of bytes used by all entries.
+    used_memory: usize,
+    /// Number of keys removed by the eviction policy.
+    evicted_keys: u64,
+    /// Clients blocked on empty lists (BLPOP/BRPOP/BLMOVE), served in arrival order.
+    blocked: BlockedClients,
+    /// Earliest field deadline of every hash with field TTLs, ordered by expiry time.
+    /// Used by the active expiry cycle to reclaim expired fields that are never read again.
+    field_expires: BTreeSet<(Instant, Vec<u8>)>,
+    /// Keys written or removed since the last `take_dirty`, and how, when write tracking is
+    /// on. A durable engine persists exactly these changes once the command that made them
+    /// is done.
+    dirty: Option<HashMap<Vec<u8>, KeyChange>>,
+    /// Number of key writes and removals since the store was created; snapshot save points
+    /// compare it with its value at the last save.
+    changes: u64,
+    /// The keyspace of the snapshot being taken, while one is; only the keys changed since
+    /// it started are copied.
+    frozen: Option<Frozen>,
+    /// Whether a log of writes is being replayed, which suspends lazy expiry.
+    replaying: bool,
+    /// Whether evicted keys are recorded as removed, for an engine that is only read at
+    /// startup and would otherwise bring them back.
+    log_evictions: bool,
 }
 
 impl TTLStore {
     /// Creates a new, empty TTLStore.
     pub fn new() -> Self {
+        TTLStore::default()
+    }
+
+    /// Creates a new, empty TTLStore with the given memory limit.
+    pub fn with_memory_config(memory: MemoryConfig) -> Self {
         TTLStore {
-            store: HashMap::new(),
+            memory,
+            ..TTLStore::default()
         }
     }
 
     /// Helper method: Check if the key has expired.
-    /// If expired, remove it from the store.
-    fn check_expiry(&mut self, key: &str) {
-        if let Some((_, Some(expiry))) = self.store.get(key) {
+    /// If expired, remove it from the store. Expired keys are not marked dirty: a durable
+    /// engine keeps their deadline and drops them on its own.
+    pub(crate) fn check_expiry(&mut self, key: &[u8]) {
+        if self.replaying {
+            return;
+        }
+        if let Some((_, Some(expiry), _)) = self.store.get(key) {
             if Instant::now() >= *expiry {
-                self.store.remove(key);
+                self.detach_entry(key);
+                self.expired_keys += 1;
+                EXPIRED_KEYS_COUNTER.with_label_values(&["lazy"]).inc();
+            }
+        }
+    }
+
+    /// Helper method: Check the key for expiry and record the access for the eviction policy.
+    pub(crate) fn lookup(&mut self, key: &[u8]) {
+        self.check_expiry(key);
+        if let Some((_, _, meta)) = self.store.get_mut(key) {
+            meta.touch();
+        }
+    }
+
+    /// Helper method: The entry of `key`, for a write that changes it in place.
+    fn entry_mut(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        self.mark_dirty(key);
+        self.preserve(key);
+        self.store.get_mut(key)
+    }
+
+    /// Helper method: The entry of `key`, for a write that records its changes with
+    /// `record_edit`.
+    fn entry_to_edit(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        self.preserve(key);
+        self.store.get_mut(key)
+    }
+
+    /// Helper method: Insert an entry and mark it dirty.
+    fn insert_entry(&mut self, key: &[u8], value: StoreValue, expiry: Option<Instant>) {
+        self.mark_dirty(key);
+        self.attach_entry(key, value, expiry);
+    }
+
+    /// Helper method: Insert an entry, keeping the deadline index and memory accounting
+    /// in sync with both the replaced entry (if any) and the new one.
+    fn attach_entry(&mut self, key: &[u8], value: StoreValue, expiry: Option<Instant>) {
+        self.preserve(key);
+        self.used_memory += entry_size(key, &value);
+        let old = self
+            .store
+            .insert(key.to_vec(), (value, expiry, AccessMeta::new()));
+        if let Some((old_value, old_expiry, _)) = old {
+            self.unindex_fields(key, &old_value);
+            self.used_memory = self.used_memory.saturating_sub(entry_size(key, &old_value));
+            if let Some(deadline) = old_expiry {
+                self.expires.remove(&(deadline, key.to_vec()));
+            }
+        }
+        if let Some(deadline) = expiry {
+            self.expires.insert((deadline, key.to_vec()));
+        }
+        self.index_fields(key);
+    }
+
+    /// Helper method: Remove an entry and mark it dirty.
+    fn remove_entry(&mut self, key: &[u8]) -> Option<Entry> {
+        self.mark_dirty(key);
+        self.detach_entry(key)
+    }
+
+    /// Helper method: Remove an entry, its deadline index record and its memory usage.
+    fn detach_entry(&mut self, key: &[u8]) -> Option<Entry> {
+        self.preserve(key);
+        let removed = self.store.swap_remove(key);
+        if let Some((value, expiry, _)) = &removed {
+            self.unindex_fields(key, value);
+            self.used_memory = self.used_memory.saturating_sub(entry_size(key, value));
+            if let Some(deadline) = expiry {
+                self.expires.remove(&(*deadline, key.to_vec()));
+            }
+        }
+        removed
+    }
+
+    /// Helper method: The value and expiry of `key`, without checking expiry or counting as
+    /// an access.
+    pub(crate) fn get_entry(&self, key: &[u8]) -> Option<(&StoreValue, Option<Instant>)> {
+        self.store.get(key).map(|(value, expiry, _)| (value, *expiry))
+    }
+
+    /// Helper method: Remove `key` and return its value and expiry, marking it dirty.
+    pub(crate) fn take_value(&mut self, key: &[u8]) -> Option<(StoreValue, Option<Instant>)> {
+        self.remove_entry(key).map(|(value, expiry, _)| (value, expiry))
+    }
+
+    /// Helper method: Store `value` under `key` with `expiry`, replacing any entry and
+    /// marking it dirty. The memory limit is left to the caller.
+    pub(crate) fn put_value(&mut self, key: &[u8], value: StoreValue, expiry: Option<Instant>) {
+        self.insert_entry(key, value, expiry);
+    }
+
+    /// Helper method: Record the field deadline of a hash entering the keyspace.
+    fn index_fields(&mut self, key: &[u8]) {
+        if let Some((StoreValue::Hash(hash), _, _)) = self.store.get(key) {
+            if let Some(deadline) = hash.next_deadline() {
+                self.field_expires.insert((deadline, key.to_vec()));
+            }
+        }
+    }
+
+    /// Helper method: Drop the field deadline index record of a hash leaving the keyspace.
+    fn unindex_fields(&mut self, key: &[u8], value: &StoreValue) {
+        if let StoreValue::Hash(hash) = value {
+            if let Some(deadline) = hash.next_deadline() {
+                self.field_expires.remove(&(deadline, key.to_vec()));
+            }
+        }
+    }
+
+    /// Helper method: Replace the expiry of an existing entry, keeping the deadline index in sync.
+    fn set_expiry(&mut self, key: &[u8], expiry: Option<Instant>) -> bool {
+        let old = match self.entry_to_edit(key) {
+            Some(entry) => std::mem::replace(&mut entry.1, expiry),
+            None => return false,
+        };
+        self.record_edit(key, Edit::Deadline(expiry.map(codec::to_system_time)));
+        if let Some(deadline) = old {
+            self.expires.remove(&(deadline, key.to_vec()));
+        }
+        if let Some(deadline) = expiry {
+            self.expires.insert((deadline, key.to_vec()));
+        }
+        true
+    }
+
+    /// Helper method: Account for bytes added to or removed from an existing value.
+    fn adjust_memory(&mut self, added: usize, removed: usize) {
+        self.used_memory = (self.used_memory + added).saturating_sub(removed);
+    }
+
+    /// Helper method: Make room for a write that may grow memory usage.
+    ///
+    /// Evicts keys according to the configured policy until usage is back under
+    /// `maxmemory`. Fails with `StoreError::OutOfMemory` if nothing can be evicted.
+    /// Evicted keys are not marked dirty unless `log_evictions` is on: an engine that
+    /// loads keys on demand keeps them, so eviction only frees memory.
+    pub(crate) fn ensure_memory(&mut self) -> Result<(), StoreError> {
+        if self.memory.maxmemory == 0 {
+            return Ok(());
+        }
+        while self.used_memory > self.memory.maxmemory {
+            let victim = self
+                .eviction_candidate()
+                .ok_or(StoreError::OutOfMemory)?;
+            self.detach_entry(&victim);
+            if self.log_evictions {
+                self.mark_dirty(&victim);
             }
+            self.evicted_keys += 1;
+            EVICTED_KEYS_COUNTER.inc();
+        }
+        Ok(())
+    }
+
+    /// Helper method: Pick the key to evict next under the configured policy.
+    fn eviction_candidate(&self) -> Option<Vec<u8>> {
+        let policy = self.memory.policy;
+        match policy {
+            EvictionPolicy::NoEviction => None,
+            EvictionPolicy::VolatileTtl => self.expires.iter().next().map(|(_, key)| key.clone()),
+            EvictionPolicy::AllKeysRandom => self.random_key(),
+            EvictionPolicy::AllKeysLru | EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLru => {
+                let volatile_only = policy == EvictionPolicy::VolatileLru;
+                if self.store.is_empty() || (volatile_only && self.expires.is_empty()) {
+                    return None;
+                }
+                let now = Instant::now();
+                let samples = self.memory.samples.max(1);
+                let mut rng = rand::thread_rng();
+                let mut best: Option<(u64, &Vec<u8>)> = None;
+                let mut found = 0;
+                let mut attempts = 0;
+                while found < samples && attempts < samples * 10 {
+                    attempts += 1;
+                    let index = rng.gen_range(0..self.store.len());
+                    let (key, (_, expiry, meta)) = self.store.get_index(index)?;
+                    if volatile_only && expiry.is_none() {
+                        continue;
+                    }
+                    found += 1;
+                    let score = meta.eviction_score(policy, now);
+                    if best.is_none_or(|(best_score, _)| score > best_score) {
+                        best = Some((score, key));
+                    }
+                }
+                if best.is_none() {
+                    // Volatile keys are rare; fall back to the ones expiring soonest.
+                    for (_, key) in self.expires.iter().take(samples) {
+                        let score = self.store[key].2.eviction_score(policy, now);
+                        if best.is_none_or(|(best_score, _)| score > best_score) {
+                            best = Some((score, key));
+                        }
+                    }
+                }
+                best.map(|(_, key)| key.clone())
+            }
+        }
+    }
+
+    /// Helper method: Return a uniformly random key.
+    fn random_key(&self) -> Option<Vec<u8>> {
+        if self.store.is_empty() {
+            return None;
+        }
+        let index = rand::thread_rng().gen_range(0..self.store.len());
+        self.store.get_index(index).map(|(key, _)| key.clone())
+    }
+
+    /// Actively reclaim keys whose TTL has elapsed, even if they are never read again.
+    ///
+    /// At most `max_keys` keys are removed per call so callers can bound the time the
+    /// store is held. Returns the number of keys removed.
+    pub fn active_expire_cycle(&mut self, max_keys: usize) -> usize {
+        let now = Instant::now();
+        let mut removed = 0;
+        while removed < max_keys {
+            let key = match self.expires.iter().next() {
+                Some((deadline, _)) if *deadline > now => break,
+                Some((_, key)) => key.clone(),
+                None => break,
+            };
+            self.detach_entry(&key);
+            removed += 1;
+        }
+        self.expired_keys += removed as u64;
+        EXPIRED_KEYS_COUNTER
+            .with_label_values(&["active"])
+            .inc_by(removed as u64);
+
+        // Hashes with expired fields get the same budget; touching them purges the fields.
+        for _ in 0..max_keys {
+            let key = match self.field_expires.iter().next() {
+                Some((deadline, _)) if *deadline > now => break,
+                Some((_, key)) => key.clone(),
+                None => break,
+            };
+            let _ = self.with_hash(&key, |_| ());
         }
+        removed
+    }
+
+    /// Replace the memory limit and eviction policy, evicting keys if the new
+    /// limit is already exceeded.
+    pub fn set_memory_config(&mut self, memory: MemoryConfig) {
+        self.memory = memory;
+        let _ = self.ensure_memory();
+    }
+
+    /// Number of keys currently held, including expired keys not yet reclaimed.
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    /// Returns true if the store holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Number of keys that carry a TTL.
+    pub fn volatile_len(&self) -> usize {
+        self.expires.len()
+    }
+
+    /// Total number of keys removed because their TTL elapsed.
+    pub fn expired_keys(&self) -> u64 {
+        self.expired_keys
+    }
+
+    /// Approximate number of bytes used by all entries.
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Total number of keys removed by the eviction policy.
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys
     }
 
     /// Set a key with a simple string value and optional TTL.
-    pub fn set(&mut self, key: &str, value: &str, ttl: Option<Duration>) {
-        let expiry = ttl.map(|dur| Instant::now() + dur);
-        self.store.insert(
-            key.to_string(),
-            (StoreValue::Simple(value.to_string()), expiry),
-        );
+    pub fn set(&mut self, key: &[u8], value: &[u8], ttl: Option<Duration>) -> Result<(), StoreError> {
+        let opts = SetOptions {
+            expiry: ttl.map(SetExpiry::In),
+            ..SetOptions::default()
+        };
+        self.set_with_options(key, value, opts).map(|_| ())
     }
 
-    /// Get the value for a key (if it exists and is a Simple value).
-    pub fn get(&mut self, key: &str) -> Option<String> {
-        self.check_expiry(key);
-        if let Some((StoreValue::Simple(ref val), _)) = self.store.get(key) {
-            Some(val.clone())
-        } else {
-            None
+    /// Set a key with a simple string value under NX/XX conditions (SET with options).
+    /// Any existing value is overwritten whatever its type, except that GET fails with
+    /// `StoreError::WrongType` if the key does not hold a string.
+    pub fn set_with_options(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        opts: SetOptions,
+    ) -> Result<SetOutcome, StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        let (old_value, old_expiry) = match self.store.get(key) {
+            Some((StoreValue::Simple(val), expiry, _)) => (Some(val.to_bytes()), *expiry),
+            Some(_) if opts.get => return Err(StoreError::WrongType),
+            Some((_, expiry, _)) => (None, *expiry),
+            None => (None, None),
+        };
+        let exists = self.store.contains_key(key);
+        let written = if exists { !opts.nx } else { !opts.xx };
+        if written {
+            let expiry = match opts.expiry {
+                Some(expiry) => Some(expiry.deadline()),
+                None if opts.keep_ttl => old_expiry,
+                None => None,
+            };
+            self.insert_entry(key, StoreValue::Simple(StringValue::new(value)), expiry);
+            // An absolute expiry in the past removes the key right away.
+            self.check_expiry(key);
+        }
+        Ok(SetOutcome {
+            written,
+            old_value: old_value.filter(|_| opts.get),
+        })
+    }
+
+    /// Get the value of a key and delete it (GETDEL).
+    /// Fails with `StoreError::WrongType` if the key does not hold a string.
+    pub fn get_del(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let value = self.get(key)?;
+        if value.is_some() {
+            self.remove_entry(key);
+        }
+        Ok(value)
+    }
+
+    /// Get the value of a key and update its TTL (GETEX): `expiry` sets a new one and
+    /// `persist` removes it; with neither, the TTL is left alone.
+    /// Fails with `StoreError::WrongType` if the key does not hold a string.
+    pub fn get_ex(
+        &mut self,
+        key: &[u8],
+        expiry: Option<SetExpiry>,
+        persist: bool,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        let value = self.get(key)?;
+        if value.is_some() {
+            if let Some(expiry) = expiry {
+                self.set_expiry(key, Some(expiry.deadline()));
+                self.check_expiry(key);
+            } else if persist {
+                self.set_expiry(key, None);
+            }
+        }
+        Ok(value)
+    }
+
+    /// Get the value for a key (if it exists).
+    /// Fails with `StoreError::WrongType` if the key does not hold a string.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.lookup(key);
+        match self.store.get(key) {
+            Some((StoreValue::Simple(val), _, _)) => Ok(Some(val.to_bytes())),
+            Some(_) => Err(StoreError::WrongType),
+            None => Ok(None),
         }
     }
 
     /// Set the expiration (TTL) for a key.
-    pub fn expire(&mut self, key: &str, ttl: Duration) -> bool {
+    pub fn expire(&mut self, key: &[u8], ttl: Duration) -> bool {
+        self.expire_with_options(key, SetExpiry::In(ttl), ExpireOptions::default())
+    }
+
+    /// Set the expiration of a key under NX/XX/GT/LT conditions (EXPIRE, PEXPIRE, EXPIREAT
+    /// and PEXPIREAT). An expiry in the past deletes the key.
+    /// Returns false if the key does not exist or a condition was not met.
+    pub fn expire_with_options(&mut self, key: &[u8], expiry: SetExpiry, opts: ExpireOptions) -> bool {
+        self.lookup(key);
+        let current = match self.store.get(key) {
+            Some((_, expiry, _)) => *expiry,
+            None => return false,
+        };
+        let deadline = expiry.deadline();
+        if !opts.allows(current, deadline) {
+            return false;
+        }
+        self.set_expiry(key, Some(deadline));
         self.check_expiry(key);
-        if let Some(entry) = self.store.get_mut(key) {
-            entry.1 = Some(Instant::now() + ttl);
-            true
-        } else {
-            false
+        true
+    }
+
+    /// Remove the TTL of a key (PERSIST).
+    /// Returns false if the key does not exist or has no TTL.
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        self.lookup(key);
+        match self.store.get(key) {
+            Some((_, Some(_), _)) => self.set_expiry(key, None),
+            _ => false,
         }
     }
 
-    /// Return the remaining TTL in seconds, or None if the key does not exist.
-    /// Returns -1 if the key exists but has no TTL.
-    pub fn ttl(&mut self, key: &str) -> Option<i64> {
+    /// Helper method: Remaining time before the key expires, `Ok(None)` if it has no TTL,
+    /// or `Err(())` if it does not exist.
+    fn remaining(&mut self, key: &[u8]) -> Result<Option<Duration>, ()> {
         self.check_expiry(key);
-        if let Some((_, Some(expiry))) = self.store.get(key) {
-            let now = Instant::now();
-            if *expiry > now {
-                Some(expiry.duration_since(now).as_secs() as i64)
-            } else {
-                Some(0)
+        match self.store.get(key) {
+            Some((_, Some(expiry), _)) => {
+                Ok(Some(expiry.saturating_duration_since(Instant::now())))
             }
-        } else if self.store.contains_key(key) {
-            Some(-1)
-        } else {
-            None
+            Some((_, None, _)) => Ok(None),
+            None => Err(()),
         }
     }
 
+    /// Return the remaining TTL in seconds, or None if the key does not exist.
+    /// Returns -1 if the key exists but has no TTL.
+    pub fn ttl(&mut self, key: &[u8]) -> Option<i64> {
+        let remaining = self.remaining(key).ok()?;
+        Some(remaining.map_or(-1, |ttl| ttl.as_secs() as i64))
+    }
+
+    /// Return the remaining TTL in milliseconds (PTTL), or None if the key does not exist.
+    /// Returns -1 if the key exists but has no TTL.
+    pub fn pttl(&mut self, key: &[u8]) -> Option<i64> {
+        let remaining = self.remaining(key).ok()?;
+        Some(remaining.map_or(-1, |ttl| ttl.as_millis() as i64))
+    }
+
+    /// Return the absolute Unix time in milliseconds at which the key expires
+    /// (PEXPIRETIME), or None if the key does not exist. Returns -1 if it has no TTL.
+    pub fn pexpire_time(&mut self, key: &[u8]) -> Option<i64> {
+        let remaining = self.remaining(key).ok()?;
+        Some(remaining.map_or(-1, |ttl| {
+            let at = SystemTime::now() + ttl;
+            at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+        }))
+    }
+
+    /// Return the absolute Unix time in seconds at which the key expires (EXPIRETIME),
+    /// or None if the key does not exist. Returns -1 if it has no TTL.
+    pub fn expire_time(&mut self, key: &[u8]) -> Option<i64> {
+        self.pexpire_time(key)
+            .map(|ms| if ms < 0 { ms } else { ms / 1000 })
+    }
+
     /// Delete a key from the store.
-    pub fn del(&mut self, key: &str) -> bool {
-        self.store.remove(key).is_some()
+    pub fn del(&mut self, key: &[u8]) -> bool {
+        self.check_expiry(key);
+        self.remove_entry(key).is_some()
     }
 
-    /// Atomically increment a key's numeric value.
-    /// If the key doesn't exist, it is created with the increment value.
-    pub fn incr(&mut self, key: &str, amount: i32) -> Option<String> {
+    /// Return the type of the value stored at `key` (TYPE), or "none" if it does not exist.
+    pub fn key_type(&mut self, key: &[u8]) -> &'static str {
+        self.lookup(key);
+        self.store
+            .get(key)
+            .map_or("none", |(value, _, _)| value.type_name())
+    }
+
+    /// Serialize the value and deadline of a key (DUMP); None if the key does not exist.
+    pub fn dump(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.lookup(key);
+        let (value, expire_at) = self.peek(key)?;
+        Some(dump::serialize(value, expire_at))
+    }
+
+    /// Create a key from a DUMP payload (RESTORE). A `ttl_ms` of 0 keeps the deadline the
+    /// key was dumped with; otherwise it is a TTL in milliseconds, or a Unix time with ABSTTL.
+    /// A key whose deadline has passed is not created. Fails with `StoreError::BusyKey` if the
+    /// key exists and `opts.replace` is not set.
+    pub fn restore(
+        &mut self,
+        key: &[u8],
+        payload: &[u8],
+        ttl_ms: i64,
+        opts: RestoreOptions,
+    ) -> Result<(), StoreError> {
+        let ttl = u64::try_from(ttl_ms)
+            .map_err(|_| StoreError::InvalidArgument("Invalid TTL value, must be >= 0".into()))?;
+        let (value, dumped) = dump::deserialize(payload)?;
+        self.ensure_memory()?;
         self.check_expiry(key);
-        let new_val = if let Some((StoreValue::Simple(ref mut val), _)) = self.store.get_mut(key) {
-            match val.parse::<i32>() {
-                Ok(num) => {
-                    let updated = num + amount;
-                    *val = updated.to_string();
-                    updated.to_string()
-                }
-                Err(_) => return None,
-            }
-        } else {
-            self.store.insert(
-                key.to_string(),
-                (StoreValue::Simple(amount.to_string()), None),
-            );
-            amount.to_string()
+        if !opts.replace && self.store.contains_key(key) {
+            return Err(StoreError::BusyKey);
+        }
+        let now = SystemTime::now();
+        let expire_at = match ttl {
+            0 => dumped,
+            ms if opts.abs_ttl => Some(UNIX_EPOCH + Duration::from_millis(ms)),
+            ms => Some(now + Duration::from_millis(ms)),
         };
-        Some(new_val)
+        if expire_at.is_some_and(|at| at <= now) {
+            self.remove_entry(key);
+            return Ok(());
+        }
+        self.insert_entry(key, value, expire_at.map(codec::to_instant));
+        if let (Some(idle), Some((_, _, meta))) = (opts.idle_time, self.store.get_mut(key)) {
+            meta.set_idle(Duration::from_secs(idle));
+        }
+        self.serve_blocked(key);
+        Ok(())
     }
 
-    /// Atomically decrement a key's numeric value.
-    pub fn decr(&mut self, key: &str, amount: i32) -> Option<String> {
-        self.incr(key, -amount)
+    /// Atomically increment a key's integer value (INCR/INCRBY).
+    /// If the key doesn't exist, it is created with the increment value.
+    pub fn incr(&mut self, key: &[u8], amount: i64) -> Result<i64, StoreError> {
+        self.update_string(key, |current| {
+            let num = match current {
+                Some(val) => val.as_int()?,
+                None => 0,
+            };
+            let updated = num.checked_add(amount).ok_or(StoreError::Overflow)?;
+            Ok((StringValue::Int(updated), updated))
+        })
     }
 
-    /// Append a string to the current value of a key.
-    pub fn append(&mut self, key: &str, value: &str) -> Option<String> {
-        self.check_expiry(key);
-        if let Some((StoreValue::Simple(ref mut val), _)) = self.store.get_mut(key) {
-            val.push_str(value);
-            Some(val.clone())
-        } else {
-            None
+    /// Atomically decrement a key's integer value (DECR/DECRBY).
+    pub fn decr(&mut self, key: &[u8], amount: i64) -> Result<i64, StoreError> {
+        self.incr(key, amount.checked_neg().ok_or(StoreError::Overflow)?)
+    }
+
+    /// Atomically increment a key's value by a float (INCRBYFLOAT) and return the new value.
+    pub fn incr_by_float(&mut self, key: &[u8], delta: f64) -> Result<String, StoreError> {
+        self.update_string(key, |current| {
+            let num = match current {
+                Some(val) => val.as_float()?,
+                None => 0.0,
+            };
+            let updated = num + delta;
+            if !updated.is_finite() {
+                return Err(StoreError::InvalidArgument(
+                    "increment would produce NaN or Infinity".into(),
+                ));
+            }
+            let formatted = format_float(updated);
+            Ok((StringValue::new(formatted.as_bytes()), formatted))
+        })
+    }
+
+    /// Append a string to the current value of a key, creating it if needed.
+    pub fn append(&mut self, key: &[u8], value: &[u8]) -> Result<Vec<u8>, StoreError> {
+        self.update_string(key, |current| {
+            let mut updated = current.cloned().unwrap_or(StringValue::Raw(Vec::new()));
+            updated.append(value);
+            let appended = updated.to_bytes();
+            Ok((updated, appended))
+        })
+    }
+
+    /// Helper method: Replace the string at `key` with the value computed by `op` from the
+    /// current one (None if the key is missing), keeping its TTL. Fails with
+    /// `StoreError::WrongType` if the key holds another type.
+    fn update_string<R>(
+        &mut self,
+        key: &[u8],
+        op: impl FnOnce(Option<&StringValue>) -> Result<(StringValue, R), StoreError>,
+    ) -> Result<R, StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        match self.entry_mut(key) {
+            Some((StoreValue::Simple(val), _, _)) => {
+                let (updated, reply) = op(Some(val))?;
+                let (added, removed) = (updated.mem_usage(), val.mem_usage());
+                *val = updated;
+                self.adjust_memory(added, removed);
+                Ok(reply)
+            }
+            Some(_) => Err(StoreError::WrongType),
+            None => {
+                let (value, reply) = op(None)?;
+                self.insert_entry(key, StoreValue::Simple(value), None);
+                Ok(reply)
+            }
         }
     }
 
-    /// Return a list of keys matching a given pattern.
-    /// For simplicity, this supports "*" for all keys or substring matching.
-    pub fn keys(&mut self, pattern: &str) -> Vec<String> {
+    /// Return a list of keys matching a glob pattern (KEYS), e.g. "user:*:session".
+    pub fn keys(&mut self, pattern: &[u8]) -> Vec<Vec<u8>> {
         // First, collect all keys to avoid mutable borrowing while iterating.
-        let all_keys: Vec<String> = self.store.keys().cloned().collect();
+        let all_keys: Vec<Vec<u8>> = self.store.keys().cloned().collect();
         let mut result = Vec::new();
         for key in all_keys {
             self.check_expiry(&key);
-            if pattern == "*" || key.contains(pattern) {
+            if !self.store.contains_key(&key) {
+                continue;
+            }
+            if glob_match(pattern, &key) {
                 result.push(key);
             }
         }
         result
     }
 
-    /// List operations: push a value onto the front of the list.
-    pub fn l_push(&mut self, key: &str, value: &str) {
-        self.check_expiry(key);
-        let entry = self
-            .store
-            .entry(key.to_string())
-            .or_insert((StoreValue::List(Vec::new()), None));
-        match &mut entry.0 {
-            StoreValue::List(list) => list.insert(0, value.to_string()),
-            _ => entry.0 = StoreValue::List(vec![value.to_string()]),
+    /// Incrementally iterate over the keyspace (SCAN), visiting `opts.count` keys per call
+    /// and expiring stale ones along the way. `type_name` keeps only keys of that type.
+    /// Start with cursor 0 and pass the returned cursor back until it is 0 again.
+    pub fn scan(
+        &mut self,
+        cursor: u64,
+        opts: &ScanOptions,
+        type_name: Option<&str>,
+    ) -> ScanPage<Vec<u8>> {
+        let (window, next) = scan_window(self.store.len(), cursor, opts.count);
+        let mut keys = Vec::new();
+        for index in window.rev() {
+            // Expiring a key moves an already visited one into its slot.
+            let Some(key) = self.store.get_index(index).map(|(key, _)| key.clone()) else {
+                continue;
+            };
+            self.check_expiry(&key);
+            let Some((value, _, _)) = self.store.get(&key) else {
+                continue;
+            };
+            if type_name.is_some_and(|name| name != value.type_name()) || !opts.matches(&key) {
+                continue;
+            }
+            keys.push(key);
         }
+        ScanPage { cursor: next, items: keys }
     }
 
-    /// List operations: pop a value from the front of the list.
-    pub fn l_pop(&mut self, key: &str) -> Option<String> {
-        self.check_expiry(key);
-        if let Some((StoreValue::List(list), _)) = self.store.get_mut(key) {
-            if !list.is_empty() {
-                Some(list.remove(0))
+    /// List operations: push values onto the head of a list (LPUSH), one after the other.
+    /// Returns the new length.
+    pub fn l_push(&mut self, key: &[u8], values: &[Vec<u8>]) -> Result<usize, StoreError> {
+        self.list_push(key, values, true, false)
+    }
+
+    /// List operations: push values onto the tail of a list (RPUSH).
+    /// Returns the new length.
+    pub fn r_push(&mut self, key: &[u8], values: &[Vec<u8>]) -> Result<usize, StoreError> {
+        self.list_push(key, values, false, false)
+    }
+
+    /// List operations: push values onto the head of an existing list (LPUSHX).
+    /// Returns the new length, or 0 if the key is missing.
+    pub fn l_push_x(&mut self, key: &[u8], values: &[Vec<u8>]) -> Result<usize, StoreError> {
+        self.list_push(key, values, true, true)
+    }
+
+    /// List operations: push values onto the tail of an existing list (RPUSHX).
+    /// Returns the new length, or 0 if the key is missing.
+    pub fn r_push_x(&mut self, key: &[u8], values: &[Vec<u8>]) -> Result<usize, StoreError> {
+        self.list_push(key, values, false, true)
+    }
+
+    fn list_push(
+        &mut self,
+        key: &[u8],
+        values: &[Vec<u8>],
+        front: bool,
+        only_existing: bool,
+    ) -> Result<usize, StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        if !self.store.contains_key(key) && (only_existing || values.is_empty()) {
+            return Ok(0);
+        }
+        self.push_values(key, values, front)
+    }
+
+    /// Helper method: Push values onto a list, creating it if needed.
+    /// Returns the new length.
+    pub(crate) fn push_values(
+        &mut self,
+        key: &[u8],
+        values: &[Vec<u8>],
+        front: bool,
+    ) -> Result<usize, StoreError> {
+        if !self.store.contains_key(key) {
+            self.insert_entry(key, StoreValue::List(VecDeque::new()), None);
+        }
+        let list = match self.entry_to_edit(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            _ => return Err(StoreError::WrongType),
+        };
+        for value in values {
+            if front {
+                list.push_front(value.clone());
             } else {
-                None
+                list.push_back(value.clone());
             }
-        } else {
-            None
         }
+        let len = list.len();
+        let added = values.iter().map(|value| string_size(value)).sum();
+        self.adjust_memory(added, 0);
+        self.record_edit(key, Edit::Push { front, values: values.to_vec() });
+        self.serve_blocked(key);
+        Ok(len)
     }
 
-    /// Set operations: add a member to a set.
-    pub fn s_add(&mut self, key: &str, member: &str) {
-        self.check_expiry(key);
-        let entry = self
-            .store
-            .entry(key.to_string())
-            .or_insert((StoreValue::Set(HashSet::new()), None));
-        match &mut entry.0 {
-            StoreValue::Set(set) => {
-                set.insert(member.to_string());
+    /// Helper method: Hand elements of a list that just received pushes to the clients
+    /// blocked on it, oldest first.
+    pub(crate) fn serve_blocked(&mut self, key: &[u8]) {
+        // Clients woken to move an element into another shard take it later; leave one
+        // element for each.
+        let mut woken = 0;
+        while self.blocked.is_waiting_on(key) {
+            match self.store.get(key) {
+                Some((StoreValue::List(list), _, _)) if list.len() > woken => {}
+                _ => return,
+            }
+            let (op, sender) = match self.blocked.pop_oldest(key) {
+                Some(waiter) => waiter,
+                None => return,
+            };
+            match op {
+                BlockedOp::Pop { front } => {
+                    let value = match self.store.get(key) {
+                        Some((StoreValue::List(list), _, _)) => {
+                            if front { list.front() } else { list.back() }.cloned()
+                        }
+                        _ => None,
+                    };
+                    let Some(value) = value else { return };
+                    // Only take the element once the client has accepted it, so a client
+                    // that went away in the meantime cannot lose it.
+                    if sender.send((key.to_vec(), value)).is_ok() {
+                        let _ = self.pop_value(key, front);
+                    }
+                }
+                BlockedOp::Move { from_front, destination, to_front } => {
+                    // A destination of another type drops the sender, which fails the call.
+                    if let Ok(Some(value)) =
+                        multi_key::move_value(self, key, &destination, from_front, to_front)
+                    {
+                        let _ = sender.send((key.to_vec(), value));
+                    }
+                }
+                BlockedOp::Wake => {
+                    if sender.send((key.to_vec(), Vec::new())).is_ok() {
+                        woken += 1;
+                    }
+                }
             }
-            _ => entry.0 = StoreValue::Set([member.to_string()].iter().cloned().collect()),
         }
     }
 
-    /// Set operations: get all members of a set.
-    pub fn s_members(&mut self, key: &str) -> Vec<String> {
+    /// Blocking list operations: hand elements of `key` to the clients blocked on it, as a
+    /// push does. Passes on an element a woken client went away without taking.
+    pub fn wake_blocked(&mut self, key: &[u8]) {
         self.check_expiry(key);
-        if let Some((StoreValue::Set(set), _)) = self.store.get(key) {
-            set.iter().cloned().collect()
+        self.serve_blocked(key);
+    }
+
+    /// List operations: pop a value from the head of the list (LPOP).
+    pub fn l_pop(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.lookup(key);
+        self.pop_value(key, true)
+    }
+
+    /// List operations: pop a value from the tail of the list (RPOP).
+    pub fn r_pop(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.lookup(key);
+        self.pop_value(key, false)
+    }
+
+    /// Helper method: Move an element from one end of a list to the other in place (LMOVE
+    /// with the same source and destination), so the list and its TTL survive even with one
+    /// element. Returns None if the list is missing.
+    pub(crate) fn rotate_list(
+        &mut self,
+        key: &[u8],
+        from_front: bool,
+        to_front: bool,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        let list = match self.entry_to_edit(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(None),
+        };
+        let value = if from_front { list.pop_front() } else { list.pop_back() };
+        if let Some(value) = &value {
+            if to_front {
+                list.push_front(value.clone());
+            } else {
+                list.push_back(value.clone());
+            }
+            self.record_edit(key, Edit::Pop { front: from_front });
+            let values = vec![value.clone()];
+            self.record_edit(key, Edit::Push { front: to_front, values });
+        }
+        Ok(value)
+    }
+
+    /// Helper method: Pop a value from one end of a list, removing the list once empty.
+    pub(crate) fn pop_value(&mut self, key: &[u8], front: bool) -> Result<Option<Vec<u8>>, StoreError> {
+        let list = match self.entry_to_edit(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(None),
+        };
+        let Some(popped) = (if front { list.pop_front() } else { list.pop_back() }) else {
+            return Ok(None);
+        };
+        let emptied = list.is_empty();
+        self.adjust_memory(0, string_size(&popped));
+        if emptied {
+            self.remove_entry(key);
         } else {
-            Vec::new()
+            self.record_edit(key, Edit::Pop { front });
         }
+        Ok(Some(popped))
     }
 
-    /// Hash operations: set a field in a hash.
-    pub fn h_set(&mut self, key: &str, field: &str, value: &str) {
-        self.check_expiry(key);
-        let entry = self
-            .store
-            .entry(key.to_string())
-            .or_insert((StoreValue::Hash(HashMap::new()), None));
-        match &mut entry.0 {
-            StoreValue::Hash(map) => {
-                map.insert(field.to_string(), value.to_string());
+    /// List operations: number of elements (LLEN).
+    pub fn l_len(&mut self, key: &[u8]) -> Result<usize, StoreError> {
+        self.lookup(key);
+        match self.store.get(key) {
+            Some((StoreValue::List(list), _, _)) => Ok(list.len()),
+            Some(_) => Err(StoreError::WrongType),
+            None => Ok(0),
+        }
+    }
+
+    /// List operations: elements at indexes `start..=stop` (LRANGE); negative indexes
+    /// count from the tail.
+    pub fn l_range(&mut self, key: &[u8], start: i64, stop: i64) -> Result<Vec<Vec<u8>>, StoreError> {
+        self.lookup(key);
+        let list = match self.store.get(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(Vec::new()),
+        };
+        Ok(match normalize_range(list.len(), start, stop) {
+            Some((start, stop)) => list.range(start..=stop).cloned().collect(),
+            None => Vec::new(),
+        })
+    }
+
+    /// List operations: element at an index (LINDEX); negative indexes count from the tail.
+    pub fn l_index(&mut self, key: &[u8], index: i64) -> Result<Option<Vec<u8>>, StoreError> {
+        self.lookup(key);
+        match self.store.get(key) {
+            Some((StoreValue::List(list), _, _)) => {
+                Ok(normalize_index(list.len(), index).map(|i| list[i].clone()))
             }
-            _ => {
-                let mut map = HashMap::new();
-                map.insert(field.to_string(), value.to_string());
-                entry.0 = StoreValue::Hash(map);
+            Some(_) => Err(StoreError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// List operations: replace the element at an index (LSET).
+    /// Fails if the key is missing or the index is out of range.
+    pub fn l_set(&mut self, key: &[u8], index: i64, value: &[u8]) -> Result<(), StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        let list = match self.entry_mut(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Err(StoreError::InvalidArgument("no such key".to_string())),
+        };
+        let index = normalize_index(list.len(), index)
+            .ok_or_else(|| StoreError::InvalidArgument("index out of range".to_string()))?;
+        let old = std::mem::replace(&mut list[index], value.to_vec());
+        self.adjust_memory(string_size(value), string_size(&old));
+        Ok(())
+    }
+
+    /// List operations: keep only the elements at indexes `start..=stop` (LTRIM).
+    pub fn l_trim(&mut self, key: &[u8], start: i64, stop: i64) -> Result<(), StoreError> {
+        self.lookup(key);
+        let list = match self.entry_mut(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(()),
+        };
+        let before = list.iter().map(|value| string_size(value)).sum::<usize>();
+        match normalize_range(list.len(), start, stop) {
+            Some((start, stop)) => {
+                list.truncate(stop + 1);
+                list.drain(..start);
             }
+            None => list.clear(),
+        }
+        let after = list.iter().map(|value| string_size(value)).sum::<usize>();
+        let emptied = list.is_empty();
+        self.adjust_memory(after, before);
+        if emptied {
+            self.remove_entry(key);
         }
+        Ok(())
     }
 
-    /// Hash operations: get a field from a hash.
-    pub fn h_get(&mut self, key: &str, field: &str) -> Option<String> {
-        self.check_expiry(key);
-        if let Some((StoreValue::Hash(map), _)) = self.store.get(key) {
-            map.get(field).cloned()
+    /// List operations: remove elements equal to `value` (LREM): the first `count` from
+    /// the head if `count` is positive, the last `-count` if negative, or all if zero.
+    /// Returns the number of removed elements.
+    pub fn l_rem(&mut self, key: &[u8], count: i64, value: &[u8]) -> Result<usize, StoreError> {
+        self.lookup(key);
+        let list = match self.entry_mut(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(0),
+        };
+        let limit = if count == 0 {
+            usize::MAX
+        } else {
+            count.unsigned_abs() as usize
+        };
+        let mut removed = 0;
+        let mut kept = VecDeque::with_capacity(list.len());
+        if count >= 0 {
+            for item in list.drain(..) {
+                if removed < limit && item == value {
+                    removed += 1;
+                } else {
+                    kept.push_back(item);
+                }
+            }
+        } else {
+            for item in list.drain(..).rev() {
+                if removed < limit && item == value {
+                    removed += 1;
+                } else {
+                    kept.push_front(item);
+                }
+            }
+        }
+        *list = kept;
+        let emptied = list.is_empty();
+        self.adjust_memory(0, removed * string_size(value));
+        if emptied {
+            self.remove_entry(key);
+        }
+        Ok(removed)
+    }
+
+    /// List operations: insert `value` before or after the first element equal to
+    /// `pivot` (LINSERT). Returns the new length, -1 if the pivot was not found, or 0 if
+    /// the key is missing.
+    pub fn l_insert(
+        &mut self,
+        key: &[u8],
+        before: bool,
+        pivot: &[u8],
+        value: &[u8],
+    ) -> Result<i64, StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        let list = match self.entry_mut(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(0),
+        };
+        let position = match list.iter().position(|item| item == pivot) {
+            Some(position) => position,
+            None => return Ok(-1),
+        };
+        list.insert(if before { position } else { position + 1 }, value.to_vec());
+        let len = list.len();
+        self.adjust_memory(string_size(value), 0);
+        Ok(len as i64)
+    }
+
+    /// List operations: indexes of elements equal to `element` (LPOS).
+    ///
+    /// `rank` selects the first match to return: 1 is the first match from the head,
+    /// 2 the second, -1 the first from the tail, and so on. At most `count` indexes are
+    /// returned (0 means all), and at most `maxlen` elements are compared (0 means all).
+    pub fn l_pos(
+        &mut self,
+        key: &[u8],
+        element: &[u8],
+        rank: i64,
+        count: usize,
+        maxlen: usize,
+    ) -> Result<Vec<usize>, StoreError> {
+        self.lookup(key);
+        let list = match self.store.get(key) {
+            Some((StoreValue::List(list), _, _)) => list,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(Vec::new()),
+        };
+        let skip = rank.unsigned_abs().saturating_sub(1) as usize;
+        let count = if count == 0 { usize::MAX } else { count };
+        let maxlen = if maxlen == 0 { usize::MAX } else { maxlen };
+        let matches = |i: &usize| list[*i] == element;
+        Ok(if rank >= 0 {
+            (0..list.len()).take(maxlen).filter(matches).skip(skip).take(count).collect()
         } else {
-            None
+            (0..list.len()).rev().take(maxlen).filter(matches).skip(skip).take(count).collect()
+        })
+    }
+
+    /// Blocking list operations: park a client until a push onto one of `keys` serves it.
+    pub fn block_client(
+        &mut self,
+        keys: Vec<Vec<u8>>,
+        op: BlockedOp,
+    ) -> (u64, oneshot::Receiver<Delivery>) {
+        self.blocked.register(keys, op)
+    }
+
+    /// Blocking list operations: park a client that may also wait in other shards, all
+    /// delivering through `slot`. Only registrations with `counted` set are reported by
+    /// `blocked_clients`. Returns the client's ID in this store.
+    pub fn block_client_slot(
+        &mut self,
+        keys: Vec<Vec<u8>>,
+        op: BlockedOp,
+        slot: DeliverySlot,
+        counted: bool,
+    ) -> u64 {
+        self.blocked.register_slot(keys, op, slot, counted)
+    }
+
+    /// Blocking list operations: stop waiting for a client parked with `block_client_slot`.
+    pub fn unblock_client_slot(&mut self, id: u64) {
+        self.blocked.unregister(id);
+    }
+
+    /// Blocking list operations: stop waiting for a client that timed out or went away.
+    /// Returns an element that was already handed to it, if any.
+    pub fn unblock_client(
+        &mut self,
+        id: u64,
+        receiver: &mut oneshot::Receiver<Delivery>,
+    ) -> Option<Delivery> {
+        self.blocked.unregister(id);
+        receiver.try_recv().ok()
+    }
+
+    /// Blocking list operations: put back an element handed to a client that went away.
+    pub fn requeue(&mut self, key: &[u8], value: Vec<u8>, front: bool) {
+        self.lookup(key);
+        // The key can only have changed type if it was deleted and recreated since; the
+        // element is then dropped, as it would have been by the deletion.
+        let _ = self.push_values(key, &[value], front);
+    }
+
+    /// Remove every key (FLUSHDB). With `lazy`, the old keys are dropped on a background
+    /// thread so large keyspaces do not hold the store lock while they are freed.
+    /// Blocked clients and statistics are kept.
+    pub fn flush(&mut self, lazy: bool) {
+        self.mark_all_dirty();
+        let keyspace = std::mem::take(&mut self.store);
+        self.expires.clear();
+        self.field_expires.clear();
+        self.used_memory = 0;
+        if let Some(frozen) = self.frozen.as_mut().filter(|frozen| !frozen.complete) {
+            // A snapshot being taken keeps the old keys instead of copying them.
+            for (key, entry) in keyspace {
+                let record = frozen_record(frozen.at, entry);
+                frozen.saved.entry(key).or_insert(record);
+            }
+            frozen.complete = true;
+        } else if lazy {
+            lazy_free::free_later(keyspace);
+        }
+    }
+
+    /// Exchange the keys of two stores (SWAPDB). Blocked clients, memory limits and
+    /// statistics stay with their store; clients blocked on a key that is now a non-empty
+    /// list are served right away.
+    pub fn swap_keyspace(&mut self, other: &mut TTLStore) {
+        self.mark_all_dirty();
+        other.mark_all_dirty();
+        self.preserve_all();
+        other.preserve_all();
+        std::mem::swap(&mut self.store, &mut other.store);
+        std::mem::swap(&mut self.expires, &mut other.expires);
+        std::mem::swap(&mut self.field_expires, &mut other.field_expires);
+        std::mem::swap(&mut self.used_memory, &mut other.used_memory);
+        for store in [self, other] {
+            // Keys that left the store were marked above; the ones that arrived are too.
+            store.mark_all_dirty();
+            for key in store.blocked.keys() {
+                store.serve_blocked(&key);
+            }
         }
     }
+
+    /// Move a key, with its TTL, to another store (MOVE).
+    /// Returns false if the key does not exist here or already exists in `target`.
+    pub fn move_to(&mut self, key: &[u8], target: &mut TTLStore) -> Result<bool, StoreError> {
+        self.check_expiry(key);
+        target.check_expiry(key);
+        if !self.store.contains_key(key) || target.store.contains_key(key) {
+            return Ok(false);
+        }
+        target.ensure_memory()?;
+        let (value, expiry, _) = self.remove_entry(key).ok_or(StoreError::NoSuchKey)?;
+        target.insert_entry(key, value, expiry);
+        target.serve_blocked(key);
+        Ok(true)
+    }
+
+    /// Load a key read from a durable engine, replacing any entry under the same name.
+    /// Clients blocked on the key are served if it is a non-empty list. The memory limit is
+    /// not checked: the key was already accounted for when it was written. The key is not
+    /// marked dirty, though serving blocked clients may change it and mark it.
+    pub fn restore_entry(&mut self, key: &[u8], value: StoreValue, expiry: Option<Instant>) {
+        self.attach_entry(key, value, expiry);
+        self.serve_blocked(key);
+    }
+
+    /// Whether the store holds `key`, expired or not, or a change to it not yet written
+    /// through. Either way, a durable engine has nothing newer for it.
+    pub fn is_loaded(&self, key: &[u8]) -> bool {
+        self.store.contains_key(key)
+            || self.dirty.as_ref().is_some_and(|dirty| dirty.contains_key(key))
+    }
+
+    /// Freeze the keyspace for a snapshot: from now on, the first change to each key saves
+    /// its record as it was, so `take_frozen` returns the keyspace as of this call without
+    /// copying the keys that do not change meanwhile.
+    pub fn freeze(&mut self) {
+        self.frozen = Some(Frozen {
+            at: Instant::now(),
+            saved: HashMap::new(),
+            complete: false,
+        });
+    }
+
+    /// Visit every live record as of the last `freeze`, and stop saving changed keys.
+    /// Without a freeze, the records live now are visited.
+    pub fn take_frozen(&mut self, visit: &mut dyn FnMut(&[u8], Record)) {
+        let Some(frozen) = self.frozen.take() else {
+            let _ = self.for_each_record(b"", visit);
+            return;
+        };
+        if !frozen.complete {
+            for (key, (value, expiry, _)) in &self.store {
+                let live = expiry.is_none_or(|deadline| deadline > frozen.at);
+                if live && !frozen.saved.contains_key(key) {
+                    visit(key, (value.clone(), expiry.map(codec::to_system_time)));
+                }
+            }
+        }
+        for (key, record) in frozen.saved {
+            if let Some(record) = record {
+                visit(&key, record);
+            }
+        }
+    }
+
+    /// Helper method: Save the record of `key` for the snapshot being taken, if any,
+    /// before its first change.
+    fn preserve(&mut self, key: &[u8]) {
+        let Some(frozen) = self.frozen.as_mut().filter(|frozen| !frozen.complete) else {
+            return;
+        };
+        if !frozen.saved.contains_key(key) {
+            let record = self.store.get(key).and_then(|(value, expiry, _)| {
+                let live = expiry.is_none_or(|deadline| deadline > frozen.at);
+                live.then(|| (value.clone(), expiry.map(codec::to_system_time)))
+            });
+            frozen.saved.insert(key.to_vec(), record);
+        }
+    }
+
+    /// Helper method: Save every key for the snapshot being taken, if any, before the
+    /// whole keyspace is replaced.
+    fn preserve_all(&mut self) {
+        if self.frozen.as_ref().is_some_and(|frozen| !frozen.complete) {
+            let keys: Vec<Vec<u8>> = self.store.keys().cloned().collect();
+            for key in keys {
+                self.preserve(&key);
+            }
+            if let Some(frozen) = &mut self.frozen {
+                frozen.complete = true;
+            }
+        }
+    }
+
+    /// Suspend lazy expiry of keys and hash fields while a log of writes is replayed: a key
+    /// whose deadline passed since it was logged still takes the edits logged after it,
+    /// such as a PERSIST. Keys still expired once the whole log is applied are dropped when
+    /// the keyspace is loaded.
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+
+    /// Record evicted keys as removed, for `take_dirty`. An engine whose keyspace is loaded
+    /// whole at startup needs this, or evicted keys come back on the next restart.
+    pub fn log_evictions(&mut self) {
+        self.log_evictions = true;
+    }
+
+    /// Start recording the keys each write touches, for `take_dirty`.
+    pub fn track_writes(&mut self) {
+        self.dirty.get_or_insert_with(HashMap::new);
+    }
+
+    /// Record that `key` was written or removed: count the change and, if write tracking is
+    /// on, remember that the key has to be written whole.
+    pub fn mark_dirty(&mut self, key: &[u8]) {
+        self.changes += 1;
+        if let Some(dirty) = &mut self.dirty {
+            match dirty.get_mut(key) {
+                Some(change) => *change = KeyChange::Rewrite,
+                None => {
+                    dirty.insert(key.to_vec(), KeyChange::Rewrite);
+                }
+            }
+        }
+    }
+
+    /// Helper method: Record that `key` was changed in place by `edit`: count the change
+    /// and, if write tracking is on and the key is not already written whole, remember it.
+    fn record_edit(&mut self, key: &[u8], edit: Edit) {
+        self.changes += 1;
+        if let Some(dirty) = &mut self.dirty {
+            match dirty.get_mut(key) {
+                Some(KeyChange::Rewrite) => {}
+                Some(KeyChange::Edits(edits)) => edits.push(edit),
+                None => {
+                    dirty.insert(key.to_vec(), KeyChange::Edits(vec![edit]));
+                }
+            }
+        }
+    }
+
+    /// Helper method: Mark every key dirty, before the whole keyspace is replaced.
+    fn mark_all_dirty(&mut self) {
+        self.changes += self.store.len() as u64;
+        if let Some(dirty) = &mut self.dirty {
+            for key in self.store.keys() {
+                dirty.insert(key.clone(), KeyChange::Rewrite);
+            }
+        }
+    }
+
+    /// Number of key writes and removals since the store was created.
+    pub fn changes(&self) -> u64 {
+        self.changes
+    }
+
+    /// The keys written or removed since the last call and how, in no particular order.
+    /// Always empty unless write tracking is on.
+    pub fn take_dirty(&mut self) -> Vec<(Vec<u8>, KeyChange)> {
+        match &mut self.dirty {
+            Some(dirty) => dirty.drain().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Mark `changes` dirty again after writing them through failed, so the next write
+    /// through retries them ahead of anything recorded since. Changes are not counted twice.
+    pub fn restore_dirty(&mut self, changes: Vec<(Vec<u8>, KeyChange)>) {
+        let Some(dirty) = &mut self.dirty else {
+            return;
+        };
+        for (key, change) in changes {
+            let merged = match (change, dirty.remove(&key)) {
+                (KeyChange::Edits(mut edits), Some(KeyChange::Edits(later))) => {
+                    edits.extend(later);
+                    KeyChange::Edits(edits)
+                }
+                (KeyChange::Edits(edits), None) => KeyChange::Edits(edits),
+                _ => KeyChange::Rewrite,
+            };
+            dirty.insert(key, merged);
+        }
+    }
+
+    /// The value of a live key and the wall-clock time it expires at, without counting as
+    /// an access. Returns None if the key is missing or expired.
+    pub fn peek(&self, key: &[u8]) -> Option<(&StoreValue, Option<SystemTime>)> {
+        match self.store.get(key)? {
+            (_, Some(expiry), _) if *expiry <= Instant::now() => None,
+            (value, expiry, _) => Some((value, expiry.map(codec::to_system_time))),
+        }
+    }
+
+    /// Number of clients blocked on lists.
+    pub fn blocked_clients(&self) -> usize {
+        self.blocked.len()
+    }
+
+    /// Whether any client is queued on `key`, including clients another shard served
+    /// that have not left the queue yet.
+    pub fn is_blocked_on(&self, key: &[u8]) -> bool {
+        self.blocked.is_waiting_on(key)
+    }
+
+    /// Set operations: add members to a set (SADD).
+    /// Returns the number of members that were not already present.
+    pub fn s_add(&mut self, key: &[u8], members: &[Vec<u8>]) -> Result<usize, StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        if !self.store.contains_key(key) && !members.is_empty() {
+            self.insert_entry(key, StoreValue::Set(IndexSet::new()), None);
+        }
+        let set = match self.entry_to_edit(key) {
+            Some((StoreValue::Set(set), _, _)) => set,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(0),
+        };
+        let added: Vec<&Vec<u8>> = members
+            .iter()
+            .filter(|member| set.insert((*member).clone()))
+            .collect();
+        let added_bytes = added
+            .iter()
+            .map(|member| string_size(member) + SLOT_OVERHEAD)
+            .sum();
+        let count = added.len();
+        for member in added {
+            self.record_edit(key, Edit::SetAdd(member.clone()));
+        }
+        self.adjust_memory(added_bytes, 0);
+        Ok(count)
+    }
+
+    /// Set operations: remove members from a set (SREM), removing the set once empty.
+    /// Returns the number of members removed.
+    pub fn s_rem(&mut self, key: &[u8], members: &[Vec<u8>]) -> Result<usize, StoreError> {
+        self.lookup(key);
+        let set = match self.entry_to_edit(key) {
+            Some((StoreValue::Set(set), _, _)) => set,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(0),
+        };
+        let removed: Vec<&Vec<u8>> = members
+            .iter()
+            .filter(|member| set.swap_remove(*member))
+            .collect();
+        let emptied = set.is_empty();
+        let removed_bytes = removed
+            .iter()
+            .map(|member| string_size(member) + SLOT_OVERHEAD)
+            .sum();
+        let count = removed.len();
+        self.adjust_memory(0, removed_bytes);
+        if emptied {
+            self.remove_entry(key);
+        } else {
+            for member in removed {
+                self.record_edit(key, Edit::SetRemove(member.clone()));
+            }
+        }
+        Ok(count)
+    }
+
+    /// Helper method: The set stored at `key`, or None if the key is missing.
+    fn set_ref(&mut self, key: &[u8]) -> Result<Option<&IndexSet<Vec<u8>>>, StoreError> {
+        self.lookup(key);
+        match self.store.get(key) {
+            Some((StoreValue::Set(set), _, _)) => Ok(Some(set)),
+            Some(_) => Err(StoreError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Set operations: get all members of a set.
+    pub fn s_members(&mut self, key: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+        Ok(self.set_ref(key)?.map_or_else(Vec::new, |set| set.iter().cloned().collect()))
+    }
+
+    /// Set operations: incrementally iterate over members (SSCAN); cursors work as in `scan`.
+    pub fn s_scan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        opts: &ScanOptions,
+    ) -> Result<ScanPage<Vec<u8>>, StoreError> {
+        let Some(set) = self.set_ref(key)? else {
+            return Ok(ScanPage { cursor: 0, items: Vec::new() });
+        };
+        let (window, next) = scan_window(set.len(), cursor, opts.count);
+        let items = window
+            .rev()
+            .filter_map(|index| set.get_index(index))
+            .filter(|member| opts.matches(member))
+            .cloned()
+            .collect();
+        Ok(ScanPage { cursor: next, items })
+    }
+
+    /// Set operations: whether `member` belongs to the set (SISMEMBER).
+    pub fn s_is_member(&mut self, key: &[u8], member: &[u8]) -> Result<bool, StoreError> {
+        Ok(self.set_ref(key)?.is_some_and(|set| set.contains(member)))
+    }
+
+    /// Set operations: whether each of `members` belongs to the set (SMISMEMBER).
+    pub fn s_mis_member(&mut self, key: &[u8], members: &[Vec<u8>]) -> Result<Vec<bool>, StoreError> {
+        let set = self.set_ref(key)?;
+        Ok(members
+            .iter()
+            .map(|member| set.is_some_and(|set| set.contains(member)))
+            .collect())
+    }
+
+    /// Set operations: number of members (SCARD).
+    pub fn s_card(&mut self, key: &[u8]) -> Result<usize, StoreError> {
+        Ok(self.set_ref(key)?.map_or(0, |set| set.len()))
+    }
+
+    /// Set operations: remove and return up to `count` random members (SPOP),
+    /// removing the set once empty.
+    pub fn s_pop(&mut self, key: &[u8], count: usize) -> Result<Vec<Vec<u8>>, StoreError> {
+        self.lookup(key);
+        let set = match self.entry_to_edit(key) {
+            Some((StoreValue::Set(set), _, _)) => set,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(Vec::new()),
+        };
+        let mut rng = rand::thread_rng();
+        let mut popped = Vec::with_capacity(count.min(set.len()));
+        while popped.len() < count && !set.is_empty() {
+            let index = rng.gen_range(0..set.len());
+            popped.extend(set.swap_remove_index(index));
+        }
+        let emptied = set.is_empty();
+        let removed_bytes = popped
+            .iter()
+            .map(|member| string_size(member) + SLOT_OVERHEAD)
+            .sum();
+        self.adjust_memory(0, removed_bytes);
+        if emptied {
+            self.remove_entry(key);
+        } else {
+            for member in &popped {
+                self.record_edit(key, Edit::SetRemove(member.clone()));
+            }
+        }
+        Ok(popped)
+    }
+
+    /// Set operations: random members without removing them (SRANDMEMBER).
+    /// A positive `count` returns up to `count` distinct members; a negative one returns
+    /// exactly `-count` members that may repeat, at most `MAX_RANDOM_REPEATS`.
+    pub fn s_rand_member(&mut self, key: &[u8], count: i64) -> Result<Vec<Vec<u8>>, StoreError> {
+        check_random_count(count)?;
+        let set = match self.set_ref(key)? {
+            Some(set) if !set.is_empty() => set,
+            _ => return Ok(Vec::new()),
+        };
+        let mut rng = rand::thread_rng();
+        if count < 0 {
+            return Ok((0..count.unsigned_abs())
+                .filter_map(|_| set.get_index(rng.gen_range(0..set.len())).cloned())
+                .collect());
+        }
+        let amount = (count as usize).min(set.len());
+        Ok(rand::seq::index::sample(&mut rng, set.len(), amount)
+            .into_iter()
+            .filter_map(|index| set.get_index(index).cloned())
+            .collect())
+    }
+
+    /// Hash operations: set fields in a hash (HSET), clearing their TTLs.
+    /// Returns the number of fields that were added.
+    pub fn h_set(&mut self, key: &[u8], fields: &[FieldValue]) -> Result<usize, StoreError> {
+        self.ensure_memory()?;
+        let added = self.with_hash(key, |hash| {
+            fields
+                .iter()
+                .filter(|(field, value)| hash.insert(field, value).is_none())
+                .count()
+        })?;
+        for (field, value) in fields {
+            let (field, value) = (field.clone(), value.clone());
+            self.record_edit(key, Edit::HashSet { field, value });
+        }
+        Ok(added)
+    }
+
+    /// Hash operations: set a field only if it does not exist yet (HSETNX).
+    /// Returns whether the field was set.
+    pub fn h_set_nx(&mut self, key: &[u8], field: &[u8], value: &[u8]) -> Result<bool, StoreError> {
+        self.ensure_memory()?;
+        let set = self.with_hash(key, |hash| {
+            if hash.get(field).is_some() {
+                return false;
+            }
+            hash.insert(field, value);
+            true
+        })?;
+        if set {
+            let (field, value) = (field.to_vec(), value.to_vec());
+            self.record_edit(key, Edit::HashSet { field, value });
+        }
+        Ok(set)
+    }
+
+    /// Hash operations: get a field from a hash.
+    pub fn h_get(&mut self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        self.read_hash(key, |hash| hash.get(field).cloned())
+    }
+
+    /// Hash operations: get several fields at once (HMGET); missing fields are None.
+    pub fn h_mget(
+        &mut self,
+        key: &[u8],
+        fields: &[Vec<u8>],
+    ) -> Result<Vec<Option<Vec<u8>>>, StoreError> {
+        self.read_hash(key, |hash| fields.iter().map(|field| hash.get(field).cloned()).collect())
+    }
+
+    /// Hash operations: all fields and values (HGETALL).
+    pub fn h_get_all(&mut self, key: &[u8]) -> Result<Vec<FieldValue>, StoreError> {
+        self.read_hash(key, |hash| {
+            hash.iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect()
+        })
+    }
+
+    /// Hash operations: all field names (HKEYS).
+    pub fn h_keys(&mut self, key: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+        self.read_hash(key, |hash| hash.iter().map(|(field, _)| field.clone()).collect())
+    }
+
+    /// Hash operations: all values (HVALS).
+    pub fn h_vals(&mut self, key: &[u8]) -> Result<Vec<Vec<u8>>, StoreError> {
+        self.read_hash(key, |hash| hash.iter().map(|(_, value)| value.clone()).collect())
+    }
+
+    /// Hash operations: remove fields (HDEL), removing the hash once empty.
+    /// Returns the number of fields removed.
+    pub fn h_del(&mut self, key: &[u8], fields: &[Vec<u8>]) -> Result<usize, StoreError> {
+        let removed: Vec<&Vec<u8>> = self.with_hash(key, |hash| {
+            fields
+                .iter()
+                .filter(|field| hash.remove(field).is_some())
+                .collect()
+        })?;
+        for field in &removed {
+            self.record_edit(key, Edit::HashRemove(field.to_vec()));
+        }
+        Ok(removed.len())
+    }
+
+    /// Hash operations: whether a field exists (HEXISTS).
+    pub fn h_exists(&mut self, key: &[u8], field: &[u8]) -> Result<bool, StoreError> {
+        self.read_hash(key, |hash| hash.get(field).is_some())
+    }
+
+    /// Hash operations: number of fields (HLEN).
+    pub fn h_len(&mut self, key: &[u8]) -> Result<usize, StoreError> {
+        self.read_hash(key, |hash| hash.len())
+    }
+
+    /// Hash operations: incrementally iterate over fields and values (HSCAN); cursors work as
+    /// in `scan`. MATCH applies to field names.
+    pub fn h_scan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        opts: &ScanOptions,
+    ) -> Result<ScanPage<FieldValue>, StoreError> {
+        self.read_hash(key, |hash| {
+            let (window, next) = scan_window(hash.len(), cursor, opts.count);
+            let items = window
+                .rev()
+                .filter_map(|index| hash.get_index(index))
+                .filter(|(field, _)| opts.matches(field))
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect();
+            ScanPage { cursor: next, items }
+        })
+    }
+
+    /// Hash operations: length of a field's value, 0 if missing (HSTRLEN).
+    pub fn h_strlen(&mut self, key: &[u8], field: &[u8]) -> Result<usize, StoreError> {
+        self.read_hash(key, |hash| hash.get(field).map_or(0, |value| value.len()))
+    }
+
+    /// Hash operations: add `delta` to the integer value of a field (HINCRBY), keeping its TTL.
+    /// A missing field counts as 0. Returns the new value.
+    pub fn h_incr_by(&mut self, key: &[u8], field: &[u8], delta: i64) -> Result<i64, StoreError> {
+        self.ensure_memory()?;
+        let updated = self.with_hash(key, |hash| {
+            let current = match hash.get(field) {
+                Some(value) => std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .ok_or(StoreError::NotInteger)?,
+                None => 0,
+            };
+            let updated = current.checked_add(delta).ok_or(StoreError::Overflow)?;
+            hash.update(field, updated.to_string().into_bytes());
+            Ok(updated)
+        })??;
+        let value = updated.to_string().into_bytes();
+        self.record_edit(key, Edit::HashUpdate { field: field.to_vec(), value });
+        Ok(updated)
+    }
+
+    /// Hash operations: add `delta` to the float value of a field (HINCRBYFLOAT), keeping its
+    /// TTL. A missing field counts as 0. Returns the new value as stored.
+    pub fn h_incr_by_float(
+        &mut self,
+        key: &[u8],
+        field: &[u8],
+        delta: f64,
+    ) -> Result<String, StoreError> {
+        self.ensure_memory()?;
+        let formatted = self.with_hash(key, |hash| {
+            let current = match hash.get(field) {
+                Some(value) => parse_float(value)?,
+                None => 0.0,
+            };
+            let updated = current + delta;
+            if !updated.is_finite() {
+                return Err(StoreError::InvalidArgument(
+                    "increment would produce NaN or Infinity".into(),
+                ));
+            }
+            let formatted = format_float(updated);
+            hash.update(field, formatted.clone().into_bytes());
+            Ok(formatted)
+        })??;
+        let value = formatted.clone().into_bytes();
+        self.record_edit(key, Edit::HashUpdate { field: field.to_vec(), value });
+        Ok(formatted)
+    }
+
+    /// Hash operations: random fields and values (HRANDFIELD).
+    /// A positive `count` returns up to `count` distinct fields; a negative one returns
+    /// exactly `-count` fields that may repeat, at most `MAX_RANDOM_REPEATS`.
+    pub fn h_rand_field(
+        &mut self,
+        key: &[u8],
+        count: i64,
+    ) -> Result<Vec<FieldValue>, StoreError> {
+        check_random_count(count)?;
+        self.read_hash(key, |hash| {
+            if hash.is_empty() {
+                return Vec::new();
+            }
+            let mut rng = rand::thread_rng();
+            let pick = |index: usize| {
+                hash.get_index(index)
+                    .map(|(field, value)| (field.clone(), value.clone()))
+            };
+            if count < 0 {
+                return (0..count.unsigned_abs())
+                    .filter_map(|_| pick(rng.gen_range(0..hash.len())))
+                    .collect();
+            }
+            let amount = (count as usize).min(hash.len());
+            rand::seq::index::sample(&mut rng, hash.len(), amount)
+                .into_iter()
+                .filter_map(pick)
+                .collect()
+        })
+    }
+
+    /// Hash operations: set a TTL on fields (HEXPIRE). Per field, returns -2 if the field
+    /// does not exist, 2 if a zero TTL deleted it, or 1 if the TTL was set.
+    pub fn h_expire(
+        &mut self,
+        key: &[u8],
+        fields: &[Vec<u8>],
+        ttl: Duration,
+    ) -> Result<Vec<i64>, StoreError> {
+        let deadline = Instant::now()
+            .checked_add(ttl)
+            .ok_or_else(|| StoreError::InvalidArgument("invalid expire time".into()))?;
+        let replies: Vec<i64> = self.with_hash(key, |hash| {
+            fields
+                .iter()
+                .map(|field| {
+                    if hash.get(field).is_none() {
+                        -2
+                    } else if ttl.is_zero() {
+                        hash.remove(field);
+                        2
+                    } else {
+                        hash.expire_at(field, deadline);
+                        1
+                    }
+                })
+                .collect()
+        })?;
+        let expire_at = Some(codec::to_system_time(deadline));
+        for (field, reply) in fields.iter().zip(&replies) {
+            let field = field.clone();
+            match reply {
+                2 => self.record_edit(key, Edit::HashRemove(field)),
+                1 => self.record_edit(key, Edit::HashDeadline { field, expire_at }),
+                _ => {}
+            }
+        }
+        Ok(replies)
+    }
+
+    /// Hash operations: remaining TTL of fields in seconds (HTTL). Per field, returns -2 if
+    /// the field does not exist or -1 if it has no TTL.
+    pub fn h_ttl(&mut self, key: &[u8], fields: &[Vec<u8>]) -> Result<Vec<i64>, StoreError> {
+        let now = Instant::now();
+        self.read_hash(key, |hash| {
+            fields
+                .iter()
+                .map(|field| match hash.deadline(field) {
+                    None => -2,
+                    Some(None) => -1,
+                    Some(Some(deadline)) => {
+                        deadline.saturating_duration_since(now).as_secs() as i64
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Hash operations: remove the TTL of fields (HPERSIST). Per field, returns -2 if the
+    /// field does not exist, -1 if it had no TTL, or 1 if the TTL was removed.
+    pub fn h_persist(&mut self, key: &[u8], fields: &[Vec<u8>]) -> Result<Vec<i64>, StoreError> {
+        let replies: Vec<i64> = self.with_hash(key, |hash| {
+            fields
+                .iter()
+                .map(|field| match hash.deadline(field) {
+                    None => -2,
+                    Some(None) => -1,
+                    Some(Some(_)) => {
+                        hash.persist(field);
+                        1
+                    }
+                })
+                .collect()
+        })?;
+        for (field, _) in fields.iter().zip(&replies).filter(|(_, reply)| **reply == 1) {
+            let field = field.clone();
+            self.record_edit(key, Edit::HashDeadline { field, expire_at: None });
+        }
+        Ok(replies)
+    }
+
+    /// Helper method: Run `op` on the hash stored at `key`, or on an empty hash if the key
+    /// is missing, without writing to it unless expired fields have to be purged first.
+    /// Fails with `StoreError::WrongType` if the key holds another type.
+    fn read_hash<R>(&mut self, key: &[u8], op: impl FnOnce(&Hash) -> R) -> Result<R, StoreError> {
+        self.lookup(key);
+        let expired = match self.store.get(key) {
+            Some((StoreValue::Hash(hash), _, _)) => hash
+                .next_deadline()
+                .is_some_and(|deadline| deadline <= Instant::now()),
+            Some(_) => return Err(StoreError::WrongType),
+            None => false,
+        };
+        if expired {
+            return self.with_hash(key, |hash| op(hash));
+        }
+        match self.store.get(key) {
+            Some((StoreValue::Hash(hash), _, _)) => Ok(op(hash)),
+            _ => Ok(op(&Hash::new())),
+        }
+    }
+
+    /// Helper method: Run `op` on the hash stored at `key` after purging its expired fields,
+    /// keeping memory accounting and the field deadline index in sync.
+    ///
+    /// A missing key is treated as an empty hash, which is only stored if `op` adds fields
+    /// to it; a hash left empty is removed. Changes to an existing hash are left for the
+    /// caller to record with `record_edit`; purged fields are not recorded, as a durable
+    /// engine keeps their deadlines. Fails with `StoreError::WrongType` if the key holds
+    /// another type.
+    fn with_hash<R>(
+        &mut self,
+        key: &[u8],
+        op: impl FnOnce(&mut Hash) -> R,
+    ) -> Result<R, StoreError> {
+        self.lookup(key);
+        let purge = !self.replaying;
+        let hash = match self.entry_to_edit(key) {
+            Some((StoreValue::Hash(hash), _, _)) => hash,
+            Some(_) => return Err(StoreError::WrongType),
+            None => {
+                let mut hash = Hash::new();
+                let result = op(&mut hash);
+                if !hash.is_empty() {
+                    self.insert_entry(key, StoreValue::Hash(hash), None);
+                }
+                return Ok(result);
+            }
+        };
+        let (bytes_before, deadline_before) = (hash.mem_usage(), hash.next_deadline());
+        if purge {
+            hash.remove_expired(Instant::now());
+        }
+        let result = op(hash);
+        let (bytes_after, deadline_after) = (hash.mem_usage(), hash.next_deadline());
+        let emptied = hash.is_empty();
+        self.adjust_memory(bytes_after, bytes_before);
+        if deadline_before != deadline_after {
+            if let Some(deadline) = deadline_before {
+                self.field_expires.remove(&(deadline, key.to_vec()));
+            }
+            if let Some(deadline) = deadline_after {
+                self.field_expires.insert((deadline, key.to_vec()));
+            }
+        }
+        if emptied {
+            self.remove_entry(key);
+        }
+        Ok(result)
+    }
+
+    /// Sorted set operations: add members or update their scores (ZADD).
+    /// Returns the number of added members (plus updated ones with `ch`).
+    pub fn z_add(
+        &mut self,
+        key: &[u8],
+        members: &[(f64, Vec<u8>)],
+        opts: ZAddOptions,
+    ) -> Result<usize, StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        if !self.store.contains_key(key) {
+            if opts.xx {
+                return Ok(0);
+            }
+            self.insert_entry(key, StoreValue::SortedSet(SortedSet::new()), None);
+        }
+        let zset = match self.entry_to_edit(key) {
+            Some((StoreValue::SortedSet(zset), _, _)) => zset,
+            _ => return Err(StoreError::WrongType),
+        };
+        let (mut added, mut changed, mut added_bytes) = (0, 0, 0);
+        let mut edits = Vec::new();
+        for (score, member) in members {
+            match zset.score(member) {
+                Some(old) => {
+                    if opts.nx
+                        || *score == old
+                        || (opts.gt && *score <= old)
+                        || (opts.lt && *score >= old)
+                    {
+                        continue;
+                    }
+                    zset.insert(member, *score);
+                    changed += 1;
+                }
+                None => {
+                    if opts.xx {
+                        continue;
+                    }
+                    zset.insert(member, *score);
+                    added += 1;
+                    added_bytes += SortedSet::member_size(member);
+                }
+            }
+            edits.push(Edit::ZAdd { member: member.clone(), score: *score });
+        }
+        let emptied = zset.is_empty();
+        self.adjust_memory(added_bytes, 0);
+        if emptied {
+            self.remove_entry(key);
+        }
+        for edit in edits {
+            self.record_edit(key, edit);
+        }
+        Ok(if opts.ch { added + changed } else { added })
+    }
+
+    /// Sorted set operations: increment the score of a member (ZINCRBY).
+    /// Returns the new score; fails if the result is NaN.
+    pub fn z_incr_by(
+        &mut self,
+        key: &[u8],
+        increment: f64,
+        member: &[u8],
+    ) -> Result<f64, StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        if !self.store.contains_key(key) {
+            self.insert_entry(key, StoreValue::SortedSet(SortedSet::new()), None);
+        }
+        let zset = match self.entry_to_edit(key) {
+            Some((StoreValue::SortedSet(zset), _, _)) => zset,
+            _ => return Err(StoreError::WrongType),
+        };
+        let old = zset.score(member);
+        let updated = old.unwrap_or(0.0) + increment;
+        if updated.is_nan() {
+            if zset.is_empty() {
+                self.remove_entry(key);
+            }
+            return Err(StoreError::InvalidArgument(
+                "resulting score is not a number (NaN)".into(),
+            ));
+        }
+        zset.insert(member, updated);
+        if old.is_none() {
+            self.adjust_memory(SortedSet::member_size(member), 0);
+        }
+        self.record_edit(key, Edit::ZAdd { member: member.to_vec(), score: updated });
+        Ok(updated)
+    }
+
+    /// Sorted set operations: remove members (ZREM). Returns how many were removed.
+    pub fn z_rem(&mut self, key: &[u8], members: &[Vec<u8>]) -> Result<usize, StoreError> {
+        self.lookup(key);
+        let zset = match self.entry_to_edit(key) {
+            Some((StoreValue::SortedSet(zset), _, _)) => zset,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(0),
+        };
+        let removed: Vec<&Vec<u8>> = members
+            .iter()
+            .filter(|member| zset.remove(member).is_some())
+            .collect();
+        let emptied = zset.is_empty();
+        let removed_bytes = removed
+            .iter()
+            .map(|member| SortedSet::member_size(member))
+            .sum();
+        let count = removed.len();
+        self.adjust_memory(0, removed_bytes);
+        if emptied {
+            self.remove_entry(key);
+        } else {
+            for member in removed {
+                self.record_edit(key, Edit::ZRemove(member.clone()));
+            }
+        }
+        Ok(count)
+    }
+
+    /// Helper method: The sorted set stored at `key`, or None if the key is missing.
+    fn zset_ref(&mut self, key: &[u8]) -> Result<Option<&SortedSet>, StoreError> {
+        self.lookup(key);
+        match self.store.get(key) {
+            Some((StoreValue::SortedSet(zset), _, _)) => Ok(Some(zset)),
+            Some(_) => Err(StoreError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Sorted set operations: get the score of a member (ZSCORE).
+    pub fn z_score(&mut self, key: &[u8], member: &[u8]) -> Result<Option<f64>, StoreError> {
+        Ok(self.zset_ref(key)?.and_then(|zset| zset.score(member)))
+    }
+
+    /// Sorted set operations: number of members (ZCARD).
+    pub fn z_card(&mut self, key: &[u8]) -> Result<usize, StoreError> {
+        Ok(self.zset_ref(key)?.map_or(0, |zset| zset.len()))
+    }
+
+    /// Sorted set operations: incrementally iterate over members and scores (ZSCAN); cursors
+    /// work as in `scan`.
+    pub fn z_scan(
+        &mut self,
+        key: &[u8],
+        cursor: u64,
+        opts: &ScanOptions,
+    ) -> Result<ScanPage<(Vec<u8>, f64)>, StoreError> {
+        let Some(zset) = self.zset_ref(key)? else {
+            return Ok(ScanPage { cursor: 0, items: Vec::new() });
+        };
+        let (window, next) = scan_window(zset.len(), cursor, opts.count);
+        let items = window
+            .rev()
+            .filter_map(|index| zset.get_index(index))
+            .filter(|(member, _)| opts.matches(member))
+            .map(|(member, score)| (member.clone(), score))
+            .collect();
+        Ok(ScanPage { cursor: next, items })
+    }
+
+    /// Sorted set operations: zero-based rank of a member (ZRANK, or ZREVRANK with `rev`).
+    pub fn z_rank(
+        &mut self,
+        key: &[u8],
+        member: &[u8],
+        rev: bool,
+    ) -> Result<Option<usize>, StoreError> {
+        Ok(self.zset_ref(key)?.and_then(|zset| {
+            let rank = zset.rank(member)?;
+            Some(if rev { zset.len() - 1 - rank } else { rank })
+        }))
+    }
+
+    /// Sorted set operations: members in a range of ranks, scores or lexicographical
+    /// values (ZRANGE). Score and lex bounds are always given as (min, max), even with `rev`.
+    /// `limit` is an `(offset, count)` pair and only applies to score and lex ranges.
+    pub fn z_range(
+        &mut self,
+        key: &[u8],
+        by: &ZRangeBy,
+        rev: bool,
+        limit: Option<(usize, Option<usize>)>,
+    ) -> Result<Vec<(Vec<u8>, f64)>, StoreError> {
+        let Some(zset) = self.zset_ref(key)? else {
+            return Ok(Vec::new());
+        };
+        Ok(match by {
+            ZRangeBy::Index(start, stop) => zset.range_by_index(*start, *stop, rev),
+            ZRangeBy::Score(min, max) => zset.range_by_score(*min, *max, rev, limit),
+            ZRangeBy::Lex(min, max) => zset.range_by_lex(min, max, rev, limit),
+        })
+    }
+
+    /// Sorted set operations: remove and return the `count` lowest-scored members (ZPOPMIN).
+    pub fn z_pop_min(&mut self, key: &[u8], count: usize) -> Result<Vec<(Vec<u8>, f64)>, StoreError> {
+        self.z_pop(key, count, false)
+    }
+
+    /// Sorted set operations: remove and return the `count` highest-scored members (ZPOPMAX).
+    pub fn z_pop_max(&mut self, key: &[u8], count: usize) -> Result<Vec<(Vec<u8>, f64)>, StoreError> {
+        self.z_pop(key, count, true)
+    }
+
+    fn z_pop(
+        &mut self,
+        key: &[u8],
+        count: usize,
+        max: bool,
+    ) -> Result<Vec<(Vec<u8>, f64)>, StoreError> {
+        self.lookup(key);
+        let zset = match self.entry_to_edit(key) {
+            Some((StoreValue::SortedSet(zset), _, _)) => zset,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(Vec::new()),
+        };
+        let popped = zset.pop(count, max);
+        let emptied = zset.is_empty();
+        let removed_bytes = popped
+            .iter()
+            .map(|(member, _)| SortedSet::member_size(member))
+            .sum();
+        self.adjust_memory(0, removed_bytes);
+        if emptied {
+            self.remove_entry(key);
+        } else {
+            for (member, _) in &popped {
+                self.record_edit(key, Edit::ZRemove(member.clone()));
+            }
+        }
+        Ok(popped)
+    }
+
+    /// Helper method: Run `op` on the stream stored at `key`, keeping memory accounting
+    /// in sync. Returns None if the key is missing.
+    fn with_stream<R>(
+        &mut self,
+        key: &[u8],
+        op: impl FnOnce(&mut Stream) -> Result<R, StoreError>,
+    ) -> Result<Option<R>, StoreError> {
+        let stream = match self.entry_mut(key) {
+            Some((StoreValue::Stream(stream), _, _)) => stream,
+            Some(_) => return Err(StoreError::WrongType),
+            None => return Ok(None),
+        };
+        let before = stream.mem_usage();
+        let result = op(stream);
+        let after = stream.mem_usage();
+        self.adjust_memory(after, before);
+        result.map(Some)
+    }
+
+    /// Helper method: Run `op` on a stream that a consumer group command requires to exist.
+    /// Fails with `StoreError::NoGroup` if the key is missing.
+    pub(crate) fn with_group_stream<R>(
+        &mut self,
+        key: &[u8],
+        op: impl FnOnce(&mut Stream) -> Result<R, StoreError>,
+    ) -> Result<R, StoreError> {
+        self.lookup(key);
+        self.with_stream(key, op)?.ok_or(StoreError::NoGroup)
+    }
+
+    /// Helper method: The stream stored at `key`, or None if the key is missing.
+    pub(crate) fn stream_ref(&mut self, key: &[u8]) -> Result<Option<&Stream>, StoreError> {
+        self.lookup(key);
+        match self.store.get(key) {
+            Some((StoreValue::Stream(stream), _, _)) => Ok(Some(stream)),
+            Some(_) => Err(StoreError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Stream operations: append an entry (XADD), then trim the stream if requested.
+    /// Returns the ID of the new entry, or None if nothing was added because the key
+    /// is missing and `nomkstream` is set.
+    pub fn x_add(
+        &mut self,
+        key: &[u8],
+        id: XAddId,
+        fields: StreamFields,
+        nomkstream: bool,
+        trim: Option<TrimSpec>,
+    ) -> Result<Option<StreamId>, StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        let created = !self.store.contains_key(key);
+        if created {
+            if nomkstream {
+                return Ok(None);
+            }
+            self.insert_entry(key, StoreValue::Stream(Stream::new()), None);
+        }
+        let now = now_ms();
+        let result = self.with_stream(key, |stream| {
+            let id = stream.add(id, fields, now)?;
+            if let Some(spec) = trim {
+                stream.trim(spec);
+            }
+            Ok(id)
+        });
+        if created && result.is_err() {
+            self.remove_entry(key);
+        }
+        result
+    }
+
+    /// Stream operations: trim a stream (XTRIM). Returns the number of removed entries.
+    pub fn x_trim(&mut self, key: &[u8], spec: TrimSpec) -> Result<usize, StoreError> {
+        self.lookup(key);
+        Ok(self.with_stream(key, |stream| Ok(stream.trim(spec)))?.unwrap_or(0))
+    }
+
+    /// Stream operations: number of entries (XLEN).
+    pub fn x_len(&mut self, key: &[u8]) -> Result<usize, StoreError> {
+        Ok(self.stream_ref(key)?.map_or(0, |stream| stream.len()))
+    }
+
+    /// Stream operations: entries with IDs in `start..=end` (XRANGE), or in descending
+    /// order with `rev` (XREVRANGE). Bounds are always given as (start, end).
+    pub fn x_range(
+        &mut self,
+        key: &[u8],
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+        rev: bool,
+    ) -> Result<Vec<StreamEntry>, StoreError> {
+        Ok(self
+            .stream_ref(key)?
+            .map_or_else(Vec::new, |stream| stream.range(start, end, count, rev)))
+    }
+
+    /// Stream operations: the ID of the last entry added to a stream, used to resolve
+    /// the special `$` ID of XREAD and XGROUP CREATE.
+    pub fn x_last_id(&mut self, key: &[u8]) -> Result<Option<StreamId>, StoreError> {
+        Ok(self.stream_ref(key)?.map(|stream| stream.last_id()))
+    }
+
+    /// Stream operations: create a consumer group (XGROUP CREATE) that delivers entries
+    /// after `id`, or only new entries if `id` is None. With `mkstream`, a missing stream
+    /// is created empty.
+    pub fn x_group_create(
+        &mut self,
+        key: &[u8],
+        group: &str,
+        id: Option<StreamId>,
+        mkstream: bool,
+    ) -> Result<(), StoreError> {
+        self.ensure_memory()?;
+        self.lookup(key);
+        if !self.store.contains_key(key) {
+            if !mkstream {
+                return Err(StoreError::InvalidArgument(
+                    "The XGROUP subcommand requires the key to exist. \
+                     Note that for CREATE you may want to use the MKSTREAM option \
+                     to create an empty stream automatically."
+                        .to_string(),
+                ));
+            }
+            self.insert_entry(key, StoreValue::Stream(Stream::new()), None);
+        }
+        self.with_group_stream(key, |stream| {
+            let id = id.unwrap_or_else(|| stream.last_id());
+            stream.create_group(group, id)
+        })
+    }
+
+    /// Stream operations: destroy a consumer group (XGROUP DESTROY).
+    /// Returns whether the group existed.
+    pub fn x_group_destroy(&mut self, key: &[u8], group: &str) -> Result<bool, StoreError> {
+        self.with_group_stream(key, |stream| Ok(stream.destroy_group(group)))
+    }
+
+    /// Stream operations: create a consumer in a group (XGROUP CREATECONSUMER).
+    /// Returns whether it was created.
+    pub fn x_group_create_consumer(
+        &mut self,
+        key: &[u8],
+        group: &str,
+        consumer: &str,
+    ) -> Result<bool, StoreError> {
+        self.ensure_memory()?;
+        let now = now_ms();
+        self.with_group_stream(key, |stream| stream.create_consumer(group, consumer, now))
+    }
+
+    /// Stream operations: delete a consumer from a group (XGROUP DELCONSUMER).
+    /// Returns the number of entries that were pending for it.
+    pub fn x_group_del_consumer(
+        &mut self,
+        key: &[u8],
+        group: &str,
+        consumer: &str,
+    ) -> Result<usize, StoreError> {
+        self.with_group_stream(key, |stream| stream.delete_consumer(group, consumer))
+    }
+
+    /// Stream operations: acknowledge entries (XACK). Returns how many were pending.
+    pub fn x_ack(&mut self, key: &[u8], group: &str, ids: &[StreamId]) -> Result<usize, StoreError> {
+        self.lookup(key);
+        Ok(self.with_stream(key, |stream| Ok(stream.ack(group, ids)))?.unwrap_or(0))
+    }
+
+    /// Stream operations: summary of a group's pending entries (XPENDING).
+    pub fn x_pending_summary(
+        &mut self,
+        key: &[u8],
+        group: &str,
+    ) -> Result<PendingSummary, StoreError> {
+        self.with_group_stream(key, |stream| stream.pending_summary(group))
+    }
+
+    /// Stream operations: pending entries with IDs in `start..=end` (extended XPENDING),
+    /// optionally only those of `consumer` and idle for at least `min_idle_ms`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn x_pending_range(
+        &mut self,
+        key: &[u8],
+        group: &str,
+        start: StreamId,
+        end: StreamId,
+        count: usize,
+        consumer: Option<&str>,
+        min_idle_ms: u64,
+    ) -> Result<Vec<PendingInfo>, StoreError> {
+        let now = now_ms();
+        self.with_group_stream(key, |stream| {
+            stream.pending_range(group, start, end, count, consumer, min_idle_ms, now)
+        })
+    }
+
+    /// Stream operations: transfer idle pending entries to another consumer (XCLAIM).
+    /// Returns the claimed entries.
+    pub fn x_claim(
+        &mut self,
+        key: &[u8],
+        group: &str,
+        consumer: &str,
+        min_idle_ms: u64,
+        ids: &[StreamId],
+        opts: ClaimOptions,
+    ) -> Result<Vec<StreamEntry>, StoreError> {
+        self.ensure_memory()?;
+        let now = now_ms();
+        self.with_group_stream(key, |stream| {
+            stream.claim(group, consumer, min_idle_ms, ids, opts, now)
+        })
+    }
+
+    /// Stream operations: scan the pending list from `start` and claim idle entries
+    /// (XAUTOCLAIM). Returns the cursor to continue from (`0-0` when done), the claimed
+    /// entries and the IDs of deleted entries dropped from the pending list.
+    #[allow(clippy::too_many_arguments)]
+    pub fn x_auto_claim(
+        &mut self,
+        key: &[u8],
+        group: &str,
+        consumer: &str,
+        min_idle_ms: u64,
+        start: StreamId,
+        count: usize,
+        justid: bool,
+    ) -> Result<AutoClaimReply, StoreError> {
+        self.ensure_memory()?;
+        let now = now_ms();
+        self.with_group_stream(key, |stream| {
+            stream.auto_claim(group, consumer, min_idle_ms, start, count, justid, now)
+        })
+    }
+}
+
+/// The storage engine operations on a single store. Records go through the same bookkeeping
+/// as commands: deadlines are indexed, memory is accounted for and blocked clients are served.
+impl TTLStore {
+    /// Read the value and deadline stored at `key`.
+    pub fn read_record(&mut self, key: &[u8]) -> Result<Option<Record>, StoreError> {
+        Ok(self.peek(key).map(|(value, expire_at)| (value.clone(), expire_at)))
+    }
+
+    /// Store `value` at `key` with the given deadline, replacing any existing record.
+    pub fn write_record(
+        &mut self,
+        key: &[u8],
+        value: &StoreValue,
+        expire_at: Option<SystemTime>,
+    ) -> Result<(), StoreError> {
+        self.write_batch(&[BatchOp::Put { key, value, expire_at }])
+    }
+
+    /// Remove `key`. Returns true if it existed.
+    pub fn delete_record(&mut self, key: &[u8]) -> Result<bool, StoreError> {
+        self.check_expiry(key);
+        Ok(self.remove_entry(key).is_some())
+    }
+
+    /// Set or clear the deadline of `key`. Returns false if the key does not exist.
+    pub fn set_deadline(
+        &mut self,
+        key: &[u8],
+        expire_at: Option<SystemTime>,
+    ) -> Result<bool, StoreError> {
+        self.check_expiry(key);
+        Ok(self.set_expiry(key, expire_at.map(codec::to_instant)))
+    }
+
+    /// Visit every live record whose key starts with `prefix`.
+    pub fn for_each_record(
+        &mut self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], Record),
+    ) -> Result<(), StoreError> {
+        let now = Instant::now();
+        for (key, (value, expiry, _)) in &self.store {
+            if key.starts_with(prefix) && expiry.is_none_or(|deadline| deadline > now) {
+                visit(key, (value.clone(), expiry.map(codec::to_system_time)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a batch of puts, deletes and updates as one unit.
+    pub fn write_batch(&mut self, batch: &[BatchOp<'_>]) -> Result<(), StoreError> {
+        // The memory limit is checked once up front, so a batch is never applied halfway.
+        self.ensure_memory()?;
+        for op in batch {
+            match *op {
+                BatchOp::Put { key, value, expire_at } => {
+                    self.insert_entry(key, value.clone(), expire_at.map(codec::to_instant));
+                    self.serve_blocked(key);
+                }
+                BatchOp::Delete { key } => {
+                    self.remove_entry(key);
+                }
+                BatchOp::Update { key, edits } => self.apply_edits(key, edits)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Helper method: Apply `edits` to the value at `key` through the commands that made
+    /// them. A missing or expired key is left alone.
+    fn apply_edits(&mut self, key: &[u8], edits: &[Edit]) -> Result<(), StoreError> {
+        self.check_expiry(key);
+        if !self.store.contains_key(key) {
+            return Ok(());
+        }
+        for edit in edits {
+            match edit {
+                Edit::Deadline(expire_at) => {
+                    self.set_expiry(key, expire_at.map(codec::to_instant));
+                }
+                Edit::Push { front, values } => {
+                    self.push_values(key, values, *front)?;
+                }
+                Edit::Pop { front } => {
+                    self.pop_value(key, *front)?;
+                }
+                Edit::SetAdd(member) => {
+                    self.s_add(key, std::slice::from_ref(member))?;
+                }
+                Edit::SetRemove(member) => {
+                    self.s_rem(key, std::slice::from_ref(member))?;
+                }
+                Edit::HashSet { field, value } => {
+                    self.h_set(key, &[(field.clone(), value.clone())])?;
+                }
+                Edit::HashUpdate { field, value } => {
+                    self.with_hash(key, |hash| hash.update(field, value.clone()))?;
+                }
+                Edit::HashDeadline { field, expire_at } => {
+                    self.with_hash(key, |hash| match expire_at {
+                        Some(at) => hash.expire_at(field, codec::to_instant(*at)),
+                        None => hash.persist(field),
+                    })?;
+                }
+                Edit::HashRemove(field) => {
+                    self.h_del(key, std::slice::from_ref(field))?;
+                }
+                Edit::ZAdd { member, score } => {
+                    self.z_add(key, &[(*score, member.clone())], ZAddOptions::default())?;
+                }
+                Edit::ZRemove(member) => {
+                    self.z_rem(key, std::slice::from_ref(member))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A locked TTLStore is the in-memory storage engine, shared by every shard it backs.
+impl StorageEngine for Mutex<TTLStore> {
+    fn read_record(&self, key: &[u8]) -> Result<Option<Record>, StoreError> {
+        util::lock(self).read_record(key)
+    }
+
+    fn write_record(
+        &self,
+        key: &[u8],
+        value: &StoreValue,
+        expire_at: Option<SystemTime>,
+    ) -> Result<(), StoreError> {
+        util::lock(self).write_record(key, value, expire_at)
+    }
+
+    fn delete_record(&self, key: &[u8]) -> Result<bool, StoreError> {
+        util::lock(self).delete_record(key)
+    }
+
+    fn set_deadline(&self, key: &[u8], expire_at: Option<SystemTime>) -> Result<bool, StoreError> {
+        util::lock(self).set_deadline(key, expire_at)
+    }
+
+    fn for_each_record(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], Record),
+    ) -> Result<(), StoreError> {
+        util::lock(self).for_each_record(prefix, visit)
+    }
+
+    fn write_batch(&self, batch: &[BatchOp<'_>]) -> Result<(), StoreError> {
+        util::lock(self).write_batch(batch)
+    }
+}
+
+/// The record of a keyspace entry as of `at`, when a snapshot froze the store; None if the
+/// entry had expired by then.
+fn frozen_record(at: Instant, (value, expiry, _): Entry) -> Option<Record> {
+    let live = expiry.is_none_or(|deadline| deadline > at);
+    live.then(|| (value, expiry.map(codec::to_system_time)))
+}
+
+/// Helper function: Reject a negative SRANDMEMBER/HRANDFIELD count asking for more than
+/// `MAX_RANDOM_REPEATS` picks.
+fn check_random_count(count: i64) -> Result<(), StoreError> {
+    if count < 0 && count.unsigned_abs() > MAX_RANDOM_REPEATS {
+        return Err(StoreError::InvalidArgument("value is out of range".into()));
+    }
+    Ok(())
+}
+
+/// Resolves a possibly negative list index against a list of length `len`.
+fn normalize_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (index >= 0 && index < len as i64).then_some(index as usize)
+}
+
+/// Resolves a possibly negative `start..=stop` range against a list of length `len`,
+/// clamping it to the list. Returns None if the range is empty.
+fn normalize_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+    (start <= stop && start < len).then_some((start as usize, stop as usize))
 }
@@ -0,0 +1,56 @@
+// src/storage/util.rs
+//
+// Helpers shared by the storage modules: locking shared state that a panicking thread may
+// have left poisoned, and the file handling of the modules that write durable files (the
+// append-only file, snapshots, backups and the markers of one-shot startup steps).
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::storage::error::StoreError;
+
+/// Locks a mutex, tolerating poisoning: every lock in the storage layer guards state that
+/// stays consistent between operations, so a panic elsewhere does not make it unusable.
+pub fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Converts an I/O error.
+pub fn io_error(err: io::Error) -> StoreError {
+    StoreError::Storage(err.to_string())
+}
+
+/// Renames `from` to `to`, replacing `to` atomically, then syncs the directory so the rename
+/// survives a crash. Not every platform can sync a directory; failing to is not an error.
+pub fn rename_durably(from: &Path, to: &Path) -> Result<(), StoreError> {
+    fs::rename(from, to).map_err(io_error)?;
+    if let Some(dir) = to.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+    Ok(())
+}
+
+/// Reads the stamp recorded in the marker file at `path`, or None if there is none. A marker
+/// records that a one-shot startup step, such as an import, was applied.
+pub fn read_marker(path: &Path) -> Result<Option<String>, StoreError> {
+    match fs::read_to_string(path) {
+        Ok(stamp) => Ok(Some(stamp.trim().to_string())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(io_error(err)),
+    }
+}
+
+/// Records `stamp` in the marker file at `path`, replacing the previous one durably. The
+/// directory of the marker is created if missing.
+pub fn write_marker(path: &Path, stamp: &str) -> Result<(), StoreError> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    fs::write(&temp, stamp).map_err(io_error)?;
+    File::open(&temp).and_then(|file| file.sync_all()).map_err(io_error)?;
+    rename_durably(&temp, path)
+}
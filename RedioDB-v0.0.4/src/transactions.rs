@@ -1,6 +1,7 @@
 // src/transactions.rs
 //
 // Stub for transaction management and scripting support.
+#[derive(Default)]
 pub struct TransactionManager;
 
 impl TransactionManager {
@@ -0,0 +1,223 @@
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rediodb::storage::aof::{AofConfig, FsyncPolicy};
+use rediodb::storage::databases::{DatabaseConfig, Databases};
+use rediodb::storage::engine::EngineKind;
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::sorted_set::ZAddOptions;
+use common::strings;
+
+/// An AOF engine logging to a fresh file named after the test.
+fn engine(name: &str) -> (EngineKind, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("rediodb-aof-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    let path = dir.join("appendonly.aof");
+    let config = AofConfig {
+        fsync: FsyncPolicy::Always,
+        ..AofConfig::new(&path)
+    };
+    (EngineKind::Aof(config), path)
+}
+
+fn open(engine: &EngineKind) -> Databases {
+    let config = DatabaseConfig { count: 2, names: Vec::new(), shards: 4 };
+    Databases::open(config, engine).unwrap()
+}
+
+#[test]
+fn test_changes_are_replayed_after_restart() {
+    let (engine, _) = engine("replay");
+    {
+        let dbs = open(&engine);
+        let db = dbs.get(0);
+        db.write(b"a", |store| store.set(b"a", b"1", None)).unwrap();
+        db.write(b"session", |store| store.set(b"session", b"s", Some(Duration::from_secs(100)))).unwrap();
+        db.write(b"list", |store| store.r_push(b"list", &strings(&["x", "y", "z"]))).unwrap();
+        db.write(b"hash", |store| store.h_set(b"hash", &[(b"f".to_vec(), b"1".to_vec())])).unwrap();
+        let members = [(1.5, b"one".to_vec())];
+        db.write(b"zset", |store| store.z_add(b"zset", &members, ZAddOptions::default())).unwrap();
+        db.write(b"gone", |store| store.set(b"gone", b"x", None)).unwrap();
+        db.write(b"gone", |store| Ok(store.del(b"gone"))).unwrap();
+        db.write_keys([&b"list"[..], b"renamed"], |store| store.rename(b"list", b"renamed")).unwrap();
+        dbs.get(1).write(b"a", |store| store.set(b"a", b"other", None)).unwrap();
+        // Reads log nothing.
+        db.lock_key(b"a").get(b"a").unwrap();
+        assert_eq!(dbs.aof().unwrap().load_report().entries, 0);
+    }
+
+    let dbs = open(&engine);
+    assert_eq!(dbs.aof().unwrap().load_report().entries, 9);
+    let db = dbs.get(0);
+    assert_eq!(db.len(), 5);
+    assert_eq!(db.lock_key(b"a").get(b"a"), Ok(Some(b"1".to_vec())));
+    assert!(db.lock_key(b"session").ttl(b"session").unwrap() > 90);
+    assert_eq!(
+        db.lock_key(b"renamed").l_range(b"renamed", 0, -1),
+        Ok(strings(&["x", "y", "z"]))
+    );
+    assert_eq!(db.lock_key(b"hash").h_get(b"hash", b"f"), Ok(Some(b"1".to_vec())));
+    assert_eq!(db.lock_key(b"zset").z_score(b"zset", b"one"), Ok(Some(1.5)));
+    assert_eq!(dbs.get(1).lock_key(b"a").get(b"a"), Ok(Some(b"other".to_vec())));
+
+    // Flushing is logged too.
+    dbs.flush_all(false).unwrap();
+    drop(dbs);
+    assert_eq!(open(&engine).get(0).len(), 0);
+}
+
+#[test]
+fn test_truncated_tail_is_dropped_and_reported() {
+    let (engine, path) = engine("truncated");
+    {
+        let dbs = open(&engine);
+        for key in ["a", "b", "c"] {
+            dbs.get(0).write(key.as_bytes(), |store| store.set(key.as_bytes(), b"v", None)).unwrap();
+        }
+    }
+    // Cut the last entry short, as a crash in the middle of a write would.
+    let len = fs::metadata(&path).unwrap().len();
+    OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+    let dbs = open(&engine);
+    let report = dbs.aof().unwrap().load_report();
+    assert_eq!(report.entries, 2);
+    let (offset, dropped) = report.truncated.unwrap();
+    assert_eq!(offset + dropped, len - 3);
+    assert_eq!(fs::metadata(&path).unwrap().len(), offset);
+    assert_eq!(dbs.get(0).len(), 2);
+    assert_eq!(dbs.get(0).lock_key(b"c").get(b"c"), Ok(None));
+
+    // The log stays usable after the cut.
+    dbs.get(0).write(b"d", |store| store.set(b"d", b"v", None)).unwrap();
+    drop(dbs);
+    let dbs = open(&engine);
+    assert_eq!(dbs.aof().unwrap().load_report().truncated, None);
+    assert_eq!(dbs.get(0).len(), 3);
+    drop(dbs);
+
+    // Damage before the last entry is not a crash artifact and fails the load.
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[20] ^= 0xff;
+    fs::write(&path, bytes).unwrap();
+    let config = DatabaseConfig { count: 2, names: Vec::new(), shards: 4 };
+    assert!(Databases::open(config, &engine).is_err());
+}
+
+#[test]
+fn test_rewrite_compacts_the_log() {
+    let (engine, path) = engine("rewrite");
+    let engine = match engine {
+        EngineKind::Aof(config) => EngineKind::Aof(AofConfig {
+            rewrite_min_size: 1024,
+            ..config
+        }),
+        other => other,
+    };
+    let dbs = open(&engine);
+    let aof = dbs.aof().unwrap();
+    for i in 0..200 {
+        dbs.get(0).write(b"counter", |store| store.set(b"counter", i.to_string().as_bytes(), None)).unwrap();
+    }
+    dbs.get(1).write(b"set", |store| store.s_add(b"set", &strings(&["x", "y"]))).unwrap();
+    assert!(aof.needs_rewrite());
+
+    let before = aof.size();
+    aof.rewrite(&dbs).unwrap();
+    assert!(aof.size() < before / 10);
+    assert_eq!(fs::metadata(&path).unwrap().len(), aof.size());
+    assert!(!aof.needs_rewrite());
+
+    // Changes after the rewrite go to the new file.
+    dbs.get(0).write(b"after", |store| store.set(b"after", b"1", None)).unwrap();
+    drop(dbs);
+    let dbs = open(&engine);
+    assert_eq!(dbs.aof().unwrap().load_report().entries, 3);
+    assert_eq!(dbs.get(0).lock_key(b"counter").get(b"counter"), Ok(Some(b"199".to_vec())));
+    assert_eq!(dbs.get(0).lock_key(b"after").get(b"after"), Ok(Some(b"1".to_vec())));
+    assert_eq!(dbs.get(1).lock_key(b"set").s_card(b"set"), Ok(2));
+}
+
+#[test]
+fn test_in_place_changes_log_only_the_edits() {
+    let (engine, _) = engine("edits");
+    {
+        let dbs = open(&engine);
+        let db = dbs.get(0);
+        let aof = dbs.aof().unwrap();
+        let long: Vec<Vec<u8>> = (0..1000).map(|i| vec![b'a' + (i % 26) as u8; 100]).collect();
+        db.write(b"list", |store| store.r_push(b"list", &long)).unwrap();
+        let before = aof.size();
+        db.write(b"list", |store| store.l_push(b"list", &strings(&["head"]))).unwrap();
+        assert!(aof.size() - before < 64);
+
+        db.write(b"list", |store| store.r_pop(b"list")).unwrap();
+        db.write(b"list", |store| Ok(store.expire(b"list", Duration::from_secs(100)))).unwrap();
+        db.write(b"set", |store| store.s_add(b"set", &strings(&["x", "y", "z"]))).unwrap();
+        db.write(b"set", |store| store.s_rem(b"set", &strings(&["y"]))).unwrap();
+        let fields = [(b"f".to_vec(), b"1".to_vec()), (b"g".to_vec(), b"2".to_vec())];
+        db.write(b"hash", |store| store.h_set(b"hash", &fields)).unwrap();
+        db.write(b"hash", |store| store.h_incr_by(b"hash", b"f", 41)).unwrap();
+        db.write(b"hash", |store| store.h_del(b"hash", &strings(&["g"]))).unwrap();
+        let fields = strings(&["f"]);
+        db.write(b"hash", |store| store.h_expire(b"hash", &fields, Duration::from_secs(100)))
+            .unwrap();
+        let members = [(1.0, b"one".to_vec()), (2.0, b"two".to_vec())];
+        db.write(b"zset", |store| store.z_add(b"zset", &members, ZAddOptions::default())).unwrap();
+        db.write(b"zset", |store| store.z_incr_by(b"zset", 5.0, b"one")).unwrap();
+        db.write(b"zset", |store| store.z_rem(b"zset", &strings(&["two"]))).unwrap();
+    }
+
+    let dbs = open(&engine);
+    let db = dbs.get(0);
+    let mut list = db.lock_key(b"list");
+    assert_eq!(list.l_len(b"list"), Ok(1000));
+    assert_eq!(list.l_index(b"list", 0), Ok(Some(b"head".to_vec())));
+    assert_eq!(list.l_index(b"list", -1), Ok(Some(vec![b'a' + (998 % 26) as u8; 100])));
+    assert!(list.ttl(b"list").unwrap() > 90);
+    drop(list);
+    let mut set = db.lock_key(b"set");
+    assert_eq!(set.s_card(b"set"), Ok(2));
+    assert_eq!(set.s_is_member(b"set", b"y"), Ok(false));
+    drop(set);
+    let mut hash = db.lock_key(b"hash");
+    assert_eq!(hash.h_get_all(b"hash"), Ok(vec![(b"f".to_vec(), b"42".to_vec())]));
+    assert!(hash.h_ttl(b"hash", &strings(&["f"])).unwrap()[0] > 90);
+    drop(hash);
+    let mut zset = db.lock_key(b"zset");
+    assert_eq!(zset.z_card(b"zset"), Ok(1));
+    assert_eq!(zset.z_score(b"zset", b"one"), Ok(Some(6.0)));
+}
+
+#[test]
+fn test_deadlines_changed_later_survive_a_restart_after_the_original_ttl() {
+    let (engine, _) = engine("deadlines");
+    let short = Some(Duration::from_millis(100));
+    {
+        let dbs = open(&engine);
+        let db = dbs.get(0);
+        db.write(b"persisted", |store| store.set(b"persisted", b"p", short)).unwrap();
+        db.write(b"persisted", |store| Ok(store.persist(b"persisted"))).unwrap();
+        db.write(b"extended", |store| store.set(b"extended", b"e", short)).unwrap();
+        db.write(b"extended", |store| Ok(store.expire(b"extended", Duration::from_secs(100))))
+            .unwrap();
+        db.write(b"set", |store| store.s_add(b"set", &strings(&["a"]))).unwrap();
+        db.write(b"set", |store| Ok(store.expire(b"set", Duration::from_millis(100)))).unwrap();
+        db.write(b"set", |store| Ok(store.persist(b"set"))).unwrap();
+        db.write(b"set", |store| store.s_add(b"set", &strings(&["b"]))).unwrap();
+        db.write(b"expired", |store| store.set(b"expired", b"x", short)).unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(150));
+
+    // The edits made before the original deadlines passed still apply on replay.
+    let dbs = open(&engine);
+    let db = dbs.get(0);
+    assert_eq!(db.lock_key(b"persisted").get(b"persisted"), Ok(Some(b"p".to_vec())));
+    assert_eq!(db.lock_key(b"persisted").ttl(b"persisted"), Some(-1));
+    assert!(db.lock_key(b"extended").ttl(b"extended").unwrap() > 90);
+    assert_eq!(db.lock_key(b"set").s_card(b"set"), Ok(2));
+    assert_eq!(db.lock_key(b"expired").get(b"expired"), Ok(None));
+}
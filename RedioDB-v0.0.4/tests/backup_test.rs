@@ -0,0 +1,128 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rediodb::storage::backup::{
+    self, BackupConfig, BackupKind, DatabaseKeys, MANIFEST_FILENAME, RESTORE_MARKER_FILENAME,
+};
+use rediodb::storage::databases::{DatabaseConfig, Databases};
+use rediodb::storage::engine::EngineKind;
+use rediodb::storage::snapshot::DEFAULT_SNAPSHOT_FILENAME;
+use common::strings;
+
+/// A fresh backup directory named after the test.
+fn backup_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rediodb-backup-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn databases() -> Databases {
+    Databases::new(DatabaseConfig { count: 2, names: Vec::new(), shards: 4 })
+}
+
+#[test]
+fn test_backup_and_restore_round_trip() {
+    let dir = backup_dir("round-trip");
+    let dbs = databases();
+    let db = dbs.get(0);
+    db.lock_key(b"a").set(b"a", b"1", None).unwrap();
+    db.lock_key(b"session").set(b"session", b"s", Some(Duration::from_secs(100))).unwrap();
+    db.lock_key(b"list").r_push(b"list", &strings(&["x", "y"])).unwrap();
+    dbs.get(1).lock_key(b"a").set(b"a", b"other", None).unwrap();
+
+    let manifest = backup::backup(&dbs, &dir).unwrap();
+    assert_eq!(manifest.kind, BackupKind::Snapshot);
+    assert_eq!(
+        manifest.databases,
+        vec![DatabaseKeys { db: 0, keys: 3 }, DatabaseKeys { db: 1, keys: 1 }]
+    );
+    assert_eq!(manifest.keys(), 4);
+    assert_eq!(manifest.files.len(), 1);
+    assert_eq!(manifest.files[0].path, DEFAULT_SNAPSHOT_FILENAME);
+    assert_eq!(backup::verify(&dir), Ok(manifest.clone()));
+
+    // Restoring replaces the keyspace rather than merging into it.
+    let copy = databases();
+    copy.get(0).lock_key(b"stale").set(b"stale", b"x", None).unwrap();
+    let marker = backup_dir("round-trip-data").join(RESTORE_MARKER_FILENAME);
+    let restored = backup::restore_files(&dir, &EngineKind::Memory, &marker).unwrap().unwrap();
+    backup::restore_keys(&dir, &restored, &copy).unwrap();
+    let db = copy.get(0);
+    assert_eq!(db.len(), 3);
+    assert_eq!(db.lock_key(b"stale").get(b"stale"), Ok(None));
+    assert_eq!(db.lock_key(b"a").get(b"a"), Ok(Some(b"1".to_vec())));
+    assert!(db.lock_key(b"session").ttl(b"session").unwrap() > 90);
+    assert_eq!(db.lock_key(b"list").l_range(b"list", 0, -1), Ok(strings(&["x", "y"])));
+    assert_eq!(copy.get(1).lock_key(b"a").get(b"a"), Ok(Some(b"other".to_vec())));
+
+    // Once recorded, the same backup is not restored again on the next startup.
+    backup::record_restore(&marker, &restored).unwrap();
+    assert_eq!(backup::restore_files(&dir, &EngineKind::Memory, &marker), Ok(None));
+}
+
+#[test]
+fn test_verify_detects_damaged_backups() {
+    let dir = backup_dir("damaged");
+    let dbs = databases();
+    dbs.get(0).lock_key(b"key").set(b"key", b"value", None).unwrap();
+    backup::backup(&dbs, &dir).unwrap();
+    let snapshot = dir.join(DEFAULT_SNAPSHOT_FILENAME);
+    let bytes = fs::read(&snapshot).unwrap();
+
+    let mut damaged = bytes.clone();
+    damaged[bytes.len() / 2] ^= 0x01;
+    fs::write(&snapshot, &damaged).unwrap();
+    let err = backup::verify(&dir).unwrap_err();
+    assert!(err.to_string().contains("is damaged"));
+    // A damaged backup is refused before anything is restored.
+    let marker = backup_dir("damaged-data").join(RESTORE_MARKER_FILENAME);
+    assert!(backup::restore_files(&dir, &EngineKind::Memory, &marker).is_err());
+
+    fs::remove_file(&snapshot).unwrap();
+    assert!(backup::verify(&dir).unwrap_err().to_string().contains("is missing"));
+
+    // So is a backup whose manifest records other key counts than its snapshot holds.
+    fs::write(&snapshot, &bytes).unwrap();
+    let manifest_path = dir.join(MANIFEST_FILENAME);
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(&manifest_path, manifest.replace("\"keys\": 1", "\"keys\": 2")).unwrap();
+    assert!(backup::verify(&dir).is_err());
+
+    fs::remove_file(&manifest_path).unwrap();
+    assert!(backup::verify(&dir).unwrap_err().to_string().contains("no backup manifest"));
+}
+
+#[test]
+fn test_backup_refuses_a_non_empty_directory() {
+    let dir = backup_dir("non-empty");
+    let dbs = databases();
+    dbs.get(0).lock_key(b"key").set(b"key", b"value", None).unwrap();
+    backup::backup(&dbs, &dir).unwrap();
+    let manifest = fs::read(dir.join(MANIFEST_FILENAME)).unwrap();
+
+    // A second backup never overwrites the first.
+    let err = backup::backup(&dbs, &dir).unwrap_err();
+    assert!(err.to_string().contains("not empty"));
+    assert_eq!(fs::read(dir.join(MANIFEST_FILENAME)).unwrap(), manifest);
+
+    // An existing empty directory is fine, and an empty dataset gives an empty backup.
+    let empty = backup_dir("empty");
+    fs::create_dir_all(&empty).unwrap();
+    let manifest = backup::backup(&databases(), &empty).unwrap();
+    assert_eq!(manifest.keys(), 0);
+    assert!(manifest.databases.is_empty());
+    assert_eq!(backup::verify(&empty).unwrap().keys(), 0);
+}
+
+#[test]
+fn test_backup_directories_stay_below_the_root() {
+    let config = BackupConfig { root: PathBuf::from("/srv/backups") };
+    assert_eq!(config.resolve("2024-06-01").unwrap(), PathBuf::from("/srv/backups/2024-06-01"));
+    assert_eq!(config.resolve("daily/01").unwrap(), PathBuf::from("/srv/backups/daily/01"));
+    for name in ["", "/etc", "../data", "daily/../../etc", "."] {
+        assert!(config.resolve(name).is_err(), "{:?} was accepted", name);
+    }
+}
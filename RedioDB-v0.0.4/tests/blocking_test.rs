@@ -0,0 +1,122 @@
+mod common;
+
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use rediodb::storage::blocking::{self, BlockedOp};
+use rediodb::storage::error::StoreError;
+use rediodb::storage::multi_key::{KeyShards, MultiKey};
+use rediodb::storage::sharded::{ShardedStore, DEFAULT_SHARDS};
+use common::strings;
+
+lazy_static! {
+    static ref FIFO_STORE: ShardedStore = ShardedStore::new(DEFAULT_SHARDS);
+    static ref CANCEL_STORE: ShardedStore = ShardedStore::new(DEFAULT_SHARDS);
+    static ref MOVE_STORE: ShardedStore = ShardedStore::new(DEFAULT_SHARDS);
+    static ref SERVED_STORE: ShardedStore = ShardedStore::new(DEFAULT_SHARDS);
+}
+
+async fn wait_for_blocked(store: &ShardedStore, clients: usize) {
+    while store.blocked_clients() != clients {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_pushes_serve_oldest_waiter_first() {
+    let pop = |keys: &[&str]| {
+        let keys = strings(keys);
+        tokio::spawn(blocking::blocking_pop(&FIFO_STORE, keys, BlockedOp::Pop { front: true }, None))
+    };
+    let first = pop(&["jobs", "urgent"]);
+    wait_for_blocked(&FIFO_STORE, 1).await;
+    let second = pop(&["jobs"]);
+    wait_for_blocked(&FIFO_STORE, 2).await;
+
+    let len = FIFO_STORE.lock_key(b"jobs").r_push(b"jobs", &strings(&["1", "2", "3"]));
+    assert_eq!(len, Ok(3));
+    assert_eq!(first.await.unwrap(), Ok(Some((b"jobs".to_vec(), b"1".to_vec()))));
+    assert_eq!(second.await.unwrap(), Ok(Some((b"jobs".to_vec(), b"2".to_vec()))));
+
+    assert_eq!(FIFO_STORE.lock_key(b"jobs").l_range(b"jobs", 0, -1), Ok(strings(&["3"])));
+    assert_eq!(FIFO_STORE.blocked_clients(), 0);
+}
+
+#[tokio::test]
+async fn test_timeouts_and_disconnects_keep_elements() {
+    let pop = || BlockedOp::Pop { front: true };
+    let timed_out = blocking::blocking_pop(
+        &CANCEL_STORE,
+        strings(&["q"]),
+        pop(),
+        Some(Duration::from_millis(20)),
+    )
+    .await;
+    assert_eq!(timed_out, Ok(None));
+    assert_eq!(CANCEL_STORE.blocked_clients(), 0);
+    let forever = blocking::blocking_pop(&CANCEL_STORE, strings(&["q"]), pop(), Some(Duration::MAX));
+    let out_of_range = Err(StoreError::InvalidArgument("timeout is out of range".into()));
+    assert_eq!(forever.await, out_of_range);
+
+    // A client that disconnects while waiting is unregistered and never takes an element.
+    let abandoned = tokio::spawn(blocking::blocking_pop(&CANCEL_STORE, strings(&["q"]), pop(), None));
+    wait_for_blocked(&CANCEL_STORE, 1).await;
+    abandoned.abort();
+    wait_for_blocked(&CANCEL_STORE, 0).await;
+    let mut store = CANCEL_STORE.lock_key(b"q");
+    store.r_push(b"q", &strings(&["a"])).unwrap();
+    assert_eq!(store.l_range(b"q", 0, -1), Ok(strings(&["a"])));
+
+    // An element handed over just before the client went away is put back at the head.
+    store.l_pop(b"q").unwrap();
+    let (id, mut receiver) = store.block_client(strings(&["q"]), pop());
+    store.r_push(b"q", &strings(&["b", "c"])).unwrap();
+    assert_eq!(store.l_range(b"q", 0, -1), Ok(strings(&["c"])));
+    let (key, value) = store.unblock_client(id, &mut receiver).unwrap();
+    store.requeue(&key, value, true);
+    assert_eq!(store.l_range(b"q", 0, -1), Ok(strings(&["b", "c"])));
+}
+
+#[tokio::test]
+async fn test_blmove_waits_for_source() {
+    let op = BlockedOp::Move {
+        from_front: false,
+        destination: b"done".to_vec(),
+        to_front: true,
+    };
+    let mover = tokio::spawn(blocking::blocking_pop(&MOVE_STORE, strings(&["todo"]), op, None));
+    wait_for_blocked(&MOVE_STORE, 1).await;
+
+    MOVE_STORE.lock_key(b"todo").l_push(b"todo", &strings(&["task"])).unwrap();
+    assert_eq!(mover.await.unwrap(), Ok(Some((b"todo".to_vec(), b"task".to_vec()))));
+    let mut store = MOVE_STORE.lock_keys([b"todo", b"done"]);
+    assert_eq!(store.shard_mut(b"todo").l_len(b"todo"), Ok(0));
+    assert_eq!(store.shard_mut(b"done").l_range(b"done", 0, -1), Ok(strings(&["task"])));
+    drop(store);
+
+    // With elements available, the first non-empty list is popped without blocking.
+    let mut store = MOVE_STORE.lock_keys([b"a", b"b"]);
+    store.shard_mut(b"b").r_push(b"b", &strings(&["x"])).unwrap();
+    let popped = store.pop_first(&strings(&["a", "b"]), &BlockedOp::Pop { front: false });
+    assert_eq!(popped, Ok(Some((b"b".to_vec(), b"x".to_vec()))));
+}
+
+#[tokio::test]
+async fn test_served_clients_leave_every_queue() {
+    let busy = b"busy".to_vec();
+    let rare = (0..)
+        .map(|i| format!("rare{}", i).into_bytes())
+        .find(|key| SERVED_STORE.shard_of(key) != SERVED_STORE.shard_of(&busy))
+        .unwrap();
+    for job in ["1", "2", "3"] {
+        let keys = vec![busy.clone(), rare.clone()];
+        let pop = BlockedOp::Pop { front: true };
+        let popper = tokio::spawn(blocking::blocking_pop(&SERVED_STORE, keys, pop, None));
+        wait_for_blocked(&SERVED_STORE, 1).await;
+        SERVED_STORE.write(&busy, |store| store.r_push(&busy, &strings(&[job]))).unwrap();
+        assert_eq!(popper.await.unwrap(), Ok(Some((busy.clone(), job.as_bytes().to_vec()))));
+    }
+
+    // Clients served from one list do not stay queued on the other lists they watched.
+    assert!(!SERVED_STORE.lock_key(&rare).is_blocked_on(&rare));
+}
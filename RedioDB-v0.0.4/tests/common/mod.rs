@@ -0,0 +1,6 @@
+// Helpers shared by the integration tests.
+
+/// The byte strings the store takes and returns, from string literals.
+pub fn strings(values: &[&str]) -> Vec<Vec<u8>> {
+    values.iter().map(|v| v.as_bytes().to_vec()).collect()
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use rediodb::storage::error::StoreError;
+use rediodb::storage::string_value::StringValue;
+use rediodb::storage::ttl_store::TTLStore;
+
+#[test]
+fn test_integer_counters() {
+    let mut store = TTLStore::new();
+    assert_eq!(store.incr(b"n", 5_000_000_000), Ok(5_000_000_000));
+    assert_eq!(store.decr(b"n", 5_000_000_001), Ok(-1));
+    assert_eq!(store.get(b"n"), Ok(Some(b"-1".to_vec())));
+
+    store.set(b"max", i64::MAX.to_string().as_bytes(), None).unwrap();
+    assert_eq!(store.incr(b"max", 1), Err(StoreError::Overflow));
+    assert_eq!(store.decr(b"max", i64::MIN), Err(StoreError::Overflow));
+    assert_eq!(store.get(b"max"), Ok(Some(i64::MAX.to_string().into_bytes())));
+
+    for value in ["abc", "1.5", " 1", "+1", "007", ""] {
+        store.set(b"bad", value.as_bytes(), None).unwrap();
+        assert_eq!(store.incr(b"bad", 1), Err(StoreError::NotInteger), "{:?}", value);
+    }
+
+    // Counters keep their TTL, and appending digits leaves a number.
+    store.set(b"ttl", b"10", Some(Duration::from_secs(100))).unwrap();
+    assert_eq!(store.incr(b"ttl", 1), Ok(11));
+    assert!(store.ttl(b"ttl").unwrap() > 0);
+    assert_eq!(store.append(b"ttl", b"0"), Ok(b"110".to_vec()));
+    assert_eq!(store.incr(b"ttl", 1), Ok(111));
+
+    store.r_push(b"list", &[b"a".to_vec()]).unwrap();
+    assert_eq!(store.incr(b"list", 1), Err(StoreError::WrongType));
+
+    for key in store.keys(b"*") {
+        store.del(&key);
+    }
+    assert_eq!(store.used_memory(), 0);
+}
+
+#[test]
+fn test_float_counters() {
+    let mut store = TTLStore::new();
+    assert_eq!(store.incr_by_float(b"f", 10.5), Ok("10.5".to_string()));
+    assert_eq!(store.incr_by_float(b"f", 0.1), Ok("10.6".to_string()));
+    assert_eq!(store.incr_by_float(b"f", -0.6), Ok("10".to_string()));
+    // An integral result goes back to the integer encoding.
+    assert_eq!(store.incr(b"f", 1), Ok(11));
+
+    store.set(b"e", b"5.0e3", None).unwrap();
+    assert_eq!(store.incr_by_float(b"e", 200.0), Ok("5200".to_string()));
+    assert_eq!(store.incr_by_float(b"e", -5200.0), Ok("0".to_string()));
+
+    store.set(b"bad", b"nan", None).unwrap();
+    assert_eq!(store.incr_by_float(b"bad", 1.0), Err(StoreError::NotFloat));
+    store.set(b"big", f64::MAX.to_string().as_bytes(), None).unwrap();
+    assert!(matches!(
+        store.incr_by_float(b"big", f64::MAX),
+        Err(StoreError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_integer_encoding() {
+    assert_eq!(StringValue::new(b"42"), StringValue::Int(42));
+    assert_eq!(StringValue::new(b"-9223372036854775808"), StringValue::Int(i64::MIN));
+    assert_eq!(StringValue::new(b"042"), StringValue::Raw(b"042".to_vec()));
+    assert_eq!(StringValue::new(b"-0"), StringValue::Raw(b"-0".to_vec()));
+    assert_eq!(
+        StringValue::new(b"9223372036854775808"),
+        StringValue::Raw(b"9223372036854775808".to_vec())
+    );
+
+    let mut value = StringValue::new(b"12");
+    value.append(b"3");
+    assert_eq!(value, StringValue::Raw(b"123".to_vec()));
+    assert_eq!(value.as_int(), Ok(123));
+    let raw = StringValue::Raw(i64::MAX.to_string().into_bytes());
+    assert!(StringValue::Int(i64::MAX).mem_usage() < raw.mem_usage());
+}
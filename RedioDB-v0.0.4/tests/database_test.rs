@@ -0,0 +1,72 @@
+mod common;
+
+use std::time::Duration;
+
+use rediodb::storage::blocking::BlockedOp;
+use rediodb::storage::databases::{DatabaseConfig, Databases, DEFAULT_DATABASES};
+use rediodb::storage::error::StoreError;
+use common::strings;
+
+#[test]
+fn test_resolve_by_index_and_name() {
+    assert_eq!(Databases::new(DatabaseConfig::default()).len(), DEFAULT_DATABASES);
+
+    let dbs = Databases::new(DatabaseConfig {
+        count: 2,
+        names: vec!["main".into(), "staging".into(), "fixtures".into()],
+        ..DatabaseConfig::default()
+    });
+    assert_eq!(dbs.len(), 3);
+    assert_eq!(dbs.resolve("0"), Ok(0));
+    assert_eq!(dbs.resolve("fixtures"), Ok(2));
+    assert!(matches!(dbs.resolve("3"), Err(StoreError::InvalidArgument(_))));
+    assert!(matches!(dbs.resolve("prod"), Err(StoreError::InvalidArgument(_))));
+
+    // Databases are independent keyspaces.
+    dbs.get(0).lock_key(b"k").set(b"k", b"main", None).unwrap();
+    dbs.get(1).lock_key(b"k").set(b"k", b"staging", None).unwrap();
+    assert_eq!(dbs.get(0).lock_key(b"k").get(b"k"), Ok(Some(b"main".to_vec())));
+    assert_eq!(dbs.get(2).len(), 0);
+}
+
+#[test]
+fn test_move_and_flush() {
+    let dbs = Databases::new(DatabaseConfig { count: 2, ..DatabaseConfig::default() });
+    dbs.get(0).lock_key(b"a").set(b"a", b"1", Some(Duration::from_secs(100))).unwrap();
+    dbs.get(0).lock_key(b"b").set(b"b", b"1", None).unwrap();
+    dbs.get(1).lock_key(b"b").set(b"b", b"2", None).unwrap();
+
+    assert_eq!(dbs.move_key(b"a", 0, 1), Ok(true));
+    assert_eq!(dbs.move_key(b"b", 0, 1), Ok(false));
+    assert_eq!(dbs.move_key(b"missing", 0, 1), Ok(false));
+    assert!(dbs.move_key(b"b", 1, 1).is_err());
+    assert_eq!(dbs.get(0).len(), 1);
+    assert!(dbs.get(1).lock_key(b"a").ttl(b"a").unwrap() > 0);
+
+    let members: Vec<Vec<u8>> = (0..1000).map(|i| i.to_string().into_bytes()).collect();
+    dbs.get(1).lock_key(b"big").s_add(b"big", &members).unwrap();
+    dbs.get(1).flush(true).unwrap();
+    assert!(dbs.get(1).is_empty());
+    assert_eq!(dbs.get(1).used_memory(), 0);
+
+    dbs.flush_all(false).unwrap();
+    assert!(dbs.all().iter().all(|db| db.is_empty()));
+}
+
+#[test]
+fn test_swap_keeps_blocked_clients_with_their_database() {
+    let dbs = Databases::new(DatabaseConfig { count: 2, ..DatabaseConfig::default() });
+    dbs.get(0).lock_key(b"only-in-0").set(b"only-in-0", b"v", None).unwrap();
+    dbs.get(1).lock_key(b"jobs").r_push(b"jobs", &strings(&["job-1"])).unwrap();
+    let (_, mut delivery) = dbs
+        .get(0)
+        .lock_key(b"jobs")
+        .block_client(strings(&["jobs"]), BlockedOp::Pop { front: true });
+
+    dbs.swap(0, 1).unwrap();
+    // The client blocked on database 0 is served from the list that just arrived there.
+    assert_eq!(delivery.try_recv(), Ok((b"jobs".to_vec(), b"job-1".to_vec())));
+    assert_eq!(dbs.get(0).lock_key(b"jobs").l_len(b"jobs"), Ok(0));
+    assert_eq!(dbs.get(1).lock_key(b"only-in-0").get(b"only-in-0"), Ok(Some(b"v".to_vec())));
+    assert_eq!(dbs.get(1).blocked_clients(), 0);
+}
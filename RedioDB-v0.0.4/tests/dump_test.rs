@@ -0,0 +1,106 @@
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rediodb::storage::dump::{RestoreOptions, DUMP_VERSION};
+use rediodb::storage::error::StoreError;
+use rediodb::storage::eviction::{EvictionPolicy, MemoryConfig};
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::sorted_set::ZAddOptions;
+use rediodb::storage::ttl_store::TTLStore;
+use common::strings;
+
+fn replace() -> RestoreOptions {
+    RestoreOptions { replace: true, ..RestoreOptions::default() }
+}
+
+#[test]
+fn test_dump_and_restore_round_trip() {
+    let mut store = TTLStore::new();
+    store.set(b"str", b"hello", Some(Duration::from_secs(100))).unwrap();
+    store.r_push(b"list", &strings(&["a", "b"])).unwrap();
+    store.h_set(b"hash", &[(b"f".to_vec(), b"1".to_vec()), (b"g".to_vec(), b"2".to_vec())]).unwrap();
+    store.h_expire(b"hash", &strings(&["g"]), Duration::from_secs(50)).unwrap();
+    let members = [(1.5, b"one".to_vec())];
+    store.z_add(b"zset", &members, ZAddOptions::default()).unwrap();
+    assert_eq!(store.dump(b"missing"), None);
+
+    let mut copy = TTLStore::new();
+    for key in [&b"str"[..], b"list", b"hash", b"zset"] {
+        let payload = store.dump(key).unwrap();
+        copy.restore(key, &payload, 0, RestoreOptions::default()).unwrap();
+    }
+    // A TTL of 0 keeps the TTL the key was dumped with, down to per-field TTLs.
+    assert_eq!(copy.get(b"str"), Ok(Some(b"hello".to_vec())));
+    assert!(copy.ttl(b"str").unwrap() > 90);
+    assert_eq!(copy.l_range(b"list", 0, -1), Ok(strings(&["a", "b"])));
+    assert_eq!(copy.ttl(b"list"), Some(-1));
+    assert!(copy.h_ttl(b"hash", &strings(&["g"])).unwrap()[0] > 40);
+    assert_eq!(copy.z_score(b"zset", b"one"), Ok(Some(1.5)));
+
+    // Existing keys are only overwritten with REPLACE.
+    let payload = store.dump(b"list").unwrap();
+    let err = copy.restore(b"str", &payload, 0, RestoreOptions::default());
+    assert_eq!(err, Err(StoreError::BusyKey));
+    assert_eq!(err.unwrap_err().to_string(), "BUSYKEY Target key name already exists.");
+    copy.restore(b"str", &payload, 0, replace()).unwrap();
+    assert_eq!(copy.l_range(b"str", 0, -1), Ok(strings(&["a", "b"])));
+
+    // Damaged or truncated payloads are refused; the version sits before the checksum.
+    let mut damaged = payload.clone();
+    damaged[2] ^= 0x01;
+    assert!(copy.restore(b"new", &damaged, 0, replace()).is_err());
+    let version_at = payload.len() - 10;
+    assert_eq!(u16::from_le_bytes([payload[version_at], payload[version_at + 1]]), DUMP_VERSION);
+    assert!(copy.restore(b"new", b"short", 0, replace()).is_err());
+    assert_eq!(copy.exists(&[b"new".to_vec()]), 0);
+}
+
+#[test]
+fn test_restore_ttl_options() {
+    let mut store = TTLStore::new();
+    store.set(b"key", b"v", None).unwrap();
+    let payload = store.dump(b"key").unwrap();
+
+    store.restore(b"relative", &payload, 5_000, replace()).unwrap();
+    let ttl = store.pttl(b"relative").unwrap();
+    assert!(ttl > 4_000 && ttl <= 5_000);
+
+    let at = SystemTime::now() + Duration::from_secs(60);
+    let at_ms = at.duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let abs = RestoreOptions { abs_ttl: true, ..replace() };
+    store.restore(b"absolute", &payload, at_ms, abs).unwrap();
+    assert!(store.ttl(b"absolute").unwrap() > 55);
+
+    // A deadline in the past leaves no key behind, even one being replaced.
+    store.restore(b"absolute", &payload, 1_000, abs).unwrap();
+    assert_eq!(store.get(b"absolute"), Ok(None));
+
+    let err = store.restore(b"key", &payload, -1, replace()).unwrap_err();
+    assert!(err.to_string().contains("Invalid TTL value"));
+}
+
+#[test]
+fn test_restore_idletime_feeds_lru_eviction() {
+    let mut store = TTLStore::with_memory_config(MemoryConfig {
+        maxmemory: 4096,
+        policy: EvictionPolicy::AllKeysLru,
+        samples: 1000,
+    });
+    let value = b"x".repeat(256);
+    store.set(b"source", &value, None).unwrap();
+    let payload = store.dump(b"source").unwrap();
+    store.del(b"source");
+
+    let idle = RestoreOptions { idle_time: Some(1000), ..RestoreOptions::default() };
+    store.restore(b"idle", &payload, 0, idle).unwrap();
+    store.restore(b"fresh", &payload, 0, RestoreOptions::default()).unwrap();
+    let mut i = 0;
+    while store.evicted_keys() == 0 {
+        store.set(format!("key:{}", i).as_bytes(), &value, None).unwrap();
+        i += 1;
+    }
+    // The key restored as idle is the least recently used one.
+    assert_eq!(store.get(b"idle"), Ok(None));
+    assert_eq!(store.get(b"fresh"), Ok(Some(value)));
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use rediodb::storage::codec;
+use rediodb::storage::engine::{BatchOp, Record, StorageEngine};
+use rediodb::storage::error::StoreError;
+use rediodb::storage::eviction::{EvictionPolicy, MemoryConfig};
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::scan::ScanOptions;
+use rediodb::storage::sharded::ShardedStore;
+use rediodb::storage::sorted_set::ZAddOptions;
+use rediodb::storage::stream::{GroupReadId, StreamId, XAddId};
+use rediodb::storage::ttl_store::{StoreValue, TTLStore};
+use common::strings;
+
+/// A TTLStore standing in for a durable engine, shared so a test can inspect it and reload
+/// from it, counting the batches written to it. Batches fail while `failing` is set.
+#[derive(Clone, Default)]
+struct SharedEngine {
+    store: Arc<Mutex<TTLStore>>,
+    batches: Arc<Mutex<usize>>,
+    failing: Arc<AtomicBool>,
+}
+
+impl StorageEngine for SharedEngine {
+    fn read_record(&self, key: &[u8]) -> Result<Option<Record>, StoreError> {
+        self.store.lock().unwrap().read_record(key)
+    }
+
+    fn write_record(
+        &self,
+        key: &[u8],
+        value: &StoreValue,
+        expire_at: Option<SystemTime>,
+    ) -> Result<(), StoreError> {
+        self.store.lock().unwrap().write_record(key, value, expire_at)
+    }
+
+    fn delete_record(&self, key: &[u8]) -> Result<bool, StoreError> {
+        self.store.lock().unwrap().delete_record(key)
+    }
+
+    fn set_deadline(
+        &self,
+        key: &[u8],
+        expire_at: Option<SystemTime>,
+    ) -> Result<bool, StoreError> {
+        self.store.lock().unwrap().set_deadline(key, expire_at)
+    }
+
+    fn for_each_record(
+        &self,
+        prefix: &[u8],
+        visit: &mut dyn FnMut(&[u8], Record),
+    ) -> Result<(), StoreError> {
+        self.store.lock().unwrap().for_each_record(prefix, visit)
+    }
+
+    fn write_batch(&self, batch: &[BatchOp<'_>]) -> Result<(), StoreError> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(StoreError::Storage("disk full".into()));
+        }
+        *self.batches.lock().unwrap() += 1;
+        self.store.lock().unwrap().write_batch(batch)
+    }
+}
+
+#[test]
+fn test_records_round_trip_every_type() {
+    let mut store = TTLStore::new();
+    store.set(b"int", b"-42", Some(Duration::from_secs(100))).unwrap();
+    store.set(b"raw", b"\x00bin\xff", None).unwrap();
+    store.r_push(b"list", &strings(&["a", "b", "c"])).unwrap();
+    store.s_add(b"set", &strings(&["x", "y"])).unwrap();
+    store.h_set(b"hash", &[(b"f".to_vec(), b"1".to_vec()), (b"g".to_vec(), b"2".to_vec())]).unwrap();
+    store.h_expire(b"hash", &strings(&["g"]), Duration::from_secs(50)).unwrap();
+    let members = [(1.5, b"one".to_vec()), (-2.0, b"two".to_vec())];
+    store.z_add(b"zset", &members, ZAddOptions::default()).unwrap();
+    let fields = vec![(b"v".to_vec(), b"1".to_vec())];
+    store.x_add(b"log", XAddId::Explicit(StreamId::new(5, 0)), fields, false, None).unwrap();
+    store.x_group_create(b"log", "workers", Some(StreamId::MIN), false).unwrap();
+    let keys = vec![b"log".to_vec()];
+    store.x_read_group("workers", "alice", &keys, &[GroupReadId::New], None, false).unwrap();
+
+    // Encode every record, then decode them into a fresh store.
+    let mut encoded = Vec::new();
+    store
+        .for_each_record(b"", &mut |key, (value, expire_at)| {
+            encoded.push((key.to_vec(), codec::encode_record(&value, expire_at)));
+        })
+        .unwrap();
+    assert_eq!(encoded.len(), 7);
+    let mut copy = TTLStore::new();
+    for (key, bytes) in &encoded {
+        let (value, expire_at) = codec::decode_record(bytes).unwrap();
+        copy.write_record(key, &value, expire_at).unwrap();
+    }
+
+    assert_eq!(copy.get(b"int"), Ok(Some(b"-42".to_vec())));
+    assert!(copy.ttl(b"int").unwrap() > 90);
+    assert_eq!(copy.get(b"raw"), Ok(Some(b"\x00bin\xff".to_vec())));
+    assert_eq!(copy.ttl(b"raw"), Some(-1));
+    assert_eq!(copy.l_range(b"list", 0, -1), Ok(strings(&["a", "b", "c"])));
+    assert_eq!(copy.s_members(b"set"), Ok(strings(&["x", "y"])));
+    assert_eq!(copy.h_ttl(b"hash", &strings(&["f", "g"])).unwrap()[0], -1);
+    assert!(copy.h_ttl(b"hash", &strings(&["g"])).unwrap()[0] > 40);
+    assert_eq!(copy.z_score(b"zset", b"two"), Ok(Some(-2.0)));
+    assert_eq!(copy.x_range(b"log", StreamId::MIN, StreamId::MAX, None, false).unwrap().len(), 1);
+    let summary = copy.x_pending_summary(b"log", "workers").unwrap();
+    assert_eq!(summary.count, 1);
+    assert_eq!(summary.consumers, vec![("alice".to_string(), 1)]);
+
+    // Corrupt input is an error, never a panic.
+    let (_, bytes) = &encoded[0];
+    assert!(matches!(codec::decode_record(&bytes[..bytes.len() - 1]), Err(StoreError::Storage(_))));
+    assert!(matches!(codec::decode_record(&[0, 9]), Err(StoreError::Storage(_))));
+}
+
+#[test]
+fn test_changes_are_written_through_and_reloaded() {
+    let engine = SharedEngine::default();
+    let store = ShardedStore::with_engine(4, Box::new(engine.clone())).unwrap();
+    store.write(b"a", |shard| shard.set(b"a", b"1", None)).unwrap();
+    store.write(b"b", |shard| shard.r_push(b"b", &strings(&["x", "y"]))).unwrap();
+    store.write(b"c", |shard| shard.set(b"c", b"gone", None)).unwrap();
+    store.write(b"c", |shard| Ok(shard.del(b"c"))).unwrap();
+    assert_eq!(*engine.batches.lock().unwrap(), 4);
+
+    // Reads write nothing.
+    store.lock_key(b"a").get(b"a").unwrap();
+    store.lock_key(b"b").l_range(b"b", 0, -1).unwrap();
+    assert_eq!(*engine.batches.lock().unwrap(), 4);
+
+    // A command spanning shards is written in one batch.
+    store
+        .write_keys([&b"a"[..], b"b", b"dest"], |shard| shard.rename(b"b", b"dest"))
+        .unwrap();
+    assert_eq!(*engine.batches.lock().unwrap(), 5);
+    assert_eq!(engine.store.lock().unwrap().len(), 2);
+
+    let reloaded = ShardedStore::with_engine(8, Box::new(engine.clone())).unwrap();
+    assert_eq!(reloaded.len(), 2);
+    assert_eq!(reloaded.lock_key(b"a").get(b"a"), Ok(Some(b"1".to_vec())));
+    assert_eq!(
+        reloaded.lock_key(b"dest").l_range(b"dest", 0, -1),
+        Ok(strings(&["x", "y"]))
+    );
+    reloaded.flush(false).unwrap();
+    assert!(engine.store.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_deadlines_survive_reloads() {
+    let engine = SharedEngine::default();
+    let store = ShardedStore::with_engine(2, Box::new(engine.clone())).unwrap();
+    store.write(b"session", |shard| shard.set(b"session", b"s", Some(Duration::from_secs(100)))).unwrap();
+    store.write(b"short", |shard| shard.set(b"short", b"s", Some(Duration::from_millis(20)))).unwrap();
+    store.write(b"kept", |shard| shard.set(b"kept", b"k", Some(Duration::from_secs(100)))).unwrap();
+    assert!(store.write(b"kept", |shard| Ok(shard.persist(b"kept"))).unwrap());
+    let expire_at = store.lock_key(b"session").pexpire_time(b"session").unwrap();
+
+    std::thread::sleep(Duration::from_millis(30));
+    let reloaded = ShardedStore::with_engine(2, Box::new(engine.clone())).unwrap();
+    assert_eq!(reloaded.len(), 2);
+    let reloaded_at = reloaded.lock_key(b"session").pexpire_time(b"session").unwrap();
+    assert!((reloaded_at - expire_at).abs() <= 5);
+    assert_eq!(reloaded.lock_key(b"kept").ttl(b"kept"), Some(-1));
+    assert_eq!(reloaded.lock_key(b"short").get(b"short"), Ok(None));
+}
+
+#[test]
+fn test_failed_writes_fail_the_command_and_evictions_reach_the_engine() {
+    let engine = SharedEngine::default();
+    let store = ShardedStore::with_engine(1, Box::new(engine.clone())).unwrap();
+
+    // The client hears about a write the engine could not store.
+    engine.failing.store(true, Ordering::SeqCst);
+    let failed = store.write(b"a", |shard| shard.set(b"a", b"1", None));
+    assert_eq!(failed, Err(StoreError::Storage("disk full".into())));
+    assert!(engine.store.lock().unwrap().is_empty());
+
+    // The key stays pending and goes out with the next write that succeeds.
+    engine.failing.store(false, Ordering::SeqCst);
+    store.write(b"b", |shard| shard.set(b"b", b"2", None)).unwrap();
+    assert_eq!(engine.store.lock().unwrap().len(), 2);
+
+    // The engine is only read at startup, so an evicted key is removed from it too.
+    let policy = EvictionPolicy::AllKeysRandom;
+    let config = MemoryConfig { maxmemory: 1, policy, ..MemoryConfig::default() };
+    store.set_memory_config(config);
+    store.write(b"c", |shard| shard.set(b"c", b"3", None)).unwrap();
+    assert_eq!(engine.store.lock().unwrap().len(), store.len());
+
+    // Loaded on demand, evicting a key only frees memory: the engine keeps it.
+    let backed = ShardedStore::backed_by(1, Box::new(engine.clone()));
+    backed.set_memory_config(config);
+    let durable = engine.store.lock().unwrap().len();
+    backed.write(b"d", |shard| shard.set(b"d", b"4", None)).unwrap();
+    backed.write(b"e", |shard| shard.set(b"e", b"5", None)).unwrap();
+    assert_eq!(backed.len(), 1);
+    assert_eq!(engine.store.lock().unwrap().len(), durable + 2);
+}
+
+#[test]
+fn test_backed_stores_load_keys_on_demand() {
+    let engine = SharedEngine::default();
+    let store = ShardedStore::with_engine(4, Box::new(engine.clone())).unwrap();
+    store.write(b"list", |shard| shard.r_push(b"list", &strings(&["a", "b", "c"]))).unwrap();
+    store.write(b"set", |shard| shard.s_add(b"set", &strings(&["x", "y"]))).unwrap();
+    store.write(b"str", |shard| shard.set(b"str", b"1", None)).unwrap();
+
+    // Nothing is loaded up front; keyspace-wide commands ask the engine.
+    let backed = ShardedStore::backed_by(4, Box::new(engine.clone()));
+    assert_eq!(backed.len(), 0);
+    assert_eq!(backed.count_keys(), Ok(3));
+    assert_eq!(backed.keys(b"s*").unwrap(), strings(&["set", "str"]));
+    let opts = ScanOptions { count: 2, ..ScanOptions::default() };
+    let page = backed.scan(0, &opts, None).unwrap();
+    assert_eq!(page.items, strings(&["list", "set"]));
+    let last = backed.scan(page.cursor, &opts, None).unwrap();
+    assert_eq!((last.cursor, last.items), (0, strings(&["str"])));
+
+    // Naming a key loads it, and edits are written through.
+    let list = backed.read(b"list", |shard| shard.l_range(b"list", 0, -1));
+    assert_eq!(list, Ok(strings(&["a", "b", "c"])));
+    assert_eq!(backed.len(), 1);
+    backed.write(b"list", |shard| shard.l_push(b"list", &strings(&["z"]))).unwrap();
+    backed.write(b"set", |shard| shard.s_rem(b"set", &strings(&["x"]))).unwrap();
+    assert_eq!(backed.len(), 2);
+    let reloaded = ShardedStore::with_engine(2, Box::new(engine.clone())).unwrap();
+    let list = reloaded.read(b"list", |shard| shard.l_range(b"list", 0, -1));
+    assert_eq!(list, Ok(strings(&["z", "a", "b", "c"])));
+    assert_eq!(reloaded.read(b"set", |shard| shard.s_members(b"set")), Ok(strings(&["y"])));
+
+    // FLUSHDB also removes the keys that were never loaded.
+    backed.flush(false).unwrap();
+    assert_eq!(backed.count_keys(), Ok(0));
+    assert!(engine.store.lock().unwrap().is_empty());
+}
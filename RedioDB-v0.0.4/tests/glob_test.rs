@@ -0,0 +1,128 @@
+use proptest::prelude::*;
+
+use rediodb::glob::{glob_escape, glob_match};
+use rediodb::pubsub::ChannelFilter;
+use rediodb::storage::ttl_store::TTLStore;
+
+/// Straight port of Redis's recursive `stringmatchlen`, used as the reference. Unlike Redis,
+/// trailing stars are skipped when the string is empty too, so "*" matches "".
+fn reference(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    while p < pattern.len() && s < string.len() {
+        match pattern[p] {
+            b'*' => {
+                while p + 1 < pattern.len() && pattern[p + 1] == b'*' {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+                return (s..=string.len()).any(|start| reference(&pattern[p + 1..], &string[start..]));
+            }
+            b'?' => s += 1,
+            b'[' => {
+                p += 1;
+                let not = pattern.get(p) == Some(&b'^');
+                if not {
+                    p += 1;
+                }
+                let mut matched = false;
+                loop {
+                    if p + 1 < pattern.len() && pattern[p] == b'\\' {
+                        p += 1;
+                        matched |= pattern[p] == string[s];
+                    } else if p < pattern.len() && pattern[p] == b']' {
+                        break;
+                    } else if p >= pattern.len() {
+                        p -= 1;
+                        break;
+                    } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+                        let (a, b) = (pattern[p], pattern[p + 2]);
+                        let (lo, hi) = if a > b { (b, a) } else { (a, b) };
+                        p += 2;
+                        matched |= lo <= string[s] && string[s] <= hi;
+                    } else {
+                        matched |= pattern[p] == string[s];
+                    }
+                    p += 1;
+                }
+                if matched == not {
+                    return false;
+                }
+                s += 1;
+            }
+            c => {
+                let c = if c == b'\\' && p + 1 < pattern.len() {
+                    p += 1;
+                    pattern[p]
+                } else {
+                    c
+                };
+                if c != string[s] {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+    }
+    if s == string.len() {
+        while p < pattern.len() && pattern[p] == b'*' {
+            p += 1;
+        }
+    }
+    p == pattern.len() && s == string.len()
+}
+
+fn matches(pattern: &str, string: &str) -> bool {
+    glob_match(pattern.as_bytes(), string.as_bytes())
+}
+
+#[test]
+fn test_glob_examples() {
+    assert!(matches("user:*:session", "user:42:session"));
+    assert!(!matches("user:*:session", "user:42:sessions"));
+    assert!(matches("h?llo", "hello") && !matches("h?llo", "hllo"));
+    assert!(matches("h[ae]llo", "hallo") && !matches("h[ae]llo", "hillo"));
+    assert!(matches("h[^e]llo", "hallo") && !matches("h[^e]llo", "hello"));
+    assert!(matches("h[a-b]llo", "hbllo") && matches("h[b-a]llo", "hallo"));
+    assert!(matches("a\\*b", "a*b") && !matches("a\\*b", "axb"));
+    assert!(matches("[\\]]", "]") && matches("a[b", "ab") && !matches("[", "a"));
+    assert!(matches("**", "") && matches("*", "anything") && !matches("", "a"));
+    assert!(matches(&glob_escape("a*[b]?\\"), "a*[b]?\\"));
+
+    let mut store = TTLStore::new();
+    for key in ["user:1:session", "user:2:session", "user:2:cart", "superuser"] {
+        store.set(key.as_bytes(), b"v", None).unwrap();
+    }
+    let mut keys = store.keys(b"user:*:session");
+    keys.sort();
+    assert_eq!(keys, [b"user:1:session".to_vec(), b"user:2:session".to_vec()]);
+    assert_eq!(store.keys(b"user"), Vec::<Vec<u8>>::new());
+
+    let filter = ChannelFilter::new(vec!["news".to_string()], "chat.[0-9]*");
+    assert!(filter.matches("news") && filter.matches("chat.1.room"));
+    assert!(!filter.matches("chat.lobby") && !filter.matches("newsletter"));
+    assert!(ChannelFilter::new(Vec::new(), "").is_empty());
+}
+
+proptest! {
+    #[test]
+    fn test_glob_matches_reference(
+        pattern in "[ab*?\\[\\]^\\\\-]{0,10}",
+        string in "[ab\\]^\\\\-]{0,10}",
+    ) {
+        prop_assert_eq!(
+            glob_match(pattern.as_bytes(), string.as_bytes()),
+            reference(pattern.as_bytes(), string.as_bytes()),
+            "pattern {:?} string {:?}", pattern, string
+        );
+    }
+
+    #[test]
+    fn test_glob_escape_matches_only_itself(literal in "[ab*?\\[\\]\\\\]{0,10}", other in "[ab*?]{0,10}") {
+        let pattern = glob_escape(&literal);
+        prop_assert!(glob_match(pattern.as_bytes(), literal.as_bytes()));
+        prop_assert_eq!(glob_match(pattern.as_bytes(), other.as_bytes()), literal == other);
+    }
+}
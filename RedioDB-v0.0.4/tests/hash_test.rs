@@ -0,0 +1,117 @@
+mod common;
+
+use std::time::Duration;
+
+use rediodb::storage::error::StoreError;
+use rediodb::storage::ttl_store::TTLStore;
+use common::strings;
+
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    pairs.iter().map(|(f, v)| (f.as_bytes().to_vec(), v.as_bytes().to_vec())).collect()
+}
+
+#[test]
+fn test_fields_roundtrip() {
+    let mut store = TTLStore::new();
+    let profile = pairs(&[("name", "ada"), ("lang", "en"), ("city", "london")]);
+    assert_eq!(store.h_set(b"user:1", &profile), Ok(3));
+    assert_eq!(store.h_set(b"user:1", &pairs(&[("lang", "fr"), ("tz", "utc")])), Ok(1));
+    assert_eq!(store.h_set_nx(b"user:1", b"lang", b"de"), Ok(false));
+    assert_eq!(store.h_set_nx(b"user:1", b"age", b"36"), Ok(true));
+
+    assert_eq!(store.h_len(b"user:1"), Ok(5));
+    assert_eq!(
+        store.h_mget(b"user:1", &strings(&["lang", "nope"])),
+        Ok(vec![Some(b"fr".to_vec()), None])
+    );
+    let mut all = store.h_get_all(b"user:1").unwrap();
+    all.sort();
+    assert_eq!(
+        all,
+        pairs(&[("age", "36"), ("city", "london"), ("lang", "fr"), ("name", "ada"), ("tz", "utc")])
+    );
+    assert_eq!(store.h_strlen(b"user:1", b"city"), Ok(6));
+    assert_eq!(store.h_exists(b"user:1", b"tz"), Ok(true));
+
+    // Emptied hashes are removed.
+    assert_eq!(store.h_del(b"user:1", &strings(&["tz", "nope"])), Ok(1));
+    let remaining = store.h_keys(b"user:1").unwrap();
+    assert_eq!(store.h_del(b"user:1", &remaining), Ok(4));
+    assert_eq!(store.keys(b"*"), Vec::<Vec<u8>>::new());
+    assert_eq!(store.used_memory(), 0);
+
+    store.set(b"str", b"value", None).unwrap();
+    assert_eq!(store.h_set(b"str", &pairs(&[("f", "v")])), Err(StoreError::WrongType));
+    assert_eq!(store.h_get(b"str", b"f"), Err(StoreError::WrongType));
+    assert_eq!(store.get(b"str"), Ok(Some(b"value".to_vec())));
+    assert_eq!(store.h_len(b"user:1"), Ok(0));
+}
+
+#[test]
+fn test_counters_and_random_fields() {
+    let mut store = TTLStore::new();
+    assert_eq!(store.h_incr_by(b"h", b"n", 5), Ok(5));
+    assert_eq!(store.h_incr_by(b"h", b"n", -7), Ok(-2));
+    store.h_set(b"h", &pairs(&[("big", &i64::MAX.to_string()), ("word", "abc")])).unwrap();
+    assert_eq!(store.h_incr_by(b"h", b"big", 1), Err(StoreError::Overflow));
+    assert_eq!(store.h_incr_by(b"h", b"word", 1), Err(StoreError::NotInteger));
+    assert_eq!(store.h_incr_by_float(b"h", b"word", 1.0), Err(StoreError::NotFloat));
+
+    store.h_set(b"h", &pairs(&[("f", "10.5"), ("e", "5.0e3")])).unwrap();
+    assert_eq!(store.h_incr_by_float(b"h", b"f", 0.1), Ok("10.6".to_string()));
+    assert_eq!(store.h_incr_by_float(b"h", b"e", 200.0), Ok("5200".to_string()));
+    assert_eq!(store.h_get(b"h", b"e"), Ok(Some(b"5200".to_vec())));
+
+    let fields = store.h_rand_field(b"h", 100).unwrap();
+    assert_eq!(Ok(fields.len()), store.h_len(b"h"));
+    let repeated = store.h_rand_field(b"h", -20).unwrap();
+    assert_eq!(repeated.len(), 20);
+    assert!(repeated
+        .iter()
+        .all(|(field, value)| store.h_get(b"h", field) == Ok(Some(value.clone()))));
+}
+
+#[test]
+fn test_field_ttls() {
+    let mut store = TTLStore::new();
+    let fields = pairs(&[("token", "t"), ("csrf", "c"), ("user", "u")]);
+    store.h_set(b"session", &fields).unwrap();
+
+    let short = Duration::from_millis(20);
+    assert_eq!(store.h_expire(b"session", &strings(&["token", "csrf", "nope"]), short), Ok(vec![1, 1, -2]));
+    assert_eq!(store.h_persist(b"session", &strings(&["csrf", "user"])), Ok(vec![1, -1]));
+    let ttls = store.h_ttl(b"session", &strings(&["token", "csrf", "nope"]));
+    assert_eq!(ttls, Ok(vec![0, -1, -2]));
+    assert_eq!(store.h_expire(b"session", &strings(&["csrf"]), Duration::ZERO), Ok(vec![2]));
+    let invalid = Err(StoreError::InvalidArgument("invalid expire time".into()));
+    assert_eq!(store.h_expire(b"session", &strings(&["user"]), Duration::MAX), invalid);
+
+    // Overwriting a field clears its TTL.
+    store.h_expire(b"session", &strings(&["user"]), short).unwrap();
+    store.h_set(b"session", &pairs(&[("user", "u2")])).unwrap();
+
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(store.h_get_all(b"session"), Ok(pairs(&[("user", "u2")])));
+
+    // Fields that are never read again are reclaimed by the active expiry cycle.
+    store.h_set(b"idle", &pairs(&[("a", "1")])).unwrap();
+    store.h_expire(b"idle", &strings(&["a"]), short).unwrap();
+    std::thread::sleep(Duration::from_millis(30));
+    store.active_expire_cycle(10);
+    assert_eq!(store.keys(b"*"), strings(&["session"]));
+}
+
+#[test]
+fn test_random_fields_of_an_expired_hash() {
+    let mut store = TTLStore::new();
+    store.h_set(b"h", &pairs(&[("a", "1"), ("b", "2")])).unwrap();
+    store.h_expire(b"h", &strings(&["a", "b"]), Duration::from_millis(20)).unwrap();
+    std::thread::sleep(Duration::from_millis(30));
+
+    // A negative count allows repeats, but there is no field left to repeat.
+    assert_eq!(store.h_rand_field(b"h", -5), Ok(Vec::new()));
+    assert_eq!(store.h_rand_field(b"h", 5), Ok(Vec::new()));
+    let out_of_range = Err(StoreError::InvalidArgument("value is out of range".into()));
+    assert_eq!(store.h_rand_field(b"h", i64::MIN), out_of_range);
+    assert_eq!(store.keys(b"*"), Vec::<Vec<u8>>::new());
+}
@@ -1,23 +1,44 @@
+use rediodb::server::my_service::MyService;
+use rediodb::server::rediodb_server::rediodb_client::RediodbClient;
+use rediodb::server::rediodb_server::rediodb_server::RediodbServer;
+use rediodb::server::rediodb_server::{KeyRequest, SetRequest, SetResponse, ValueResponse};
 use tokio::time::{sleep, Duration};
-use rediodb::server::rediodb_server::edgedb_client::EdgedbClient;
-use rediodb::server::rediodb_server::{SetRequest, KeyRequest, ResponseMessage, ValueResponse};
+use tonic::transport::{Channel, Server};
 use tonic::Request; // Import tonic::Request
 
+/// Starts the gRPC server on a free port and connects to it.
+async fn connect() -> RediodbClient<Channel> {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("Failed to find a free port");
+    tokio::spawn(
+        Server::builder()
+            .add_service(RediodbServer::new(MyService::default()))
+            .serve(addr),
+    );
+    for _ in 0..50 {
+        if let Ok(client) = RediodbClient::connect(format!("http://{}", addr)).await {
+            return client;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Failed to connect to gRPC server");
+}
+
 #[tokio::test]
 async fn test_set_get_expire() {
     // Connect to the gRPC server.
-    let mut client = EdgedbClient::connect("http://127.0.0.1:50051")
-        .await
-        .expect("Failed to connect to gRPC server");
+    let mut client = connect().await;
 
-    // Test SET with a TTL of 3 seconds.
+    // Test SET with a TTL of 1 second.
     let set_req = Request::new(SetRequest {
         key: "integrationKey".into(),
         value: "Integration Test".into(),
-        ttl: 3,
+        ttl: 1,
+        ..Default::default()
     });
-    let set_resp: ResponseMessage = client.set(set_req).await.unwrap().into_inner();
-    assert_eq!(set_resp.status, "success");
+    let set_resp: SetResponse = client.set(set_req).await.unwrap().into_inner();
+    assert!(set_resp.written);
 
     // Immediately GET the key.
     let get_req = Request::new(KeyRequest {
@@ -26,8 +47,8 @@ async fn test_set_get_expire() {
     let get_resp: ValueResponse = client.get(get_req).await.unwrap().into_inner();
     assert_eq!(get_resp.value, "Integration Test");
 
-    // Wait for 4 seconds so the key expires.
-    sleep(Duration::from_secs(4)).await;
+    // Wait for 2 seconds so the key expires.
+    sleep(Duration::from_secs(2)).await;
 
     // GET the key again; it should have expired.
     let get_req2 = Request::new(KeyRequest {
@@ -35,4 +56,4 @@ async fn test_set_get_expire() {
     });
     let get_resp2: ValueResponse = client.get(get_req2).await.unwrap().into_inner();
     assert_eq!(get_resp2.value, "");
-}
\ No newline at end of file
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rediodb::storage::error::StoreError;
+use rediodb::storage::expiry::ExpireOptions;
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::string_value::SetExpiry;
+use rediodb::storage::ttl_store::TTLStore;
+use common::strings;
+
+#[test]
+fn test_exists_type_and_multi_key_delete() {
+    let mut store = TTLStore::new();
+    store.set(b"a", b"1", None).unwrap();
+    store.r_push(b"list", &strings(&["x"])).unwrap();
+    store.set(b"short", b"v", Some(Duration::from_millis(10))).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+
+    assert_eq!(store.exists(&strings(&["a", "a", "list", "short", "missing"])), 3);
+    assert_eq!(store.key_type(b"a"), "string");
+    assert_eq!(store.key_type(b"list"), "list");
+    assert_eq!(store.key_type(b"short"), "none");
+    assert_eq!(store.touch(&strings(&["a", "missing"])), 1);
+
+    assert_eq!(store.del_keys(&strings(&["a", "missing", "a"])), 1);
+    let members: Vec<Vec<u8>> = (0..500).map(|i| i.to_string().into_bytes()).collect();
+    store.s_add(b"big", &members).unwrap();
+    assert_eq!(store.unlink(&strings(&["big", "list", "missing"])), 2);
+    assert!(store.is_empty());
+    assert_eq!(store.used_memory(), 0);
+}
+
+#[test]
+fn test_expire_conditions_and_ttl_queries() {
+    let mut store = TTLStore::new();
+    store.set(b"k", b"v", None).unwrap();
+    let secs = |s| SetExpiry::In(Duration::from_secs(s));
+    let gt = ExpireOptions { gt: true, ..ExpireOptions::default() };
+    let lt = ExpireOptions { lt: true, ..ExpireOptions::default() };
+    let nx = ExpireOptions { nx: true, ..ExpireOptions::default() };
+    let xx = ExpireOptions { xx: true, ..ExpireOptions::default() };
+
+    // No TTL counts as infinite: GT and XX fail, LT and NX succeed.
+    assert!(!store.expire_with_options(b"k", secs(100), gt));
+    assert!(!store.expire_with_options(b"k", secs(100), xx));
+    assert!(store.expire_with_options(b"k", secs(100), lt));
+    assert!(!store.expire_with_options(b"k", secs(50), nx));
+    assert!(!store.expire_with_options(b"k", secs(200), lt));
+    assert!(store.expire_with_options(b"k", secs(200), gt));
+    assert!(!store.expire_with_options(b"missing", secs(10), ExpireOptions::default()));
+    assert!(ExpireOptions { nx: true, gt: true, ..ExpireOptions::default() }.validate().is_err());
+    assert!(ExpireOptions { gt: true, lt: true, ..ExpireOptions::default() }.validate().is_err());
+
+    let pttl = store.pttl(b"k").unwrap();
+    assert!(pttl > 199_000 && pttl <= 200_000);
+    assert_eq!(store.ttl(b"k"), Some(199));
+    let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let at = store.pexpire_time(b"k").unwrap();
+    assert!((at - (now_ms + 200_000)).abs() < 1000);
+    assert_eq!(store.expire_time(b"k"), Some(at / 1000));
+
+    assert!(store.persist(b"k"));
+    assert!(!store.persist(b"k"));
+    assert_eq!((store.ttl(b"k"), store.pttl(b"k"), store.expire_time(b"k")), (Some(-1), Some(-1), Some(-1)));
+    assert_eq!(store.ttl(b"missing"), None);
+
+    // An absolute time in the past deletes the key.
+    assert!(store.expire_with_options(b"k", SetExpiry::At(UNIX_EPOCH), ExpireOptions::default()));
+    assert_eq!(store.exists(&strings(&["k"])), 0);
+}
+
+#[test]
+fn test_rename_and_copy() {
+    let mut store = TTLStore::new();
+    store.set(b"src", b"v", Some(Duration::from_secs(100))).unwrap();
+    store.set(b"other", b"x", None).unwrap();
+
+    store.rename(b"src", b"dst").unwrap();
+    assert_eq!(store.get(b"src"), Ok(None));
+    assert_eq!(store.get(b"dst"), Ok(Some(b"v".to_vec())));
+    assert!(store.ttl(b"dst").unwrap() > 0);
+    assert_eq!(store.rename(b"src", b"dst"), Err(StoreError::NoSuchKey));
+    assert_eq!(store.rename_nx(b"dst", b"other"), Ok(false));
+    assert_eq!(store.rename_nx(b"missing", b"x"), Err(StoreError::NoSuchKey));
+    assert_eq!(store.rename_nx(b"dst", b"renamed"), Ok(true));
+
+    assert_eq!(store.copy(b"renamed", b"other", false), Ok(false));
+    assert_eq!(store.copy(b"renamed", b"other", true), Ok(true));
+    assert_eq!(store.get(b"other"), Ok(Some(b"v".to_vec())));
+    assert!(store.ttl(b"other").unwrap() > 0);
+    assert_eq!(store.copy(b"missing", b"other", true), Ok(false));
+    assert!(store.copy(b"other", b"other", true).is_err());
+
+    // Hash field deadlines follow the value to its new name.
+    store.h_set(b"hash", &[(b"f".to_vec(), b"v".to_vec())]).unwrap();
+    store.h_expire(b"hash", &strings(&["f"]), Duration::from_millis(10)).unwrap();
+    store.copy(b"hash", b"hash2", false).unwrap();
+    store.rename(b"hash", b"hash3").unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    store.active_expire_cycle(10);
+    assert_eq!(store.exists(&strings(&["hash2", "hash3"])), 0);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use rediodb::storage::error::StoreError;
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::ttl_store::TTLStore;
+use common::strings;
+
+#[test]
+fn test_push_pop_and_ranges() {
+    let mut store = TTLStore::new();
+    assert_eq!(store.r_push(b"q", &strings(&["b", "c"])), Ok(2));
+    assert_eq!(store.l_push(b"q", &strings(&["a", "z"])), Ok(4));
+    assert_eq!(store.l_range(b"q", 0, -1), Ok(strings(&["z", "a", "b", "c"])));
+    assert_eq!(store.l_push_x(b"missing", &strings(&["x"])), Ok(0));
+    assert_eq!(store.l_len(b"missing"), Ok(0));
+
+    assert_eq!(store.l_pop(b"q"), Ok(Some(b"z".to_vec())));
+    assert_eq!(store.r_pop(b"q"), Ok(Some(b"c".to_vec())));
+    assert_eq!(store.l_index(b"q", -1), Ok(Some(b"b".to_vec())));
+    assert_eq!(store.l_range(b"q", -100, 100), Ok(strings(&["a", "b"])));
+    assert_eq!(store.l_set(b"q", 5, b"x"), Err(StoreError::InvalidArgument("index out of range".into())));
+    assert_eq!(store.l_set(b"q", 0, b"A"), Ok(()));
+
+    // Emptied lists are removed.
+    store.l_pop(b"q").unwrap();
+    store.l_pop(b"q").unwrap();
+    assert_eq!(store.keys(b"q"), Vec::<Vec<u8>>::new());
+    assert_eq!(store.used_memory(), 0);
+
+    // Pushing onto a key of another type fails and leaves it untouched.
+    store.set(b"s", b"value", None).unwrap();
+    assert_eq!(store.r_push(b"s", &strings(&["x"])), Err(StoreError::WrongType));
+    assert_eq!(store.l_len(b"s"), Err(StoreError::WrongType));
+    assert_eq!(store.get(b"s"), Ok(Some(b"value".to_vec())));
+}
+
+#[test]
+fn test_trim_rem_insert_and_pos() {
+    let mut store = TTLStore::new();
+    store
+        .r_push(b"l", &strings(&["a", "x", "b", "x", "c", "x"]))
+        .unwrap();
+    assert_eq!(store.l_pos(b"l", b"x", 1, 1, 0), Ok(vec![1]));
+    assert_eq!(store.l_pos(b"l", b"x", -1, 0, 0), Ok(vec![5, 3, 1]));
+    assert_eq!(store.l_pos(b"l", b"x", 2, 0, 4), Ok(vec![3]));
+
+    assert_eq!(store.l_rem(b"l", -2, b"x"), Ok(2));
+    assert_eq!(store.l_range(b"l", 0, -1), Ok(strings(&["a", "x", "b", "c"])));
+    assert_eq!(store.l_insert(b"l", true, b"b", b"before-b"), Ok(5));
+    assert_eq!(store.l_insert(b"l", false, b"nope", b"v"), Ok(-1));
+
+    store.l_trim(b"l", 1, -2).unwrap();
+    assert_eq!(store.l_range(b"l", 0, -1), Ok(strings(&["x", "before-b", "b"])));
+    store.l_trim(b"l", 5, 10).unwrap();
+    assert_eq!(store.l_len(b"l"), Ok(0));
+}
+
+#[test]
+fn test_lmove_rotates_and_transfers() {
+    let mut store = TTLStore::new();
+    store.r_push(b"src", &strings(&["1", "2", "3"])).unwrap();
+
+    assert_eq!(store.l_move(b"src", b"src", false, true), Ok(Some(b"3".to_vec())));
+    assert_eq!(store.l_range(b"src", 0, -1), Ok(strings(&["3", "1", "2"])));
+
+    assert_eq!(store.l_move(b"src", b"dst", false, true), Ok(Some(b"2".to_vec())));
+    assert_eq!(store.l_move(b"src", b"dst", true, false), Ok(Some(b"3".to_vec())));
+    assert_eq!(store.l_range(b"dst", 0, -1), Ok(strings(&["2", "3"])));
+    assert_eq!(store.l_move(b"empty", b"dst", true, true), Ok(None));
+
+    // The destination's type is only checked once there is something to move.
+    store.set(b"str", b"value", None).unwrap();
+    assert_eq!(store.l_move(b"empty", b"str", true, true), Ok(None));
+    assert_eq!(store.l_move(b"src", b"str", true, true), Err(StoreError::WrongType));
+    assert_eq!(store.l_len(b"src"), Ok(1));
+}
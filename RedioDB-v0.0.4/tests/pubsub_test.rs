@@ -1,4 +1,4 @@
-use rediodb::pubsub::PubSub;
+use rediodb::pubsub::{Message, PubSub};
 
 #[tokio::test]
 async fn test_pubsub() {
@@ -10,10 +10,13 @@ async fn test_pubsub() {
     let mut subscriber2 = pubsub.subscribe();
 
     // Define the message to publish.
-    let message = "Hello, subscribers!".to_string();
+    let message = Message {
+        channel: "news".to_string(),
+        payload: b"Hello, subscribers!".to_vec(),
+    };
 
     // Publish the message.
-    pubsub.publish(message.clone());
+    pubsub.publish(&message.channel, &message.payload);
 
     // Both subscribers should receive the message.
     let received1 = subscriber1.recv().await.expect("Subscriber1 did not receive message");
@@ -22,4 +25,4 @@ async fn test_pubsub() {
     // Assert that both received messages match the published message.
     assert_eq!(received1, message);
     assert_eq!(received2, message);
-}
\ No newline at end of file
+}
@@ -0,0 +1,274 @@
+mod common;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rediodb::storage::databases::{DatabaseConfig, Databases};
+use rediodb::storage::rdb::{self, crc64, RdbReport};
+use rediodb::storage::sorted_set::ZAddOptions;
+use rediodb::storage::stream::XAddId;
+use rediodb::storage::util::{read_marker, write_marker};
+use common::strings;
+
+fn databases() -> Databases {
+    Databases::new(DatabaseConfig { count: 2, names: Vec::new(), shards: 4 })
+}
+
+/// Appends a short length-prefixed string, as Redis writes it.
+fn put_string(out: &mut Vec<u8>, bytes: &[u8]) {
+    assert!(bytes.len() < 64);
+    out.push(bytes.len() as u8);
+    out.extend_from_slice(bytes);
+}
+
+/// A ziplist of short strings and small integers.
+fn ziplist(entries: &[&str]) -> Vec<u8> {
+    let mut zl = vec![0; 10];
+    for entry in entries {
+        zl.push(0); // Length of the previous entry, unused when reading forwards.
+        match entry.parse::<i16>() {
+            Ok(num @ 0..=12) => zl.push(0xF1 + num as u8),
+            Ok(num) => {
+                zl.push(0xC0);
+                zl.extend_from_slice(&num.to_le_bytes());
+            }
+            Err(_) => put_string(&mut zl, entry.as_bytes()),
+        }
+    }
+    zl.push(0xFF);
+    zl
+}
+
+/// A listpack of short strings and integers from -4096 to 4095.
+fn listpack(entries: &[&str]) -> Vec<u8> {
+    let mut lp = vec![0; 6];
+    for entry in entries {
+        let start = lp.len();
+        match entry.parse::<i64>() {
+            Ok(num @ 0..=127) => lp.push(num as u8),
+            Ok(num) => {
+                let bits = (num & 0x1FFF) as u16;
+                lp.push(0xC0 | (bits >> 8) as u8);
+                lp.push(bits as u8);
+            }
+            Err(_) => {
+                lp.push(0x80 | entry.len() as u8);
+                lp.extend_from_slice(entry.as_bytes());
+            }
+        }
+        lp.push((lp.len() - start) as u8);
+    }
+    lp.push(0xFF);
+    lp
+}
+
+#[test]
+fn test_export_round_trips_through_import() {
+    // The check value of the CRC-64/Jones variant Redis uses.
+    assert_eq!(crc64(b"123456789"), 0xe9c6d914c4b8d9ca);
+
+    let dbs = databases();
+    let db = dbs.get(0);
+    db.lock_key(b"str").set(b"str", b"hello", None).unwrap();
+    db.lock_key(b"num").set(b"num", b"-42", None).unwrap();
+    db.lock_key(b"session").set(b"session", b"s", Some(Duration::from_secs(100))).unwrap();
+    db.lock_key(b"list").r_push(b"list", &strings(&["a", "b", "a"])).unwrap();
+    db.lock_key(b"set").s_add(b"set", &strings(&["x", "y"])).unwrap();
+    db.lock_key(b"hash").h_set(b"hash", &[(b"f".to_vec(), b"1".to_vec())]).unwrap();
+    let members = [(1.5, b"one".to_vec()), (f64::INFINITY, b"top".to_vec())];
+    db.lock_key(b"zset").z_add(b"zset", &members, ZAddOptions::default()).unwrap();
+    let fields = vec![(b"f".to_vec(), b"v".to_vec())];
+    db.lock_key(b"events").x_add(b"events", XAddId::Auto, fields, false, None).unwrap();
+    dbs.get(1).lock_key(b"str").set(b"str", b"other", None).unwrap();
+
+    let export = rdb::export(&dbs).unwrap();
+    assert!(export.bytes.starts_with(b"REDIS0009"));
+    assert_eq!((export.keys, export.skipped), (8, 1));
+
+    let copy = databases();
+    let report = rdb::import(&export.bytes, &copy).unwrap();
+    assert_eq!(report, RdbReport { version: 9, keys: 8, expired: 0, skipped: 0 });
+    let db = copy.get(0);
+    assert_eq!(db.len(), 7);
+    assert_eq!(db.lock_key(b"str").get(b"str"), Ok(Some(b"hello".to_vec())));
+    assert_eq!(db.lock_key(b"num").incr(b"num", 1), Ok(-41));
+    assert!(db.lock_key(b"session").ttl(b"session").unwrap() > 90);
+    assert_eq!(db.lock_key(b"list").l_range(b"list", 0, -1), Ok(strings(&["a", "b", "a"])));
+    assert_eq!(db.lock_key(b"set").s_card(b"set"), Ok(2));
+    assert_eq!(db.lock_key(b"hash").h_get(b"hash", b"f"), Ok(Some(b"1".to_vec())));
+    assert_eq!(db.lock_key(b"zset").z_score(b"zset", b"top"), Ok(Some(f64::INFINITY)));
+    assert_eq!(copy.get(1).lock_key(b"str").get(b"str"), Ok(Some(b"other".to_vec())));
+}
+
+#[test]
+fn test_import_reads_compact_encodings() {
+    let mut file = b"REDIS0011".to_vec();
+    // Auxiliary fields and size hints are skipped.
+    file.push(0xFA);
+    put_string(&mut file, b"redis-ver");
+    put_string(&mut file, b"7.2.4");
+    file.push(0xFA);
+    put_string(&mut file, b"redis-bits");
+    file.extend_from_slice(&[0xC0, 64]); // An integer-encoded string.
+    file.extend_from_slice(&[0xFE, 0, 0xFB, 8, 1]);
+
+    // Strings: integer-encoded and LZF-compressed.
+    file.push(0);
+    put_string(&mut file, b"int16");
+    file.extend_from_slice(&[0xC1, 0x39, 0x30]);
+    file.push(0);
+    put_string(&mut file, b"int32");
+    file.extend_from_slice(&[0xC2]);
+    file.extend_from_slice(&(-100_000i32).to_le_bytes());
+    file.push(0);
+    put_string(&mut file, b"lzf");
+    // One literal "a" followed by a back reference copying it 9 times.
+    file.extend_from_slice(&[0xC3, 5, 10, 0x00, b'a', 0xE0, 0x00, 0x00]);
+
+    // A set as an intset, a hash as a listpack, a sorted set as a listpack.
+    file.push(11);
+    put_string(&mut file, b"intset");
+    let mut intset = 2u32.to_le_bytes().to_vec();
+    intset.extend_from_slice(&3u32.to_le_bytes());
+    for num in [-5i16, 7, 300] {
+        intset.extend_from_slice(&num.to_le_bytes());
+    }
+    put_string(&mut file, &intset);
+    file.push(16);
+    put_string(&mut file, b"hash");
+    put_string(&mut file, &listpack(&["name", "ann", "age", "-100"]));
+    file.push(17);
+    put_string(&mut file, b"zset");
+    put_string(&mut file, &listpack(&["low", "-100", "high", "2.5"]));
+
+    // A list as a quicklist of one listpack and one plain node, expiring in the future.
+    let future = SystemTime::now() + Duration::from_secs(1000);
+    file.push(0xFC);
+    let deadline = future.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    file.extend_from_slice(&deadline.to_le_bytes());
+    file.push(18);
+    put_string(&mut file, b"list");
+    file.extend_from_slice(&[2, 2]);
+    put_string(&mut file, &listpack(&["a", "1"]));
+    file.push(1);
+    put_string(&mut file, b"plain");
+
+    // An already expired key is not imported.
+    file.push(0xFD);
+    file.extend_from_slice(&1_000u32.to_le_bytes());
+    file.push(0);
+    put_string(&mut file, b"expired");
+    put_string(&mut file, b"x");
+
+    // Older encodings in database 1: a ziplist list, a ziplist sorted set and a zipmap hash.
+    file.extend_from_slice(&[0xFE, 1]);
+    file.push(10);
+    put_string(&mut file, b"old-list");
+    put_string(&mut file, &ziplist(&["x", "3", "-300"]));
+    file.push(12);
+    put_string(&mut file, b"old-zset");
+    put_string(&mut file, &ziplist(&["m", "12"]));
+    file.push(9);
+    put_string(&mut file, b"old-hash");
+    put_string(&mut file, &[1, 1, b'f', 2, 1, b'v', b'w', 0, 0xFF]);
+
+    // Keys of a database this server does not have are skipped.
+    file.extend_from_slice(&[0xFE, 5, 0]);
+    put_string(&mut file, b"far");
+    put_string(&mut file, b"away");
+
+    file.push(0xFF);
+    let checksum = crc64(&file);
+    file.extend_from_slice(&checksum.to_le_bytes());
+
+    let dbs = databases();
+    let report = rdb::import(&file, &dbs).unwrap();
+    assert_eq!(report, RdbReport { version: 11, keys: 12, expired: 1, skipped: 1 });
+    assert_eq!(report.imported(), 10);
+
+    let db = dbs.get(0);
+    assert_eq!(db.lock_key(b"int16").get(b"int16"), Ok(Some(b"12345".to_vec())));
+    assert_eq!(db.lock_key(b"int32").get(b"int32"), Ok(Some(b"-100000".to_vec())));
+    assert_eq!(db.lock_key(b"lzf").get(b"lzf"), Ok(Some(b"aaaaaaaaaa".to_vec())));
+    assert_eq!(db.lock_key(b"intset").s_is_member(b"intset", b"-5"), Ok(true));
+    assert_eq!(db.lock_key(b"intset").s_card(b"intset"), Ok(3));
+    assert_eq!(db.lock_key(b"hash").h_get(b"hash", b"age"), Ok(Some(b"-100".to_vec())));
+    assert_eq!(db.lock_key(b"zset").z_score(b"zset", b"low"), Ok(Some(-100.0)));
+    assert_eq!(db.lock_key(b"zset").z_score(b"zset", b"high"), Ok(Some(2.5)));
+    assert_eq!(
+        db.lock_key(b"list").l_range(b"list", 0, -1),
+        Ok(strings(&["a", "1", "plain"]))
+    );
+    assert!(db.lock_key(b"list").ttl(b"list").unwrap() > 990);
+    assert_eq!(db.lock_key(b"expired").get(b"expired"), Ok(None));
+
+    let db = dbs.get(1);
+    assert_eq!(
+        db.lock_key(b"old-list").l_range(b"old-list", 0, -1),
+        Ok(strings(&["x", "3", "-300"]))
+    );
+    assert_eq!(db.lock_key(b"old-zset").z_score(b"old-zset", b"m"), Ok(Some(12.0)));
+    assert_eq!(db.lock_key(b"old-hash").h_get(b"old-hash", b"f"), Ok(Some(b"vw".to_vec())));
+}
+
+#[test]
+fn test_invalid_rdb_files_are_rejected() {
+    let dbs = databases();
+    dbs.get(0).lock_key(b"key").set(b"key", b"value", None).unwrap();
+    let file = rdb::export(&dbs).unwrap().bytes;
+
+    // A damaged or truncated file is refused before any of its keys is written.
+    let target = databases();
+    let mut damaged = file.clone();
+    damaged[12] ^= 0x01;
+    let err = rdb::import(&damaged, &target).unwrap_err();
+    assert!(err.to_string().contains("checksum mismatch"));
+    assert!(rdb::import(&file[..file.len() - 9], &target).is_err());
+    assert_eq!(target.get(0).len(), 0);
+
+    // A zero checksum means the writer disabled checksums.
+    let mut unchecked = file.clone();
+    let len = unchecked.len();
+    unchecked[len - 8..].fill(0);
+    assert_eq!(rdb::import(&unchecked, &databases()).unwrap().imported(), 1);
+
+    assert!(rdb::import(b"REDIS0013\xff", &databases())
+        .unwrap_err()
+        .to_string()
+        .contains("unsupported RDB version 13"));
+    assert!(rdb::import(b"NOTRDB0009", &databases()).is_err());
+
+    // Module values cannot be imported.
+    let mut module = b"REDIS0009\x07".to_vec();
+    put_string(&mut module, b"key");
+    module.extend_from_slice(&[0, 0, 0xFF]);
+    assert!(rdb::import(&module, &databases()).unwrap_err().to_string().contains("module"));
+
+    // An LZF string claiming more bytes than its data can expand to is refused up front.
+    let mut lzf = b"REDIS0009\x00".to_vec();
+    put_string(&mut lzf, b"key");
+    lzf.extend_from_slice(&[0xC3, 2, 0x81]);
+    lzf.extend_from_slice(&u64::MAX.to_be_bytes());
+    lzf.extend_from_slice(&[0x00, b'a', 0xFF]);
+    assert!(rdb::import(&lzf, &databases()).unwrap_err().to_string().contains("invalid LZF data"));
+}
+
+#[test]
+fn test_import_marker_recognizes_the_file_imported() {
+    let dbs = databases();
+    dbs.get(0).lock_key(b"key").set(b"key", b"value", None).unwrap();
+    let file = rdb::export(&dbs).unwrap().bytes;
+    let dir = std::env::temp_dir().join(format!("rediodb-rdb-{}-marker", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let marker = dir.join(rdb::IMPORT_MARKER_FILENAME);
+
+    assert_eq!(read_marker(&marker), Ok(None));
+    write_marker(&marker, &rdb::import_stamp(&file)).unwrap();
+    assert_eq!(read_marker(&marker).unwrap(), Some(rdb::import_stamp(&file)));
+
+    // Another dump, even one of the same size, is a different file to import.
+    dbs.get(0).lock_key(b"key").set(b"key", b"other", None).unwrap();
+    let newer = rdb::export(&dbs).unwrap().bytes;
+    assert_eq!(newer.len(), file.len());
+    assert_ne!(rdb::import_stamp(&newer), rdb::import_stamp(&file));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use bytes::BytesMut;
+use rediodb::server::resp::{self, parse_command, Protocol, Reply, RequestParser};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn encode(reply: &Reply, protocol: Protocol) -> String {
+    let mut out = Vec::new();
+    reply.encode(protocol, &mut out);
+    String::from_utf8(out).unwrap()
+}
+
+/// Sends `request` and reads until the replies received end with `expected`'s length.
+async fn round_trip(stream: &mut TcpStream, request: &str, expected: &str) {
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut received = Vec::new();
+    while received.len() < expected.len() {
+        let mut buf = [0; 4096];
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .expect("timed out waiting for a reply")
+            .unwrap();
+        assert!(read > 0, "connection closed after {:?}", String::from_utf8_lossy(&received));
+        received.extend_from_slice(&buf[..read]);
+    }
+    assert_eq!(String::from_utf8_lossy(&received), expected);
+}
+
+#[test]
+fn test_parse_multibulk_and_inline_commands() {
+    let input = b"*2\r\n$3\r\nGET\r\n$4\r\na\r\nb\r\n*1\r\n$4\r\nPI";
+    let (args, used) = parse_command(input).unwrap().unwrap();
+    assert_eq!(args, vec![b"GET".to_vec(), b"a\r\nb".to_vec()]);
+    // Pipelined commands are parsed one at a time; a partial one waits for more input.
+    assert_eq!(parse_command(&input[used..]), Ok(None));
+    assert_eq!(parse_command(b"*2\r\n$3\r\nGET"), Ok(None));
+
+    let (args, used) = parse_command(b"set key \"a b\\x41\\n\" 'it\\'s'\r\nPING\r\n").unwrap().unwrap();
+    assert_eq!(args, vec![b"set".to_vec(), b"key".to_vec(), b"a bA\n".to_vec(), b"it's".to_vec()]);
+    assert_eq!(used, 29);
+    assert_eq!(parse_command(b"\r\n"), Ok(Some((Vec::new(), 2))));
+
+    let err = parse_command(b"set \"unterminated\r\n").unwrap_err();
+    assert_eq!(err, "Protocol error: unbalanced quotes in request");
+    let err = parse_command(b"*1\r\n:3\r\n").unwrap_err();
+    assert_eq!(err, "Protocol error: expected '$', got ':'");
+    assert!(parse_command(b"*x\r\n").is_err());
+}
+
+#[test]
+fn test_commands_arriving_in_pieces_are_parsed_incrementally() {
+    let request = b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$5\r\nvalue\r\nPING\r\n";
+    let mut parser = RequestParser::default();
+    let mut input = BytesMut::new();
+    let mut commands = Vec::new();
+    // One byte at a time: parsed arguments are consumed, only the unparsed tail stays.
+    for byte in request {
+        input.extend_from_slice(&[*byte]);
+        while let Some(args) = parser.parse(&mut input).unwrap() {
+            commands.push(args);
+        }
+        assert!(input.len() <= 8, "{:?} left unparsed", input);
+    }
+    let set = vec![b"SET".to_vec(), b"k".to_vec(), b"value".to_vec()];
+    assert_eq!(commands, vec![set, vec![b"PING".to_vec()]]);
+    assert!(input.is_empty());
+}
+
+#[test]
+fn test_replies_are_encoded_for_the_protocol() {
+    let map = Reply::Map(vec![(Reply::Bulk(b"f".to_vec()), Reply::Double(1.5))]);
+    assert_eq!(encode(&map, Protocol::Resp2), "*2\r\n$1\r\nf\r\n$3\r\n1.5\r\n");
+    assert_eq!(encode(&map, Protocol::Resp3), "%1\r\n$1\r\nf\r\n,1.5\r\n");
+
+    let set = Reply::Set(vec![Reply::Integer(7), Reply::Boolean(true)]);
+    assert_eq!(encode(&set, Protocol::Resp2), "*2\r\n:7\r\n:1\r\n");
+    assert_eq!(encode(&set, Protocol::Resp3), "~2\r\n:7\r\n#t\r\n");
+
+    assert_eq!(encode(&Reply::Null, Protocol::Resp2), "$-1\r\n");
+    assert_eq!(encode(&Reply::NullArray, Protocol::Resp2), "*-1\r\n");
+    assert_eq!(encode(&Reply::NullArray, Protocol::Resp3), "_\r\n");
+    assert_eq!(encode(&Reply::Error("ERR oops".into()), Protocol::Resp3), "-ERR oops\r\n");
+}
+
+#[tokio::test]
+async fn test_pipelined_commands_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(resp::serve(listener));
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    // Several commands in one write get their replies in order.
+    let request = "*3\r\n$3\r\nSET\r\n$4\r\nresp\r\n$1\r\n1\r\nINCR resp\r\nGET resp\r\nGET missing\r\n";
+    round_trip(&mut stream, request, "+OK\r\n:2\r\n$1\r\n2\r\n$-1\r\n").await;
+    round_trip(&mut stream, "LPUSH resp 1\r\n", "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n").await;
+    round_trip(&mut stream, "NOSUCH a\r\n", "-ERR unknown command 'NOSUCH', with args beginning with: 'a' \r\n").await;
+
+    // HELLO 3 switches the connection to RESP3.
+    stream.write_all(b"HELLO 3\r\n").await.unwrap();
+    let mut buf = [0; 4096];
+    let read = stream.read(&mut buf).await.unwrap();
+    assert!(buf[..read].starts_with(b"%7\r\n$6\r\nserver\r\n$7\r\nrediodb\r\n"));
+    let hash = "HSET h f 1.5\r\nHGETALL h\r\nZADD z 2 m\r\nZSCORE z m\r\nGET missing\r\n";
+    round_trip(&mut stream, hash, ":1\r\n%1\r\n$1\r\nf\r\n$3\r\n1.5\r\n:1\r\n,2\r\n_\r\n").await;
+
+    // Timeouts too long to wait for are refused rather than overflowing the deadline.
+    let out_of_range = "-ERR timeout is out of range\r\n";
+    round_trip(&mut stream, "BLPOP queue 1e20\r\n", out_of_range).await;
+    round_trip(&mut stream, "BLPOP queue 1e19\r\n", out_of_range).await;
+
+    // A blocked BRPOP is served by a push from another connection.
+    let mut other = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"BRPOP queue 5\r\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    round_trip(&mut other, "RPUSH queue job\r\n", ":1\r\n").await;
+    round_trip(&mut stream, "", "*2\r\n$5\r\nqueue\r\n$3\r\njob\r\n").await;
+
+    round_trip(&mut stream, "QUIT\r\n", "+OK\r\n").await;
+    assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_stream_reads_groups_and_publish_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(resp::serve(listener));
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+
+    // A consumer group hands out an entry once, and XACK clears it from the pending list.
+    round_trip(&mut stream, "XADD events 1-1 kind signup\r\n", "$3\r\n1-1\r\n").await;
+    round_trip(&mut stream, "XGROUP CREATE events workers 0\r\n", "+OK\r\n").await;
+    let signup = "*1\r\n*2\r\n$6\r\nevents\r\n*1\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$4\r\nkind\r\n$6\r\nsignup\r\n";
+    round_trip(&mut stream, "XREADGROUP GROUP workers alice STREAMS events >\r\n", signup).await;
+    round_trip(&mut stream, "XREADGROUP GROUP workers bob STREAMS events >\r\n", "*-1\r\n").await;
+    round_trip(&mut stream, "XACK events workers 1-1\r\n", ":1\r\n").await;
+    round_trip(&mut stream, "XGROUP DESTROY events workers\r\n", ":1\r\n").await;
+
+    // XREAD BLOCK is served by an XADD from another connection.
+    round_trip(&mut stream, "XREAD STREAMS events 1-1\r\n", "*-1\r\n").await;
+    let mut other = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"XREAD BLOCK 5000 STREAMS events $\r\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    round_trip(&mut other, "XADD events 2-1 kind login\r\n", "$3\r\n2-1\r\n").await;
+    let login = "*1\r\n*2\r\n$6\r\nevents\r\n*1\r\n*2\r\n$3\r\n2-1\r\n*2\r\n$4\r\nkind\r\n$5\r\nlogin\r\n";
+    round_trip(&mut stream, "", login).await;
+
+    let unbalanced = "-ERR Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.\r\n";
+    round_trip(&mut stream, "XREAD STREAMS events\r\n", unbalanced).await;
+    round_trip(&mut stream, "PUBLISH news hello\r\n", ":0\r\n").await;
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::env;
+use std::time::{Duration, SystemTime};
+
+use rediodb::storage::codec::{self, Decoder};
+use rediodb::storage::engine::EngineKind;
+use rediodb::storage::rocksdb_store::{
+    add_element, drop_expired, elements, header, split_deadline, with_deadline, Compression,
+    RocksDBConfig, DEFAULT_BLOCK_CACHE,
+};
+use rediodb::storage::sorted_set::ZAddOptions;
+use rediodb::storage::ttl_store::TTLStore;
+use rocksdb::CompactionDecision;
+use common::strings;
+
+#[test]
+fn test_containers_round_trip_through_elements() {
+    let mut store = TTLStore::new();
+    store.r_push(b"list", &strings(&["a", "b", "a"])).unwrap();
+    store.s_add(b"set", &strings(&["x", "y"])).unwrap();
+    store.h_set(b"hash", &[(b"f".to_vec(), b"1".to_vec()), (b"g".to_vec(), b"2".to_vec())]).unwrap();
+    store.h_expire(b"hash", &strings(&["g"]), Duration::from_secs(50)).unwrap();
+    let members = [(1.5, b"one".to_vec()), (-2.0, b"two".to_vec())];
+    store.z_add(b"zset", &members, ZAddOptions::default()).unwrap();
+
+    // Store each container as a metadata record plus one entry per element, then rebuild it.
+    let mut copy = TTLStore::new();
+    for key in [&b"list"[..], b"set", b"hash", b"zset"] {
+        let (value, expire_at) = store.read_record(key).unwrap().unwrap();
+        let mut meta = Vec::new();
+        codec::encode_value(&header(&value), &mut meta);
+        let mut rebuilt = codec::decode_value(&mut Decoder::new(&meta)).unwrap();
+        for (element, payload) in elements(&value) {
+            add_element(&mut rebuilt, &element, &payload).unwrap();
+        }
+        copy.write_record(key, &rebuilt, expire_at).unwrap();
+    }
+
+    assert_eq!(copy.l_range(b"list", 0, -1), Ok(strings(&["a", "b", "a"])));
+    assert_eq!(copy.s_card(b"set"), Ok(2));
+    assert_eq!(copy.h_get(b"hash", b"f"), Ok(Some(b"1".to_vec())));
+    assert_eq!(copy.h_ttl(b"hash", &strings(&["f"])).unwrap()[0], -1);
+    assert!(copy.h_ttl(b"hash", &strings(&["g"])).unwrap()[0] > 40);
+    assert_eq!(copy.z_score(b"zset", b"two"), Ok(Some(-2.0)));
+
+    // A string has no elements, and elements never belong to one.
+    store.set(b"str", b"v", None).unwrap();
+    let (mut value, _) = store.read_record(b"str").unwrap().unwrap();
+    assert!(elements(&value).is_empty());
+    assert!(add_element(&mut value, b"e", b"").is_err());
+}
+
+#[test]
+fn test_compaction_filter_drops_expired_entries() {
+    let past = SystemTime::now() - Duration::from_secs(1);
+    let future = SystemTime::now() + Duration::from_secs(100);
+    let expired = with_deadline(Some(past), b"payload");
+    assert!(matches!(drop_expired(0, b"k", &expired), CompactionDecision::Remove));
+    assert!(matches!(
+        drop_expired(0, b"k", &with_deadline(Some(future), b"payload")),
+        CompactionDecision::Keep
+    ));
+    assert!(matches!(
+        drop_expired(0, b"k", &with_deadline(None, b"payload")),
+        CompactionDecision::Keep
+    ));
+
+    // Deadlines round trip to the millisecond, and unreadable values are kept.
+    let record = with_deadline(Some(future), b"p");
+    let (at, payload) = split_deadline(&record).unwrap();
+    assert!(future.duration_since(at.unwrap()).unwrap() < Duration::from_millis(1));
+    assert_eq!(payload, b"p");
+    assert!(split_deadline(b"short").is_err());
+    assert!(matches!(drop_expired(0, b"k", b"short"), CompactionDecision::Keep));
+}
+
+#[test]
+fn test_rocksdb_config_from_env() {
+    env::set_var("REDIO_STORAGE", "rocksdb");
+    env::set_var("REDIO_DATA_DIR", "/tmp/redio-data");
+    let config = match EngineKind::from_env().unwrap() {
+        EngineKind::RocksDb(config) => config,
+        other => panic!("unexpected engine {:?}", other),
+    };
+    assert_eq!(config, RocksDBConfig::new("/tmp/redio-data"));
+    assert_eq!(config.block_cache, DEFAULT_BLOCK_CACHE);
+
+    env::set_var("REDIO_ROCKSDB_BLOCK_CACHE", "256mb");
+    env::set_var("REDIO_ROCKSDB_COMPRESSION", "ZSTD");
+    env::set_var("REDIO_ROCKSDB_SYNC", "yes");
+    let config = RocksDBConfig::from_env().unwrap();
+    assert_eq!(config.block_cache, 256 * 1024 * 1024);
+    assert_eq!(config.compression, Compression::Zstd);
+    assert!(config.sync_writes);
+
+    env::set_var("REDIO_ROCKSDB_COMPRESSION", "brotli");
+    assert!(RocksDBConfig::from_env().is_err());
+    env::set_var("REDIO_ROCKSDB_COMPRESSION", "none");
+    env::set_var("REDIO_ROCKSDB_SYNC", "maybe");
+    assert!(RocksDBConfig::from_env().is_err());
+}
@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use rediodb::storage::error::StoreError;
+use rediodb::storage::scan::{scan_window, ScanOptions};
+use rediodb::storage::ttl_store::TTLStore;
+
+fn count(count: usize) -> ScanOptions {
+    ScanOptions { count, ..ScanOptions::default() }
+}
+
+#[test]
+fn test_scan_survives_deletes() {
+    let mut store = TTLStore::new();
+    for i in 0..100 {
+        store.set(format!("key:{}", i).as_bytes(), b"v", None).unwrap();
+    }
+
+    // Delete a key after every page; every key that is never deleted must still be seen.
+    let mut seen = HashSet::new();
+    let mut deleted = HashSet::new();
+    let mut cursor = 0;
+    let mut pages = 0;
+    loop {
+        let page = store.scan(cursor, &count(7), None);
+        seen.extend(page.items);
+        let victim = format!("key:{}", pages * 3).into_bytes();
+        store.del(&victim);
+        deleted.insert(victim);
+        store.set(format!("new:{}", pages).as_bytes(), b"v", None).unwrap();
+        pages += 1;
+        cursor = page.cursor;
+        if cursor == 0 {
+            break;
+        }
+    }
+    assert!(pages <= 100 / 7 + 1);
+    for i in 0..100 {
+        let key = format!("key:{}", i).into_bytes();
+        assert!(deleted.contains(&key) || seen.contains(&key), "{:?} was missed", key);
+    }
+
+    assert_eq!(scan_window(5, 0, 10), (0..5, 0));
+    assert_eq!(scan_window(50, 30, 10), (20..30, 20));
+    assert_eq!(scan_window(5, 30, 10), (0..5, 0));
+}
+
+#[test]
+fn test_scan_filters() {
+    let mut store = TTLStore::new();
+    store.set(b"user:1", b"a", None).unwrap();
+    store.set(b"user:2", b"b", Some(Duration::from_millis(10))).unwrap();
+    store.r_push(b"user:list", &[b"x".to_vec()]).unwrap();
+    store.s_add(b"other", &[b"m".to_vec()]).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+
+    let opts = ScanOptions { pattern: Some(b"user:*".to_vec()), count: 100 };
+    let mut keys = store.scan(0, &opts, None).items;
+    keys.sort();
+    assert_eq!(keys, [b"user:1".to_vec(), b"user:list".to_vec()]);
+    // The expired key was reclaimed while scanning.
+    assert_eq!(store.len(), 3);
+
+    let page = store.scan(0, &count(100), Some("list"));
+    assert_eq!(page.items, [b"user:list".to_vec()]);
+    assert_eq!(page.cursor, 0);
+    assert!(store.scan(0, &count(100), Some("zset")).items.is_empty());
+}
+
+#[test]
+fn test_member_scans() {
+    let mut store = TTLStore::new();
+    let members: Vec<Vec<u8>> = (0..25).map(|i| format!("m{}", i).into_bytes()).collect();
+    store.s_add(b"set", &members).unwrap();
+    let fields: Vec<(Vec<u8>, Vec<u8>)> = members.iter().map(|m| (m.clone(), b"v".into())).collect();
+    store.h_set(b"hash", &fields).unwrap();
+    let scored: Vec<(f64, Vec<u8>)> = members.iter().map(|m| (1.0, m.clone())).collect();
+    store.z_add(b"zset", &scored, Default::default()).unwrap();
+
+    let mut cursor = 0;
+    let mut seen = Vec::new();
+    loop {
+        let page = store.s_scan(b"set", cursor, &count(10)).unwrap();
+        seen.extend(page.items);
+        cursor = page.cursor;
+        if cursor == 0 {
+            break;
+        }
+    }
+    seen.sort();
+    let mut expected = members.clone();
+    expected.sort();
+    assert_eq!(seen, expected);
+
+    let opts = ScanOptions { pattern: Some(b"m2*".to_vec()), count: 100 };
+    let page = store.h_scan(b"hash", 0, &opts).unwrap();
+    assert_eq!(page.items.len(), 6);
+    let page = store.z_scan(b"zset", 0, &opts).unwrap();
+    assert!(page.items.iter().all(|(member, score)| member.starts_with(b"m2") && *score == 1.0));
+    assert_eq!(page.cursor, 0);
+
+    assert_eq!(store.z_scan(b"missing", 0, &count(10)).unwrap().items, Vec::new());
+    assert_eq!(store.s_scan(b"hash", 0, &count(10)), Err(StoreError::WrongType));
+}
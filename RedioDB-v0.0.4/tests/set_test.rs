@@ -0,0 +1,90 @@
+mod common;
+
+use rediodb::storage::error::StoreError;
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::ttl_store::{TTLStore, MAX_RANDOM_REPEATS};
+use common::strings;
+
+fn sorted(mut members: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    members.sort();
+    members
+}
+
+#[test]
+fn test_membership_and_removal() {
+    let mut store = TTLStore::new();
+    assert_eq!(store.s_add(b"s", &strings(&["a", "b", "a"])), Ok(2));
+    assert_eq!(store.s_add(b"s", &strings(&["b", "c"])), Ok(1));
+    assert_eq!(store.s_card(b"s"), Ok(3));
+    assert_eq!(store.s_is_member(b"s", b"c"), Ok(true));
+    assert_eq!(store.s_mis_member(b"s", &strings(&["a", "x"])), Ok(vec![true, false]));
+
+    assert_eq!(store.s_rem(b"s", &strings(&["a", "x"])), Ok(1));
+    assert_eq!(store.s_move(b"s", b"t", b"b"), Ok(true));
+    assert_eq!(store.s_move(b"s", b"t", b"b"), Ok(false));
+    assert_eq!(store.s_members(b"t"), Ok(strings(&["b"])));
+
+    // Emptied sets are removed.
+    store.s_rem(b"s", &strings(&["c"])).unwrap();
+    store.s_rem(b"t", &strings(&["b"])).unwrap();
+    assert_eq!(store.keys(b"*"), Vec::<Vec<u8>>::new());
+    assert_eq!(store.used_memory(), 0);
+
+    store.set(b"str", b"value", None).unwrap();
+    assert_eq!(store.s_add(b"str", &strings(&["x"])), Err(StoreError::WrongType));
+    assert_eq!(store.s_move(b"str", b"t", b"x"), Err(StoreError::WrongType));
+    assert_eq!(store.s_is_member(b"str", b"x"), Err(StoreError::WrongType));
+}
+
+#[test]
+fn test_pop_and_random_members() {
+    let mut store = TTLStore::new();
+    store.s_add(b"s", &strings(&["1", "2", "3", "4"])).unwrap();
+
+    let distinct = store.s_rand_member(b"s", 10).unwrap();
+    assert_eq!(sorted(distinct), strings(&["1", "2", "3", "4"]));
+    let repeated = store.s_rand_member(b"s", -10).unwrap();
+    assert_eq!(repeated.len(), 10);
+    assert!(repeated.iter().all(|member| store.s_is_member(b"s", member) == Ok(true)));
+    let out_of_range = Err(StoreError::InvalidArgument("value is out of range".into()));
+    assert_eq!(store.s_rand_member(b"s", i64::MIN), out_of_range);
+    assert_eq!(store.s_rand_member(b"s", -(MAX_RANDOM_REPEATS as i64) - 1), out_of_range);
+
+    let popped = store.s_pop(b"s", 3).unwrap();
+    assert_eq!(popped.len(), 3);
+    assert!(popped.iter().all(|member| store.s_is_member(b"s", member) == Ok(false)));
+    assert_eq!(store.s_pop(b"s", 3).map(|popped| popped.len()), Ok(1));
+    assert_eq!(store.s_card(b"s"), Ok(0));
+    assert_eq!(store.s_rand_member(b"s", 1), Ok(Vec::new()));
+}
+
+#[test]
+fn test_algebra_and_store_variants() {
+    let mut store = TTLStore::new();
+    store.s_add(b"a", &strings(&["1", "2", "3", "4"])).unwrap();
+    store.s_add(b"b", &strings(&["2", "3", "5"])).unwrap();
+    store.s_add(b"c", &strings(&["3", "4", "5"])).unwrap();
+    let keys = |names: &[&str]| strings(names);
+
+    assert_eq!(store.s_inter(&keys(&["a", "b", "c"])).map(sorted), Ok(strings(&["3"])));
+    assert_eq!(store.s_inter(&keys(&["a", "missing"])), Ok(Vec::new()));
+    assert_eq!(
+        store.s_union(&keys(&["a", "b", "missing"])).map(sorted),
+        Ok(strings(&["1", "2", "3", "4", "5"]))
+    );
+    assert_eq!(store.s_diff(&keys(&["a", "b", "c"])).map(sorted), Ok(strings(&["1"])));
+    assert_eq!(store.s_inter_card(&keys(&["a", "b"]), 0), Ok(2));
+    assert_eq!(store.s_inter_card(&keys(&["a", "b"]), 1), Ok(1));
+
+    assert_eq!(store.s_union_store(b"u", &keys(&["b", "c"])), Ok(4));
+    assert_eq!(store.s_members(b"u").map(sorted), Ok(strings(&["2", "3", "4", "5"])));
+    // A source may also be the destination.
+    assert_eq!(store.s_diff_store(b"u", &keys(&["u", "a"])), Ok(1));
+    assert_eq!(store.s_members(b"u"), Ok(strings(&["5"])));
+    assert_eq!(store.s_inter_store(b"u", &keys(&["a", "missing"])), Ok(0));
+    assert_eq!(store.s_card(b"u"), Ok(0));
+
+    store.set(b"str", b"value", None).unwrap();
+    assert_eq!(store.s_union(&keys(&["a", "str"])), Err(StoreError::WrongType));
+    assert_eq!(store.s_inter_store(b"u", &keys(&["a", "str"])), Err(StoreError::WrongType));
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use rediodb::storage::blocking::{self, BlockedOp};
+use rediodb::storage::error::StoreError;
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::scan::ScanOptions;
+use rediodb::storage::sharded::{hash_tag, ShardedStore};
+use common::strings;
+
+lazy_static! {
+    static ref BLOCKING_STORE: ShardedStore = ShardedStore::new(8);
+}
+
+/// The first key named `prefix:<n>` that does not live in the shard of `other`.
+fn key_in_other_shard(store: &ShardedStore, prefix: &str, other: &[u8]) -> Vec<u8> {
+    (0..)
+        .map(|i| format!("{}:{}", prefix, i).into_bytes())
+        .find(|key| store.shard_of(key) != store.shard_of(other))
+        .unwrap()
+}
+
+#[test]
+fn test_multi_key_commands_span_shards() {
+    assert_eq!(hash_tag(b"{user:1}:name"), b"user:1");
+    assert_eq!(hash_tag(b"{}:name"), b"{}:name");
+    assert_eq!(hash_tag(b"plain"), b"plain");
+
+    let store = ShardedStore::new(8);
+    assert_eq!(
+        store.shard_of(b"{user:1}:name"),
+        store.shard_of(b"{user:1}:email")
+    );
+    let a = b"set:a".to_vec();
+    let b = key_in_other_shard(&store, "set", &a);
+
+    store.lock_key(&a).s_add(&a, &strings(&["x", "y"])).unwrap();
+    store.lock_key(&b).s_add(&b, &strings(&["y", "z"])).unwrap();
+    store.lock_key(&a).expire(&a, Duration::from_secs(100));
+    let stored = store
+        .lock_keys([&a, &b, &b"out".to_vec()])
+        .s_inter_store(b"out", &[a.clone(), b.clone()]);
+    assert_eq!(stored, Ok(1));
+
+    // Keys go back to their own shards with their TTLs once the command is done.
+    assert!(store.lock_key(&a).ttl(&a).unwrap() > 0);
+    assert_eq!(store.lock_key(&b).s_card(&b), Ok(2));
+    assert_eq!(
+        store.lock_key(b"out").s_members(b"out"),
+        Ok(strings(&["y"]))
+    );
+
+    // Commands run in place, so the keys they read keep their place in their shards.
+    for i in 0..20 {
+        let key = format!("fill:{}", i).into_bytes();
+        store.lock_key(&key).set(&key, b"v", None).unwrap();
+    }
+    let order = |key: &[u8]| store.lock_key(key).keys(b"*");
+    let before = (order(&a), order(&b));
+    store.lock_keys([&a, &b]).m_get(&[a.clone(), b.clone()]);
+    assert_eq!((order(&a), order(&b)), before);
+
+    store.lock_keys([&a, &b]).rename(&a, &b).unwrap();
+    assert_eq!(store.lock_key(&a).key_type(&a), "none");
+    assert!(store.lock_key(&b).ttl(&b).unwrap() > 0);
+    assert_eq!(store.len(), 22);
+}
+
+#[test]
+fn test_keys_and_scan_cover_every_shard() {
+    let store = ShardedStore::new(8);
+    for i in 0..500 {
+        let key = format!("key:{}", i).into_bytes();
+        store.lock_key(&key).set(&key, b"v", None).unwrap();
+    }
+    assert!(store
+        .shards()
+        .iter()
+        .all(|shard| !shard.lock().unwrap().is_empty()));
+    assert_eq!(store.len(), 500);
+    assert_eq!(store.keys(b"key:1*").unwrap().len(), 111);
+
+    let opts = ScanOptions { count: 20, ..ScanOptions::default() };
+    let mut seen = HashSet::new();
+    let mut cursor = 0;
+    loop {
+        let page = store.scan(cursor, &opts, None).unwrap();
+        seen.extend(page.items);
+        cursor = page.cursor;
+        if cursor == 0 {
+            break;
+        }
+    }
+    assert_eq!(seen.len(), 500);
+
+    store.flush(false).unwrap();
+    assert!(store.is_empty());
+}
+
+#[test]
+fn test_concurrent_multi_key_commands() {
+    let store = Arc::new(ShardedStore::new(4));
+    let lists: Vec<Vec<u8>> = (0..8).map(|i| format!("list:{}", i).into_bytes()).collect();
+    let elements: Vec<Vec<u8>> = (0..100).map(|i| i.to_string().into_bytes()).collect();
+    store
+        .lock_key(&lists[0])
+        .r_push(&lists[0], &elements)
+        .unwrap();
+
+    // Threads shuffle elements between lists in every direction while counting; shards
+    // are always locked in the same order, so this neither deadlocks nor loses anything.
+    let workers: Vec<_> = (0..8)
+        .map(|worker| {
+            let (store, lists) = (store.clone(), lists.clone());
+            thread::spawn(move || {
+                for round in 0..500 {
+                    let source = &lists[(worker + round) % lists.len()];
+                    let destination = &lists[(worker * 3 + round * 7 + 1) % lists.len()];
+                    let _ = store.lock_keys([source, destination]).l_move(
+                        source,
+                        destination,
+                        true,
+                        false,
+                    );
+                    store.lock_key(b"moves").incr(b"moves", 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    assert_eq!(
+        store.lock_key(b"moves").get(b"moves"),
+        Ok(Some(b"4000".to_vec()))
+    );
+    let mut remaining: Vec<Vec<u8>> = lists
+        .iter()
+        .flat_map(|list| store.lock_key(list).l_range(list, 0, -1).unwrap())
+        .collect();
+    remaining.sort();
+    let mut expected = elements.clone();
+    expected.sort();
+    assert_eq!(remaining, expected);
+}
+
+#[tokio::test]
+async fn test_blocking_pops_across_shards() {
+    let store: &'static ShardedStore = &BLOCKING_STORE;
+    let first = b"queue:a".to_vec();
+    let second = key_in_other_shard(store, "queue", &first);
+    let keys = vec![first.clone(), second.clone()];
+    let waiter = tokio::spawn(blocking::blocking_pop(
+        store,
+        keys,
+        BlockedOp::Pop { front: true },
+        None,
+    ));
+    while store.blocked_clients() != 1 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    store
+        .lock_key(&second)
+        .r_push(&second, &strings(&["job"]))
+        .unwrap();
+    assert_eq!(
+        waiter.await.unwrap(),
+        Ok(Some((second.clone(), b"job".to_vec())))
+    );
+    // The client left the other shard too, so a later push stays in its list.
+    store
+        .lock_key(&first)
+        .r_push(&first, &strings(&["later"]))
+        .unwrap();
+    assert_eq!(store.lock_key(&first).l_len(&first), Ok(1));
+
+    // BLMOVE into another shard completes the move once it is served.
+    let destination = key_in_other_shard(store, "done", &first);
+    let op = BlockedOp::Move {
+        from_front: true,
+        destination: destination.clone(),
+        to_front: false,
+    };
+    let timeout = Some(Duration::from_secs(1));
+    let moved = blocking::blocking_pop(store, vec![first.clone()], op.clone(), timeout).await;
+    assert_eq!(moved, Ok(Some((first.clone(), b"later".to_vec()))));
+    assert_eq!(
+        store.lock_key(&destination).l_range(&destination, 0, -1),
+        Ok(strings(&["later"]))
+    );
+    assert_eq!(store.blocked_clients(), 0);
+
+    // A blocked BLMOVE into another shard moves the element once a push wakes it, and a
+    // destination of another type fails the call without touching the source.
+    let blocked_move = |op: BlockedOp| {
+        tokio::spawn(blocking::blocking_pop(store, vec![first.clone()], op, None))
+    };
+    let mover = blocked_move(op.clone());
+    while store.blocked_clients() != 1 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    store.lock_key(&first).r_push(&first, &strings(&["next"])).unwrap();
+    assert_eq!(mover.await.unwrap(), Ok(Some((first.clone(), b"next".to_vec()))));
+    assert_eq!(
+        store.lock_key(&destination).l_range(&destination, 0, -1),
+        Ok(strings(&["later", "next"]))
+    );
+
+    let wrong = key_in_other_shard(store, "wrong", &first);
+    store.lock_key(&wrong).set(&wrong, b"string", None).unwrap();
+    let mover = blocked_move(BlockedOp::Move {
+        from_front: true,
+        destination: wrong.clone(),
+        to_front: false,
+    });
+    while store.blocked_clients() != 1 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    store.lock_key(&first).r_push(&first, &strings(&["kept"])).unwrap();
+    assert_eq!(mover.await.unwrap(), Err(StoreError::WrongType));
+    assert_eq!(store.lock_key(&first).l_range(&first, 0, -1), Ok(strings(&["kept"])));
+}
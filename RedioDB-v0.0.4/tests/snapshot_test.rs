@@ -0,0 +1,166 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use rediodb::storage::databases::{DatabaseConfig, Databases};
+use rediodb::storage::sharded::ShardedStore;
+use rediodb::storage::snapshot::{
+    self, parse_save_points, SavePoint, SnapshotConfig, Snapshotter, SNAPSHOT_MAGIC,
+};
+use rediodb::storage::sorted_set::ZAddOptions;
+use rediodb::storage::ttl_store::StoreValue;
+use common::strings;
+
+/// A snapshot path in a fresh directory named after the test.
+fn snapshot_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rediodb-snap-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    dir.join("dump.rdio")
+}
+
+fn databases() -> Databases {
+    Databases::new(DatabaseConfig { count: 2, names: Vec::new(), shards: 4 })
+}
+
+#[test]
+fn test_snapshot_round_trips_every_database() {
+    let path = snapshot_path("round-trip");
+    let dbs = databases();
+    let db = dbs.get(0);
+    db.lock_key(b"a").set(b"a", b"1", None).unwrap();
+    db.lock_key(b"session").set(b"session", b"s", Some(Duration::from_secs(100))).unwrap();
+    db.lock_key(b"list").r_push(b"list", &strings(&["x", "y"])).unwrap();
+    let members = [(1.5, b"one".to_vec())];
+    db.lock_key(b"zset").z_add(b"zset", &members, ZAddOptions::default()).unwrap();
+    dbs.get(1).lock_key(b"a").set(b"a", b"other", None).unwrap();
+
+    let snapshotter = Snapshotter::new(SnapshotConfig::new(&path), &dbs);
+    let before = SystemTime::now() - Duration::from_secs(1);
+    snapshotter.save(&dbs).unwrap();
+    assert!(snapshotter.last_save() >= before);
+    assert!(!snapshotter.is_saving());
+
+    let copy = databases();
+    let info = snapshot::load(&path, &copy).unwrap().unwrap();
+    assert_eq!(info.keys, 5);
+    // Times are stored to the millisecond.
+    let created = snapshotter.last_save().duration_since(info.created).unwrap();
+    assert!(created < Duration::from_millis(1));
+    let db = copy.get(0);
+    assert_eq!(db.len(), 4);
+    assert_eq!(db.lock_key(b"a").get(b"a"), Ok(Some(b"1".to_vec())));
+    assert!(db.lock_key(b"session").ttl(b"session").unwrap() > 90);
+    assert_eq!(db.lock_key(b"list").l_range(b"list", 0, -1), Ok(strings(&["x", "y"])));
+    assert_eq!(db.lock_key(b"zset").z_score(b"zset", b"one"), Ok(Some(1.5)));
+    assert_eq!(copy.get(1).lock_key(b"a").get(b"a"), Ok(Some(b"other".to_vec())));
+
+    // Keys of databases the loading server does not have are skipped.
+    let single = Databases::new(DatabaseConfig { count: 1, names: Vec::new(), shards: 4 });
+    assert_eq!(snapshot::load(&path, &single).unwrap().unwrap().keys, 5);
+    assert_eq!(single.get(0).len(), 4);
+}
+
+#[test]
+fn test_damaged_snapshots_are_rejected() {
+    let path = snapshot_path("damaged");
+    assert_eq!(snapshot::load(&path, &databases()), Ok(None));
+
+    let dbs = databases();
+    dbs.get(0).lock_key(b"key").set(b"key", b"value", None).unwrap();
+    Snapshotter::new(SnapshotConfig::new(&path), &dbs).save(&dbs).unwrap();
+    let bytes = fs::read(&path).unwrap();
+    assert!(bytes.starts_with(SNAPSHOT_MAGIC));
+
+    // A flipped bit anywhere fails the checksum.
+    let mut damaged = bytes.clone();
+    damaged[bytes.len() - 8] ^= 0x01;
+    fs::write(&path, &damaged).unwrap();
+    assert!(snapshot::load(&path, &databases()).is_err());
+
+    // So does a truncated file.
+    fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(snapshot::load(&path, &databases()).is_err());
+
+    // Versions from the future are refused rather than misread.
+    let mut future = bytes.clone();
+    future[SNAPSHOT_MAGIC.len()] = snapshot::SNAPSHOT_VERSION + 1;
+    fs::write(&path, &future).unwrap();
+    let err = snapshot::load(&path, &databases()).unwrap_err();
+    assert!(err.to_string().contains("unsupported snapshot version"));
+
+    fs::write(&path, b"not a snapshot").unwrap();
+    assert!(snapshot::load(&path, &databases()).is_err());
+}
+
+#[test]
+fn test_save_points_trigger_after_enough_changes() {
+    assert_eq!(
+        parse_save_points("3600 1 300 100"),
+        Ok(vec![
+            SavePoint { seconds: 3600, changes: 1 },
+            SavePoint { seconds: 300, changes: 100 },
+        ])
+    );
+    assert_eq!(parse_save_points(""), Ok(Vec::new()));
+    assert!(parse_save_points("60").is_err());
+    assert!(parse_save_points("60 x").is_err());
+
+    let path = snapshot_path("save-points");
+    let dbs = databases();
+    let config = SnapshotConfig {
+        save_points: vec![SavePoint { seconds: 0, changes: 2 }],
+        ..SnapshotConfig::new(&path)
+    };
+    let snapshotter = Snapshotter::new(config, &dbs);
+    assert!(!snapshotter.save_due(&dbs));
+    dbs.get(0).lock_key(b"a").set(b"a", b"1", None).unwrap();
+    assert!(!snapshotter.save_due(&dbs));
+    dbs.get(1).lock_key(b"b").set(b"b", b"1", None).unwrap();
+    assert!(snapshotter.save_due(&dbs));
+
+    // Saving resets the count.
+    snapshotter.save(&dbs).unwrap();
+    assert!(!snapshotter.save_due(&dbs));
+    dbs.get(0).lock_key(b"a").del(b"a");
+    dbs.get(0).lock_key(b"c").set(b"c", b"1", None).unwrap();
+    assert!(snapshotter.save_due(&dbs));
+
+    // Without save points, snapshots are only taken on request.
+    let config = SnapshotConfig { save_points: Vec::new(), ..SnapshotConfig::new(&path) };
+    let manual = Snapshotter::new(config, &dbs);
+    dbs.get(0).lock_key(b"d").set(b"d", b"1", None).unwrap();
+    assert!(!manual.save_due(&dbs));
+}
+
+#[test]
+fn test_frozen_keyspace_ignores_changes_made_while_copying() {
+    let store = ShardedStore::new(4);
+    store.write(b"kept", |shard| shard.set(b"kept", b"1", None)).unwrap();
+    store.write(b"changed", |shard| shard.r_push(b"changed", &strings(&["a"]))).unwrap();
+    store.write(b"removed", |shard| shard.set(b"removed", b"2", None)).unwrap();
+    assert!(store.freeze(&mut store.lock_all()));
+
+    // Commands keep running once the shards are frozen; the copy does not see them.
+    store.write(b"changed", |shard| shard.r_push(b"changed", &strings(&["b"]))).unwrap();
+    store.write(b"removed", |shard| Ok(shard.del(b"removed"))).unwrap();
+    store.write(b"added", |shard| shard.set(b"added", b"3", None)).unwrap();
+    let mut records = Vec::new();
+    store.take_frozen(&mut |key, record| records.push((key.to_vec(), record)));
+    records.sort_by(|a, b| a.0.cmp(&b.0));
+    let keys: Vec<_> = records.iter().map(|(key, _)| key.clone()).collect();
+    assert_eq!(keys, strings(&["changed", "kept", "removed"]));
+    assert!(matches!(&records[0].1 .0, StoreValue::List(list) if list.len() == 1));
+
+    // A flush during the copy hands the old keys over, and later copies see the present.
+    assert!(store.freeze(&mut store.lock_all()));
+    store.flush(false).unwrap();
+    let mut keys = Vec::new();
+    store.take_frozen(&mut |key, _| keys.push(key.to_vec()));
+    keys.sort();
+    assert_eq!(keys, strings(&["added", "changed", "kept"]));
+    let mut keys = Vec::new();
+    store.take_frozen(&mut |key, _| keys.push(key.to_vec()));
+    assert!(keys.is_empty());
+}
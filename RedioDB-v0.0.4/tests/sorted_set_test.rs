@@ -0,0 +1,82 @@
+use rediodb::storage::error::StoreError;
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::sorted_set::{Aggregate, LexBound, ScoreBound, ZAddOptions, ZRangeBy};
+use rediodb::storage::ttl_store::TTLStore;
+
+fn members(pairs: &[(f64, &str)]) -> Vec<(f64, Vec<u8>)> {
+    pairs.iter().map(|(s, m)| (*s, m.as_bytes().to_vec())).collect()
+}
+
+fn names(range: Result<Vec<(Vec<u8>, f64)>, StoreError>) -> Vec<String> {
+    range.unwrap().into_iter().map(|(m, _)| String::from_utf8(m).unwrap()).collect()
+}
+
+#[test]
+fn test_zadd_flags_and_ranks() {
+    let mut store = TTLStore::new();
+    let board = members(&[(10.0, "alice"), (20.0, "bob"), (15.0, "carol")]);
+    assert_eq!(store.z_add(b"board", &board, ZAddOptions::default()), Ok(3));
+
+    let nx = ZAddOptions { nx: true, ..Default::default() };
+    assert_eq!(store.z_add(b"board", &members(&[(99.0, "alice"), (5.0, "dave")]), nx), Ok(1));
+    assert_eq!(store.z_score(b"board", b"alice"), Ok(Some(10.0)));
+
+    let gt_ch = ZAddOptions { gt: true, ch: true, ..Default::default() };
+    let update = members(&[(12.0, "alice"), (1.0, "bob")]);
+    assert_eq!(store.z_add(b"board", &update, gt_ch), Ok(1));
+    assert_eq!(store.z_score(b"board", b"bob"), Ok(Some(20.0)));
+
+    assert_eq!(store.z_rank(b"board", b"dave", false), Ok(Some(0)));
+    assert_eq!(store.z_rank(b"board", b"bob", true), Ok(Some(0)));
+    assert_eq!(store.z_incr_by(b"board", 10.0, b"dave"), Ok(15.0));
+    assert_eq!(
+        names(store.z_range(b"board", &ZRangeBy::Index(0, -1), false, None)),
+        vec!["alice", "carol", "dave", "bob"]
+    );
+}
+
+#[test]
+fn test_zrange_by_score_and_lex() {
+    let mut store = TTLStore::new();
+    let scored = members(&[(1.0, "a"), (2.0, "b"), (3.0, "c"), (4.0, "d")]);
+    store.z_add(b"scores", &scored, ZAddOptions::default()).unwrap();
+
+    let by = ZRangeBy::Score(ScoreBound::Exclusive(1.0), ScoreBound::Inclusive(f64::INFINITY));
+    assert_eq!(names(store.z_range(b"scores", &by, false, None)), vec!["b", "c", "d"]);
+    assert_eq!(names(store.z_range(b"scores", &by, true, Some((1, Some(1))))), vec!["c"]);
+
+    let flat = members(&[(0.0, "apple"), (0.0, "banana"), (0.0, "cherry")]);
+    store.z_add(b"words", &flat, ZAddOptions::default()).unwrap();
+    let by = ZRangeBy::Lex(LexBound::Inclusive(b"b".into()), LexBound::Max);
+    assert_eq!(names(store.z_range(b"words", &by, false, None)), vec!["banana", "cherry"]);
+    assert_eq!(ScoreBound::parse("(2.5"), Ok(ScoreBound::Exclusive(2.5)));
+    assert!(LexBound::parse(b"b").is_err());
+}
+
+#[test]
+fn test_zpop_and_store_operations() {
+    let mut store = TTLStore::new();
+    store
+        .z_add(b"a", &members(&[(1.0, "x"), (2.0, "y")]), ZAddOptions::default())
+        .unwrap();
+    store
+        .z_add(b"b", &members(&[(10.0, "y"), (20.0, "z")]), ZAddOptions::default())
+        .unwrap();
+
+    let keys = vec![b"a".to_vec(), b"b".to_vec()];
+    assert_eq!(store.z_union_store(b"u", &keys, &[], Aggregate::Sum), Ok(3));
+    assert_eq!(store.z_score(b"u", b"y"), Ok(Some(12.0)));
+    assert_eq!(store.z_inter_store(b"i", &keys, &[2.0, 1.0], Aggregate::Max), Ok(1));
+    assert_eq!(store.z_score(b"i", b"y"), Ok(Some(10.0)));
+
+    assert_eq!(store.z_pop_max(b"u", 1), Ok(vec![(b"z".to_vec(), 20.0)]));
+    assert_eq!(store.z_pop_min(b"u", 5).map(|popped| popped.len()), Ok(2));
+    // Emptied sorted sets are removed.
+    assert_eq!(store.z_card(b"u"), Ok(0));
+    assert_eq!(store.keys(b"u"), Vec::<Vec<u8>>::new());
+
+    // Plain sets may be combined with sorted sets, other types may not.
+    store.set(b"str", b"value", None).unwrap();
+    let keys = vec![b"a".to_vec(), b"str".to_vec()];
+    assert_eq!(store.z_union_store(b"u", &keys, &[], Aggregate::Sum), Err(StoreError::WrongType));
+}
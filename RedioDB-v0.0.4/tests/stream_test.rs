@@ -0,0 +1,140 @@
+use rediodb::storage::error::StoreError;
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::stream::{
+    parse_range_end, parse_range_start, ClaimOptions, GroupReadId, StreamEntry, StreamId,
+    TrimSpec, TrimStrategy, XAddId,
+};
+use rediodb::storage::ttl_store::TTLStore;
+
+fn fields(pairs: &[(&str, &str)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    pairs.iter().map(|(f, v)| (f.as_bytes().to_vec(), v.as_bytes().to_vec())).collect()
+}
+
+fn add(store: &mut TTLStore, key: &[u8], id: &str, value: &str) -> StreamId {
+    let id = id.parse::<XAddId>().unwrap();
+    store
+        .x_add(key, id, fields(&[("v", value)]), false, None)
+        .unwrap()
+        .unwrap()
+}
+
+fn ids(entries: &[StreamEntry]) -> Vec<String> {
+    entries.iter().map(|(id, _)| id.to_string()).collect()
+}
+
+#[test]
+fn test_xadd_ids_ranges_and_trimming() {
+    let mut store = TTLStore::new();
+    assert_eq!(add(&mut store, b"log", "5-1", "a"), StreamId::new(5, 1));
+    assert_eq!(add(&mut store, b"log", "5-*", "b"), StreamId::new(5, 2));
+    assert_eq!(add(&mut store, b"log", "7", "c"), StreamId::new(7, 0));
+    assert!(matches!(
+        store.x_add(b"log", XAddId::Explicit(StreamId::new(6, 0)), fields(&[("v", "d")]), false, None),
+        Err(StoreError::InvalidArgument(_))
+    ));
+    let auto = add(&mut store, b"log", "*", "d");
+    assert!(auto > StreamId::new(7, 0));
+
+    let (start, end) = (parse_range_start("(5-1").unwrap(), parse_range_end("+").unwrap());
+    let range = store.x_range(b"log", start.unwrap(), end.unwrap(), Some(2), false).unwrap();
+    assert_eq!(ids(&range), vec!["5-2", "7-0"]);
+    let range = store.x_range(b"log", StreamId::MIN, StreamId::MAX, Some(1), true).unwrap();
+    assert_eq!(range, vec![(auto, fields(&[("v", "d")]))]);
+
+    let maxlen = TrimSpec { strategy: TrimStrategy::MaxLen(2), approximate: false, limit: 0 };
+    assert_eq!(store.x_trim(b"log", maxlen), Ok(2));
+    assert_eq!(store.x_len(b"log"), Ok(2));
+    // Trimming never lowers the last ID, so new IDs keep increasing.
+    assert!(store.x_add(b"log", XAddId::Explicit(StreamId::new(6, 0)), fields(&[("v", "e")]), false, None).is_err());
+
+    let nomk = store.x_add(b"missing", XAddId::Auto, fields(&[("v", "x")]), true, None);
+    assert_eq!(nomk, Ok(None));
+    assert_eq!(store.x_len(b"missing"), Ok(0));
+
+    store.set(b"str", b"value", None).unwrap();
+    let wrong = store.x_add(b"str", XAddId::Auto, fields(&[("v", "x")]), false, None);
+    assert_eq!(wrong, Err(StoreError::WrongType));
+    assert_eq!(store.x_len(b"str"), Err(StoreError::WrongType));
+}
+
+#[test]
+fn test_consumer_group_delivery_and_acks() {
+    let mut store = TTLStore::new();
+    let keys = vec![b"jobs".to_vec()];
+    assert!(matches!(store.x_group_create(b"jobs", "workers", None, false), Err(StoreError::InvalidArgument(_))));
+    assert_eq!(store.x_group_create(b"jobs", "workers", None, true), Ok(()));
+    assert_eq!(store.x_group_create(b"jobs", "workers", None, true), Err(StoreError::BusyGroup));
+
+    let first = add(&mut store, b"jobs", "1-0", "a");
+    let second = add(&mut store, b"jobs", "2-0", "b");
+    add(&mut store, b"jobs", "3-0", "c");
+
+    let read = store
+        .x_read_group("workers", "alice", &keys, &[GroupReadId::New], Some(2), false)
+        .unwrap();
+    assert_eq!(ids(&read[0].1), vec!["1-0", "2-0"]);
+    let read = store
+        .x_read_group("workers", "bob", &keys, &[GroupReadId::New], None, false)
+        .unwrap();
+    assert_eq!(ids(&read[0].1), vec!["3-0"]);
+
+    let summary = store.x_pending_summary(b"jobs", "workers").unwrap();
+    assert_eq!(summary.count, 3);
+    assert_eq!(summary.consumers, vec![("alice".to_string(), 2), ("bob".to_string(), 1)]);
+
+    assert_eq!(store.x_ack(b"jobs", "workers", &[first, first]), Ok(1));
+    // After a restart, alice replays only her own unacknowledged entries.
+    let replay = store
+        .x_read_group("workers", "alice", &keys, &[GroupReadId::After(StreamId::MIN)], None, false)
+        .unwrap();
+    assert_eq!(ids(&replay[0].1), vec![second.to_string()]);
+
+    assert_eq!(store.x_group_del_consumer(b"jobs", "workers", "bob"), Ok(1));
+    assert_eq!(store.x_pending_summary(b"jobs", "workers").unwrap().count, 1);
+    assert_eq!(
+        store.x_read_group("missing", "nobody", &keys, &[GroupReadId::New], None, false),
+        Err(StoreError::NoGroup)
+    );
+}
+
+#[test]
+fn test_claiming_idle_pending_entries() {
+    let mut store = TTLStore::new();
+    let keys = vec![b"jobs".to_vec()];
+    store.x_group_create(b"jobs", "workers", Some(StreamId::MIN), true).unwrap();
+    for i in 1..=3 {
+        add(&mut store, b"jobs", &format!("{}-0", i), "job");
+    }
+    store
+        .x_read_group("workers", "crashed", &keys, &[GroupReadId::New], None, false)
+        .unwrap();
+
+    // Entries are not idle long enough yet.
+    let claimed = store
+        .x_claim(b"jobs", "workers", "alice", 60_000, &[StreamId::new(1, 0)], ClaimOptions::default())
+        .unwrap();
+    assert!(claimed.is_empty());
+
+    let backdated = ClaimOptions { idle_ms: Some(120_000), ..ClaimOptions::default() };
+    let claimed = store
+        .x_claim(b"jobs", "workers", "alice", 0, &[StreamId::new(1, 0)], backdated)
+        .unwrap();
+    assert_eq!(ids(&claimed), vec!["1-0"]);
+    let pending = store
+        .x_pending_range(b"jobs", "workers", StreamId::MIN, StreamId::MAX, 10, Some("alice"), 60_000)
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].deliveries, 2);
+
+    // Entries deleted from the stream are dropped from the pending list by XAUTOCLAIM.
+    let maxlen = TrimSpec { strategy: TrimStrategy::MaxLen(1), approximate: false, limit: 0 };
+    store.x_trim(b"jobs", maxlen).unwrap();
+    let (next, claimed, deleted) = store
+        .x_auto_claim(b"jobs", "workers", "bob", 0, StreamId::MIN, 10, true)
+        .unwrap();
+    assert_eq!(next, StreamId::MIN);
+    assert_eq!(ids(&claimed), vec!["3-0"]);
+    assert!(claimed[0].1.is_empty());
+    assert_eq!(deleted, vec![StreamId::new(1, 0), StreamId::new(2, 0)]);
+    assert_eq!(store.x_pending_summary(b"jobs", "workers").unwrap().count, 1);
+}
@@ -0,0 +1,111 @@
+use std::time::{Duration, SystemTime};
+
+use rediodb::storage::error::StoreError;
+use rediodb::storage::multi_key::MultiKey;
+use rediodb::storage::string_value::{SetExpiry, SetOptions, SetOutcome};
+use rediodb::storage::ttl_store::TTLStore;
+
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(Vec<u8>, Vec<u8>)> {
+    pairs.iter().map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec())).collect()
+}
+
+fn outcome(written: bool, old_value: Option<&[u8]>) -> Result<SetOutcome, StoreError> {
+    Ok(SetOutcome { written, old_value: old_value.map(|v| v.to_vec()) })
+}
+
+#[test]
+fn test_conditional_set() {
+    let mut store = TTLStore::new();
+    let nx = SetOptions { nx: true, ..SetOptions::default() };
+    let xx = SetOptions { xx: true, ..SetOptions::default() };
+    let get = SetOptions { get: true, ..SetOptions::default() };
+
+    assert_eq!(store.set_with_options(b"lock", b"a", xx), outcome(false, None));
+    assert_eq!(store.set_with_options(b"lock", b"a", nx), outcome(true, None));
+    assert_eq!(store.set_with_options(b"lock", b"b", nx), outcome(false, None));
+    assert_eq!(store.set_with_options(b"lock", b"c", xx), outcome(true, None));
+    assert_eq!(store.set_with_options(b"lock", b"d", get), outcome(true, Some(b"c")));
+    let nx_get = SetOptions { nx: true, get: true, ..SetOptions::default() };
+    assert_eq!(store.set_with_options(b"lock", b"e", nx_get), outcome(false, Some(b"d")));
+    assert_eq!(store.get(b"lock"), Ok(Some(b"d".to_vec())));
+
+    assert!(SetOptions { nx: true, xx: true, ..SetOptions::default() }.validate().is_err());
+
+    // GET refuses to read another type; a plain SET overwrites it.
+    store.r_push(b"list", &[b"a".to_vec()]).unwrap();
+    assert_eq!(store.set_with_options(b"list", b"v", get), Err(StoreError::WrongType));
+    assert_eq!(store.l_len(b"list"), Ok(1));
+    assert_eq!(store.set_with_options(b"list", b"v", xx), outcome(true, None));
+    assert_eq!(store.get(b"list"), Ok(Some(b"v".to_vec())));
+}
+
+#[test]
+fn test_set_expiry() {
+    let mut store = TTLStore::new();
+    let px = SetOptions {
+        expiry: Some(SetExpiry::In(Duration::from_millis(20))),
+        ..SetOptions::default()
+    };
+    store.set_with_options(b"k", b"v1", px).unwrap();
+    let keep_ttl = SetOptions { keep_ttl: true, ..SetOptions::default() };
+    store.set_with_options(b"k", b"v2", keep_ttl).unwrap();
+    assert_eq!(store.ttl(b"k"), Some(0));
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(store.get(b"k"), Ok(None));
+
+    // An absolute time in the past removes the key at once.
+    let past = SetOptions {
+        expiry: Some(SetExpiry::At(SystemTime::now() - Duration::from_secs(1))),
+        ..SetOptions::default()
+    };
+    assert_eq!(store.set_with_options(b"k", b"v", past), outcome(true, None));
+    assert_eq!(store.get(b"k"), Ok(None));
+
+    let later = SetExpiry::At(SystemTime::now() + Duration::from_secs(100));
+    store.set(b"k", b"v", None).unwrap();
+    assert_eq!(store.get_ex(b"k", Some(later), false), Ok(Some(b"v".to_vec())));
+    assert!(store.ttl(b"k").unwrap() >= 98);
+    assert_eq!(store.get_ex(b"k", None, false), Ok(Some(b"v".to_vec())));
+    assert!(store.ttl(b"k").unwrap() >= 98);
+    assert_eq!(store.get_ex(b"k", None, true), Ok(Some(b"v".to_vec())));
+    assert_eq!(store.ttl(b"k"), Some(-1));
+    assert_eq!(store.get_ex(b"nope", Some(later), false), Ok(None));
+}
+
+#[test]
+fn test_batch_and_getdel() {
+    let mut store = TTLStore::new();
+    store.m_set(&pairs(&[("a", "1"), ("b", "2")])).unwrap();
+    assert_eq!(store.m_set_nx(&pairs(&[("b", "x"), ("c", "3")])), Ok(false));
+    assert_eq!(store.m_set_nx(&pairs(&[("c", "3"), ("d", "4")])), Ok(true));
+
+    store.s_add(b"set", &[b"m".to_vec()]).unwrap();
+    let keys: Vec<Vec<u8>> = ["a", "set", "nope", "d"].iter().map(|k| k.as_bytes().to_vec()).collect();
+    assert_eq!(
+        store.m_get(&keys),
+        vec![Some(b"1".to_vec()), None, None, Some(b"4".to_vec())]
+    );
+
+    assert_eq!(store.get_del(b"a"), Ok(Some(b"1".to_vec())));
+    assert_eq!(store.get_del(b"a"), Ok(None));
+    assert_eq!(store.get_del(b"set"), Err(StoreError::WrongType));
+    let mut remaining = store.keys(b"*");
+    remaining.sort();
+    assert_eq!(remaining, [b"b".to_vec(), b"c".to_vec(), b"d".to_vec(), b"set".to_vec()]);
+}
+
+#[test]
+fn test_binary_safe_keys_and_values() {
+    let mut store = TTLStore::new();
+    let key = b"img:\x00\xff".to_vec();
+    let value = vec![0x89, b'P', b'N', b'G', 0x00, 0xff, 0xfe, b'\n'];
+    store.set(&key, &value, None).unwrap();
+    assert_eq!(store.get(&key), Ok(Some(value.clone())));
+    assert_eq!(store.append(&key, &[0x00]), Ok([value.as_slice(), &[0x00]].concat()));
+    assert_eq!(store.keys(b"img:*"), vec![key.clone()]);
+
+    // Invalid UTF-8 is data, not an error, until it is used as a number.
+    store.set(b"n", &[0xff], None).unwrap();
+    assert_eq!(store.incr(b"n", 1), Err(StoreError::NotInteger));
+    assert_eq!(store.incr_by_float(b"n", 1.0), Err(StoreError::NotFloat));
+}
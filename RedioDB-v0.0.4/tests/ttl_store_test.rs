@@ -0,0 +1,136 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use rediodb::storage::error::StoreError;
+use rediodb::storage::eviction::{EvictionPolicy, MemoryConfig};
+use rediodb::storage::expiry::{self, ActiveExpireConfig};
+use rediodb::storage::ttl_store::TTLStore;
+
+lazy_static! {
+    static ref SHARED_STORE: Mutex<TTLStore> = Mutex::new(TTLStore::new());
+}
+
+#[test]
+fn test_active_expire_reclaims_unread_keys() {
+    let mut store = TTLStore::new();
+    for i in 0..100 {
+        store
+            .set(format!("session:{}", i).as_bytes(), b"data", Some(Duration::from_millis(10)))
+            .unwrap();
+    }
+    store.set(b"persistent", b"data", None).unwrap();
+    assert_eq!(store.len(), 101);
+    assert_eq!(store.volatile_len(), 100);
+
+    std::thread::sleep(Duration::from_millis(20));
+
+    // The keys are never read; only the active cycle can reclaim them.
+    assert_eq!(store.active_expire_cycle(30), 30);
+    assert_eq!(store.active_expire_cycle(usize::MAX), 70);
+    assert_eq!(store.len(), 1);
+    assert_eq!(store.volatile_len(), 0);
+    assert_eq!(store.expired_keys(), 100);
+    assert_eq!(store.get(b"persistent"), Ok(Some(b"data".to_vec())));
+}
+
+#[test]
+fn test_active_expire_respects_updated_ttls() {
+    let mut store = TTLStore::new();
+    store.set(b"extended", b"v", Some(Duration::from_millis(10))).unwrap();
+    store.set(b"overwritten", b"v", Some(Duration::from_millis(10))).unwrap();
+    store.set(b"deleted", b"v", Some(Duration::from_millis(10))).unwrap();
+
+    assert!(store.expire(b"extended", Duration::from_secs(60)));
+    store.set(b"overwritten", b"v2", None).unwrap();
+    assert!(store.del(b"deleted"));
+    assert_eq!(store.volatile_len(), 1);
+
+    std::thread::sleep(Duration::from_millis(20));
+
+    assert_eq!(store.active_expire_cycle(usize::MAX), 0);
+    assert_eq!(store.get(b"extended"), Ok(Some(b"v".to_vec())));
+    assert_eq!(store.get(b"overwritten"), Ok(Some(b"v2".to_vec())));
+}
+
+#[tokio::test]
+async fn test_background_task_reclaims_unread_keys() {
+    {
+        let mut store = SHARED_STORE.lock().unwrap();
+        for i in 0..500 {
+            store
+                .set(format!("cache:{}", i).as_bytes(), b"data", Some(Duration::from_millis(20)))
+                .unwrap();
+        }
+    }
+
+    let handle = expiry::spawn_active_expiry(
+        &SHARED_STORE,
+        ActiveExpireConfig { hz: 100, effort: 10 },
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    handle.abort();
+
+    let store = SHARED_STORE.lock().unwrap();
+    assert!(store.is_empty());
+    assert_eq!(store.expired_keys(), 500);
+}
+
+#[test]
+fn test_noeviction_rejects_writes_over_maxmemory() {
+    let mut store = TTLStore::with_memory_config(MemoryConfig {
+        maxmemory: 1024,
+        policy: EvictionPolicy::NoEviction,
+        samples: 5,
+    });
+    let value = b"x".repeat(256);
+    let mut written = 0;
+    while store.set(format!("key:{}", written).as_bytes(), &value, None).is_ok() {
+        written += 1;
+    }
+    assert!(written > 0);
+    assert!(store.used_memory() > 1024);
+    assert_eq!(store.l_push(b"queue", &[b"job".to_vec()]), Err(StoreError::OutOfMemory));
+
+    // Reads and deletes still work, and freeing memory lets writes through again.
+    assert_eq!(store.get(b"key:0"), Ok(Some(value.clone())));
+    for i in 0..written {
+        store.del(format!("key:{}", i).as_bytes());
+    }
+    assert_eq!(store.used_memory(), 0);
+    assert!(store.set(b"key:0", &value, None).is_ok());
+}
+
+#[test]
+fn test_eviction_policies_free_memory() {
+    let value = b"x".repeat(256);
+
+    let mut store = TTLStore::with_memory_config(MemoryConfig {
+        maxmemory: 4096,
+        policy: EvictionPolicy::AllKeysLru,
+        samples: 64,
+    });
+    store.set(b"hot", &value, None).unwrap();
+    for i in 0..100 {
+        store.get(b"hot").unwrap();
+        store.set(format!("cold:{}", i).as_bytes(), &value, None).unwrap();
+    }
+    assert!(store.evicted_keys() > 0);
+    assert_eq!(store.get(b"hot"), Ok(Some(value.clone())));
+
+    let mut store = TTLStore::with_memory_config(MemoryConfig {
+        maxmemory: 4096,
+        policy: EvictionPolicy::VolatileTtl,
+        samples: 5,
+    });
+    store.set(b"persistent", &value, None).unwrap();
+    for i in 0..100 {
+        store
+            .set(format!("session:{}", i).as_bytes(), &value, Some(Duration::from_secs(60 + i)))
+            .unwrap();
+    }
+    assert!(store.evicted_keys() > 0);
+    assert_eq!(store.get(b"persistent"), Ok(Some(value.clone())));
+    assert_eq!(store.get(b"session:99"), Ok(Some(value)));
+    assert_eq!(store.get(b"session:0"), Ok(None));
+}